@@ -81,6 +81,15 @@ mod build_bundled {
         if cfg!(feature = "session") {
             cfg.flag("-DSQLITE_ENABLE_SESSION");
         }
+        if cfg!(feature = "scanstatus") {
+            cfg.flag("-DSQLITE_ENABLE_STMT_SCANSTATUS");
+        }
+        if cfg!(feature = "snapshot") {
+            cfg.flag("-DSQLITE_ENABLE_SNAPSHOT");
+        }
+        if cfg!(feature = "serialize") {
+            cfg.flag("-DSQLITE_ENABLE_DESERIALIZE");
+        }
 
         if let Ok(limit) = env::var("SQLITE_MAX_VARIABLE_NUMBER") {
             cfg.flag(&format!("-DSQLITE_MAX_VARIABLE_NUMBER={}", limit));
@@ -310,6 +319,15 @@ mod bindings {
         if cfg!(feature = "session") {
             bindings = bindings.clang_arg("-DSQLITE_ENABLE_SESSION");
         }
+        if cfg!(feature = "scanstatus") {
+            bindings = bindings.clang_arg("-DSQLITE_ENABLE_STMT_SCANSTATUS");
+        }
+        if cfg!(feature = "snapshot") {
+            bindings = bindings.clang_arg("-DSQLITE_ENABLE_SNAPSHOT");
+        }
+        if cfg!(feature = "serialize") {
+            bindings = bindings.clang_arg("-DSQLITE_ENABLE_DESERIALIZE");
+        }
 
         bindings
             .generate()
@@ -55,10 +55,14 @@ pub enum ErrorCode {
     Unknown,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug)]
 pub struct Error {
     pub code: ErrorCode,
     pub extended_code: c_int,
+    /// The error reported by the operating system for a failed file
+    /// operation (see `sqlite3_system_errno`), or 0. It is not compared by
+    /// `eq`.
+    pub system_errno: c_int,
 }
 
 impl Error {
@@ -93,10 +97,19 @@ impl Error {
         Error {
             code,
             extended_code: result_code,
+            system_errno: 0,
         }
     }
 }
 
+impl PartialEq for Error {
+    fn eq(&self, other: &Error) -> bool {
+        self.code == other.code && self.extended_code == other.extended_code
+    }
+}
+
+impl Eq for Error {}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -51,6 +51,282 @@ pub enum Limit {
 
 include!(concat!(env!("OUT_DIR"), "/bindgen.rs"));
 
+/// Authorizer action code of recursive common table expressions (3.8.3),
+/// which the pre-generated bindings predate.
+#[cfg(not(any(feature = "bundled", feature = "buildtime_bindgen")))]
+pub const SQLITE_RECURSIVE: i32 = 33;
+
+/// `sqlite3_db_status` verbs of SQLite 3.7.9 and 3.7.12, which the
+/// pre-generated bindings older than 3.7.16 predate.
+#[cfg(not(any(
+    feature = "bundled",
+    feature = "buildtime_bindgen",
+    feature = "min_sqlite_version_3_7_16"
+)))]
+pub const SQLITE_DBSTATUS_CACHE_HIT: i32 = 7;
+#[cfg(not(any(
+    feature = "bundled",
+    feature = "buildtime_bindgen",
+    feature = "min_sqlite_version_3_7_16"
+)))]
+pub const SQLITE_DBSTATUS_CACHE_MISS: i32 = 8;
+#[cfg(not(any(
+    feature = "bundled",
+    feature = "buildtime_bindgen",
+    feature = "min_sqlite_version_3_7_16"
+)))]
+pub const SQLITE_DBSTATUS_CACHE_WRITE: i32 = 9;
+
+#[cfg(all(
+    feature = "system_errno",
+    not(any(feature = "bundled", feature = "buildtime_bindgen"))
+))]
+extern "C" {
+    /// Available since SQLite 3.12.0, which the pre-generated bindings
+    /// predate: check `sqlite3_libversion_number` before calling it.
+    pub fn sqlite3_system_errno(db: *mut sqlite3) -> ::std::os::raw::c_int;
+}
+
+// Preupdate hook interfaces (3.13.0), only declared by `bindgen` when
+// SQLite is compiled with `SQLITE_ENABLE_PREUPDATE_HOOK`, which neither the
+// pre-generated nor the bundled bindings are.
+#[cfg(all(feature = "preupdate_hook", not(feature = "buildtime_bindgen")))]
+extern "C" {
+    pub fn sqlite3_preupdate_hook(
+        db: *mut sqlite3,
+        xPreUpdate: ::std::option::Option<
+            unsafe extern "C" fn(
+                pCtx: *mut ::std::os::raw::c_void,
+                db: *mut sqlite3,
+                op: ::std::os::raw::c_int,
+                zDb: *const ::std::os::raw::c_char,
+                zName: *const ::std::os::raw::c_char,
+                iKey1: sqlite3_int64,
+                iKey2: sqlite3_int64,
+            ),
+        >,
+        arg1: *mut ::std::os::raw::c_void,
+    ) -> *mut ::std::os::raw::c_void;
+    pub fn sqlite3_preupdate_old(
+        arg1: *mut sqlite3,
+        arg2: ::std::os::raw::c_int,
+        arg3: *mut *mut sqlite3_value,
+    ) -> ::std::os::raw::c_int;
+    pub fn sqlite3_preupdate_count(arg1: *mut sqlite3) -> ::std::os::raw::c_int;
+    pub fn sqlite3_preupdate_depth(arg1: *mut sqlite3) -> ::std::os::raw::c_int;
+    pub fn sqlite3_preupdate_new(
+        arg1: *mut sqlite3,
+        arg2: ::std::os::raw::c_int,
+        arg3: *mut *mut sqlite3_value,
+    ) -> ::std::os::raw::c_int;
+}
+
+/// Session extension interfaces (3.13.0), only declared by `bindgen` when
+/// SQLite is compiled with `SQLITE_ENABLE_SESSION`, which neither the
+/// pre-generated nor the bundled bindings are.
+#[cfg(all(feature = "session", not(feature = "buildtime_bindgen")))]
+mod session {
+    use super::{sqlite3, sqlite3_value};
+    use std::os::raw::{c_char, c_int, c_uchar, c_void};
+
+    pub const SQLITE_CHANGESET_DATA: i32 = 1;
+    pub const SQLITE_CHANGESET_NOTFOUND: i32 = 2;
+    pub const SQLITE_CHANGESET_CONFLICT: i32 = 3;
+    pub const SQLITE_CHANGESET_CONSTRAINT: i32 = 4;
+    pub const SQLITE_CHANGESET_FOREIGN_KEY: i32 = 5;
+    pub const SQLITE_CHANGESET_OMIT: i32 = 0;
+    pub const SQLITE_CHANGESET_REPLACE: i32 = 1;
+    pub const SQLITE_CHANGESET_ABORT: i32 = 2;
+
+    #[repr(C)]
+    #[derive(Debug, Copy, Clone)]
+    pub struct sqlite3_session {
+        _unused: [u8; 0],
+    }
+    #[repr(C)]
+    #[derive(Debug, Copy, Clone)]
+    pub struct sqlite3_changeset_iter {
+        _unused: [u8; 0],
+    }
+    #[repr(C)]
+    #[derive(Debug, Copy, Clone)]
+    pub struct sqlite3_changegroup {
+        _unused: [u8; 0],
+    }
+
+    type xFilter = Option<unsafe extern "C" fn(pCtx: *mut c_void, zTab: *const c_char) -> c_int>;
+    type xConflict = Option<
+        unsafe extern "C" fn(
+            pCtx: *mut c_void,
+            eConflict: c_int,
+            p: *mut sqlite3_changeset_iter,
+        ) -> c_int,
+    >;
+    type xInput = Option<
+        unsafe extern "C" fn(pIn: *mut c_void, pData: *mut c_void, pnData: *mut c_int) -> c_int,
+    >;
+    type xOutput = Option<
+        unsafe extern "C" fn(pOut: *mut c_void, pData: *const c_void, nData: c_int) -> c_int,
+    >;
+
+    extern "C" {
+        pub fn sqlite3session_create(
+            db: *mut sqlite3,
+            zDb: *const c_char,
+            ppSession: *mut *mut sqlite3_session,
+        ) -> c_int;
+        pub fn sqlite3session_delete(pSession: *mut sqlite3_session);
+        pub fn sqlite3session_enable(pSession: *mut sqlite3_session, bEnable: c_int) -> c_int;
+        pub fn sqlite3session_indirect(pSession: *mut sqlite3_session, bIndirect: c_int) -> c_int;
+        pub fn sqlite3session_attach(pSession: *mut sqlite3_session, zTab: *const c_char) -> c_int;
+        pub fn sqlite3session_table_filter(
+            pSession: *mut sqlite3_session,
+            xFilter: xFilter,
+            pCtx: *mut c_void,
+        );
+        pub fn sqlite3session_changeset(
+            pSession: *mut sqlite3_session,
+            pnChangeset: *mut c_int,
+            ppChangeset: *mut *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3session_diff(
+            pSession: *mut sqlite3_session,
+            zFromDb: *const c_char,
+            zTbl: *const c_char,
+            pzErrMsg: *mut *mut c_char,
+        ) -> c_int;
+        pub fn sqlite3session_patchset(
+            pSession: *mut sqlite3_session,
+            pnPatchset: *mut c_int,
+            ppPatchset: *mut *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3session_isempty(pSession: *mut sqlite3_session) -> c_int;
+        pub fn sqlite3session_changeset_strm(
+            pSession: *mut sqlite3_session,
+            xOutput: xOutput,
+            pOut: *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3session_patchset_strm(
+            pSession: *mut sqlite3_session,
+            xOutput: xOutput,
+            pOut: *mut c_void,
+        ) -> c_int;
+
+        pub fn sqlite3changeset_start(
+            pp: *mut *mut sqlite3_changeset_iter,
+            nChangeset: c_int,
+            pChangeset: *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3changeset_start_strm(
+            pp: *mut *mut sqlite3_changeset_iter,
+            xInput: xInput,
+            pIn: *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3changeset_next(pIter: *mut sqlite3_changeset_iter) -> c_int;
+        pub fn sqlite3changeset_op(
+            pIter: *mut sqlite3_changeset_iter,
+            pzTab: *mut *const c_char,
+            pnCol: *mut c_int,
+            pOp: *mut c_int,
+            pbIndirect: *mut c_int,
+        ) -> c_int;
+        pub fn sqlite3changeset_pk(
+            pIter: *mut sqlite3_changeset_iter,
+            pabPK: *mut *mut c_uchar,
+            pnCol: *mut c_int,
+        ) -> c_int;
+        pub fn sqlite3changeset_old(
+            pIter: *mut sqlite3_changeset_iter,
+            iVal: c_int,
+            ppValue: *mut *mut sqlite3_value,
+        ) -> c_int;
+        pub fn sqlite3changeset_new(
+            pIter: *mut sqlite3_changeset_iter,
+            iVal: c_int,
+            ppValue: *mut *mut sqlite3_value,
+        ) -> c_int;
+        pub fn sqlite3changeset_conflict(
+            pIter: *mut sqlite3_changeset_iter,
+            iVal: c_int,
+            ppValue: *mut *mut sqlite3_value,
+        ) -> c_int;
+        pub fn sqlite3changeset_fk_conflicts(
+            pIter: *mut sqlite3_changeset_iter,
+            pnOut: *mut c_int,
+        ) -> c_int;
+        pub fn sqlite3changeset_finalize(pIter: *mut sqlite3_changeset_iter) -> c_int;
+        pub fn sqlite3changeset_invert(
+            nIn: c_int,
+            pIn: *const c_void,
+            pnOut: *mut c_int,
+            ppOut: *mut *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3changeset_invert_strm(
+            xInput: xInput,
+            pIn: *mut c_void,
+            xOutput: xOutput,
+            pOut: *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3changeset_concat(
+            nA: c_int,
+            pA: *mut c_void,
+            nB: c_int,
+            pB: *mut c_void,
+            pnOut: *mut c_int,
+            ppOut: *mut *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3changeset_concat_strm(
+            xInputA: xInput,
+            pInA: *mut c_void,
+            xInputB: xInput,
+            pInB: *mut c_void,
+            xOutput: xOutput,
+            pOut: *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3changeset_apply(
+            db: *mut sqlite3,
+            nChangeset: c_int,
+            pChangeset: *mut c_void,
+            xFilter: xFilter,
+            xConflict: xConflict,
+            pCtx: *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3changeset_apply_strm(
+            db: *mut sqlite3,
+            xInput: xInput,
+            pIn: *mut c_void,
+            xFilter: xFilter,
+            xConflict: xConflict,
+            pCtx: *mut c_void,
+        ) -> c_int;
+
+        pub fn sqlite3changegroup_new(pp: *mut *mut sqlite3_changegroup) -> c_int;
+        pub fn sqlite3changegroup_add(
+            arg1: *mut sqlite3_changegroup,
+            nData: c_int,
+            pData: *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3changegroup_add_strm(
+            arg1: *mut sqlite3_changegroup,
+            xInput: xInput,
+            pIn: *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3changegroup_output(
+            arg1: *mut sqlite3_changegroup,
+            pnData: *mut c_int,
+            ppData: *mut *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3changegroup_output_strm(
+            arg1: *mut sqlite3_changegroup,
+            xOutput: xOutput,
+            pOut: *mut c_void,
+        ) -> c_int;
+        pub fn sqlite3changegroup_delete(arg1: *mut sqlite3_changegroup);
+    }
+}
+#[cfg(all(feature = "session", not(feature = "buildtime_bindgen")))]
+pub use self::session::*;
+
 pub type sqlite3_index_constraint = sqlite3_index_info_sqlite3_index_constraint;
 pub type sqlite3_index_constraint_usage = sqlite3_index_info_sqlite3_index_constraint_usage;
 
@@ -0,0 +1,200 @@
+//! Derive macros for rusqlite, enabled by its `derive` feature.
+//!
+//! * `#[derive(FromRow)]` implements `rusqlite::FromRow` for a struct with
+//!   named fields, reading each field from the column of the same name.
+//! * `#[derive(ToParams)]` implements `rusqlite::ToParams`, binding each
+//!   field to the named parameter `:field`.
+//!
+//! A field can be mapped to another column or parameter name with
+//! `#[rusqlite(rename = "name")]`.
+//!
+//! ```rust,ignore
+//! use rusqlite::{FromRow, ToParams};
+//!
+//! #[derive(FromRow, ToParams)]
+//! struct Person {
+//!     id: i64,
+//!     #[rusqlite(rename = "full_name")]
+//!     name: String,
+//! }
+//! ```
+extern crate proc_macro;
+
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+/// Derive `rusqlite::FromRow`.
+#[proc_macro_derive(FromRow, attributes(rusqlite))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let (name, fields) = match parse_struct(input) {
+        Ok(parsed) => parsed,
+        Err(msg) => return compile_error(&msg),
+    };
+    let inits: String = fields
+        .iter()
+        .map(|f| format!("{}: row.get({:?})?,", f.ident, f.column))
+        .collect();
+    format!(
+        "impl ::rusqlite::FromRow for {name} {{
+             fn from_row(row: &::rusqlite::Row<'_>) -> ::rusqlite::Result<Self> {{
+                 ::std::result::Result::Ok({name} {{ {inits} }})
+             }}
+         }}",
+        name = name,
+        inits = inits
+    )
+    .parse()
+    .unwrap()
+}
+
+/// Derive `rusqlite::ToParams`.
+#[proc_macro_derive(ToParams, attributes(rusqlite))]
+pub fn derive_to_params(input: TokenStream) -> TokenStream {
+    let (name, fields) = match parse_struct(input) {
+        Ok(parsed) => parsed,
+        Err(msg) => return compile_error(&msg),
+    };
+    let params: String = fields
+        .iter()
+        .map(|f| {
+            format!(
+                "({:?}, &self.{} as &dyn ::rusqlite::ToSql),",
+                format!(":{}", f.column),
+                f.ident
+            )
+        })
+        .collect();
+    format!(
+        "impl ::rusqlite::ToParams for {name} {{
+             fn to_params(&self) -> ::std::vec::Vec<(&str, &dyn ::rusqlite::ToSql)> {{
+                 ::std::vec![{params}]
+             }}
+         }}",
+        name = name,
+        params = params
+    )
+    .parse()
+    .unwrap()
+}
+
+struct Field {
+    /// Identifier of the field, as written (e.g. `r#type`).
+    ident: String,
+    /// Column or parameter name (without prefix).
+    column: String,
+}
+
+fn compile_error(msg: &str) -> TokenStream {
+    format!("compile_error!({:?});", msg).parse().unwrap()
+}
+
+/// Parse a struct with named fields, and return its name and fields.
+fn parse_struct(input: TokenStream) -> Result<(String, Vec<Field>), String> {
+    let mut tokens = input.into_iter();
+    let mut name = None;
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Ident(ref ident) if ident.to_string() == "struct" => {
+                match tokens.next() {
+                    Some(TokenTree::Ident(ident)) => name = Some(ident.to_string()),
+                    _ => return Err("expected a struct name".to_owned()),
+                }
+                break;
+            }
+            TokenTree::Ident(ref ident) if ["enum", "union"].contains(&&*ident.to_string()) => {
+                return Err("rusqlite derives only support structs".to_owned());
+            }
+            _ => (),
+        }
+    }
+    let name = name.ok_or_else(|| "expected a struct".to_owned())?;
+    match tokens.next() {
+        Some(TokenTree::Group(ref group)) if group.delimiter() == Delimiter::Brace => {
+            Ok((name, parse_fields(group.stream())?))
+        }
+        Some(TokenTree::Punct(ref punct)) if punct.as_char() == '<' => {
+            Err("rusqlite derives do not support generic structs".to_owned())
+        }
+        _ => Err("rusqlite derives only support structs with named fields".to_owned()),
+    }
+}
+
+fn parse_fields(body: TokenStream) -> Result<Vec<Field>, String> {
+    let mut fields = Vec::new();
+    let mut rename = None;
+    let mut ident = None;
+    // Nesting level of angle brackets, whose commas do not separate fields.
+    let mut depth = 0;
+    let mut in_type = false;
+    let mut prev_dash = false;
+    for token in body {
+        let is_dash = match token {
+            TokenTree::Punct(ref punct) => punct.as_char() == '-',
+            _ => false,
+        };
+        match token {
+            TokenTree::Punct(ref punct) if in_type => match punct.as_char() {
+                '<' => depth += 1,
+                '>' if !prev_dash => depth -= 1,
+                ',' if depth == 0 => {
+                    fields.push(field(ident.take(), rename.take())?);
+                    in_type = false;
+                }
+                _ => (),
+            },
+            _ if in_type => (),
+            TokenTree::Punct(ref punct) if punct.as_char() == ':' => in_type = true,
+            TokenTree::Group(ref group) if group.delimiter() == Delimiter::Bracket => {
+                if let Some(value) = parse_attribute(group.stream())? {
+                    rename = Some(value);
+                }
+            }
+            TokenTree::Ident(ref i) => {
+                let i = i.to_string();
+                if i != "pub" {
+                    ident = Some(i);
+                }
+            }
+            _ => (),
+        }
+        prev_dash = is_dash;
+    }
+    if in_type {
+        fields.push(field(ident, rename)?);
+    }
+    Ok(fields)
+}
+
+fn field(ident: Option<String>, rename: Option<String>) -> Result<Field, String> {
+    let ident = ident.ok_or_else(|| "expected a field name".to_owned())?;
+    let column = rename.unwrap_or_else(|| ident.trim_start_matches("r#").to_owned());
+    Ok(Field { ident, column })
+}
+
+/// Return the name of a `rusqlite(rename = "name")` attribute, or `None` for
+/// other attributes.
+fn parse_attribute(attr: TokenStream) -> Result<Option<String>, String> {
+    let mut tokens = attr.into_iter();
+    match tokens.next() {
+        Some(TokenTree::Ident(ref ident)) if ident.to_string() == "rusqlite" => (),
+        _ => return Ok(None),
+    }
+    let args: Vec<TokenTree> = match tokens.next() {
+        Some(TokenTree::Group(ref group)) if group.delimiter() == Delimiter::Parenthesis => {
+            group.stream().into_iter().collect()
+        }
+        _ => return Err("expected #[rusqlite(rename = \"name\")]".to_owned()),
+    };
+    match args.as_slice() {
+        [TokenTree::Ident(key), TokenTree::Punct(eq), TokenTree::Literal(lit)]
+            if key.to_string() == "rename" && eq.as_char() == '=' =>
+        {
+            let lit = lit.to_string();
+            if lit.len() >= 2 && lit.starts_with('"') && lit.ends_with('"') && !lit.contains('\\') {
+                Ok(Some(lit[1..lit.len() - 1].to_owned()))
+            } else {
+                Err(format!("invalid rename: {}", lit))
+            }
+        }
+        _ => Err("expected #[rusqlite(rename = \"name\")]".to_owned()),
+    }
+}
@@ -0,0 +1,389 @@
+//! Asynchronous access to a connection
+//!
+//! An `AsyncConnection` owns a `Connection` on a dedicated thread, and runs
+//! the calls made from async code on it, one at a time, in order. Each call
+//! returns a future which resolves once the call has run on the thread, so
+//! that async tasks are never blocked by SQLite.
+//!
+//! The futures only use the standard library: they can be awaited from any
+//! executor. With tokio, `open_async` also opens the database without
+//! blocking the calling task.
+//!
+//! This module requires the `tokio` feature. As `async` is a keyword, its
+//! path is written `rusqlite::r#async`.
+//!
+//! ```rust,no_run
+//! # use rusqlite::r#async::AsyncConnection;
+//! # use rusqlite::Result;
+//! async fn names(conn: &AsyncConnection) -> Result<Vec<String>> {
+//!     conn.execute("INSERT INTO people(name) VALUES(?)", ["alice".to_owned()])
+//!         .await?;
+//!     conn.query_map("SELECT name FROM people WHERE id > ?", [0], |row| row.get(0))
+//!         .await
+//! }
+//! ```
+use std::future::Future;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::types::Params;
+use crate::{Connection, Error, Result, Row, Transaction};
+
+type Call = Box<dyn FnOnce(&mut Connection) + Send>;
+
+enum Message {
+    Call(Call),
+    Close(Completer<()>),
+}
+
+/// A connection owned by a dedicated thread, used from async code.
+///
+/// Dropping an `AsyncConnection` does not wait for the calls already made
+/// (whose futures may have been dropped): they still run, then the
+/// connection is closed on its thread.
+pub struct AsyncConnection {
+    // `Sender` is only `Sync` since Rust 1.72
+    sender: Mutex<Sender<Message>>,
+}
+
+impl AsyncConnection {
+    /// Open a new connection to a SQLite database, and start its thread.
+    ///
+    /// The database is opened by the calling thread.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `path` cannot be converted to a C-compatible
+    /// string or if the underlying SQLite open call fails.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<AsyncConnection> {
+        Connection::open(path).map(AsyncConnection::from_connection)
+    }
+
+    /// Open a new connection to a SQLite database on the blocking thread pool
+    /// of the current tokio runtime, without blocking the calling task, and
+    /// start its thread.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `path` cannot be converted to a C-compatible
+    /// string or if the underlying SQLite open call fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime, or if the runtime shuts
+    /// down before the database is opened. A panic while opening the
+    /// database is propagated to the calling task.
+    pub async fn open_async<P: AsRef<Path>>(path: P) -> Result<AsyncConnection> {
+        let path = path.as_ref().to_owned();
+        tokio::task::spawn_blocking(move || AsyncConnection::open(path))
+            .await
+            .unwrap_or_else(|err| {
+                if err.is_panic() {
+                    resume_unwind(err.into_panic())
+                }
+                // A blocking task can only be cancelled by a shutdown of the
+                // runtime.
+                panic!("tokio runtime shut down while opening the database")
+            })
+    }
+
+    /// Open a new connection to an in-memory SQLite database, and start its
+    /// thread.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite open call fails.
+    pub fn open_in_memory() -> Result<AsyncConnection> {
+        Connection::open_in_memory().map(AsyncConnection::from_connection)
+    }
+
+    /// Move `conn` to a new thread, to be used from async code.
+    pub fn from_connection(mut conn: Connection) -> AsyncConnection {
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for message in receiver {
+                match message {
+                    Message::Call(call) => call(&mut conn),
+                    Message::Close(completer) => {
+                        completer.complete(Ok(conn.close().map_err(|(_, err)| err)));
+                        return;
+                    }
+                }
+            }
+        });
+        AsyncConnection {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    fn send(&self, message: Message) {
+        // If the thread is gone, the message is dropped with the completer it
+        // holds, and the future resolves to an error.
+        let sender = match self.sender.lock() {
+            Ok(sender) => sender,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let _ = sender.send(message);
+    }
+
+    /// Run `f` with the connection on its thread, and return a future
+    /// resolving to its result.
+    ///
+    /// If `f` panics, the panic is resumed in the task awaiting the future,
+    /// and the connection can still be used.
+    pub fn call<F, R>(&self, f: F) -> CallFuture<R>
+    where
+        F: FnOnce(&mut Connection) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (completer, future) = call_future();
+        let call: Call = Box::new(move |conn| {
+            completer.complete(catch_unwind(AssertUnwindSafe(|| f(conn))));
+        });
+        self.send(Message::Call(call));
+        future
+    }
+
+    /// Convenience method to prepare and execute a single SQL statement (see
+    /// `Connection::execute`).
+    ///
+    /// The parameters are moved to the thread of the connection: they must
+    /// be owned, e.g. `[1, 2]` or `vec![Value::from(1)]` (`NO_PARAMS` cannot
+    /// be used).
+    pub fn execute<P>(&self, sql: &str, params: P) -> CallFuture<usize>
+    where
+        P: Params + Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.call(move |conn| conn.execute(&sql, params))
+    }
+
+    /// Execute a query, and return the result of `f` for each row (see
+    /// `Statement::query_map`), with owned parameters (see `execute`).
+    pub fn query_map<T, P, F>(&self, sql: &str, params: P, f: F) -> CallFuture<Vec<T>>
+    where
+        T: Send + 'static,
+        P: Params + Send + 'static,
+        F: FnMut(&Row<'_>) -> Result<T> + Send + 'static,
+    {
+        let sql = sql.to_owned();
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(params, f)?;
+            rows.collect()
+        })
+    }
+
+    /// Run `f` in a transaction (see `Connection::transaction`), which is
+    /// committed if `f` succeeds, and rolled back otherwise.
+    pub fn transaction<F, R>(&self, f: F) -> CallFuture<R>
+    where
+        F: FnOnce(&Transaction<'_>) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            let r = f(&tx)?;
+            tx.commit()?;
+            Ok(r)
+        })
+    }
+
+    /// Close the connection once the calls already made have run, and
+    /// return a future resolving to the result of `Connection::close`.
+    pub fn close(self) -> CallFuture<()> {
+        let (completer, future) = call_future();
+        self.send(Message::Close(completer));
+        future
+    }
+}
+
+struct Slot<R> {
+    // `Err` with the panic of the call.
+    result: Option<thread::Result<Result<R>>>,
+    done: bool,
+    waker: Option<Waker>,
+}
+
+/// The future of a call made with an `AsyncConnection`.
+#[must_use = "futures do nothing unless awaited"]
+pub struct CallFuture<R> {
+    slot: Arc<Mutex<Slot<R>>>,
+}
+
+impl<R> Future for CallFuture<R> {
+    type Output = Result<R>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<R>> {
+        let mut slot = self.slot.lock().unwrap();
+        if slot.done {
+            match slot.result.take() {
+                Some(Ok(result)) => Poll::Ready(result),
+                Some(Err(panic)) => {
+                    // Not to poison the mutex.
+                    drop(slot);
+                    resume_unwind(panic)
+                }
+                None => Poll::Ready(Err(Error::AsyncConnectionClosed)),
+            }
+        } else {
+            slot.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Completes a `CallFuture`, with an error if it is dropped first.
+struct Completer<R> {
+    slot: Arc<Mutex<Slot<R>>>,
+}
+
+impl<R> Completer<R> {
+    fn complete(self, result: thread::Result<Result<R>>) {
+        self.slot.lock().unwrap().result = Some(result);
+        // `drop` wakes up the future.
+    }
+}
+
+impl<R> Drop for Completer<R> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut slot = match self.slot.lock() {
+                Ok(slot) => slot,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            slot.done = true;
+            slot.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+fn call_future<R>() -> (Completer<R>, CallFuture<R>) {
+    let slot = Arc::new(Mutex::new(Slot {
+        result: None,
+        done: false,
+        waker: None,
+    }));
+    (Completer { slot: slot.clone() }, CallFuture { slot })
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake};
+    use std::thread::{self, Thread};
+
+    use super::AsyncConnection;
+    use crate::{Error, NO_PARAMS};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(mut future: F) -> F::Output {
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = unsafe { Pin::new_unchecked(&mut future) };
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_async_connection() {
+        let conn = AsyncConnection::open_in_memory().unwrap();
+        block_on(conn.call(|conn| conn.execute_batch("CREATE TABLE foo(x INTEGER)"))).unwrap();
+        assert_eq!(
+            1,
+            block_on(conn.execute("INSERT INTO foo VALUES(?)", [1])).unwrap()
+        );
+        // Calls run in order, even if their futures are not awaited.
+        drop(conn.execute("INSERT INTO foo VALUES(?)", [2]));
+        let xs = block_on(
+            conn.query_map("SELECT x FROM foo WHERE x > ? ORDER BY x", [0], |r| {
+                r.get::<_, i64>(0)
+            }),
+        )
+        .unwrap();
+        assert_eq!(vec![1, 2], xs);
+
+        let r = block_on(conn.transaction(|tx| {
+            tx.execute("INSERT INTO foo VALUES(3)", NO_PARAMS)?;
+            Err::<(), _>(Error::QueryReturnedNoRows)
+        }));
+        assert_eq!(Err(Error::QueryReturnedNoRows), r);
+        block_on(conn.transaction(|tx| tx.execute("INSERT INTO foo VALUES(4)", NO_PARAMS)))
+            .unwrap();
+        let sum: i64 = block_on(
+            conn.call(|conn| conn.query_row("SELECT sum(x) FROM foo", NO_PARAMS, |r| r.get(0))),
+        )
+        .unwrap();
+        assert_eq!(7, sum);
+
+        let r = std::panic::catch_unwind(|| {
+            block_on(conn.call(|_| -> crate::Result<()> { panic!("oops") }))
+        });
+        assert_eq!(Some(&"oops"), r.unwrap_err().downcast_ref::<&str>());
+        block_on(conn.call(|conn| conn.execute_batch("SELECT 1"))).unwrap();
+        block_on(conn.close()).unwrap();
+    }
+
+    #[test]
+    fn test_open_async() {
+        let temp_dir = tempdir::TempDir::new("test_open_async").unwrap();
+        let path = temp_dir.path().join("test.db");
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let count: i64 = runtime
+            .block_on(async {
+                let conn = AsyncConnection::open_async(&path).await?;
+                conn.call(|conn| conn.execute_batch("CREATE TABLE foo(x INTEGER)"))
+                    .await?;
+                conn.execute("INSERT INTO foo VALUES(?)", [1]).await?;
+                conn.call(|conn| {
+                    conn.query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))
+                })
+                .await
+            })
+            .unwrap();
+        assert_eq!(1, count);
+    }
+
+    #[test]
+    fn test_async_connection_from_threads() {
+        let conn = Arc::new(AsyncConnection::open_in_memory().unwrap());
+        block_on(conn.call(|conn| conn.execute_batch("CREATE TABLE foo(x INTEGER)"))).unwrap();
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let conn = conn.clone();
+                thread::spawn(move || block_on(conn.execute("INSERT INTO foo VALUES(?)", [i])))
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap().unwrap();
+        }
+        let count: i64 = block_on(
+            conn.call(|conn| conn.query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))),
+        )
+        .unwrap();
+        assert_eq!(4, count);
+    }
+}
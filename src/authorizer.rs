@@ -0,0 +1,123 @@
+//! Authorizers restricting the statements prepared on a connection (see
+//! `ReadOnlyView` and `Sandbox`).
+//!
+//! SQLite has a single authorizer per connection, so rusqlite registers one
+//! callback which denies a statement if any of the active authorizers denies
+//! it: a `Sandbox` inside a `ReadOnlyView` (or the other way around) is
+//! restricted by both, and each one is removed, whatever the order, when it
+//! is dropped.
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+use crate::ffi;
+use crate::{Connection, InnerConnection, Result};
+
+/// An action checked by an authorizer, see
+/// [`sqlite3_set_authorizer`](https://sqlite.org/c3ref/set_authorizer.html).
+pub(crate) struct Action<'a> {
+    /// The action code, e.g. `ffi::SQLITE_READ`.
+    pub code: c_int,
+    pub arg1: Option<&'a str>,
+    pub arg2: Option<&'a str>,
+    /// The name of the inner-most view or trigger from which the action is
+    /// performed.
+    pub view: Option<&'a str>,
+}
+
+type Authorize = Box<dyn Fn(&Action<'_>) -> bool + Send>;
+
+/// The active authorizers of a connection: boxed so that its address, given
+/// to SQLite, is stable.
+#[derive(Default)]
+pub(crate) struct Authorizers {
+    next_id: u64,
+    active: Vec<(u64, Authorize)>,
+}
+
+/// Remove its authorizer from the connection when dropped.
+pub(crate) struct AuthorizerGuard<'conn> {
+    conn: &'conn Connection,
+    id: u64,
+}
+
+impl Drop for AuthorizerGuard<'_> {
+    fn drop(&mut self) {
+        self.conn.db.borrow_mut().remove_authorizer(self.id);
+    }
+}
+
+impl Connection {
+    /// Deny the statements prepared on this connection for which `authorize`
+    /// returns `false`, until the guard is dropped.
+    pub(crate) fn push_authorizer<F>(&self, authorize: F) -> Result<AuthorizerGuard<'_>>
+    where
+        F: Fn(&Action<'_>) -> bool + Send + 'static,
+    {
+        let id = self.db.borrow_mut().push_authorizer(Box::new(authorize))?;
+        Ok(AuthorizerGuard { conn: self, id })
+    }
+}
+
+impl InnerConnection {
+    fn push_authorizer(&mut self, authorize: Authorize) -> Result<u64> {
+        if self.authorizers.active.is_empty() {
+            let r = unsafe {
+                ffi::sqlite3_set_authorizer(
+                    self.db(),
+                    Some(call_authorizers),
+                    &*self.authorizers as *const Authorizers as *mut c_void,
+                )
+            };
+            self.decode_result(r)?;
+        }
+        let id = self.authorizers.next_id;
+        self.authorizers.next_id += 1;
+        self.authorizers.active.push((id, authorize));
+        Ok(id)
+    }
+
+    fn remove_authorizer(&mut self, id: u64) {
+        self.authorizers.active.retain(|&(i, _)| i != id);
+        if self.authorizers.active.is_empty() && !self.db.is_null() {
+            unsafe { ffi::sqlite3_set_authorizer(self.db(), None, ptr::null_mut()) };
+        }
+    }
+}
+
+unsafe extern "C" fn call_authorizers(
+    p_arg: *mut c_void,
+    code: c_int,
+    arg1: *const c_char,
+    arg2: *const c_char,
+    _: *const c_char,
+    view: *const c_char,
+) -> c_int {
+    unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+        if s.is_null() {
+            None
+        } else {
+            CStr::from_ptr(s).to_str().ok()
+        }
+    }
+
+    let authorizers = &*(p_arg as *const Authorizers);
+    let action = Action {
+        code,
+        arg1: to_str(arg1),
+        arg2: to_str(arg2),
+        view: to_str(view),
+    };
+    let allowed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        authorizers
+            .active
+            .iter()
+            .all(|(_, authorize)| authorize(&action))
+    }));
+    if let Ok(true) = allowed {
+        ffi::SQLITE_OK
+    } else {
+        ffi::SQLITE_DENY
+    }
+}
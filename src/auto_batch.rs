@@ -0,0 +1,501 @@
+//! Implicit transactions grouping consecutive writes.
+use std::time::{Duration, Instant};
+
+use crate::script::skip_comments;
+use crate::{Connection, Result, Statement};
+
+#[derive(Debug)]
+pub(crate) struct AutoBatch {
+    max_statements: usize,
+    max_delay: Duration,
+    pending: usize,
+    // When the transaction of the pending batch was started by us.
+    started: Option<Instant>,
+}
+
+enum Mode {
+    Plain,
+    Flush,
+    Batch,
+}
+
+impl Connection {
+    /// Group the statements run by `execute`, `execute_named` and `run`
+    /// (outside of any explicit transaction) into implicit transactions of at
+    /// most `max_statements` statements, committed once `max_delay` has
+    /// elapsed since the first one. Committing once per batch instead of once
+    /// per statement greatly improves the throughput of many small writes.
+    ///
+    /// The pending batch is also committed by `flush`, before any other
+    /// statement is prepared (e.g. by a query or `transaction`), and when the
+    /// connection is closed. The delay is only checked when a statement is
+    /// executed: call `flush` when the connection becomes idle.
+    ///
+    /// Call `flush` (or `close`, which returns the failure) before dropping
+    /// the connection: if the commit of the pending batch fails when the
+    /// connection is dropped, the batch is rolled back and the failure is
+    /// only written to the SQLite error log (with the `trace` feature).
+    ///
+    /// If `flush` fails (e.g. because of a deferred foreign key violation),
+    /// the batch is still pending and the statements which need to flush it
+    /// fail too: fix the batch and `flush` again, or roll it back with
+    /// `discard_batch` (or a `ROLLBACK` statement).
+    ///
+    /// Only `INSERT`, `UPDATE`, `DELETE` and `REPLACE` statements are batched,
+    /// with or without a `WITH` clause (which is only recognized with the
+    /// `bundled` feature: before SQLite 3.7.4, `WITH` statements flush the
+    /// batch).
+    /// If SQLite rolls back the implicit transaction because of an error
+    /// (e.g. `SQLITE_FULL`), the statements of the pending batch are lost, as
+    /// they would be in an explicit transaction.
+    ///
+    /// Auto-batching is disabled by default, and `max_statements` 0 disables
+    /// it, committing the pending batch or, if the commit fails, rolling it
+    /// back.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use std::time::Duration;
+    /// fn log_events(conn: &Connection, events: &[String]) -> Result<()> {
+    ///     conn.set_auto_batch(1000, Duration::from_millis(100))?;
+    ///     for event in events {
+    ///         conn.execute("INSERT INTO events (data) VALUES (?)", &[event])?;
+    ///     }
+    ///     conn.flush()
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if auto-batching is disabled and the pending batch
+    /// cannot be committed, in which case it has been rolled back.
+    pub fn set_auto_batch(&self, max_statements: usize, max_delay: Duration) -> Result<()> {
+        if max_statements == 0 {
+            let r = self.end_batch();
+            self.db.borrow_mut().auto_batch = None;
+            self.auto_batch.set(false);
+            return r;
+        }
+        self.auto_batch.set(true);
+        let mut db = self.db.borrow_mut();
+        match db.auto_batch {
+            Some(ref mut batch) => {
+                batch.max_statements = max_statements;
+                batch.max_delay = max_delay;
+            }
+            None => {
+                db.auto_batch = Some(AutoBatch {
+                    max_statements,
+                    max_delay,
+                    pending: 0,
+                    started: None,
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Commit the pending batch of statements, if any (see
+    /// `set_auto_batch`).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the commit fails, in which case the batch is
+    /// still pending (see `discard_batch`).
+    pub fn flush(&self) -> Result<()> {
+        if !self.auto_batch.get() {
+            return Ok(());
+        }
+        let mut db = self.db.borrow_mut();
+        let started = match db.auto_batch {
+            Some(ref batch) => batch.started.is_some(),
+            None => false,
+        };
+        if !started {
+            return Ok(());
+        }
+        // The transaction may have been ended by a `COMMIT` or a rollback.
+        if !db.is_autocommit() {
+            db.execute_internal("COMMIT")?;
+        }
+        if let Some(ref mut batch) = db.auto_batch {
+            batch.pending = 0;
+            batch.started = None;
+        }
+        Ok(())
+    }
+
+    /// Roll back the pending batch of statements, if any (see
+    /// `set_auto_batch`), e.g. after `flush` failed, and return the number of
+    /// statements lost.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the rollback fails.
+    pub fn discard_batch(&self) -> Result<usize> {
+        if !self.auto_batch.get() {
+            return Ok(0);
+        }
+        let pending = self.pending_batch_len();
+        let mut db = self.db.borrow_mut();
+        let started = match db.auto_batch {
+            Some(ref batch) => batch.started.is_some(),
+            None => false,
+        };
+        if started && !db.is_autocommit() {
+            db.execute_internal("ROLLBACK")?;
+        }
+        if let Some(ref mut batch) = db.auto_batch {
+            batch.pending = 0;
+            batch.started = None;
+        }
+        Ok(pending)
+    }
+
+    /// Return the number of statements successfully executed in the pending
+    /// batch.
+    pub fn pending_batch_len(&self) -> usize {
+        let db = self.db.borrow();
+        match db.auto_batch {
+            // The transaction may have been ended by a rollback.
+            Some(ref batch) if batch.started.is_some() && !db.is_autocommit() => batch.pending,
+            _ => 0,
+        }
+    }
+
+    /// Commit the pending batch or, if the commit fails, roll it back.
+    pub(crate) fn end_batch(&self) -> Result<()> {
+        match self.flush() {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                self.discard_batch()?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Commit the pending batch, unless `sql` rolls it back.
+    pub(crate) fn flush_before(&self, sql: &str) -> Result<()> {
+        if is_rollback(sql) {
+            Ok(())
+        } else {
+            self.flush()
+        }
+    }
+
+    /// Prepare `sql` and call `f` with it, in the pending batch if
+    /// auto-batching is enabled and `sql` is a batchable statement.
+    pub(crate) fn batched<T, F>(&self, sql: &str, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Statement<'_>) -> Result<T>,
+    {
+        let mut stmt = self.db.borrow_mut().prepare(self, sql)?;
+        if !self.auto_batch.get() {
+            return f(&mut stmt);
+        }
+        let mode = {
+            let mut db = self.db.borrow_mut();
+            let autocommit = db.is_autocommit();
+            match db.auto_batch {
+                None => Mode::Plain,
+                Some(ref mut batch) => {
+                    if batch.started.is_some() && autocommit {
+                        // The transaction has been ended by a rollback.
+                        batch.pending = 0;
+                        batch.started = None;
+                    }
+                    if is_rollback(sql) {
+                        Mode::Plain
+                    } else if !is_batchable(&stmt) {
+                        Mode::Flush
+                    } else if batch.started.is_none() && !autocommit {
+                        // Explicit transaction
+                        Mode::Plain
+                    } else {
+                        Mode::Batch
+                    }
+                }
+            }
+        };
+        match mode {
+            Mode::Plain => f(&mut stmt),
+            Mode::Flush => {
+                self.flush()?;
+                f(&mut stmt)
+            }
+            Mode::Batch => {
+                {
+                    let mut db = self.db.borrow_mut();
+                    let started = match db.auto_batch {
+                        Some(ref batch) => batch.started.is_some(),
+                        None => false,
+                    };
+                    if !started {
+                        db.execute_internal("BEGIN")?;
+                        if let Some(ref mut batch) = db.auto_batch {
+                            batch.started = Some(Instant::now());
+                        }
+                    }
+                }
+                let r = f(&mut stmt);
+                let full = match self.db.borrow_mut().auto_batch {
+                    Some(AutoBatch {
+                        ref mut pending,
+                        started: Some(started),
+                        max_statements,
+                        max_delay,
+                    }) => {
+                        if r.is_ok() {
+                            *pending += 1;
+                        }
+                        *pending >= max_statements || started.elapsed() >= max_delay
+                    }
+                    _ => false,
+                };
+                if full {
+                    self.flush()?;
+                }
+                r
+            }
+        }
+    }
+}
+
+/// Return the keyword at the start of `sql`, ignoring leading whitespace and
+/// comments, and advance `sql` past it.
+fn next_keyword<'a>(sql: &mut &'a str) -> &'a str {
+    let rest = skip_comments(sql, false);
+    let end = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or_else(|| rest.len());
+    *sql = &rest[end..];
+    &rest[..end]
+}
+
+/// Whether `stmt` is a data modification statement: a `WITH` clause may
+/// also start a query, so the statement must also write to the database.
+fn is_batchable(stmt: &Statement<'_>) -> bool {
+    let sql = stmt.sql();
+    let keyword = next_keyword(&mut &*sql);
+    ["INSERT", "UPDATE", "DELETE", "REPLACE", "WITH"]
+        .iter()
+        .any(|k| keyword.eq_ignore_ascii_case(k))
+        && stmt.may_write()
+        && (cfg!(feature = "bundled") || !keyword.eq_ignore_ascii_case("WITH"))
+}
+
+/// Whether `sql` starts with a statement rolling back the whole transaction
+/// (not `ROLLBACK TO`).
+fn is_rollback(mut sql: &str) -> bool {
+    if !next_keyword(&mut sql).eq_ignore_ascii_case("ROLLBACK") {
+        return false;
+    }
+    let mut keyword = next_keyword(&mut sql);
+    if keyword.eq_ignore_ascii_case("TRANSACTION") {
+        keyword = next_keyword(&mut sql);
+    }
+    !keyword.eq_ignore_ascii_case("TO")
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::is_rollback;
+    use crate::{Connection, NO_PARAMS};
+
+    fn count(db: &Connection) -> i64 {
+        db.query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_auto_batch() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        db.set_auto_batch(3, Duration::from_secs(3600)).unwrap();
+
+        db.execute("INSERT INTO foo VALUES(?)", [1]).unwrap();
+        db.execute("INSERT INTO foo VALUES(?)", [2]).unwrap();
+        assert_eq!(2, db.pending_batch_len());
+        assert!(!db.is_autocommit());
+        db.execute("INSERT INTO foo VALUES(?)", [3]).unwrap();
+        assert_eq!(0, db.pending_batch_len());
+        assert!(db.is_autocommit());
+
+        // A failed statement does not end the batch.
+        db.execute("INSERT INTO foo VALUES(?)", [4]).unwrap();
+        assert!(db.execute("INSERT INTO bar VALUES(?)", [5]).is_err());
+        assert_eq!(1, db.pending_batch_len());
+        // Queries flush the batch.
+        assert_eq!(4, count(&db));
+        assert_eq!(0, db.pending_batch_len());
+        assert!(db.is_autocommit());
+
+        // Explicit transactions are not batched.
+        db.execute_batch("BEGIN").unwrap();
+        db.execute("INSERT INTO foo VALUES(?)", [5]).unwrap();
+        assert_eq!(0, db.pending_batch_len());
+        db.execute_batch("ROLLBACK").unwrap();
+
+        db.execute("INSERT INTO foo VALUES(?)", [6]).unwrap();
+        db.flush().unwrap();
+        assert!(db.is_autocommit());
+        db.execute("INSERT INTO foo VALUES(?)", [7]).unwrap();
+        db.set_auto_batch(0, Duration::from_secs(0)).unwrap();
+        assert!(db.is_autocommit());
+        db.execute("INSERT INTO foo VALUES(?)", [8]).unwrap();
+        assert!(db.is_autocommit());
+        assert_eq!(7, count(&db));
+    }
+
+    #[test]
+    fn test_auto_batch_delay() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        db.set_auto_batch(100, Duration::from_millis(0)).unwrap();
+        db.execute("INSERT INTO foo VALUES(?)", [1]).unwrap();
+        assert_eq!(0, db.pending_batch_len());
+        assert!(db.is_autocommit());
+    }
+
+    #[test]
+    fn test_auto_batch_statements() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x TEXT)").unwrap();
+        db.set_auto_batch(100, Duration::from_secs(3600)).unwrap();
+
+        db.execute("-- comment\n/* ; */ INSERT INTO foo VALUES('a')", NO_PARAMS)
+            .unwrap();
+        assert_eq!(1, db.pending_batch_len());
+        db.execute("INSERT INTO foo VALUES(';ROLLBACK')", NO_PARAMS)
+            .unwrap();
+        assert_eq!(2, db.pending_batch_len());
+        if cfg!(feature = "bundled") {
+            db.execute(
+                "WITH t(x) AS (SELECT 'b') INSERT INTO foo SELECT x FROM t",
+                NO_PARAMS,
+            )
+            .unwrap();
+            assert_eq!(3, db.pending_batch_len());
+        }
+        // Statements which do not modify data flush the batch.
+        db.execute("/* INSERT */ CREATE TABLE bar(x)", NO_PARAMS)
+            .unwrap();
+        assert_eq!(0, db.pending_batch_len());
+        assert!(db.is_autocommit());
+    }
+
+    #[test]
+    fn test_is_rollback() {
+        assert!(is_rollback("ROLLBACK"));
+        assert!(is_rollback(" /* x */ rollback transaction; SELECT 1"));
+        assert!(!is_rollback("ROLLBACK TO sp"));
+        assert!(!is_rollback("ROLLBACK TRANSACTION -- x\n TO SAVEPOINT sp"));
+        assert!(!is_rollback("SELECT 'ROLLBACK'"));
+    }
+
+    #[test]
+    fn test_auto_batch_close() {
+        let temp_dir = tempdir::TempDir::new("test_auto_batch").unwrap();
+        let path = temp_dir.path().join("test.db");
+        {
+            let db = Connection::open(&path).unwrap();
+            db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+            db.set_auto_batch(100, Duration::from_secs(3600)).unwrap();
+            db.execute("INSERT INTO foo VALUES(?)", [1]).unwrap();
+            assert_eq!(1, db.pending_batch_len());
+        }
+        let db = Connection::open(&path).unwrap();
+        assert_eq!(1, count(&db));
+    }
+
+    #[test]
+    fn test_auto_batch_failed_statement() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER UNIQUE)")
+            .unwrap();
+        db.set_auto_batch(2, Duration::from_secs(3600)).unwrap();
+
+        db.execute("INSERT INTO foo VALUES(?)", &[1]).unwrap();
+        assert!(db.execute("INSERT INTO foo VALUES(?)", &[1]).is_err());
+        assert_eq!(1, db.pending_batch_len());
+        db.execute("INSERT INTO foo VALUES(?)", &[2]).unwrap();
+        assert_eq!(0, db.pending_batch_len());
+        assert!(db.is_autocommit());
+
+        // A batch whose statements all failed is committed too.
+        assert!(db.execute("INSERT INTO foo VALUES(?)", &[2]).is_err());
+        assert_eq!(0, db.pending_batch_len());
+        db.flush().unwrap();
+        assert!(db.is_autocommit());
+        assert_eq!(2, count(&db));
+    }
+
+    fn setup_deferred_fk(db: &Connection) {
+        db.execute_batch(
+            "PRAGMA foreign_keys = ON;
+             CREATE TABLE IF NOT EXISTS parent(id INTEGER PRIMARY KEY);
+             CREATE TABLE IF NOT EXISTS foo(x INTEGER REFERENCES parent(id)
+                                            DEFERRABLE INITIALLY DEFERRED);",
+        )
+        .unwrap();
+        db.set_auto_batch(100, Duration::from_secs(3600)).unwrap();
+    }
+
+    #[test]
+    fn test_auto_batch_failed_commit() {
+        let db = Connection::open_in_memory().unwrap();
+        setup_deferred_fk(&db);
+
+        // The batch can be fixed and flushed again.
+        db.execute("INSERT INTO foo VALUES(?)", &[1]).unwrap();
+        assert!(db.flush().is_err());
+        assert!(db.prepare("SELECT 1").is_err());
+        db.execute("INSERT INTO parent VALUES(?)", &[1]).unwrap();
+        db.flush().unwrap();
+        assert_eq!(1, count(&db));
+
+        // Or discarded.
+        db.execute("INSERT INTO foo VALUES(?)", &[2]).unwrap();
+        db.execute("INSERT INTO foo VALUES(?)", &[1]).unwrap();
+        assert!(db.flush().is_err());
+        assert_eq!(2, db.discard_batch().unwrap());
+        assert!(db.is_autocommit());
+        assert_eq!(1, count(&db));
+
+        // Or rolled back by a statement.
+        db.execute("INSERT INTO foo VALUES(?)", &[2]).unwrap();
+        assert!(db.flush().is_err());
+        db.execute_batch("ROLLBACK").unwrap();
+        assert_eq!(0, db.pending_batch_len());
+        assert_eq!(1, count(&db));
+        db.execute("INSERT INTO foo VALUES(?)", &[2]).unwrap();
+        assert!(db.flush().is_err());
+        db.execute("ROLLBACK", NO_PARAMS).unwrap();
+        assert_eq!(1, count(&db));
+
+        // Disabling auto-batching rolls back the batch if it cannot be
+        // committed.
+        db.execute("INSERT INTO foo VALUES(?)", &[2]).unwrap();
+        assert!(db.set_auto_batch(0, Duration::from_secs(0)).is_err());
+        assert!(db.is_autocommit());
+        assert_eq!(1, count(&db));
+    }
+
+    #[test]
+    fn test_auto_batch_drop_failed_commit() {
+        let temp_dir = tempdir::TempDir::new("test_auto_batch").unwrap();
+        let path = temp_dir.path().join("test.db");
+        let r = std::panic::catch_unwind(|| {
+            let db = Connection::open(&path).unwrap();
+            setup_deferred_fk(&db);
+            db.execute("INSERT INTO foo VALUES(?)", &[1]).unwrap();
+            assert_eq!(1, db.pending_batch_len());
+            // The commit fails: the batch is rolled back, without panicking.
+        });
+        assert!(r.is_ok());
+        let db = Connection::open(&path).unwrap();
+        assert_eq!(0, count(&db));
+    }
+}
@@ -28,19 +28,25 @@
 //!     backup.run_to_completion(5, time::Duration::from_millis(250), Some(progress))
 //! }
 //! ```
+//!
+//! For periodic backups to rotating files, see `Scheduler`, and to check that
+//! a backup is usable, see `verify`.
 
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::ptr;
 
 use std::os::raw::c_int;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::ffi;
 
 use crate::error::{error_from_handle, error_from_sqlite_code};
-use crate::{Connection, DatabaseName, Result};
+use crate::{Connection, DatabaseName, Error, Result};
 
 impl Connection {
     /// Back up the `name` database to the given destination path.
@@ -300,10 +306,283 @@ impl Drop for Backup<'_, '_> {
     }
 }
 
+/// Result of `verify`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Verification {
+    /// Number of pages of the source database.
+    pub src_page_count: i64,
+    /// Number of pages of the destination database.
+    pub dst_page_count: i64,
+    /// Hash of the schema of the source database.
+    pub src_schema_hash: u64,
+    /// Hash of the schema of the destination database.
+    pub dst_schema_hash: u64,
+    /// Problems found by `PRAGMA integrity_check` on the destination
+    /// database (empty if there are none).
+    pub integrity_errors: Vec<String>,
+}
+
+impl Verification {
+    /// Return `true` if the destination has the same number of pages and the
+    /// same schema as the source, and passes the integrity check.
+    pub fn is_ok(&self) -> bool {
+        self.src_page_count == self.dst_page_count
+            && self.src_schema_hash == self.dst_schema_hash
+            && self.integrity_errors.is_empty()
+    }
+}
+
+/// Check that `dst`, the destination of a completed backup, is a usable copy
+/// of the main database of `src`: compare their page counts and schemas, and
+/// run [`PRAGMA integrity_check`](https://sqlite.org/pragma.html#pragma_integrity_check)
+/// on `dst`.
+///
+/// `src` must not be modified between the end of the backup and the
+/// verification.
+///
+/// ```rust,no_run
+/// # use rusqlite::{backup, Connection, Result};
+/// # use std::time::Duration;
+/// fn backup_and_verify(src: &Connection, dst: &mut Connection) -> Result<bool> {
+///     backup::Backup::new(src, dst)?.run_to_completion(100, Duration::from_millis(250), None)?;
+///     Ok(backup::verify(src, dst)?.is_ok())
+/// }
+/// ```
+///
+/// # Failure
+///
+/// Will return `Err` if the underlying SQLite calls fail, e.g. if `dst` is
+/// not a database.
+pub fn verify(src: &Connection, dst: &Connection) -> Result<Verification> {
+    let mut integrity_errors = Vec::new();
+    dst.pragma_query(None, "integrity_check", |row| {
+        let message: String = row.get(0)?;
+        if message != "ok" {
+            integrity_errors.push(message);
+        }
+        Ok(())
+    })?;
+    Ok(Verification {
+        src_page_count: page_count(src)?,
+        dst_page_count: page_count(dst)?,
+        src_schema_hash: schema_hash(src)?,
+        dst_schema_hash: schema_hash(dst)?,
+        integrity_errors,
+    })
+}
+
+fn page_count(conn: &Connection) -> Result<i64> {
+    conn.pragma_query_value(None, "page_count", |row| row.get(0))
+}
+
+fn schema_hash(conn: &Connection) -> Result<u64> {
+    let mut stmt =
+        conn.prepare("SELECT type, name, tbl_name, sql FROM main.sqlite_master ORDER BY name")?;
+    let mut rows = stmt.query(crate::NO_PARAMS)?;
+    let mut hasher = DefaultHasher::new();
+    while let Some(row) = rows.next()? {
+        for i in 0..4 {
+            row.get::<_, Option<String>>(i)?.hash(&mut hasher);
+        }
+    }
+    Ok(hasher.finish())
+}
+
+/// Result of a successful `Scheduler` run.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScheduledBackup {
+    /// The database has not changed since the last backup: no backup was made.
+    Unchanged,
+    /// A new backup was written.
+    Completed {
+        /// Path of the new backup.
+        path: PathBuf,
+        /// Total number of pages backed up.
+        pagecount: c_int,
+        /// Time spent backing up.
+        elapsed: Duration,
+    },
+}
+
+type OnResult = dyn FnMut(&Result<ScheduledBackup>) + Send;
+
+/// Periodic online backups of the main database to rotating files.
+///
+/// The most recent backup is written to `path`, and older ones are kept as
+/// `path.1`, `path.2`, ... up to `keep` files in total. A backup is first
+/// written to `path.tmp` so that an interrupted run never replaces a good
+/// backup.
+///
+/// Runs are skipped when the database has not changed since the last backup
+/// (according to [`PRAGMA data_version`](https://sqlite.org/pragma.html#pragma_data_version)
+/// and to the changes, including schema changes, made by the source
+/// connection itself).
+///
+/// ```rust,no_run
+/// # use rusqlite::{backup, Connection, Result};
+/// # use std::time::Duration;
+/// fn backup_loop(src: &Connection) -> Result<()> {
+///     let mut scheduler = backup::Scheduler::new("app.db.bak", 3)
+///         .interval(Duration::from_secs(3600))
+///         .on_result(|r| match r {
+///             Ok(r) => println!("backup: {:?}", r),
+///             Err(e) => eprintln!("backup failed: {}", e),
+///         });
+///     loop {
+///         scheduler.tick(src)?;
+///         std::thread::sleep(Duration::from_secs(60));
+///     }
+/// }
+/// ```
+pub struct Scheduler {
+    path: PathBuf,
+    keep: usize,
+    interval: Duration,
+    pages_per_step: c_int,
+    pause_between_pages: Duration,
+    on_result: Option<Box<OnResult>>,
+    last_run: Option<Instant>,
+    last_version: Option<(i64, i64, usize)>,
+}
+
+impl Scheduler {
+    /// Create a scheduler writing backups to `path` and keeping at most
+    /// `keep` (at least one) backup files.
+    ///
+    /// Defaults to an interval of one hour, 100 pages per step and a 250ms
+    /// pause between steps.
+    pub fn new<P: AsRef<Path>>(path: P, keep: usize) -> Scheduler {
+        Scheduler {
+            path: path.as_ref().to_path_buf(),
+            keep: keep.max(1),
+            interval: Duration::from_secs(3600),
+            pages_per_step: 100,
+            pause_between_pages: Duration::from_millis(250),
+            on_result: None,
+            last_run: None,
+            last_version: None,
+        }
+    }
+
+    /// Set the minimum delay between two backups.
+    pub fn interval(mut self, interval: Duration) -> Scheduler {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the number of pages copied per step, and the pause between steps
+    /// (see `Backup::run_to_completion`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pages_per_step` is not positive.
+    pub fn throttle(mut self, pages_per_step: c_int, pause_between_pages: Duration) -> Scheduler {
+        assert!(pages_per_step > 0, "pages_per_step must be positive");
+        self.pages_per_step = pages_per_step;
+        self.pause_between_pages = pause_between_pages;
+        self
+    }
+
+    /// Set a callback called with the result of each run.
+    pub fn on_result<F>(mut self, f: F) -> Scheduler
+    where
+        F: FnMut(&Result<ScheduledBackup>) + Send + 'static,
+    {
+        self.on_result = Some(Box::new(f));
+        self
+    }
+
+    /// Path of the `n`-th most recent backup (0 is the most recent one).
+    pub fn backup_path(&self, n: usize) -> PathBuf {
+        if n == 0 {
+            self.path.clone()
+        } else {
+            self.suffixed(&n.to_string())
+        }
+    }
+
+    /// Back up `src` if the interval has elapsed since the last run.
+    ///
+    /// Returns `Ok(None)` if no run was due.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the backup fails.
+    pub fn tick(&mut self, src: &Connection) -> Result<Option<ScheduledBackup>> {
+        match self.last_run {
+            Some(last_run) if last_run.elapsed() < self.interval => Ok(None),
+            _ => self.run(src).map(Some),
+        }
+    }
+
+    /// Back up `src` now, unless it has not changed since the last backup.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the backup fails, or `Err(Error::Io)` if the backup
+    /// files cannot be rotated.
+    pub fn run(&mut self, src: &Connection) -> Result<ScheduledBackup> {
+        self.last_run = Some(Instant::now());
+        let r = self.backup(src);
+        if let Some(ref mut f) = self.on_result {
+            f(&r);
+        }
+        r
+    }
+
+    fn backup(&mut self, src: &Connection) -> Result<ScheduledBackup> {
+        // `data_version` only changes with the commits of other connections,
+        // and `total_changes` does not count schema changes.
+        let version = (
+            src.query_row("PRAGMA data_version", crate::NO_PARAMS, |r| r.get(0))?,
+            src.query_row("PRAGMA schema_version", crate::NO_PARAMS, |r| r.get(0))?,
+            src.db.borrow().total_changes(),
+        );
+        if self.last_version == Some(version) && self.path.exists() {
+            return Ok(ScheduledBackup::Unchanged);
+        }
+
+        let start = Instant::now();
+        let tmp = self.suffixed("tmp");
+        let pagecount = {
+            let mut dst = Connection::open(&tmp)?;
+            let backup = Backup::new(src, &mut dst)?;
+            backup.run_to_completion(self.pages_per_step, self.pause_between_pages, None)?;
+            backup.progress().pagecount
+        };
+        self.rotate(&tmp)?;
+        self.last_version = Some(version);
+        Ok(ScheduledBackup::Completed {
+            path: self.path.clone(),
+            pagecount,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    // Backups are renamed over the older ones, so that a backup is never
+    // removed before it is replaced.
+    fn rotate(&self, tmp: &Path) -> Result<()> {
+        for n in (0..self.keep - 1).rev() {
+            let from = self.backup_path(n);
+            if from.exists() {
+                fs::rename(&from, self.backup_path(n + 1)).map_err(Error::Io)?;
+            }
+        }
+        fs::rename(tmp, &self.path).map_err(Error::Io)
+    }
+
+    fn suffixed(&self, suffix: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".");
+        path.push(suffix);
+        path.into()
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::Backup;
-    use crate::{Connection, DatabaseName, NO_PARAMS};
+    use super::{verify, Backup, ScheduledBackup, Scheduler};
+    use crate::{Connection, DatabaseName, Error, NO_PARAMS};
     use std::time::Duration;
 
     #[test]
@@ -430,4 +709,97 @@ mod test {
             .unwrap();
         assert_eq!(42 + 43, the_answer);
     }
+
+    #[test]
+    fn test_verify() {
+        let src = Connection::open_in_memory().unwrap();
+        src.execute_batch(
+            "CREATE TABLE foo(x INTEGER);
+             CREATE INDEX foo_x ON foo(x);
+             INSERT INTO foo VALUES(42);",
+        )
+        .unwrap();
+
+        let mut dst = Connection::open_in_memory().unwrap();
+        Backup::new(&src, &mut dst).unwrap().step(-1).unwrap();
+        let verification = verify(&src, &dst).unwrap();
+        assert!(verification.is_ok(), "{:?}", verification);
+        assert!(verification.src_page_count > 0);
+
+        dst.execute_batch("CREATE TABLE bar(y INTEGER)").unwrap();
+        let verification = verify(&src, &dst).unwrap();
+        assert!(!verification.is_ok());
+        assert_ne!(verification.src_schema_hash, verification.dst_schema_hash);
+        assert!(verification.integrity_errors.is_empty());
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let temp_dir = tempdir::TempDir::new("test_scheduler").unwrap();
+        let path = temp_dir.path().join("backup.db");
+
+        let src = Connection::open_in_memory().unwrap();
+        src.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES(42);")
+            .unwrap();
+
+        let mut scheduler = Scheduler::new(&path, 2).interval(Duration::from_secs(3600));
+        match scheduler.tick(&src).unwrap() {
+            Some(ScheduledBackup::Completed { pagecount, .. }) => assert!(pagecount > 0),
+            r => panic!("unexpected result {:?}", r),
+        }
+        // Interval not elapsed
+        assert_eq!(None, scheduler.tick(&src).unwrap());
+        // No change
+        assert_eq!(ScheduledBackup::Unchanged, scheduler.run(&src).unwrap());
+
+        src.execute_batch("INSERT INTO foo VALUES(43)").unwrap();
+        scheduler.run(&src).unwrap();
+        src.execute_batch("INSERT INTO foo VALUES(44)").unwrap();
+        scheduler.run(&src).unwrap();
+        assert!(!scheduler.backup_path(2).exists());
+
+        let sum = |path| -> i64 {
+            let db = Connection::open(path).unwrap();
+            db.query_row("SELECT SUM(x) FROM foo", NO_PARAMS, |r| r.get(0))
+                .unwrap()
+        };
+        assert_eq!(42 + 43 + 44, sum(scheduler.backup_path(0)));
+        assert_eq!(42 + 43, sum(scheduler.backup_path(1)));
+
+        // The oldest backup cannot be replaced.
+        std::fs::remove_file(scheduler.backup_path(1)).unwrap();
+        std::fs::create_dir(scheduler.backup_path(1)).unwrap();
+        src.execute_batch("INSERT INTO foo VALUES(45)").unwrap();
+        match scheduler.run(&src) {
+            Err(Error::Io(_)) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_scheduler_keep_one() {
+        let temp_dir = tempdir::TempDir::new("test_scheduler_keep_one").unwrap();
+        let path = temp_dir.path().join("backup.db");
+
+        let src = Connection::open_in_memory().unwrap();
+        src.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        let mut scheduler = Scheduler::new(&path, 1);
+        scheduler.run(&src).unwrap();
+        assert_eq!(ScheduledBackup::Unchanged, scheduler.run(&src).unwrap());
+
+        // A schema change is a change.
+        src.execute_batch("CREATE TABLE bar(x INTEGER)").unwrap();
+        match scheduler.run(&src).unwrap() {
+            ScheduledBackup::Completed { .. } => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert!(!scheduler.backup_path(1).exists());
+        let db = Connection::open(&path).unwrap();
+        let tables: i64 = db
+            .query_row("SELECT count(*) FROM sqlite_master", NO_PARAMS, |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(2, tables);
+    }
 }
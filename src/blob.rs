@@ -14,7 +14,7 @@
 //! ## Example
 //!
 //! ```rust
-//! use rusqlite::blob::ZeroBlob;
+//! use rusqlite::blob::{BlobMode, ZeroBlob};
 //! use rusqlite::{Connection, DatabaseName, NO_PARAMS};
 //! use std::error::Error;
 //! use std::io::{Read, Seek, SeekFrom, Write};
@@ -28,7 +28,13 @@
 //!     )?;
 //!
 //!     let rowid = db.last_insert_rowid();
-//!     let mut blob = db.blob_open(DatabaseName::Main, "test", "content", rowid, false)?;
+//!     let mut blob = db.blob_open(
+//!         DatabaseName::Main,
+//!         "test",
+//!         "content",
+//!         rowid,
+//!         BlobMode::ReadWrite,
+//!     )?;
 //!
 //!     // Make sure to test that the number of bytes written matches what you expect;
 //!     // if you try to write too much, the data will be truncated to the size of the
@@ -53,12 +59,71 @@
 //! }
 //! ```
 use std::cmp::min;
+use std::fmt;
 use std::io;
 use std::ptr;
 
 use super::ffi;
 use super::types::{ToSql, ToSqlOutput};
-use crate::{Connection, DatabaseName, Result};
+use crate::{Connection, DatabaseName, Error, Result};
+
+/// Whether a BLOB is opened for reading only, or for reading and writing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlobMode {
+    /// The BLOB can only be read.
+    ReadOnly,
+    /// The BLOB can be read and written.
+    ReadWrite,
+}
+
+/// Why a BLOB cannot be opened (see `Error::BlobOpenFailure`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlobOpenError {
+    /// There is no row with the given rowid.
+    NoSuchRow(i64),
+    /// There is no such column in the table.
+    NoSuchColumn,
+    /// The value is neither a BLOB nor a TEXT (e.g. `NULL`).
+    NotABlob,
+    /// The column is part of an index (or of a foreign key), so it cannot be
+    /// opened in `BlobMode::ReadWrite`.
+    IndexedColumn,
+}
+
+impl fmt::Display for BlobOpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            BlobOpenError::NoSuchRow(row_id) => write!(f, "no such rowid: {}", row_id),
+            BlobOpenError::NoSuchColumn => write!(f, "no such column"),
+            BlobOpenError::NotABlob => write!(f, "value is neither a BLOB nor a TEXT"),
+            BlobOpenError::IndexedColumn => write!(f, "cannot open indexed column for writing"),
+        }
+    }
+}
+
+/// Map the `SQLITE_ERROR`s of `sqlite3_blob_open` and `sqlite3_blob_reopen`,
+/// which only differ by their message, to a `BlobOpenError`.
+fn blob_open_error(err: Error, row_id: i64) -> Error {
+    let kind = match err {
+        Error::SqliteFailure(ref e, Some(ref msg)) if e.extended_code == ffi::SQLITE_ERROR => {
+            if msg.starts_with("no such rowid") {
+                BlobOpenError::NoSuchRow(row_id)
+            } else if msg.starts_with("no such column") {
+                BlobOpenError::NoSuchColumn
+            } else if msg.starts_with("cannot open value of type") {
+                BlobOpenError::NotABlob
+            } else if msg.starts_with("cannot open indexed column")
+                || msg.starts_with("cannot open foreign key column")
+            {
+                BlobOpenError::IndexedColumn
+            } else {
+                return err;
+            }
+        }
+        _ => return err,
+    };
+    Error::BlobOpenFailure(kind)
+}
 
 /// Handle to an open BLOB.
 pub struct Blob<'conn> {
@@ -75,14 +140,15 @@ impl Connection {
     ///
     /// Will return `Err` if `db`/`table`/`column` cannot be converted to a
     /// C-compatible string or if the underlying SQLite BLOB open call
-    /// fails.
+    /// fails, with `Error::BlobOpenFailure` if the row, the column or the
+    /// value is not suitable.
     pub fn blob_open<'a>(
         &'a self,
         db: DatabaseName<'_>,
         table: &str,
         column: &str,
         row_id: i64,
-        read_only: bool,
+        mode: BlobMode,
     ) -> Result<Blob<'a>> {
         let mut c = self.db.borrow_mut();
         let mut blob = ptr::null_mut();
@@ -96,15 +162,20 @@ impl Connection {
                 table.as_ptr(),
                 column.as_ptr(),
                 row_id,
-                if read_only { 0 } else { 1 },
+                match mode {
+                    BlobMode::ReadOnly => 0,
+                    BlobMode::ReadWrite => 1,
+                },
                 &mut blob,
             )
         };
-        c.decode_result(rc).map(|_| Blob {
-            conn: self,
-            blob,
-            pos: 0,
-        })
+        c.decode_result(rc)
+            .map(|_| Blob {
+                conn: self,
+                blob,
+                pos: 0,
+            })
+            .map_err(|err| blob_open_error(err, row_id))
     }
 }
 
@@ -113,11 +184,15 @@ impl Blob<'_> {
     ///
     /// # Failure
     ///
-    /// Will return `Err` if the underlying SQLite BLOB reopen call fails.
+    /// Will return `Err` if the underlying SQLite BLOB reopen call fails,
+    /// with `Error::BlobOpenFailure` if the row or the value is not suitable.
     pub fn reopen(&mut self, row: i64) -> Result<()> {
         let rc = unsafe { ffi::sqlite3_blob_reopen(self.blob, row) };
         if rc != ffi::SQLITE_OK {
-            return self.conn.decode_result(rc);
+            return self
+                .conn
+                .decode_result(rc)
+                .map_err(|err| blob_open_error(err, row));
         }
         self.pos = 0;
         Ok(())
@@ -256,7 +331,8 @@ impl ToSql for ZeroBlob {
 
 #[cfg(test)]
 mod test {
-    use crate::{Connection, DatabaseName, Result};
+    use super::{BlobMode, BlobOpenError};
+    use crate::{Connection, DatabaseName, Error, Result};
     use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 
     fn db_with_test_blob() -> Result<(Connection, i64)> {
@@ -275,7 +351,13 @@ mod test {
         let (db, rowid) = db_with_test_blob().unwrap();
 
         let mut blob = db
-            .blob_open(DatabaseName::Main, "test", "content", rowid, false)
+            .blob_open(
+                DatabaseName::Main,
+                "test",
+                "content",
+                rowid,
+                BlobMode::ReadWrite,
+            )
             .unwrap();
         assert_eq!(4, blob.write(b"Clob").unwrap());
         assert_eq!(6, blob.write(b"567890xxxxxx").unwrap()); // cannot write past 10
@@ -285,7 +367,13 @@ mod test {
         blob.close().unwrap();
 
         blob = db
-            .blob_open(DatabaseName::Main, "test", "content", rowid, true)
+            .blob_open(
+                DatabaseName::Main,
+                "test",
+                "content",
+                rowid,
+                BlobMode::ReadOnly,
+            )
             .unwrap();
         let mut bytes = [0u8; 5];
         assert_eq!(5, blob.read(&mut bytes[..]).unwrap());
@@ -327,7 +415,13 @@ mod test {
         let (db, rowid) = db_with_test_blob().unwrap();
 
         let mut blob = db
-            .blob_open(DatabaseName::Main, "test", "content", rowid, false)
+            .blob_open(
+                DatabaseName::Main,
+                "test",
+                "content",
+                rowid,
+                BlobMode::ReadWrite,
+            )
             .unwrap();
         assert_eq!(8, blob.write(b"one\ntwo\n").unwrap());
 
@@ -353,7 +447,13 @@ mod test {
 
         {
             let blob = db
-                .blob_open(DatabaseName::Main, "test", "content", rowid, false)
+                .blob_open(
+                    DatabaseName::Main,
+                    "test",
+                    "content",
+                    rowid,
+                    BlobMode::ReadWrite,
+                )
                 .unwrap();
             let mut writer = BufWriter::new(blob);
 
@@ -366,7 +466,13 @@ mod test {
         {
             // ... but it should've written the first 10 bytes
             let mut blob = db
-                .blob_open(DatabaseName::Main, "test", "content", rowid, false)
+                .blob_open(
+                    DatabaseName::Main,
+                    "test",
+                    "content",
+                    rowid,
+                    BlobMode::ReadWrite,
+                )
                 .unwrap();
             let mut bytes = [0u8; 10];
             assert_eq!(10, blob.read(&mut bytes[..]).unwrap());
@@ -375,7 +481,13 @@ mod test {
 
         {
             let blob = db
-                .blob_open(DatabaseName::Main, "test", "content", rowid, false)
+                .blob_open(
+                    DatabaseName::Main,
+                    "test",
+                    "content",
+                    rowid,
+                    BlobMode::ReadWrite,
+                )
                 .unwrap();
             let mut writer = BufWriter::new(blob);
 
@@ -387,11 +499,67 @@ mod test {
         {
             // ... but it should've written the first 10 bytes
             let mut blob = db
-                .blob_open(DatabaseName::Main, "test", "content", rowid, false)
+                .blob_open(
+                    DatabaseName::Main,
+                    "test",
+                    "content",
+                    rowid,
+                    BlobMode::ReadWrite,
+                )
                 .unwrap();
             let mut bytes = [0u8; 10];
             assert_eq!(10, blob.read(&mut bytes[..]).unwrap());
             assert_eq!(b"aaaaaaaaaa", &bytes);
         }
     }
+
+    #[test]
+    fn test_blob_open_errors() {
+        let (db, rowid) = db_with_test_blob().unwrap();
+        db.execute_batch(
+            "ALTER TABLE test ADD COLUMN n INTEGER;
+             CREATE INDEX test_content ON test(content);",
+        )
+        .unwrap();
+
+        let err = |column, rowid, mode| match db.blob_open(
+            DatabaseName::Main,
+            "test",
+            column,
+            rowid,
+            mode,
+        ) {
+            Err(Error::BlobOpenFailure(kind)) => kind,
+            r => panic!("unexpected result {:?}", r.map(|_| ()).err()),
+        };
+        assert_eq!(
+            BlobOpenError::NoSuchRow(rowid + 1),
+            err("content", rowid + 1, BlobMode::ReadOnly)
+        );
+        assert_eq!(
+            BlobOpenError::NoSuchColumn,
+            err("missing", rowid, BlobMode::ReadOnly)
+        );
+        assert_eq!(BlobOpenError::NotABlob, err("n", rowid, BlobMode::ReadOnly));
+        assert_eq!(
+            BlobOpenError::IndexedColumn,
+            err("content", rowid, BlobMode::ReadWrite)
+        );
+
+        let mut blob = db
+            .blob_open(
+                DatabaseName::Main,
+                "test",
+                "content",
+                rowid,
+                BlobMode::ReadOnly,
+            )
+            .unwrap();
+        match blob.reopen(rowid + 1) {
+            Err(Error::BlobOpenFailure(BlobOpenError::NoSuchRow(row))) => {
+                assert_eq!(rowid + 1, row)
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
 }
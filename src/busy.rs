@@ -1,17 +1,23 @@
 ///! Busy handler (when the database is locked)
+use std::ffi::CStr;
+use std::fmt;
 use std::mem;
-use std::os::raw::{c_int, c_void};
-use std::panic::catch_unwind;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::ptr;
 use std::time::Duration;
 
 use crate::ffi;
-use crate::{Connection, InnerConnection, Result};
+use crate::{Connection, Error, ErrorCode, InnerConnection, Result};
 
 impl Connection {
     /// Set a busy handler that sleeps for a specified amount of time when a
     /// table is locked. The handler will sleep multiple times until at
-    /// least "ms" milliseconds of sleeping have accumulated.
+    /// least "ms" milliseconds of sleeping have accumulated. A timeout which
+    /// is not a whole number of milliseconds is rounded up, so that a
+    /// subsecond (or submillisecond) timeout does not turn off the handler,
+    /// and a timeout longer than `i32::MAX` milliseconds (about 24 days) is
+    /// reduced to that.
     ///
     /// Calling this routine with an argument equal to zero turns off all busy
     /// handlers.
@@ -21,12 +27,25 @@ impl Connection {
     /// (using `busy_handler`) prior to calling this routine, that other
     /// busy handler is cleared.
     pub fn busy_timeout(&self, timeout: Duration) -> Result<()> {
-        let ms = timeout
-            .as_secs()
-            .checked_mul(1000)
-            .and_then(|t| t.checked_add(timeout.subsec_millis().into()))
-            .expect("too big");
-        self.db.borrow_mut().busy_timeout(ms as i32)
+        let mut ms = timeout.as_millis();
+        if timeout.subsec_nanos() > timeout.subsec_millis() * 1_000_000 {
+            ms += 1;
+        }
+        // `sqlite3_busy_timeout` takes an `int`.
+        let ms = ms.min(i32::MAX as u128) as i32;
+        self.db.borrow_mut().busy_timeout(ms)
+    }
+
+    /// Return the busy timeout currently set by `busy_timeout` (5 seconds by
+    /// default), or zero if there is none (e.g. if a `busy_handler` has been
+    /// set instead).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn get_busy_timeout(&self) -> Result<Duration> {
+        let ms: i64 = self.pragma_query_value(None, "busy_timeout", |row| row.get(0))?;
+        Ok(Duration::from_millis(ms as u64))
     }
 
     /// Register a callback to handle `SQLITE_BUSY` errors.
@@ -46,29 +65,245 @@ impl Connection {
     /// handler. Note that calling `busy_timeout()` or evaluating `PRAGMA
     /// busy_timeout=N` will change the busy handler and thus
     /// clear any previously set busy handler.
+    ///
+    /// To register a closure, use `set_busy_handler`.
     pub fn busy_handler(&self, callback: Option<fn(i32) -> bool>) -> Result<()> {
+        let callback = callback.map(|f| Box::new(Box::new(f) as BusyHandler));
+        self.db
+            .borrow_mut()
+            .replace_busy_handler(callback)
+            .map(|_| ())
+    }
+
+    /// Register a closure to handle `SQLITE_BUSY` errors, like
+    /// `busy_handler` does with a function, e.g. to retry with an
+    /// exponential backoff until a deadline:
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use std::time::{Duration, Instant};
+    /// fn backoff(conn: &Connection) -> Result<()> {
+    ///     let mut started = None;
+    ///     conn.set_busy_handler(move |attempts| {
+    ///         if attempts == 0 {
+    ///             started = Some(Instant::now());
+    ///         }
+    ///         if started.map_or(true, |t| t.elapsed() > Duration::from_secs(10)) {
+    ///             return false;
+    ///         }
+    ///         std::thread::sleep(Duration::from_millis(1 << attempts.min(8)));
+    ///         true
+    ///     })
+    /// }
+    /// ```
+    ///
+    /// The closure is owned by the connection: it is dropped when it is
+    /// replaced with `busy_handler`, `set_busy_handler` or `busy_timeout`,
+    /// or when the connection is closed.
+    pub fn set_busy_handler<F>(&self, callback: F) -> Result<()>
+    where
+        F: FnMut(i32) -> bool + Send + 'static,
+    {
+        self.db
+            .borrow_mut()
+            .replace_busy_handler(Some(Box::new(Box::new(callback) as BusyHandler)))
+            .map(|_| ())
+    }
+}
+
+/// A callback registered with `Connection::busy_handler` or
+/// `Connection::set_busy_handler`.
+pub(crate) type BusyHandler = Box<dyn FnMut(i32) -> bool + Send>;
+
+/// Most likely reason for a `SQLITE_BUSY` error (see `BusyDiagnostics`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusyCause {
+    /// Another connection is recovering the WAL of the database
+    /// (`SQLITE_BUSY_RECOVERY`).
+    Recovery,
+    /// The read transaction of this connection cannot be upgraded to a write
+    /// transaction because another connection has committed since it started
+    /// (`SQLITE_BUSY_SNAPSHOT`): the transaction must be restarted.
+    StaleSnapshot,
+    /// Another connection has an open write transaction (WAL mode: readers
+    /// never block writers).
+    Writer,
+    /// Another connection holds a conflicting lock (rollback journal modes):
+    /// either it has an open write transaction or it is committing, or it
+    /// has an open read transaction which prevents this connection from
+    /// committing.
+    Locked,
+}
+
+impl fmt::Display for BusyCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match *self {
+            BusyCause::Recovery => "another connection is recovering the WAL",
+            BusyCause::StaleSnapshot => {
+                "the read transaction is stale: another connection committed since it started"
+            }
+            BusyCause::Writer => "another connection holds the write lock",
+            BusyCause::Locked => "another connection holds a conflicting lock",
+        })
+    }
+}
+
+/// Context captured when a `SQLITE_BUSY` error occurs (see
+/// `Connection::busy_diagnostics`).
+///
+/// SQLite does not tell which connection or process holds a lock, so
+/// `cause` is only a best guess made from the extended error code, the
+/// journal mode and the transaction state of the connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusyDiagnostics {
+    /// Extended result code of the error.
+    pub extended_code: c_int,
+    /// `true` if a transaction was already open on this connection, i.e. the
+    /// lock was needed to upgrade a read transaction to a write transaction
+    /// (or to commit), in which case retrying the statement alone may never
+    /// succeed: the whole transaction should be rolled back and retried.
+    pub upgrading: bool,
+    /// Journal mode of the main database (e.g. `"wal"` or `"delete"`), if it
+    /// could be read.
+    pub journal_mode: Option<String>,
+    /// Most likely reason for the error.
+    pub cause: BusyCause,
+}
+
+impl fmt::Display for BusyDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "database is busy: {}", self.cause)?;
+        if self.upgrading {
+            write!(f, " (while upgrading a read transaction)")?;
+        }
+        if let Some(ref journal_mode) = self.journal_mode {
+            write!(f, " [journal_mode={}]", journal_mode)?;
+        }
+        Ok(())
+    }
+}
+
+impl Connection {
+    /// Return the context captured when `err`, a `SQLITE_BUSY` error which
+    /// occurred on this connection, was returned. Return `None` for any
+    /// other error.
+    ///
+    /// The context is captured for each `SQLITE_BUSY` error, before the
+    /// transaction state of the connection can change, and only the one of
+    /// the most recent error is kept.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn insert(conn: &Connection) -> Result<usize> {
+    ///     conn.execute_batch("INSERT INTO foo VALUES(1)")
+    ///         .map(|_| 1)
+    ///         .map_err(|err| {
+    ///             if let Some(diagnostics) = conn.busy_diagnostics(&err) {
+    ///                 eprintln!("{}", diagnostics);
+    ///             }
+    ///             err
+    ///         })
+    /// }
+    /// ```
+    pub fn busy_diagnostics(&self, err: &Error) -> Option<BusyDiagnostics> {
+        let extended_code = match *err {
+            Error::SqliteFailure(ref err, _) if err.code == ErrorCode::DatabaseBusy => {
+                err.extended_code
+            }
+            _ => return None,
+        };
+        match self.db.borrow().busy_context {
+            Some(ref diagnostics) if diagnostics.extended_code == extended_code => {
+                Some(diagnostics.clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+const SQLITE_BUSY_RECOVERY: c_int = ffi::SQLITE_BUSY | (1 << 8);
+const SQLITE_BUSY_SNAPSHOT: c_int = ffi::SQLITE_BUSY | (2 << 8);
+
+impl InnerConnection {
+    /// Capture the context of a `SQLITE_BUSY` error (see
+    /// `Connection::busy_diagnostics`), right after it occurred.
+    pub(crate) fn capture_busy_context(&mut self, extended_code: c_int) {
+        let upgrading = unsafe { ffi::sqlite3_get_autocommit(self.db) } == 0;
+        let journal_mode = self.journal_mode();
+        let wal = journal_mode.iter().any(|mode| mode == "wal");
+        let cause = match extended_code {
+            SQLITE_BUSY_RECOVERY => BusyCause::Recovery,
+            SQLITE_BUSY_SNAPSHOT => BusyCause::StaleSnapshot,
+            _ if wal => BusyCause::Writer,
+            _ => BusyCause::Locked,
+        };
+        self.busy_context = Some(BusyDiagnostics {
+            extended_code,
+            upgrading,
+            journal_mode,
+            cause,
+        });
+    }
+
+    // Without going through `Statement`, which may fail with another error
+    // while this one is being built.
+    fn journal_mode(&mut self) -> Option<String> {
+        let sql = CStr::from_bytes_with_nul(b"PRAGMA journal_mode\0").unwrap();
+        let mut stmt = ptr::null_mut();
+        unsafe {
+            let r = ffi::sqlite3_prepare_v2(self.db, sql.as_ptr(), -1, &mut stmt, ptr::null_mut());
+            if r != ffi::SQLITE_OK || stmt.is_null() {
+                return None;
+            }
+            let mut mode = None;
+            if ffi::sqlite3_step(stmt) == ffi::SQLITE_ROW {
+                let text = ffi::sqlite3_column_text(stmt, 0);
+                if !text.is_null() {
+                    let text = CStr::from_ptr(text as *const c_char);
+                    mode = Some(text.to_string_lossy().to_ascii_lowercase());
+                }
+            }
+            ffi::sqlite3_finalize(stmt);
+            mode
+        }
+    }
+
+    /// Register `handler` (or unregister the busy handler with `None`), and
+    /// return the previous one, if any, which is not dropped so that it can
+    /// be registered again.
+    pub(crate) fn replace_busy_handler(
+        &mut self,
+        mut handler: Option<Box<BusyHandler>>,
+    ) -> Result<Option<Box<BusyHandler>>> {
         unsafe extern "C" fn busy_handler_callback(p_arg: *mut c_void, count: c_int) -> c_int {
-            let handler_fn: fn(i32) -> bool = mem::transmute(p_arg);
-            if let Ok(true) = catch_unwind(|| handler_fn(count)) {
+            let r = catch_unwind(AssertUnwindSafe(|| {
+                let boxed_handler = p_arg as *mut BusyHandler;
+                (*boxed_handler)(count)
+            }));
+            if let Ok(true) = r {
                 1
             } else {
                 0
             }
         }
-        let mut c = self.db.borrow_mut();
-        let r = match callback {
-            Some(f) => unsafe {
-                ffi::sqlite3_busy_handler(c.db(), Some(busy_handler_callback), mem::transmute(f))
-            },
-            None => unsafe { ffi::sqlite3_busy_handler(c.db(), None, ptr::null_mut()) },
+        let r = unsafe {
+            match handler {
+                Some(ref mut handler) => ffi::sqlite3_busy_handler(
+                    self.db(),
+                    Some(busy_handler_callback),
+                    &mut **handler as *mut BusyHandler as *mut _,
+                ),
+                None => ffi::sqlite3_busy_handler(self.db(), None, ptr::null_mut()),
+            }
         };
-        c.decode_result(r)
+        self.decode_result(r)?;
+        Ok(mem::replace(&mut self.busy_handler, handler))
     }
-}
 
-impl InnerConnection {
     fn busy_timeout(&mut self, timeout: c_int) -> Result<()> {
         let r = unsafe { ffi::sqlite3_busy_timeout(self.db, timeout) };
+        // The previous busy handler, if any, has been replaced.
+        self.busy_handler = None;
         self.decode_result(r)
     }
 }
@@ -78,10 +313,13 @@ mod test {
     use self::tempdir::TempDir;
     use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::mpsc::sync_channel;
+    #[cfg(feature = "testing")]
+    use std::sync::{Arc, Mutex};
     use std::thread;
     use std::time::Duration;
     use tempdir;
 
+    use super::BusyCause;
     use crate::{Connection, Error, ErrorCode, Result, TransactionBehavior, NO_PARAMS};
 
     #[test]
@@ -104,6 +342,54 @@ mod test {
         tx1.rollback().unwrap();
     }
 
+    #[test]
+    fn test_busy_diagnostics() {
+        let temp_dir = TempDir::new("test_busy_diagnostics").unwrap();
+        let path = temp_dir.path().join("test.db3");
+
+        let mut db1 = Connection::open(&path).unwrap();
+        db1.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        let tx1 = db1
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .unwrap();
+        let db2 = Connection::open(&path).unwrap();
+        db2.execute_batch("BEGIN; SELECT count(*) FROM foo;")
+            .unwrap();
+        let err = db2.execute_batch("INSERT INTO foo VALUES(1)").unwrap_err();
+        // the context is the one of the error, not the current one
+        db2.execute_batch("ROLLBACK").unwrap();
+        let diagnostics = db2.busy_diagnostics(&err).unwrap();
+        assert!(diagnostics.upgrading);
+        assert_eq!(
+            Some("delete"),
+            diagnostics.journal_mode.as_ref().map(|s| &s[..])
+        );
+        assert_eq!(BusyCause::Locked, diagnostics.cause);
+
+        assert!(db2.busy_diagnostics(&Error::QueryReturnedNoRows).is_none());
+        tx1.rollback().unwrap();
+    }
+
+    #[test]
+    fn test_get_busy_timeout() {
+        let db = Connection::open_in_memory().unwrap();
+        assert_eq!(Duration::from_secs(5), db.get_busy_timeout().unwrap());
+        db.busy_timeout(Duration::from_millis(1500)).unwrap();
+        assert_eq!(Duration::from_millis(1500), db.get_busy_timeout().unwrap());
+        db.busy_timeout(Duration::from_micros(1)).unwrap();
+        assert_eq!(Duration::from_millis(1), db.get_busy_timeout().unwrap());
+        db.busy_timeout(Duration::from_micros(2500)).unwrap();
+        assert_eq!(Duration::from_millis(3), db.get_busy_timeout().unwrap());
+        db.busy_timeout(Duration::from_secs(u64::max_value()))
+            .unwrap();
+        assert_eq!(
+            Duration::from_millis(i32::MAX as u64),
+            db.get_busy_timeout().unwrap()
+        );
+        db.busy_handler(None).unwrap();
+        assert_eq!(Duration::from_secs(0), db.get_busy_timeout().unwrap());
+    }
+
     #[test]
     #[ignore] // FIXME: unstable
     fn test_busy_timeout() {
@@ -173,4 +459,40 @@ mod test {
 
         child.join().unwrap();
     }
+
+    #[test]
+    #[cfg(feature = "testing")]
+    fn test_busy_handler_closure() {
+        let temp_dir = TempDir::new("test_busy_handler_closure").unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = Connection::open(&path).unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+
+        let lock =
+            crate::testing::LockHolder::exclusive_for(&path, Duration::from_secs(60)).unwrap();
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let recorded = attempts.clone();
+        db.set_busy_handler(move |n| {
+            recorded.lock().unwrap().push(n);
+            n < 3
+        })
+        .unwrap();
+        match db.execute_batch("INSERT INTO foo VALUES(1)") {
+            Err(Error::SqliteFailure(err, _)) => assert_eq!(ErrorCode::DatabaseBusy, err.code),
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert_eq!(vec![0, 1, 2, 3], *attempts.lock().unwrap());
+        lock.release().unwrap();
+
+        let _lock =
+            crate::testing::LockHolder::exclusive_for(&path, Duration::from_millis(50)).unwrap();
+        db.set_busy_handler(|_| {
+            crate::testing::sleep(Duration::from_millis(10));
+            true
+        })
+        .unwrap();
+        db.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
+        // The previous closure has been dropped.
+        assert_eq!(1, Arc::strong_count(&attempts));
+    }
 }
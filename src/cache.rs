@@ -1,10 +1,12 @@
 //! Prepared statements cache for faster execution.
 
 use crate::raw_statement::RawStatement;
-use crate::{Connection, Result, Statement};
+use crate::{Connection, Error, Result, Statement};
 use lru_cache::LruCache;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
 
 impl Connection {
     /// Prepare a SQL statement for execution, returning a previously prepared
@@ -34,6 +36,7 @@ impl Connection {
     /// Will return `Err` if `sql` cannot be converted to a C-compatible string
     /// or if the underlying SQLite call fails.
     pub fn prepare_cached(&self, sql: &str) -> Result<CachedStatement<'_>> {
+        self.flush_before(sql)?;
         self.cache.get(self, sql)
     }
 
@@ -46,15 +49,94 @@ impl Connection {
         self.cache.set_capacity(capacity)
     }
 
+    /// Prepare the statements `sqls` and put them in the statement cache, so
+    /// that invalid statements are detected at startup instead of at first
+    /// use, and that their first `prepare_cached` is cheap.
+    ///
+    /// The cache capacity is increased, if needed, to hold all of them.
+    ///
+    /// Return the statements which could not be prepared, with their error
+    /// (i.e. an empty vector if all of them are valid).
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// const QUERIES: &[&str] = &[
+    ///     "SELECT name FROM people WHERE id = ?",
+    ///     "INSERT INTO people (name) VALUES (?)",
+    /// ];
+    ///
+    /// fn open() -> Result<Connection> {
+    ///     let conn = Connection::open("people.db")?;
+    ///     for (sql, err) in conn.preprepare(QUERIES) {
+    ///         panic!("invalid query {}: {}", sql, err);
+    ///     }
+    ///     Ok(conn)
+    /// }
+    /// ```
+    pub fn preprepare<'a>(&self, sqls: &[&'a str]) -> Vec<(&'a str, Error)> {
+        if self.cache.capacity() < sqls.len() {
+            self.cache.set_capacity(sqls.len());
+        }
+        let mut errors = Vec::new();
+        for &sql in sqls {
+            if self.cache.contains(sql) {
+                continue;
+            }
+            if let Err(err) = self.prepare_cached(sql) {
+                errors.push((sql, err));
+            }
+        }
+        errors
+    }
+
     /// Remove/finalize all prepared statements currently in the cache.
     pub fn flush_prepared_statement_cache(&self) {
         self.cache.flush()
     }
+
+    /// Invalidate all cached prepared statements.
+    ///
+    /// Like `flush_prepared_statement_cache`, but statements currently in use
+    /// will also be discarded (instead of being returned to the cache) once
+    /// they are dropped.
+    ///
+    /// This is done automatically when a transaction is rolled back (by a
+    /// `Transaction`, a raw `ROLLBACK` or SQLite itself after an error) or a
+    /// `Savepoint` is rolled back, because cached statements may refer to
+    /// schema objects created inside the transaction that no longer exist.
+    pub fn invalidate_cached_statements(&self) {
+        self.cache.invalidate()
+    }
+
+    /// Return the number of statements in the prepared statement cache, and
+    /// the heap memory they use (see `Statement::memory_used`), e.g. to
+    /// adjust its capacity with `set_prepared_statement_cache_capacity`.
+    ///
+    /// Statements currently in use are not in the cache, so they are not
+    /// counted.
+    pub fn prepared_statement_cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Prepare a SQL statement which, unlike `prepare_cached`, is neither
+    /// looked up in nor returned to the cache.
+    pub(crate) fn prepare_uncached(&self, sql: &str) -> Result<CachedStatement<'_>> {
+        self.prepare(sql).map(|stmt| CachedStatement {
+            stmt: Some(stmt),
+            cache: &self.cache,
+            generation: None,
+        })
+    }
 }
 
 /// Prepared statements LRU cache.
 #[derive(Debug)]
-pub struct StatementCache(RefCell<LruCache<String, RawStatement>>);
+pub struct StatementCache {
+    statements: RefCell<LruCache<String, RawStatement>>,
+    // Incremented each time the cache is invalidated, so that the statements
+    // borrowed from it before are not returned to it.
+    generation: Cell<u64>,
+}
 
 /// Cacheable statement.
 ///
@@ -63,6 +145,8 @@ pub struct StatementCache(RefCell<LruCache<String, RawStatement>>);
 pub struct CachedStatement<'conn> {
     stmt: Option<Statement<'conn>>,
     cache: &'conn StatementCache,
+    // `None` if the statement must not be cached.
+    generation: Option<u64>,
 }
 
 impl<'conn> Deref for CachedStatement<'conn> {
@@ -83,7 +167,9 @@ impl Drop for CachedStatement<'_> {
     #[allow(unused_must_use)]
     fn drop(&mut self) {
         if let Some(stmt) = self.stmt.take() {
-            self.cache.cache_stmt(stmt.into());
+            if self.generation == Some(self.cache.generation.get()) {
+                self.cache.cache_stmt(stmt.into());
+            }
         }
     }
 }
@@ -93,6 +179,7 @@ impl CachedStatement<'_> {
         CachedStatement {
             stmt: Some(stmt),
             cache,
+            generation: Some(cache.generation.get()),
         }
     }
 
@@ -103,14 +190,168 @@ impl CachedStatement<'_> {
     }
 }
 
+/// Statistics of the prepared statement cache of a connection (see
+/// `Connection::prepared_statement_cache_stats`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of statements in the cache.
+    pub statements: usize,
+    /// Maximum number of statements in the cache.
+    pub capacity: usize,
+    /// Approximate number of bytes of heap memory used by the statements in
+    /// the cache.
+    pub memory_used: usize,
+}
+
+/// Usage statistics of a SQL statement registered in a `StatementRegistry`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatementStats {
+    /// The SQL text.
+    pub sql: String,
+    /// Number of times the statement has been requested, on any connection.
+    pub uses: u64,
+    /// Number of times the statement had to be prepared because it was not
+    /// in the cache of the requesting connection.
+    pub prepares: u64,
+}
+
+/// A registry of hot SQL statements, meant to be shared (e.g. behind an
+/// `Arc` or in a `static`) by all the connections of a pool.
+///
+/// Each connection prepares its own instances lazily, through its statement
+/// cache, while usage statistics are shared.
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result, StatementRegistry};
+/// fn open(registry: &StatementRegistry) -> Result<Connection> {
+///     let conn = Connection::open("pool.db")?;
+///     // Pre-warm the statements already used by other connections.
+///     registry.warm_up(&conn)?;
+///     Ok(conn)
+/// }
+///
+/// fn count(registry: &StatementRegistry, conn: &Connection) -> Result<i64> {
+///     let mut stmt = registry.prepare(conn, "SELECT count(*) FROM people")?;
+///     stmt.query_row(&[] as &[i64], |row| row.get(0))
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct StatementRegistry {
+    stats: Mutex<HashMap<String, StatementStats>>,
+}
+
+impl StatementRegistry {
+    /// Create an empty registry.
+    pub fn new() -> StatementRegistry {
+        StatementRegistry::default()
+    }
+
+    /// Register `sql` without preparing it, so that it is prepared by
+    /// `warm_up`.
+    pub fn register(&self, sql: &str) {
+        self.entry(sql, |_| {});
+    }
+
+    /// Prepare `sql` on `conn` through its statement cache, and record the
+    /// usage in the registry.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails.
+    pub fn prepare<'conn>(
+        &self,
+        conn: &'conn Connection,
+        sql: &str,
+    ) -> Result<CachedStatement<'conn>> {
+        let cached = conn.cache.contains(sql);
+        let stmt = conn.prepare_cached(sql)?;
+        self.entry(sql, |stats| {
+            stats.uses += 1;
+            if !cached {
+                stats.prepares += 1;
+            }
+        });
+        Ok(stmt)
+    }
+
+    /// Prepare all registered statements on `conn`, most used first, and
+    /// put them in its statement cache.
+    ///
+    /// Only as many statements as the cache capacity are prepared.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn warm_up(&self, conn: &Connection) -> Result<()> {
+        let capacity = conn.cache.capacity();
+        for stats in self.stats().into_iter().take(capacity) {
+            if !conn.cache.contains(&stats.sql) {
+                conn.prepare_cached(&stats.sql)?;
+                self.entry(&stats.sql, |stats| stats.prepares += 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Snapshot of the statistics of all registered statements, most used
+    /// first.
+    pub fn stats(&self) -> Vec<StatementStats> {
+        let mut stats: Vec<StatementStats> = self.stats.lock().unwrap().values().cloned().collect();
+        stats.sort_by(|a, b| b.uses.cmp(&a.uses).then_with(|| a.sql.cmp(&b.sql)));
+        stats
+    }
+
+    /// Reset the statistics of all registered statements.
+    pub fn reset_stats(&self) {
+        for stats in self.stats.lock().unwrap().values_mut() {
+            stats.uses = 0;
+            stats.prepares = 0;
+        }
+    }
+
+    fn entry<F: FnOnce(&mut StatementStats)>(&self, sql: &str, f: F) {
+        let sql = sql.trim();
+        let mut stats = self.stats.lock().unwrap();
+        let entry = stats
+            .entry(sql.to_owned())
+            .or_insert_with(|| StatementStats {
+                sql: sql.to_owned(),
+                uses: 0,
+                prepares: 0,
+            });
+        f(entry)
+    }
+}
+
 impl StatementCache {
     /// Create a statement cache.
     pub fn with_capacity(capacity: usize) -> StatementCache {
-        StatementCache(RefCell::new(LruCache::new(capacity)))
+        StatementCache {
+            statements: RefCell::new(LruCache::new(capacity)),
+            generation: Cell::new(0),
+        }
     }
 
     fn set_capacity(&self, capacity: usize) {
-        self.0.borrow_mut().set_capacity(capacity)
+        self.statements.borrow_mut().set_capacity(capacity)
+    }
+
+    fn capacity(&self) -> usize {
+        self.statements.borrow().capacity()
+    }
+
+    fn stats(&self) -> CacheStats {
+        let cache = self.statements.borrow();
+        CacheStats {
+            statements: cache.len(),
+            capacity: cache.capacity(),
+            memory_used: cache.iter().map(|(_, stmt)| stmt.memory_used()).sum(),
+        }
+    }
+
+    fn contains(&self, sql: &str) -> bool {
+        self.statements.borrow_mut().contains_key(sql.trim())
     }
 
     // Search the cache for a prepared-statement object that implements `sql`.
@@ -125,9 +366,14 @@ impl StatementCache {
         conn: &'conn Connection,
         sql: &str,
     ) -> Result<CachedStatement<'conn>> {
-        let mut cache = self.0.borrow_mut();
+        // Cached statements may refer to schema objects created inside a
+        // transaction which has been rolled back since.
+        if conn.db.borrow().tx_hooks.take_rolled_back() {
+            self.invalidate();
+        }
+        let mut cache = self.statements.borrow_mut();
         let stmt = match cache.remove(sql.trim()) {
-            Some(raw_stmt) => Ok(Statement::new(conn, raw_stmt)),
+            Some(raw_stmt) => Ok(Statement::new(conn, raw_stmt, &conn.db.borrow().decoders)),
             None => conn.prepare(sql),
         };
         stmt.map(|stmt| CachedStatement::new(stmt, self))
@@ -135,7 +381,7 @@ impl StatementCache {
 
     // Return a statement to the cache.
     fn cache_stmt(&self, stmt: RawStatement) {
-        let mut cache = self.0.borrow_mut();
+        let mut cache = self.statements.borrow_mut();
         stmt.clear_bindings();
         let sql = String::from_utf8_lossy(stmt.sql().to_bytes())
             .trim()
@@ -144,28 +390,29 @@ impl StatementCache {
     }
 
     fn flush(&self) {
-        let mut cache = self.0.borrow_mut();
+        let mut cache = self.statements.borrow_mut();
         cache.clear()
     }
+
+    fn invalidate(&self) {
+        self.generation.set(self.generation.get().wrapping_add(1));
+        self.flush()
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::StatementCache;
+    use super::{StatementCache, StatementRegistry};
     use crate::{Connection, NO_PARAMS};
     use fallible_iterator::FallibleIterator;
 
     impl StatementCache {
         fn clear(&self) {
-            self.0.borrow_mut().clear();
+            self.statements.borrow_mut().clear();
         }
 
         fn len(&self) -> usize {
-            self.0.borrow().len()
-        }
-
-        fn capacity(&self) -> usize {
-            self.0.borrow().capacity()
+            self.statements.borrow().len()
         }
     }
 
@@ -244,6 +491,30 @@ mod test {
         assert_eq!(1, cache.len());
     }
 
+    #[test]
+    fn test_cache_stats() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!(0, stats.statements);
+        assert_eq!(0, stats.memory_used);
+
+        let memory_used = {
+            let stmt = db.prepare_cached("SELECT x FROM foo WHERE x > ?").unwrap();
+            assert!(stmt.memory_used() > 0);
+            assert_eq!(0, db.prepared_statement_cache_stats().statements);
+            stmt.memory_used()
+        };
+        db.prepare_cached("INSERT INTO foo VALUES(?)").unwrap();
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!(2, stats.statements);
+        assert_eq!(db.cache.capacity(), stats.capacity);
+        assert!(stats.memory_used > memory_used);
+
+        db.flush_prepared_statement_cache();
+        assert_eq!(0, db.prepared_statement_cache_stats().memory_used);
+    }
+
     #[test]
     fn test_discard() {
         let db = Connection::open_in_memory().unwrap();
@@ -303,6 +574,161 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_invalidate() {
+        let db = Connection::open_in_memory().unwrap();
+        let cache = &db.cache;
+
+        let sql = "PRAGMA schema_version";
+        {
+            let mut stmt = db.prepare_cached(sql).unwrap();
+            assert_eq!(
+                0,
+                stmt.query_row(NO_PARAMS, |r| r.get::<_, i64>(0)).unwrap()
+            );
+        }
+        assert_eq!(1, cache.len());
+        db.invalidate_cached_statements();
+        assert_eq!(0, cache.len());
+
+        {
+            let _stmt = db.prepare_cached(sql).unwrap();
+            // statements in use when the cache is invalidated are discarded
+            db.invalidate_cached_statements();
+        }
+        assert_eq!(0, cache.len());
+    }
+
+    #[test]
+    fn test_rollback_invalidates() {
+        let mut db = Connection::open_in_memory().unwrap();
+        {
+            let tx = db.transaction().unwrap();
+            tx.execute_batch("CREATE TABLE foo (x INT)").unwrap();
+            tx.prepare_cached("SELECT x FROM foo").unwrap();
+            assert_eq!(1, tx.cache.len());
+            tx.rollback().unwrap();
+        }
+        assert_eq!(0, db.cache.len());
+        {
+            let mut sp = db.savepoint().unwrap();
+            sp.execute_batch("CREATE TABLE foo (x INT)").unwrap();
+            sp.prepare_cached("SELECT x FROM foo").unwrap();
+            assert_eq!(1, sp.cache.len());
+            sp.rollback().unwrap();
+            assert_eq!(0, sp.cache.len());
+        }
+        db.execute_batch("CREATE TABLE foo (y INT)").unwrap();
+        db.prepare_cached("SELECT y FROM foo").unwrap();
+    }
+
+    #[test]
+    fn test_raw_rollback_invalidates() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE bar (x INT PRIMARY KEY)")
+            .unwrap();
+        db.execute_batch("BEGIN; CREATE TABLE foo (x INT)").unwrap();
+        db.prepare_cached("SELECT x FROM foo").unwrap();
+        db.execute_batch("ROLLBACK").unwrap();
+        assert_eq!(1, db.cache.len());
+        db.prepare_cached("SELECT x FROM bar").unwrap();
+        assert_eq!(1, db.cache.len());
+        assert!(!db.cache.contains("SELECT x FROM foo"));
+
+        // rolled back by SQLite itself
+        db.execute_batch("BEGIN; CREATE TABLE foo (x INT)").unwrap();
+        db.prepare_cached("SELECT x FROM foo").unwrap();
+        db.execute_batch("INSERT INTO bar VALUES (1)").unwrap();
+        db.execute_batch("INSERT OR ROLLBACK INTO bar VALUES (1)")
+            .unwrap_err();
+        assert!(db.is_autocommit());
+        db.prepare_cached("SELECT x FROM bar").unwrap();
+        assert!(!db.cache.contains("SELECT x FROM foo"));
+    }
+
+    #[test]
+    fn test_transaction_prepare_cached() {
+        let mut db = Connection::open_in_memory().unwrap();
+        {
+            let mut tx = db.transaction().unwrap();
+            tx.prepare_cached("SELECT 1").unwrap();
+            assert_eq!(1, tx.cache.len());
+
+            tx.set_statement_caching(false);
+            tx.prepare_cached("SELECT 2").unwrap();
+            let stmt = tx.prepare_cached("SELECT 1").unwrap();
+            assert_eq!(1, tx.cache.len());
+            drop(stmt);
+            assert_eq!(1, tx.cache.len());
+
+            let mut sp = tx.savepoint().unwrap();
+            sp.set_statement_caching(false);
+            sp.prepare_cached("SELECT 3").unwrap();
+            assert_eq!(1, sp.cache.len());
+            sp.set_statement_caching(true);
+            sp.prepare_cached("SELECT 3").unwrap();
+            assert_eq!(2, sp.cache.len());
+            sp.commit().unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(2, db.cache.len());
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = StatementRegistry::new();
+        registry.register("SELECT 2");
+
+        let db1 = Connection::open_in_memory().unwrap();
+        for _ in 0..3 {
+            let mut stmt = registry.prepare(&db1, "SELECT 1").unwrap();
+            stmt.query_row(NO_PARAMS, |r| r.get::<_, i64>(0)).unwrap();
+        }
+        let stats = registry.stats();
+        assert_eq!(2, stats.len());
+        assert_eq!("SELECT 1", stats[0].sql);
+        assert_eq!(3, stats[0].uses);
+        assert_eq!(1, stats[0].prepares);
+        assert_eq!(0, stats[1].uses);
+
+        let db2 = Connection::open_in_memory().unwrap();
+        registry.warm_up(&db2).unwrap();
+        assert_eq!(2, db2.cache.len());
+        registry.prepare(&db2, "SELECT 1").unwrap();
+        let stats = registry.stats();
+        assert_eq!(4, stats[0].uses);
+        assert_eq!(2, stats[0].prepares);
+        assert_eq!(1, stats[1].prepares);
+
+        registry.reset_stats();
+        assert_eq!(0, registry.stats()[0].uses);
+    }
+
+    #[test]
+    fn test_preprepare() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        db.set_prepared_statement_cache_capacity(1);
+
+        let errors = db.preprepare(&[
+            "SELECT x FROM foo",
+            "SELECT y FROM foo",
+            "INSERT INTO foo VALUES (?)",
+            "SELECT x FROM foo",
+        ]);
+        assert_eq!(1, errors.len());
+        assert_eq!("SELECT y FROM foo", errors[0].0);
+        assert!(errors[0].1.to_string().contains("no such column"));
+        assert_eq!(4, db.cache.capacity());
+        assert_eq!(2, db.cache.len());
+
+        db.prepare_cached("INSERT INTO foo VALUES (?)")
+            .unwrap()
+            .execute([1])
+            .unwrap();
+        assert_eq!(2, db.cache.len());
+    }
+
     #[test]
     fn test_connection_close() {
         let conn = Connection::open_in_memory().unwrap();
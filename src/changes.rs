@@ -0,0 +1,336 @@
+//! Row-level change capture
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use crate::ffi;
+use crate::hooks::Action;
+use crate::tx_hooks::{Settle, TxBuffer, TxHooks};
+use crate::types::{Value, ValueRef};
+use crate::{Connection, InnerConnection};
+
+/// A row inserted, updated or deleted by a committed transaction (see
+/// `Connection::subscribe_changes`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangeEvent {
+    /// `SQLITE_INSERT`, `SQLITE_UPDATE` or `SQLITE_DELETE`.
+    pub action: Action,
+    /// Name of the table.
+    pub table: String,
+    /// Rowid of the row (after an insert or an update, before a delete).
+    pub rowid: i64,
+    /// Values of the columns before an update or a delete.
+    ///
+    /// Texts which are not valid UTF-8 are converted like
+    /// `Row::get_str_lossy` does.
+    pub old: Option<Vec<Value>>,
+    /// Values of the columns after an insert or an update.
+    pub new: Option<Vec<Value>>,
+}
+
+/// State of the preupdate hook, owned by the transaction hooks of the
+/// connection (see `tx_hooks`).
+pub(crate) struct ChangeCapture {
+    tables: Vec<String>,
+    events: TxBuffer<ChangeEvent>,
+    sender: Sender<ChangeEvent>,
+}
+
+impl ChangeCapture {
+    /// Settle the buffered events, and send them once their transaction is
+    /// committed.
+    pub(crate) fn settle(&mut self, op: Settle) {
+        self.events.settle(op);
+        if op == Settle::Commit {
+            for event in self.events.take_committed() {
+                let _ = self.sender.send(event);
+            }
+        }
+    }
+}
+
+impl Connection {
+    /// Capture the rows inserted, updated or deleted in the given tables (in
+    /// any table if `tables` is empty), and return a channel receiving them,
+    /// with their old and new values, once their transaction is committed.
+    ///
+    /// This is useful to invalidate caches or refresh live queries. The
+    /// changes are buffered until their transaction is committed (not only
+    /// when the commit hook fires: the commit may still fail), and
+    /// discarded when the statement which made them fails, when the
+    /// transaction is rolled back, or when they are undone by `ROLLBACK TO`.
+    ///
+    /// The callbacks of `commit_hook` and `rollback_hook`, if any, are kept.
+    /// This replaces the previous subscription, if any (whose channel is
+    /// then disconnected).
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, NO_PARAMS};
+    /// fn watch(conn: &Connection) -> Result<()> {
+    ///     let changes = conn.subscribe_changes(&["people"]);
+    ///     conn.execute("UPDATE people SET age = age + 1", NO_PARAMS)?;
+    ///     for change in changes.try_iter() {
+    ///         println!("{:?} {}: {:?}", change.action, change.rowid, change.new);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn subscribe_changes(&self, tables: &[&str]) -> Receiver<ChangeEvent> {
+        let (sender, receiver) = channel();
+        self.db.borrow_mut().capture_changes(Some(ChangeCapture {
+            tables: tables.iter().map(|&table| table.to_owned()).collect(),
+            events: TxBuffer::new(usize::MAX),
+            sender,
+        }));
+        receiver
+    }
+
+    /// Stop the capture started by `subscribe_changes`. The changes of a
+    /// transaction which is not committed yet are discarded.
+    pub fn unsubscribe_changes(&self) {
+        self.db.borrow_mut().capture_changes(None);
+    }
+}
+
+impl InnerConnection {
+    pub(crate) fn capture_changes(&mut self, capture: Option<ChangeCapture>) {
+        unsafe extern "C" fn capture_change(
+            p_arg: *mut c_void,
+            db: *mut ffi::sqlite3,
+            action_code: c_int,
+            _db_str: *const c_char,
+            tbl_str: *const c_char,
+            key1: i64,
+            key2: i64,
+        ) {
+            let _ = catch_unwind(AssertUnwindSafe(|| {
+                let hooks = &*(p_arg as *const TxHooks);
+                let mut capture = match hooks.changes.try_borrow_mut() {
+                    Ok(capture) => capture,
+                    Err(_) => return,
+                };
+                let capture = match *capture {
+                    Some(ref mut capture) => capture,
+                    None => return,
+                };
+                let table = CStr::from_ptr(tbl_str).to_string_lossy();
+                if !capture.tables.is_empty()
+                    && !capture
+                        .tables
+                        .iter()
+                        .any(|t| t.eq_ignore_ascii_case(&table))
+                {
+                    return;
+                }
+                let action = Action::from(action_code);
+                let event = ChangeEvent {
+                    action,
+                    table: table.into_owned(),
+                    rowid: if action == Action::SQLITE_DELETE {
+                        key1
+                    } else {
+                        key2
+                    },
+                    old: if action == Action::SQLITE_INSERT {
+                        None
+                    } else {
+                        Some(preupdate_values(db, ffi::sqlite3_preupdate_old))
+                    },
+                    new: if action == Action::SQLITE_DELETE {
+                        None
+                    } else {
+                        Some(preupdate_values(db, ffi::sqlite3_preupdate_new))
+                    },
+                };
+                capture.events.push(hooks.next_seq(), event);
+            }));
+        }
+
+        // The capture is owned by the transaction hooks, which settle its
+        // events, and whose address is stable.
+        let enabled = capture.is_some();
+        *self.tx_hooks.changes.borrow_mut() = capture;
+        unsafe {
+            if enabled {
+                ffi::sqlite3_preupdate_hook(
+                    self.db(),
+                    Some(capture_change),
+                    &*self.tx_hooks as *const TxHooks as *mut _,
+                );
+            } else {
+                ffi::sqlite3_preupdate_hook(self.db(), None, ptr::null_mut());
+            }
+        }
+    }
+}
+
+unsafe fn preupdate_values(
+    db: *mut ffi::sqlite3,
+    value_at: unsafe extern "C" fn(*mut ffi::sqlite3, c_int, *mut *mut ffi::sqlite3_value) -> c_int,
+) -> Vec<Value> {
+    (0..ffi::sqlite3_preupdate_count(db))
+        .map(|i| {
+            let mut value = ptr::null_mut();
+            if value_at(db, i, &mut value) != ffi::SQLITE_OK || value.is_null() {
+                return Value::Null;
+            }
+            match ValueRef::from_value_checked(value) {
+                Ok(value) => value.into(),
+                // Converted like `Row::get_str_lossy` does.
+                Err(_) => {
+                    let text = ffi::sqlite3_value_text(value);
+                    let len = ffi::sqlite3_value_bytes(value) as usize;
+                    let bytes = slice::from_raw_parts(text, len);
+                    Value::Text(String::from_utf8_lossy(bytes).into_owned())
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::ChangeEvent;
+    use crate::types::Value;
+    use crate::{Action, Connection, NO_PARAMS};
+
+    #[test]
+    fn test_subscribe_changes() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER, t TEXT); CREATE TABLE bar(x INTEGER);")
+            .unwrap();
+        let changes = db.subscribe_changes(&["FOO"]);
+
+        db.execute("INSERT INTO foo VALUES(1, 'a')", NO_PARAMS)
+            .unwrap();
+        db.execute("INSERT INTO bar VALUES(1)", NO_PARAMS).unwrap();
+        assert_eq!(
+            vec![ChangeEvent {
+                action: Action::SQLITE_INSERT,
+                table: "foo".to_owned(),
+                rowid: 1,
+                old: None,
+                new: Some(vec![Value::Integer(1), Value::Text("a".to_owned())]),
+            }],
+            changes.try_iter().collect::<Vec<_>>()
+        );
+
+        db.execute_batch("BEGIN; UPDATE foo SET x = 2; DELETE FROM foo;")
+            .unwrap();
+        assert_eq!(0, changes.try_iter().count());
+        db.execute_batch("COMMIT").unwrap();
+        let events: Vec<_> = changes.try_iter().collect();
+        assert_eq!(2, events.len());
+        assert_eq!(Action::SQLITE_UPDATE, events[0].action);
+        assert_eq!(
+            Some(Value::Integer(1)),
+            events[0].old.as_ref().map(|v| v[0].clone())
+        );
+        assert_eq!(
+            Some(Value::Integer(2)),
+            events[0].new.as_ref().map(|v| v[0].clone())
+        );
+        assert_eq!(Action::SQLITE_DELETE, events[1].action);
+        assert_eq!(1, events[1].rowid);
+        assert_eq!(None, events[1].new);
+
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES(3, 'c'); ROLLBACK;")
+            .unwrap();
+        db.execute_batch("INSERT INTO foo VALUES(4, 'd')").unwrap();
+        let events: Vec<_> = changes.try_iter().collect();
+        assert_eq!(1, events.len());
+        assert_eq!(
+            Some(Value::Integer(4)),
+            events[0].new.as_ref().map(|v| v[0].clone())
+        );
+
+        db.unsubscribe_changes();
+        db.execute_batch("INSERT INTO foo VALUES(5, 'e')").unwrap();
+        assert!(changes.recv().is_err());
+    }
+
+    #[test]
+    fn test_subscribe_changes_rollback_to() {
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        let changes = db.subscribe_changes(&[]);
+
+        db.execute_batch(
+            "BEGIN; INSERT INTO foo VALUES(1);
+             SAVEPOINT a; INSERT INTO foo VALUES(2);
+             SAVEPOINT \"B\"; INSERT INTO foo VALUES(3);
+             /* undo 2 and 3 */ ROLLBACK TRANSACTION TO SAVEPOINT A;
+             INSERT INTO foo VALUES(4);
+             SAVEPOINT c; INSERT INTO foo VALUES(5); RELEASE c;
+             COMMIT;",
+        )
+        .unwrap();
+        let values: Vec<_> = changes
+            .try_iter()
+            .map(|event| event.new.unwrap()[0].clone())
+            .collect();
+        assert_eq!(
+            vec![Value::Integer(1), Value::Integer(4), Value::Integer(5)],
+            values
+        );
+
+        {
+            let mut sp = db.savepoint().unwrap();
+            sp.execute("INSERT INTO foo VALUES(6)", NO_PARAMS).unwrap();
+            {
+                let mut nested = sp.savepoint().unwrap();
+                nested
+                    .execute("INSERT INTO foo VALUES(7)", NO_PARAMS)
+                    .unwrap();
+                nested.rollback().unwrap();
+                nested
+                    .execute("INSERT INTO foo VALUES(8)", NO_PARAMS)
+                    .unwrap();
+                nested.commit().unwrap();
+            }
+            sp.commit().unwrap();
+        }
+        let values: Vec<_> = changes
+            .try_iter()
+            .map(|event| event.new.unwrap()[0].clone())
+            .collect();
+        assert_eq!(vec![Value::Integer(6), Value::Integer(8)], values);
+    }
+
+    #[test]
+    fn test_subscribe_changes_keeps_hooks() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER UNIQUE)")
+            .unwrap();
+        let commits = Arc::new(AtomicUsize::new(0));
+        let counter = commits.clone();
+        db.commit_hook(Some(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            false
+        }));
+        let changes = db.subscribe_changes(&[]);
+
+        db.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
+        assert_eq!(1, commits.load(Ordering::SeqCst));
+        assert_eq!(1, changes.try_iter().count());
+
+        // the changes of a failed statement are discarded
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES(2);")
+            .unwrap();
+        assert!(db.execute_batch("INSERT INTO foo VALUES(3), (1)").is_err());
+        db.execute_batch("COMMIT").unwrap();
+        let events: Vec<_> = changes.try_iter().collect();
+        assert_eq!(1, events.len());
+        assert_eq!(2, events[0].rowid);
+
+        db.unsubscribe_changes();
+        db.execute_batch("INSERT INTO foo VALUES(4)").unwrap();
+        assert_eq!(3, commits.load(Ordering::SeqCst));
+    }
+}
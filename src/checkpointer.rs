@@ -0,0 +1,206 @@
+//! Background WAL checkpoints
+//!
+//! By default, the connection which commits a transaction making the WAL
+//! file grow beyond 1000 pages runs a checkpoint, which stalls this commit.
+//! A `Checkpointer` runs the checkpoints on its own connection and thread
+//! instead, so that writer connections can disable automatic checkpoints:
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! # use rusqlite::checkpointer::{CheckpointPolicy, Checkpointer};
+//! fn open_writer() -> Result<(Connection, Checkpointer)> {
+//!     let conn = Connection::open("app.db")?;
+//!     conn.pragma_update(None, "journal_mode", &"WAL")?;
+//!     conn.set_wal_autocheckpoint(0)?;
+//!     let checkpointer = Checkpointer::spawn("app.db", CheckpointPolicy::default())?;
+//!     Ok((conn, checkpointer))
+//! }
+//! ```
+use std::ffi::OsString;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use crate::wal::wal_state;
+pub use crate::wal::{CheckpointMode, WalInfo};
+use crate::{Connection, DatabaseName, Error, ErrorCode, Result};
+
+/// When a `Checkpointer` runs checkpoints.
+///
+/// Every `interval`, if the WAL file has changed, a `Passive` checkpoint is
+/// run, which reports the number of frames of the WAL file.
+#[derive(Clone, Copy, Debug)]
+pub struct CheckpointPolicy {
+    /// Delay between two checks of the WAL file.
+    pub interval: Duration,
+    /// Run a `Truncate` checkpoint after the `Passive` one when the WAL file
+    /// holds at least this number of frames (pages), to bound its size.
+    pub truncate_frames: Option<u32>,
+    /// How long a `Truncate` checkpoint waits for other connections before
+    /// giving up until the next check.
+    pub busy_timeout: Duration,
+}
+
+impl Default for CheckpointPolicy {
+    /// Check every second, and run a `Truncate` checkpoint from 10000
+    /// frames.
+    fn default() -> CheckpointPolicy {
+        CheckpointPolicy {
+            interval: Duration::from_secs(1),
+            truncate_frames: Some(10_000),
+            busy_timeout: Duration::from_millis(100),
+        }
+    }
+}
+
+/// A background thread, with its own connection, running checkpoints on
+/// the WAL file of a database according to a `CheckpointPolicy`.
+///
+/// The WAL file is polled, because WAL hooks are only invoked on the
+/// connection committing the transaction. The thread is stopped when the
+/// `Checkpointer` is stopped or dropped.
+#[derive(Debug)]
+pub struct Checkpointer {
+    stop: Sender<()>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl Checkpointer {
+    /// Open a connection to the database at `path` and start checkpointing
+    /// it in a background thread.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the connection cannot be opened, or if the
+    /// database is not in WAL mode.
+    pub fn spawn<P: AsRef<Path>>(path: P, policy: CheckpointPolicy) -> Result<Checkpointer> {
+        let path = path.as_ref();
+        let conn = Connection::open(path)?;
+        let journal_mode: String =
+            conn.pragma_query_value(None, "journal_mode", |row| row.get(0))?;
+        if !journal_mode.eq_ignore_ascii_case("wal") {
+            return Err(Error::IncompatibleDatabaseSetting(format!(
+                "journal_mode is {} (expected wal)",
+                journal_mode
+            )));
+        }
+        conn.busy_timeout(policy.busy_timeout)?;
+        let mut wal_path = OsString::from(path);
+        wal_path.push("-wal");
+        let wal_path = PathBuf::from(wal_path);
+
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let mut last_seen: Option<(u64, SystemTime)> = None;
+            loop {
+                match stopped.recv_timeout(policy.interval) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return Ok(()),
+                }
+                let seen = wal_state(&wal_path);
+                if seen.is_none() || last_seen == seen {
+                    continue;
+                }
+                let r = conn
+                    .wal_checkpoint(Some(DatabaseName::Main), CheckpointMode::Passive)
+                    .and_then(|(frames, _)| match policy.truncate_frames {
+                        Some(n) if frames >= 0 && frames as u32 >= n => conn
+                            .wal_checkpoint(Some(DatabaseName::Main), CheckpointMode::Truncate)
+                            .map(|_| ()),
+                        _ => Ok(()),
+                    });
+                match r {
+                    Ok(()) => {}
+                    Err(Error::SqliteFailure(ref err, _))
+                        if err.code == ErrorCode::DatabaseBusy
+                            || err.code == ErrorCode::DatabaseLocked => {}
+                    Err(err) => return Err(err),
+                }
+                last_seen = wal_state(&wal_path);
+            }
+        });
+        Ok(Checkpointer {
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Stop the background thread and wait for it.
+    ///
+    /// # Failure
+    ///
+    /// Will return the error which stopped the background thread, if any
+    /// (busy databases are not errors: the checkpoint is retried at the next
+    /// check).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the background thread panicked.
+    pub fn stop(mut self) -> Result<()> {
+        let _ = self.stop.send(());
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(r)) => r,
+            Some(Err(panic)) => panic::resume_unwind(panic),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Checkpointer {
+    /// Stop the background thread and wait for it, ignoring its error or
+    /// panic, if any.
+    fn drop(&mut self) {
+        let _ = self.stop.send(());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tempdir::TempDir;
+
+    use super::{CheckpointPolicy, Checkpointer};
+    use crate::{Connection, Error};
+
+    #[test]
+    fn test_checkpointer() {
+        let temp_dir = TempDir::new("test_checkpointer").unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let wal_path = temp_dir.path().join("test.db3-wal");
+        let db = Connection::open(&path).unwrap();
+        match Checkpointer::spawn(&path, CheckpointPolicy::default()) {
+            Err(Error::IncompatibleDatabaseSetting(_)) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        db.pragma_update(None, "journal_mode", &"WAL").unwrap();
+        db.pragma_update(None, "wal_autocheckpoint", &0).unwrap();
+        let policy = CheckpointPolicy {
+            interval: Duration::from_millis(10),
+            truncate_frames: Some(5),
+            ..CheckpointPolicy::default()
+        };
+        let checkpointer = Checkpointer::spawn(&path, policy).unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(x BLOB);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 20)
+             INSERT INTO foo SELECT zeroblob(4096) FROM n;",
+        )
+        .unwrap();
+        assert!(fs::metadata(&wal_path).unwrap().len() > 0);
+
+        let start = Instant::now();
+        while fs::metadata(&wal_path).unwrap().len() > 0 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(10));
+        }
+        checkpointer.stop().unwrap();
+    }
+}
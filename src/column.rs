@@ -1,5 +1,6 @@
 use std::str;
 
+use crate::types::{FromSql, Params};
 use crate::{Error, Result, Row, Rows, Statement};
 
 /// Information about a column of a SQLite query.
@@ -21,11 +22,48 @@ impl Column<'_> {
     }
 }
 
+/// Owned information about the columns of a query result, available even
+/// if the query returns no row (see `Statement::query_with_columns`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Columns {
+    names: Vec<String>,
+    decl_types: Vec<Option<String>>,
+}
+
+impl Columns {
+    /// Return the number of columns.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Return `true` if there is no column (e.g. for a statement which does
+    /// not return data).
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Return the column names.
+    pub fn names(&self) -> Vec<&str> {
+        self.names.iter().map(String::as_str).collect()
+    }
+
+    /// Return the name of the column at `idx`, if any.
+    pub fn name(&self, idx: usize) -> Option<&str> {
+        self.names.get(idx).map(String::as_str)
+    }
+
+    /// Return the declared type of the column at `idx` (`None` for an
+    /// expression, or if there is no such column).
+    pub fn decl_type(&self, idx: usize) -> Option<&str> {
+        self.decl_types.get(idx).and_then(Option::as_deref)
+    }
+}
+
 impl Statement<'_> {
     /// Get all the column names in the result set of the prepared statement.
     pub fn column_names(&self) -> Vec<&str> {
         let n = self.column_count();
-        let mut cols = Vec::with_capacity(n as usize);
+        let mut cols = Vec::with_capacity(n);
         for i in 0..n {
             let slice = self.stmt.column_name(i);
             let s = str::from_utf8(slice.to_bytes()).unwrap();
@@ -63,7 +101,7 @@ impl Statement<'_> {
     /// Returns a slice describing the columns of the result of the query.
     pub fn columns<'stmt>(&'stmt self) -> Vec<Column<'stmt>> {
         let n = self.column_count();
-        let mut cols = Vec::with_capacity(n as usize);
+        let mut cols = Vec::with_capacity(n);
         for i in 0..n {
             let slice = self.stmt.column_name(i);
             let name = str::from_utf8(slice.to_bytes()).unwrap();
@@ -73,6 +111,52 @@ impl Statement<'_> {
         }
         cols
     }
+
+    /// Returns owned information about the columns of the result of the
+    /// query.
+    pub fn owned_columns(&self) -> Columns {
+        let columns = self.columns();
+        Columns {
+            names: columns.iter().map(|c| c.name.to_owned()).collect(),
+            decl_types: columns
+                .iter()
+                .map(|c| c.decl_type.map(str::to_owned))
+                .collect(),
+        }
+    }
+
+    /// Execute the prepared statement, returning the columns of the result
+    /// along with the resulting rows, so that the columns are known even if
+    /// no row matches (e.g. to write the header of a CSV export).
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, NO_PARAMS};
+    /// fn export_csv(conn: &Connection, out: &mut String) -> Result<()> {
+    ///     let mut stmt = conn.prepare("SELECT name, email FROM people")?;
+    ///     let (columns, mut rows) = stmt.query_with_columns(NO_PARAMS)?;
+    ///     out.push_str(&columns.names().join(","));
+    ///     out.push('\n');
+    ///     while let Some(row) = rows.next()? {
+    ///         let name: String = row.get(0)?;
+    ///         let email: String = row.get(1)?;
+    ///         out.push_str(&format!("{},{}\n", name, email));
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails.
+    pub fn query_with_columns<P>(&mut self, params: P) -> Result<(Columns, Rows<'_>)>
+    where
+        P: Params,
+    {
+        let columns = self.owned_columns();
+        self.query(params).map(|rows| (columns, rows))
+    }
 }
 
 impl<'stmt> Rows<'stmt> {
@@ -104,10 +188,78 @@ impl<'stmt> Row<'stmt> {
     }
 }
 
+/// Column-major ("struct of arrays") collection of query results, filled
+/// one row at a time by `Statement::query_columns`.
+///
+/// Implemented for tuples of up to 8 `Vec<T>` where `T: FromSql`: the `n`-th
+/// `Vec` receives the values of the `n`-th column.
+pub trait FromColumns: Default {
+    /// Append the values of `row` to the columns.
+    fn push_row(&mut self, row: &Row<'_>) -> Result<()>;
+}
+
+macro_rules! from_columns_tuple {
+    ($($t:ident: $idx:tt),+) => {
+        impl<$($t: FromSql),+> FromColumns for ($(Vec<$t>,)+) {
+            fn push_row(&mut self, row: &Row<'_>) -> Result<()> {
+                $(self.$idx.push(row.get($idx)?);)+
+                Ok(())
+            }
+        }
+    };
+}
+
+from_columns_tuple!(A: 0);
+from_columns_tuple!(A: 0, B: 1);
+from_columns_tuple!(A: 0, B: 1, C: 2);
+from_columns_tuple!(A: 0, B: 1, C: 2, D: 3);
+from_columns_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4);
+from_columns_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+from_columns_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+from_columns_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+
+impl Statement<'_> {
+    /// Execute the prepared statement and collect the results column by
+    /// column.
+    ///
+    /// This avoids building one value per row, which is faster for
+    /// analytics-style processing of large results, or to hand the columns
+    /// to a columnar library (see also `query_polars`, with the `polars`
+    /// feature). The values keep their requested type: integers are not
+    /// widened to `f64`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, NO_PARAMS};
+    /// fn ages(conn: &Connection) -> Result<(Vec<String>, Vec<i64>)> {
+    ///     let mut stmt = conn.prepare("SELECT name, age FROM people")?;
+    ///     stmt.query_columns(NO_PARAMS)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails, if a value cannot be
+    /// converted to the requested type or if the underlying SQLite call fails.
+    pub fn query_columns<C, P>(&mut self, params: P) -> Result<C>
+    where
+        C: FromColumns,
+        P: Params,
+    {
+        let mut columns = C::default();
+        let mut rows = self.query(params)?;
+        while let Some(row) = rows.next()? {
+            columns.push_row(row)?;
+        }
+        Ok(columns)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::Column;
-    use crate::Connection;
+    use crate::{Connection, Error, NO_PARAMS};
 
     #[test]
     fn test_columns() {
@@ -125,4 +277,45 @@ mod test {
             &[Some("text"), Some("text"), Some("text"),]
         );
     }
+
+    #[test]
+    fn test_query_with_columns() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER, y TEXT);")
+            .unwrap();
+        let mut stmt = db.prepare("SELECT x, y, x + 1 AS z FROM foo").unwrap();
+        let (columns, mut rows) = stmt.query_with_columns(NO_PARAMS).unwrap();
+        assert!(rows.next().unwrap().is_none());
+        assert_eq!(3, columns.len());
+        assert_eq!(vec!["x", "y", "z"], columns.names());
+        assert_eq!(Some("y"), columns.name(1));
+        assert_eq!(None, columns.name(3));
+        assert_eq!(Some("INTEGER"), columns.decl_type(0));
+        assert_eq!(Some("TEXT"), columns.decl_type(1));
+        assert_eq!(None, columns.decl_type(2));
+
+        let stmt = db.prepare("INSERT INTO foo VALUES(1, 'a')").unwrap();
+        assert!(stmt.owned_columns().is_empty());
+    }
+
+    #[test]
+    fn test_query_columns() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER, y TEXT);
+             INSERT INTO foo VALUES(1, 'a');
+             INSERT INTO foo VALUES(2, NULL);",
+        )
+        .unwrap();
+        let mut stmt = db.prepare("SELECT x, y FROM foo ORDER BY x").unwrap();
+        let (x, y): (Vec<i64>, Vec<Option<String>>) = stmt.query_columns(NO_PARAMS).unwrap();
+        assert_eq!(vec![1, 2], x);
+        assert_eq!(vec![Some("a".to_owned()), None], y);
+
+        let r: crate::Result<(Vec<i64>, Vec<String>)> = stmt.query_columns(NO_PARAMS);
+        match r.unwrap_err() {
+            Error::InvalidColumnType(1, _) => (),
+            err => panic!("Unexpected error {}", err),
+        }
+    }
 }
@@ -0,0 +1,340 @@
+//! Read-your-writes consistency across the connections of a pool.
+use std::cmp::Ordering;
+use std::fmt;
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::ffi;
+use crate::{Connection, DatabaseName, Error, ErrorCode, Result, NO_PARAMS};
+
+/// Identifies the state of a WAL database after a commit: the WAL snapshot
+/// seen by a read transaction started then (see
+/// [`sqlite3_snapshot_get`](https://sqlite.org/c3ref/snapshot_get.html)),
+/// or none if the WAL was empty.
+///
+/// Tokens of the same database are ordered like the commits they follow,
+/// as long as the WAL is not reset in between by a `RESTART` or `TRUNCATE`
+/// checkpoint: comparing tokens from before and after a reset is
+/// meaningless. `CommitTracker::wait_for` detects resets itself.
+#[derive(Clone, Copy)]
+pub struct CommitToken(Option<ffi::sqlite3_snapshot>);
+
+impl CommitToken {
+    /// Return the snapshot of the main database of `conn`, which must not be
+    /// in a transaction.
+    fn current(conn: &Connection) -> Result<CommitToken> {
+        if !is_wal(conn)? {
+            return Err(Error::IncompatibleDatabaseSetting(
+                "database main is not in WAL mode".to_owned(),
+            ));
+        }
+        let schema = DatabaseName::Main.to_cstring()?;
+        // Start a read transaction, so that the snapshot is the latest one
+        conn.execute_batch("BEGIN")?;
+        let mut snapshot: *mut ffi::sqlite3_snapshot = ptr::null_mut();
+        let db = conn.db.borrow().db();
+        let r = conn.decode_result(unsafe {
+            ffi::sqlite3_snapshot_get(db, schema.as_ptr(), &mut snapshot)
+        });
+        let token = r.map(|_| unsafe {
+            let token = CommitToken(Some(*snapshot));
+            ffi::sqlite3_snapshot_free(snapshot);
+            token
+        });
+        conn.execute_batch("COMMIT")?;
+        match token {
+            // There is no snapshot of an empty WAL (e.g. right after a
+            // `TRUNCATE` checkpoint): the whole state is in the database file
+            Err(Error::SqliteFailure(ref err, _)) if err.code == ErrorCode::Unknown => {
+                Ok(CommitToken(None))
+            }
+            token => token,
+        }
+    }
+
+    /// Whether a read transaction started now by `conn`, which must not be
+    /// in a transaction, sees this state (or a later one).
+    fn is_visible(&self, conn: &Connection) -> Result<bool> {
+        // `sqlite3_snapshot_open` may only read the snapshot
+        let mut snapshot = match self.0 {
+            Some(snapshot) => snapshot,
+            None => return Ok(true),
+        };
+        // Also opens the WAL if `conn` has not read the database yet
+        let current = CommitToken::current(conn)?;
+        let schema = DatabaseName::Main.to_cstring()?;
+        conn.execute_batch("BEGIN")?;
+        let db = conn.db.borrow().db();
+        let rc = unsafe { ffi::sqlite3_snapshot_open(db, schema.as_ptr(), &mut snapshot) };
+        let r = conn.decode_result(rc);
+        conn.execute_batch("COMMIT")?;
+        if rc == ffi::SQLITE_ERROR_SNAPSHOT {
+            // The WAL has been reset, or checkpointed past the snapshot, since
+            // this state, so the database file includes it
+            return Ok(true);
+        }
+        r?;
+        // Both snapshots are in the same WAL, unless it was reset in between:
+        // this state was then committed after the reset and is visible, so
+        // a wrong `true` is harmless and a wrong `false` is polled again
+        Ok(current >= *self)
+    }
+}
+
+fn is_wal(conn: &Connection) -> Result<bool> {
+    let mode: String = conn.query_row("PRAGMA journal_mode", NO_PARAMS, |r| r.get(0))?;
+    Ok(mode.eq_ignore_ascii_case("wal"))
+}
+
+impl PartialEq for CommitToken {
+    fn eq(&self, other: &CommitToken) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for CommitToken {}
+
+impl PartialOrd for CommitToken {
+    fn partial_cmp(&self, other: &CommitToken) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CommitToken {
+    fn cmp(&self, other: &CommitToken) -> Ordering {
+        match (self.0, other.0) {
+            // `sqlite3_snapshot_cmp` only reads the snapshots
+            (Some(mut p1), Some(mut p2)) => {
+                unsafe { ffi::sqlite3_snapshot_cmp(&mut p1, &mut p2) }.cmp(&0)
+            }
+            (p1, p2) => p1.is_some().cmp(&p2.is_some()),
+        }
+    }
+}
+
+impl fmt::Debug for CommitToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CommitToken")
+            .field(&self.0.as_ref().map(|snapshot| &snapshot.hidden[..]))
+            .finish()
+    }
+}
+
+/// Lets a reader connection of a pool wait until a write of the writer
+/// connection is visible before serving a query, e.g. in the request which
+/// made the write.
+///
+/// `record_commit` returns the WAL snapshot of the writer once its writes
+/// are committed, and `wait_for` polls the snapshot of the reader until it
+/// is at least as recent, or until the WAL no longer contains the recorded
+/// snapshot because a checkpoint moved it to the database file. The database
+/// must be in WAL mode.
+///
+/// When writes are delegated to a writer thread, or grouped by
+/// `Connection::set_auto_batch`, the token must be recorded once they are
+/// committed (`record_commit` commits the pending batch itself).
+///
+/// ```rust,no_run
+/// # use rusqlite::{CommitTracker, Connection, Result};
+/// # use std::time::Duration;
+/// fn update_then_read(
+///     tracker: &CommitTracker,
+///     writer: &Connection,
+///     reader: &Connection,
+/// ) -> Result<String> {
+///     writer.execute("UPDATE users SET name = 'bob' WHERE id = 1", rusqlite::NO_PARAMS)?;
+///     let token = tracker.record_commit(writer)?;
+///     // ... later, possibly on another thread:
+///     tracker.wait_for(reader, token, Duration::from_secs(1))?;
+///     reader.query_row("SELECT name FROM users WHERE id = 1", rusqlite::NO_PARAMS, |r| r.get(0))
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CommitTracker {
+    poll_interval: Duration,
+}
+
+impl Default for CommitTracker {
+    fn default() -> CommitTracker {
+        CommitTracker {
+            poll_interval: Duration::from_millis(1),
+        }
+    }
+}
+
+impl CommitTracker {
+    /// Create a tracker polling readers every millisecond.
+    pub fn new() -> CommitTracker {
+        CommitTracker::default()
+    }
+
+    /// Set how long `wait_for` sleeps between two polls of the reader.
+    pub fn set_poll_interval(&mut self, poll_interval: Duration) {
+        self.poll_interval = poll_interval;
+    }
+
+    /// Commit the pending writes of `writer` (see `Connection::flush`), and
+    /// return the token of the database state which includes them.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with `Error::IncompatibleDatabaseSetting` if the
+    /// database is not in WAL mode, or `Err` if `writer` is in an explicit
+    /// transaction or committing its pending batch fails.
+    pub fn record_commit(&self, writer: &Connection) -> Result<CommitToken> {
+        writer.flush()?;
+        if !writer.is_autocommit() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some("the writer has an uncommitted transaction".to_owned()),
+            ));
+        }
+        CommitToken::current(writer)
+    }
+
+    /// Wait until a read transaction started by `reader` sees the database
+    /// state `token` (or a later one), so that its next queries see the
+    /// writes before `token`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::CommitWaitTimeout)` if `reader` does not see
+    /// the state within `timeout`, or with `SQLITE_MISUSE` if `reader` is in
+    /// a transaction (which may predate the write).
+    pub fn wait_for(
+        &self,
+        reader: &Connection,
+        token: CommitToken,
+        timeout: Duration,
+    ) -> Result<()> {
+        if !reader.is_autocommit() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some("the reader is in a transaction".to_owned()),
+            ));
+        }
+        // No deadline if it is too far away to be represented
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            if token.is_visible(reader)? {
+                return Ok(());
+            }
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if now >= deadline => {
+                    return Err(Error::CommitWaitTimeout(token));
+                }
+                Some(deadline) => thread::sleep(self.poll_interval.min(deadline - now)),
+                None => thread::sleep(self.poll_interval),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::CommitTracker;
+    use crate::{Connection, Error, ErrorCode, NO_PARAMS};
+
+    #[test]
+    fn test_read_your_writes() {
+        let temp_dir = tempdir::TempDir::new("test_read_your_writes").unwrap();
+        let path = temp_dir.path().join("test.db");
+        let writer = Connection::open(&path).unwrap();
+        writer
+            .execute_batch("PRAGMA journal_mode = WAL; CREATE TABLE foo(x INTEGER);")
+            .unwrap();
+        let reader = Connection::open(&path).unwrap();
+        let tracker = CommitTracker::new();
+
+        writer
+            .set_auto_batch(100, Duration::from_secs(3600))
+            .unwrap();
+        writer
+            .execute("INSERT INTO foo VALUES(1)", NO_PARAMS)
+            .unwrap();
+        let first = tracker.record_commit(&writer).unwrap();
+        tracker
+            .wait_for(&reader, first, Duration::from_secs(0))
+            .unwrap();
+        let count: i64 = reader
+            .query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(1, count);
+
+        writer
+            .execute("INSERT INTO foo VALUES(2)", NO_PARAMS)
+            .unwrap();
+        let second = tracker.record_commit(&writer).unwrap();
+        assert!(first < second);
+        assert_eq!(second, tracker.record_commit(&writer).unwrap());
+        tracker
+            .wait_for(&reader, second, Duration::from_secs(u64::MAX))
+            .unwrap();
+
+        writer.execute_batch("BEGIN").unwrap();
+        assert!(tracker.record_commit(&writer).is_err());
+        writer.execute_batch("COMMIT").unwrap();
+        reader.execute_batch("BEGIN").unwrap();
+        assert!(tracker
+            .wait_for(&reader, first, Duration::from_secs(0))
+            .is_err());
+    }
+
+    #[test]
+    fn test_wal_reset() {
+        let temp_dir = tempdir::TempDir::new("test_wal_reset").unwrap();
+        let path = temp_dir.path().join("test.db");
+        let writer = Connection::open(&path).unwrap();
+        writer
+            .execute_batch("PRAGMA journal_mode = WAL; CREATE TABLE foo(x INTEGER);")
+            .unwrap();
+        let reader = Connection::open(&path).unwrap();
+        let tracker = CommitTracker::new();
+
+        writer
+            .execute("INSERT INTO foo VALUES(1)", NO_PARAMS)
+            .unwrap();
+        let before = tracker.record_commit(&writer).unwrap();
+        writer
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE)")
+            .unwrap();
+        drop(writer);
+        // The WAL is empty
+        let writer = Connection::open(&path).unwrap();
+        let truncated = tracker.record_commit(&writer).unwrap();
+        assert_eq!("CommitToken(None)", format!("{:?}", truncated));
+        tracker
+            .wait_for(&reader, truncated, Duration::from_secs(0))
+            .unwrap();
+
+        writer
+            .execute("INSERT INTO foo VALUES(2)", NO_PARAMS)
+            .unwrap();
+        let after = tracker.record_commit(&writer).unwrap();
+        assert!(truncated < after);
+        tracker
+            .wait_for(&reader, before, Duration::from_secs(0))
+            .unwrap();
+        tracker
+            .wait_for(&reader, after, Duration::from_secs(0))
+            .unwrap();
+        let count: i64 = reader
+            .query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn test_record_commit_not_wal() {
+        let db = Connection::open_in_memory().unwrap();
+        match CommitTracker::new().record_commit(&db) {
+            Err(Error::IncompatibleDatabaseSetting(_)) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert!(db.is_autocommit());
+    }
+}
@@ -16,6 +16,9 @@ pub(crate) unsafe fn set_result(ctx: *mut sqlite3_context, result: &ToSqlOutput<
     let value = match *result {
         ToSqlOutput::Borrowed(v) => v,
         ToSqlOutput::Owned(ref v) => ValueRef::from(v),
+        // The result must outlive the borrowed BLOB, so it is copied.
+        #[cfg(feature = "borrowed_blob")]
+        ToSqlOutput::BorrowedBlob(b) => ValueRef::Blob(b.as_bytes()),
 
         #[cfg(feature = "blob")]
         ToSqlOutput::ZeroBlob(len) => {
@@ -0,0 +1,221 @@
+//! Export of query results to a Polars `DataFrame`.
+use polars::prelude::{Column, DataFrame, DataType, NamedFrom, Series};
+
+use crate::types::{Params, Type, Value};
+use crate::{Error, Result, Statement};
+
+impl Statement<'_> {
+    /// Execute the prepared statement and collect the results into a
+    /// [Polars](https://docs.rs/polars/) `DataFrame`.
+    ///
+    /// The values are collected column by column, and the type of each
+    /// column is chosen from the values it holds: `Int64` for integers,
+    /// `Float64` for reals (and integers mixed with reals), `String` for
+    /// text and `Binary` for BLOBs, with `NULL` values as nulls. A column
+    /// holding only `NULL` values (or of an empty result) gets the type of
+    /// its declared type affinity, or `Null` for an expression.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, NO_PARAMS};
+    /// # use polars::prelude::DataFrame;
+    /// fn people(conn: &Connection) -> Result<DataFrame> {
+    ///     let mut stmt = conn.prepare("SELECT name, age FROM people")?;
+    ///     stmt.query_polars(NO_PARAMS)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::InvalidColumnType)` if a column holds both
+    /// text (or BLOBs) and another type of values,
+    /// `Err(Error::InvalidColumnName)` if two columns have the same name
+    /// (use `AS` to rename them), or `Err` if binding parameters fails or
+    /// if the underlying SQLite call fails.
+    pub fn query_polars<P>(&mut self, params: P) -> Result<DataFrame>
+    where
+        P: Params,
+    {
+        let columns = self.owned_columns();
+        let names = columns.names();
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                return Err(Error::InvalidColumnName((*name).to_owned()));
+            }
+        }
+
+        let mut values: Vec<Vec<Value>> = vec![Vec::new(); columns.len()];
+        let mut height = 0;
+        let mut rows = self.query(params)?;
+        while let Some(row) = rows.next()? {
+            for (i, column) in values.iter_mut().enumerate() {
+                column.push(row.get_raw_checked(i)?.into());
+            }
+            height += 1;
+        }
+
+        let mut series = Vec::with_capacity(values.len());
+        for (i, column) in values.into_iter().enumerate() {
+            let data_type = column_type(i, &column)?
+                .or_else(|| affinity(columns.decl_type(i)))
+                .unwrap_or(Type::Null);
+            series.push(Column::from(to_series(names[i], data_type, column)));
+        }
+        // The names are unique, and all the columns have `height` values.
+        Ok(DataFrame::new_with_height(height, series)
+            .expect("columns with unique names and the same length"))
+    }
+}
+
+/// Return the type of the values of a column (`None` if they are all
+/// `NULL`), with integers widened to reals when both are present.
+fn column_type(idx: usize, column: &[Value]) -> Result<Option<Type>> {
+    let mut column_type = None;
+    for value in column {
+        column_type = match (column_type, value.data_type()) {
+            (t, Type::Null) => t,
+            (None, t) => Some(t),
+            (Some(Type::Integer), Type::Real) | (Some(Type::Real), Type::Integer) => {
+                Some(Type::Real)
+            }
+            (Some(t1), t2) if t1 == t2 => Some(t1),
+            (Some(_), t) => return Err(Error::InvalidColumnType(idx, t)),
+        };
+    }
+    Ok(column_type)
+}
+
+/// Return the type matching the affinity of a declared column type (see
+/// [Determination Of Column Affinity](https://sqlite.org/datatype3.html#determination_of_column_affinity)),
+/// or `None` for the `NUMERIC` affinity.
+fn affinity(decl_type: Option<&str>) -> Option<Type> {
+    let decl_type = decl_type?.to_ascii_uppercase();
+    if decl_type.contains("INT") {
+        Some(Type::Integer)
+    } else if ["CHAR", "CLOB", "TEXT"]
+        .iter()
+        .any(|t| decl_type.contains(t))
+    {
+        Some(Type::Text)
+    } else if decl_type.is_empty() || decl_type.contains("BLOB") {
+        Some(Type::Blob)
+    } else if ["REAL", "FLOA", "DOUB"]
+        .iter()
+        .any(|t| decl_type.contains(t))
+    {
+        Some(Type::Real)
+    } else {
+        None
+    }
+}
+
+fn to_series(name: &str, data_type: Type, column: Vec<Value>) -> Series {
+    let name = name.into();
+    match data_type {
+        Type::Null => Series::full_null(name, column.len(), &DataType::Null),
+        Type::Integer => {
+            let values: Vec<Option<i64>> = column
+                .into_iter()
+                .map(|value| match value {
+                    Value::Integer(i) => Some(i),
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, values)
+        }
+        Type::Real => {
+            let values: Vec<Option<f64>> = column
+                .into_iter()
+                .map(|value| match value {
+                    Value::Integer(i) => Some(i as f64),
+                    Value::Real(f) => Some(f),
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, values)
+        }
+        Type::Text => {
+            let values: Vec<Option<String>> = column
+                .into_iter()
+                .map(|value| match value {
+                    Value::Text(s) => Some(s),
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, values)
+        }
+        Type::Blob => {
+            let values: Vec<Option<Vec<u8>>> = column
+                .into_iter()
+                .map(|value| match value {
+                    Value::Blob(b) => Some(b),
+                    _ => None,
+                })
+                .collect();
+            Series::new(name, values)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use polars::prelude::DataType;
+
+    use crate::{Connection, Error, NO_PARAMS};
+
+    #[test]
+    fn test_query_polars() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE people (name TEXT, age INTEGER, score REAL, photo BLOB);
+             INSERT INTO people VALUES ('Alice', 30, 1, x'00');
+             INSERT INTO people VALUES ('Bob', NULL, 2.5, NULL);",
+        )
+        .unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT name, age, score, photo, NULL AS missing FROM people")
+            .unwrap();
+        let df = stmt.query_polars(NO_PARAMS).unwrap();
+        assert_eq!((2, 5), df.shape());
+        let dtypes: Vec<DataType> = df.dtypes();
+        assert_eq!(
+            vec![
+                DataType::String,
+                DataType::Int64,
+                DataType::Float64,
+                DataType::Binary,
+                DataType::Null
+            ],
+            dtypes
+        );
+        let names = df.column("name").unwrap().str().unwrap();
+        assert_eq!(Some("Bob"), names.get(1));
+        let ages = df.column("age").unwrap().i64().unwrap();
+        assert_eq!(vec![Some(30), None], ages.into_iter().collect::<Vec<_>>());
+        let scores = df.column("score").unwrap().f64().unwrap();
+        assert_eq!(Some(1.0), scores.get(0));
+
+        // The declared types give the types of the columns of an empty result.
+        let mut stmt = db.prepare("SELECT * FROM people WHERE 0").unwrap();
+        let df = stmt.query_polars(NO_PARAMS).unwrap();
+        assert_eq!((0, 4), df.shape());
+        assert_eq!(DataType::Int64, *df.column("age").unwrap().dtype());
+    }
+
+    #[test]
+    fn test_query_polars_errors() {
+        let db = Connection::open_in_memory().unwrap();
+        let mut stmt = db.prepare("SELECT 1 UNION ALL SELECT 'a'").unwrap();
+        match stmt.query_polars(NO_PARAMS).unwrap_err() {
+            Error::InvalidColumnType(0, _) => {}
+            err => panic!("Unexpected error {}", err),
+        }
+        let mut stmt = db.prepare("SELECT 1 AS x, 2 AS x").unwrap();
+        assert_eq!(
+            Error::InvalidColumnName("x".to_owned()),
+            stmt.query_polars(NO_PARAMS).unwrap_err()
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Per-connection decoders of columns by declared type.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::raw_statement::RawStatement;
+use crate::row::column_error;
+use crate::types::{FromSqlResult, Value, ValueRef};
+use crate::{Connection, Result, Statement};
+
+type Decoder = dyn Fn(ValueRef<'_>) -> FromSqlResult<Value> + Send + Sync;
+
+/// The decoders registered on a connection, by upper-cased declared type.
+pub(crate) type ColumnDecoders = HashMap<String, Arc<Decoder>>;
+
+/// The decoders of the columns of a statement, by column index.
+pub(crate) type StatementDecoders = Vec<Option<Arc<Decoder>>>;
+
+impl Connection {
+    /// Register a decoder for the columns declared with the type `decl_type`
+    /// (compared ignoring ASCII case, e.g. `DATETIME` or `VARCHAR(10)`).
+    ///
+    /// The values of these columns are then converted with `decoder` as rows
+    /// are fetched, before `Row::get`, `Row::get_raw` or `Row::to_owned` see
+    /// them, e.g. to normalize dates stored in several formats by another
+    /// tool. A decoding error is returned by the fetch of the row. Values of
+    /// expressions, which have no declared type, are not decoded. A decoder
+    /// replaces the previous one registered for the same type.
+    ///
+    /// Decoders are looked up when a statement is prepared (or taken from
+    /// the statement cache): statements prepared before the registration are
+    /// not affected.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use rusqlite::types::{Value, ValueRef};
+    /// fn unix_dates(conn: &Connection) {
+    ///     conn.register_column_decoder("DATETIME", |value| match value {
+    ///         ValueRef::Integer(i) => Ok(Value::Text(format!("@{}", i))),
+    ///         v => Ok(v.into()),
+    ///     });
+    /// }
+    /// ```
+    pub fn register_column_decoder<F>(&self, decl_type: &str, decoder: F)
+    where
+        F: Fn(ValueRef<'_>) -> FromSqlResult<Value> + Send + Sync + 'static,
+    {
+        self.db
+            .borrow_mut()
+            .decoders
+            .insert(decl_type.to_ascii_uppercase(), Arc::new(decoder));
+    }
+
+    /// Remove the decoder registered for `decl_type`, and return `false` if
+    /// there was none.
+    pub fn remove_column_decoder(&self, decl_type: &str) -> bool {
+        self.db
+            .borrow_mut()
+            .decoders
+            .remove(&decl_type.to_ascii_uppercase())
+            .is_some()
+    }
+}
+
+/// Return the decoder of each column of `stmt`, or an empty `Vec` if no
+/// column has one.
+pub(crate) fn resolve(decoders: &ColumnDecoders, stmt: &RawStatement) -> StatementDecoders {
+    if decoders.is_empty() {
+        return Vec::new();
+    }
+    let resolved: StatementDecoders = (0..stmt.column_count())
+        .map(|idx| {
+            let decl_type = stmt.column_decltype(idx)?.to_str().ok()?;
+            decoders.get(&decl_type.to_ascii_uppercase()).cloned()
+        })
+        .collect();
+    if resolved.iter().all(Option::is_none) {
+        return Vec::new();
+    }
+    resolved
+}
+
+impl Statement<'_> {
+    /// Decode the values of the current row which have a decoder, the other
+    /// values being `None`.
+    pub(crate) fn decode_row(&self) -> Result<Vec<Option<Value>>> {
+        self.decoders
+            .iter()
+            .enumerate()
+            .map(|(idx, decoder)| match *decoder {
+                Some(ref decoder) => {
+                    let value = self.value_ref(idx)?;
+                    decoder(value)
+                        .map(Some)
+                        .map_err(|err| column_error(idx, value.data_type(), err))
+                }
+                None => Ok(None),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::{FromSqlError, Value, ValueRef};
+    use crate::{Connection, Error, NO_PARAMS};
+
+    #[test]
+    fn test_column_decoder() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(d DateTime, t TEXT);
+             INSERT INTO foo VALUES(86400, 'x');
+             INSERT INTO foo VALUES('bad', 'y');",
+        )
+        .unwrap();
+        db.register_column_decoder("DATETIME", |value| match value {
+            ValueRef::Integer(i) => Ok(Value::Text(format!("day {}", i / 86400))),
+            _ => Err(FromSqlError::InvalidType),
+        });
+
+        let (d, t): (String, String) = db
+            .query_row("SELECT d, t FROM foo WHERE rowid = 1", NO_PARAMS, |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(("day 1".to_owned(), "x".to_owned()), (d, t));
+        // Raw and owned values are decoded too
+        let mut stmt = db.prepare("SELECT d FROM foo WHERE rowid = 1").unwrap();
+        {
+            let mut rows = stmt.query(NO_PARAMS).unwrap();
+            let row = rows.next().unwrap().unwrap();
+            assert_eq!(ValueRef::Text("day 1"), row.get_raw(0));
+            assert_eq!(&[Value::Text("day 1".to_owned())], row.to_owned().values());
+        }
+        // Expressions are not decoded
+        let d: i64 = db
+            .query_row("SELECT d + 0 FROM foo WHERE rowid = 1", NO_PARAMS, |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(86400, d);
+        match db.query_row("SELECT d FROM foo WHERE rowid = 2", NO_PARAMS, |r| {
+            r.get::<_, String>(0)
+        }) {
+            Err(Error::InvalidColumnType(0, _)) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        assert!(db.remove_column_decoder("datetime"));
+        assert!(!db.remove_column_decoder("datetime"));
+        // Statements prepared before keep their decoders
+        let d: String = stmt.query_row(NO_PARAMS, |r| r.get(0)).unwrap();
+        assert_eq!("day 1", d);
+        let d: i64 = db
+            .query_row("SELECT d FROM foo WHERE rowid = 1", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(86400, d);
+    }
+}
@@ -0,0 +1,216 @@
+//! SQL normalization and digests
+//!
+//! Normalized SQL has its comments removed, its literals and parameters
+//! replaced by `?`, its keywords and unquoted identifiers upper-cased and its
+//! whitespace canonicalized, so that queries which only differ by their
+//! values or formatting share the same normalized form and digest.
+//!
+//! The normalization is implemented in Rust (`sqlite3_normalized_sql`
+//! requires a SQLite compiled with `SQLITE_ENABLE_NORMALIZE`) and does not
+//! depend on the SQLite version, so digests are stable and can be compared
+//! across processes.
+
+use crate::Statement;
+
+/// Normalize `sql`.
+///
+/// ```rust
+/// # use rusqlite::digest::normalize_sql;
+/// assert_eq!(
+///     "SELECT * FROM FOO WHERE ID = ? AND NAME IN(?, ?)",
+///     normalize_sql("select *\n  from foo -- comment\n where id=42 and name in ('a', :b);")
+/// );
+/// ```
+pub fn normalize_sql(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let chars: Vec<char> = sql.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).cloned();
+        let start = i;
+        let token: Token = if c.is_whitespace() {
+            i += 1;
+            continue;
+        } else if c == '-' && next == Some('-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        } else if c == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+            continue;
+        } else if c == '\'' || ((c == 'x' || c == 'X') && next == Some('\'')) {
+            // String or blob literal
+            if c != '\'' {
+                i += 1;
+            }
+            i = skip_quoted(&chars, i, '\'');
+            Token::Value
+        } else if c.is_ascii_digit() || (c == '.' && next.into_iter().any(|n| n.is_ascii_digit())) {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                let e = chars[i] == 'e' || chars[i] == 'E';
+                i += 1;
+                if e && i < chars.len() && (chars[i] == '+' || chars[i] == '-') {
+                    i += 1;
+                }
+            }
+            Token::Value
+        } else if c == '?' {
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            Token::Value
+        } else if (c == ':' || c == '@' || c == '$') && next.into_iter().any(is_identifier_char) {
+            i += 1;
+            while i < chars.len() && is_identifier_char(chars[i]) {
+                i += 1;
+            }
+            Token::Value
+        } else if c == '"' || c == '`' {
+            i = skip_quoted(&chars, i, c);
+            Token::Word
+        } else if c == '[' {
+            while i < chars.len() && chars[i] != ']' {
+                i += 1;
+            }
+            i += 1;
+            Token::Word
+        } else if is_identifier_char(c) {
+            while i < chars.len() && is_identifier_char(chars[i]) {
+                i += 1;
+            }
+            Token::Word
+        } else {
+            i += 1;
+            if let Some(n) = next {
+                match (c, n) {
+                    ('<', '=')
+                    | ('>', '=')
+                    | ('!', '=')
+                    | ('=', '=')
+                    | ('<', '>')
+                    | ('<', '<')
+                    | ('>', '>')
+                    | ('|', '|') => i += 1,
+                    _ => {}
+                }
+            }
+            Token::Punct(c)
+        };
+        let end = i.min(chars.len());
+
+        if let Token::Punct(';') = token {
+            continue;
+        }
+        if let Some(last) = out.chars().last() {
+            let glued = match token {
+                Token::Punct('(') | Token::Punct(')') | Token::Punct(',') | Token::Punct('.') => {
+                    true
+                }
+                _ => last == '(' || last == '.',
+            };
+            if !glued {
+                out.push(' ');
+            }
+        }
+        match token {
+            Token::Value => out.push('?'),
+            Token::Word if c == '"' || c == '`' || c == '[' => out.extend(&chars[start..end]),
+            Token::Word => out.extend(chars[start..end].iter().map(char::to_ascii_uppercase)),
+            Token::Punct(_) => out.extend(&chars[start..end]),
+        }
+    }
+    out
+}
+
+/// Return a stable 64-bit digest (FNV-1a) of the normalized form of `sql`.
+pub fn sql_digest(sql: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in normalize_sql(sql).bytes() {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+impl Statement<'_> {
+    /// Return the normalized SQL text of this statement (see `normalize_sql`).
+    pub fn normalized_sql(&self) -> String {
+        normalize_sql(&self.stmt.sql().to_string_lossy())
+    }
+
+    /// Return the digest of the SQL text of this statement (see
+    /// `sql_digest`).
+    pub fn digest(&self) -> u64 {
+        sql_digest(&self.stmt.sql().to_string_lossy())
+    }
+}
+
+enum Token {
+    Value,
+    Word,
+    Punct(char),
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$' || !c.is_ascii()
+}
+
+/// Return the index following the closing quote of the quoted token starting
+/// at `start` (doubled quotes are escapes).
+fn skip_quoted(chars: &[char], start: usize, quote: char) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() {
+        if chars[i] == quote {
+            if chars.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod test {
+    use super::{normalize_sql, sql_digest};
+    use crate::Connection;
+
+    #[test]
+    fn test_normalize_sql() {
+        assert_eq!(
+            "SELECT COUNT(*) FROM \"my table\" WHERE A <= ? AND B = ?",
+            normalize_sql("select  count( * ) from \"my table\" where a<=1.5e-3 and b = 'it''s'")
+        );
+        assert_eq!(
+            "INSERT INTO T VALUES(?, ?, ?)",
+            normalize_sql("/* c */ INSERT INTO t VALUES (x'00ff', ?1, @p);")
+        );
+        assert_eq!("SELECT T.X FROM T", normalize_sql("SELECT t . x FROM t"));
+    }
+
+    #[test]
+    fn test_digest() {
+        assert_eq!(
+            sql_digest("SELECT * FROM foo WHERE id = 1"),
+            sql_digest("select *\nfrom foo\nwhere id=2")
+        );
+        assert_ne!(
+            sql_digest("SELECT * FROM foo WHERE id = 1"),
+            sql_digest("SELECT * FROM bar WHERE id = 1")
+        );
+
+        let db = Connection::open_in_memory().unwrap();
+        let stmt = db.prepare("SELECT 1, 'a'").unwrap();
+        assert_eq!("SELECT ?, ?", stmt.normalized_sql());
+        assert_eq!(sql_digest("SELECT 2, 'b'"), stmt.digest());
+    }
+}
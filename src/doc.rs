@@ -0,0 +1,266 @@
+//! Document stores over the JSON1 extension
+//!
+//! A `DocTable` stores `serde` serializable values as JSON documents in a table with
+//! an `id INTEGER PRIMARY KEY` and a `doc TEXT` column (which is created on
+//! demand), without writing schema code for each of their fields. Fields are
+//! updated and searched in place with `json_set` and `json_extract`, and can
+//! be indexed.
+//!
+//! Requires SQLite 3.9.0 or later, with the JSON1 extension enabled (it is
+//! built-in since SQLite 3.38.0).
+//!
+//! ```rust,no_run
+//! # use rusqlite::{doc::DocTable, Connection, Result};
+//! # use serde_json::{json, Value};
+//! fn move_to_paris(conn: &Connection) -> Result<Vec<(i64, Value)>> {
+//!     let people = DocTable::open(conn, "people")?;
+//!     people.create_index("$.city")?;
+//!     let id = people.insert_doc(&json!({"name": "alice", "city": "Lyon"}))?;
+//!     people.update_doc_fields(id, &[("$.city", json!("Paris"))])?;
+//!     people.find_docs("$.city", &"Paris")
+//! }
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::pragma::Sql;
+use crate::types::{ToSql, Type};
+use crate::{Connection, Error, Result};
+
+/// A table of JSON documents.
+pub struct DocTable<'conn> {
+    conn: &'conn Connection,
+    name: String,
+    /// Quoted name of the table.
+    table: String,
+}
+
+impl DocTable<'_> {
+    /// Open the `name` document table, creating it if it does not exist.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn open<'conn>(conn: &'conn Connection, name: &str) -> Result<DocTable<'conn>> {
+        let mut table = Sql::new();
+        table.push_identifier(name);
+        let table = table.as_str().to_owned();
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {}(
+                 id INTEGER PRIMARY KEY,
+                 doc TEXT NOT NULL CHECK (json_valid(doc))
+             )",
+            table
+        ))?;
+        Ok(DocTable {
+            conn,
+            name: name.to_owned(),
+            table,
+        })
+    }
+
+    /// Insert `doc`, and return its id.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `doc` cannot be serialized to JSON, or if the
+    /// underlying SQLite calls fail.
+    pub fn insert_doc<T: Serialize>(&self, doc: &T) -> Result<i64> {
+        let json =
+            serde_json::to_string(doc).map_err(|e| Error::ToSqlConversionFailure(e.into()))?;
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!("INSERT INTO {}(doc) VALUES(?)", self.table))?;
+        stmt.insert(&[json])
+    }
+
+    /// Return the document `id`, or `None` if there is none.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the document cannot be deserialized to `T`, or if
+    /// the underlying SQLite calls fail.
+    pub fn get_doc<T: DeserializeOwned>(&self, id: i64) -> Result<Option<T>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!("SELECT doc FROM {} WHERE id = ?", self.table))?;
+        let mut rows = stmt.query([id])?;
+        match rows.next()? {
+            Some(row) => from_json(&row.get::<_, String>(0)?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Set the fields of the document `id` at the given
+    /// [JSON paths](https://sqlite.org/json1.html#path_arguments) (e.g.
+    /// `$.address.city`) to the given values, creating them if needed, and
+    /// return `false` if there is no such document.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a path is malformed, or if the underlying SQLite
+    /// calls fail.
+    pub fn update_doc_fields(&self, id: i64, patch: &[(&str, Value)]) -> Result<bool> {
+        if patch.is_empty() {
+            return self.get_doc::<Value>(id).map(|doc| doc.is_some());
+        }
+        let mut sql = format!("UPDATE {} SET doc = json_set(doc", self.table);
+        let mut params: Vec<&dyn ToSql> = Vec::with_capacity(patch.len() * 2 + 1);
+        for (path, value) in patch {
+            sql.push_str(", ?, json(?)");
+            params.push(path);
+            params.push(value);
+        }
+        sql.push_str(") WHERE id = ?");
+        params.push(&id);
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        Ok(stmt.execute(&params)? > 0)
+    }
+
+    /// Delete the document `id`, and return `false` if there was none.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn delete_doc(&self, id: i64) -> Result<bool> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!("DELETE FROM {} WHERE id = ?", self.table))?;
+        Ok(stmt.execute([id])? > 0)
+    }
+
+    /// Return the documents whose field at the JSON path `path` is equal to
+    /// `value`, with their ids, in id order.
+    ///
+    /// The search uses the index created by `create_index` for `path`, if
+    /// any.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `path` is malformed, if a document cannot be
+    /// deserialized to `T`, or if the underlying SQLite calls fail.
+    pub fn find_docs<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        value: &dyn ToSql,
+    ) -> Result<Vec<(i64, T)>> {
+        // The path must be a literal for the expression to match the one of
+        // the index.
+        let mut sql = Sql::new();
+        sql.push_string_literal(path);
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT id, doc FROM {} WHERE json_extract(doc, {}) = ? ORDER BY id",
+            self.table,
+            sql.as_str()
+        ))?;
+        let mut rows = stmt.query(&[value])?;
+        let mut docs = Vec::new();
+        while let Some(row) = rows.next()? {
+            docs.push((row.get(0)?, from_json(&row.get::<_, String>(1)?)?));
+        }
+        Ok(docs)
+    }
+
+    /// Create (if it does not exist) an index on the field of the documents
+    /// at the JSON path `path`, used by `find_docs` and by the queries
+    /// filtering on `json_extract(doc, '<path>')`.
+    ///
+    /// The index is named after the table and the path, e.g.
+    /// `people_$.city`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn create_index(&self, path: &str) -> Result<()> {
+        let mut sql = Sql::new();
+        sql.push_identifier(&format!("{}_{}", self.name, path));
+        let index = sql.as_str().to_owned();
+        let mut sql = Sql::new();
+        sql.push_string_literal(path);
+        self.conn.execute_batch(&format!(
+            "CREATE INDEX IF NOT EXISTS {} ON {}(json_extract(doc, {}))",
+            index,
+            self.table,
+            sql.as_str()
+        ))
+    }
+}
+
+fn from_json<T: DeserializeOwned>(json: &str) -> Result<T> {
+    serde_json::from_str(json).map_err(|e| Error::FromSqlConversionFailure(0, Type::Text, e.into()))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use super::DocTable;
+    use crate::{Connection, NO_PARAMS};
+
+    fn person(name: &str, age: u32) -> Value {
+        json!({ "name": name, "age": age })
+    }
+
+    #[test]
+    fn test_doc_table() {
+        let db = Connection::open_in_memory().unwrap();
+        let people = DocTable::open(&db, "people").unwrap();
+        let alice = people.insert_doc(&person("alice", 30)).unwrap();
+        let bob = people.insert_doc(&person("bob", 40)).unwrap();
+        assert_eq!(Some(person("alice", 30)), people.get_doc(alice).unwrap());
+        assert_eq!(None, people.get_doc::<Value>(bob + 1).unwrap());
+        assert!(people.get_doc::<Vec<i64>>(alice).is_err());
+
+        assert!(people
+            .update_doc_fields(bob, &[("$.age", json!(41)), ("$.city", json!("Paris"))])
+            .unwrap());
+        assert!(!people
+            .update_doc_fields(bob + 1, &[("$.age", json!(1))])
+            .unwrap());
+        assert_eq!(
+            Some(json!({ "name": "bob", "age": 41, "city": "Paris" })),
+            people.get_doc(bob).unwrap()
+        );
+
+        // Nested values are stored as JSON, not as strings
+        people
+            .update_doc_fields(alice, &[("$.tags", json!(["a", "b"]))])
+            .unwrap();
+        let tags: String = db
+            .query_row(
+                "SELECT json_type(doc, '$.tags') FROM people WHERE id = ?",
+                &[alice],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!("array", tags);
+
+        assert!(people.delete_doc(alice).unwrap());
+        assert!(!people.delete_doc(alice).unwrap());
+    }
+
+    #[test]
+    fn test_doc_index() {
+        let db = Connection::open_in_memory().unwrap();
+        let people = DocTable::open(&db, "people").unwrap();
+        for (i, name) in ["alice", "bob", "carol"].iter().enumerate() {
+            people.insert_doc(&person(name, 30 + i as u32)).unwrap();
+        }
+        people.create_index("$.age").unwrap();
+        people.create_index("$.age").unwrap();
+
+        let found: Vec<(i64, Value)> = people.find_docs("$.age", &31).unwrap();
+        assert_eq!(vec![(2, person("bob", 31))], found);
+
+        let plan: String = db
+            .query_row(
+                "EXPLAIN QUERY PLAN SELECT id FROM people WHERE json_extract(doc, '$.age') = 31",
+                NO_PARAMS,
+                |r| r.get(3),
+            )
+            .unwrap();
+        assert!(plan.contains("people_$.age"), "{}", plan);
+    }
+}
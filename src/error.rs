@@ -1,7 +1,10 @@
 use crate::types::Type;
+#[cfg(feature = "snapshot")]
+use crate::CommitToken;
 use crate::{errmsg_to_string, ffi};
 use std::error;
 use std::fmt;
+use std::io;
 use std::os::raw::c_int;
 use std::path::PathBuf;
 use std::str;
@@ -11,8 +14,26 @@ use std::str;
 #[allow(clippy::enum_variant_names)]
 pub enum Error {
     /// An error from an underlying SQLite call.
+    ///
+    /// When SQLite fails to open or access a file (`SQLITE_CANTOPEN` or
+    /// `SQLITE_IOERR`), the error reported by the operating system is
+    /// available from `Error::os_error`.
     SqliteFailure(ffi::Error, Option<String>),
 
+    /// Error from a file operation done by rusqlite itself, not by SQLite
+    /// (e.g. when `backup::Scheduler` rotates the backup files).
+    Io(io::Error),
+
+    /// Error when SQLite refuses to write to a database (`SQLITE_READONLY` and
+    /// its extended result codes), e.g. to reopen the database after it has
+    /// been moved or replaced (`ReadOnlyReason::DbMoved`).
+    ReadOnly {
+        /// Why the database cannot be written.
+        reason: ReadOnlyReason,
+        /// The error message reported by SQLite, if any.
+        message: Option<String>,
+    },
+
     /// Error reported when attempting to open a connection when SQLite was
     /// configured to allow single-threaded use only.
     SqliteSingleThreadedMode,
@@ -41,6 +62,18 @@ pub enum Error {
     /// Error converting a file path to a string.
     InvalidPath(PathBuf),
 
+    /// Error when a `DatabaseName` is empty or contains a quote or nul
+    /// character.
+    InvalidDatabaseName(String),
+
+    /// Error when an existing database does not have the page size or text
+    /// encoding requested by `OpenOptions`.
+    IncompatibleDatabaseSetting(String),
+
+    /// Error when the SQL passed to `Connection::prepare_single` contains more
+    /// than one statement, with the SQL following the first statement.
+    MultipleStatement(String),
+
     /// Error returned when an `execute` call returns rows.
     ExecuteReturnedResults,
 
@@ -65,6 +98,11 @@ pub enum Error {
     /// any or insert many.
     StatementChangedRows(usize),
 
+    /// Error when a BLOB cannot be opened by `Connection::blob_open` (or
+    /// `Blob::reopen`) because of the row, column or value requested.
+    #[cfg(feature = "blob")]
+    BlobOpenFailure(crate::blob::BlobOpenError),
+
     /// Error returned by `functions::Context::get` when the function argument
     /// cannot be converted to the requested type.
     #[cfg(feature = "functions")]
@@ -86,6 +124,33 @@ pub enum Error {
     /// Error when the SQL is not a `SELECT`, is not read-only.
     InvalidQuery,
 
+    /// Error when an operation could not complete before its deadline (see
+    /// `Transaction::commit_with_deadline`).
+    DeadlineExceeded,
+
+    /// Error when a table given to `Connection::reset_sequence` is not an
+    /// `AUTOINCREMENT` table of the main database. The `String` is the name
+    /// of the table.
+    NotAutoincrementTable(String),
+
+    /// Error when the temp directory is changed while a connection is open
+    /// (see `set_temp_directory`).
+    TempDirectoryInUse,
+
+    /// Error when a reader does not see a commit within the timeout given to
+    /// `CommitTracker::wait_for`.
+    #[cfg(feature = "snapshot")]
+    CommitWaitTimeout(CommitToken),
+
+    /// Error when a connection URL (see `Connection::open_from_url`) is
+    /// invalid, or has an unsupported parameter.
+    InvalidUrl(String),
+
+    /// Error when the thread of an `AsyncConnection` stopped before running
+    /// a call.
+    #[cfg(feature = "tokio")]
+    AsyncConnectionClosed,
+
     /// An error case available for implementors of custom modules (e.g.,
     /// `create_module`).
     #[cfg(feature = "vtab")]
@@ -99,12 +164,115 @@ pub enum Error {
     /// of a different type than what had been stored using `Context::set_aux`.
     #[cfg(feature = "functions")]
     GetAuxWrongType,
+
+    /// An error returned when `Context::get_pointer_arg` is given a value
+    /// bound with the same type tag, but which is not of the requested type.
+    /// The `usize` is the index of the argument.
+    #[cfg(all(feature = "functions", feature = "pointer"))]
+    PointerWrongType(usize),
+}
+
+/// Why SQLite refused to write to a database (see `Error::ReadOnly`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadOnlyReason {
+    /// The database or the connection is read-only (`SQLITE_READONLY`).
+    ReadOnly,
+    /// A WAL mode database needs recovery, which requires write access
+    /// (`SQLITE_READONLY_RECOVERY`).
+    Recovery,
+    /// The shared-memory file of a WAL mode database is read-only
+    /// (`SQLITE_READONLY_CANTLOCK`).
+    CantLock,
+    /// The database has a hot journal which must be rolled back, which
+    /// requires write access (`SQLITE_READONLY_ROLLBACK`).
+    Rollback,
+    /// The database file has been moved or unlinked since it was opened
+    /// (`SQLITE_READONLY_DBMOVED`).
+    DbMoved,
+    /// The shared-memory file of a WAL mode database cannot be initialized
+    /// (`SQLITE_READONLY_CANTINIT`).
+    CantInit,
+    /// The directory of the database is read-only, so its journal cannot be
+    /// created (`SQLITE_READONLY_DIRECTORY`).
+    Directory,
+    /// Another extended result code.
+    Other(c_int),
+}
+
+impl ReadOnlyReason {
+    fn from_extended_code(code: c_int) -> ReadOnlyReason {
+        match code >> 8 {
+            0 => ReadOnlyReason::ReadOnly,
+            1 => ReadOnlyReason::Recovery,
+            2 => ReadOnlyReason::CantLock,
+            3 => ReadOnlyReason::Rollback,
+            4 => ReadOnlyReason::DbMoved,
+            5 => ReadOnlyReason::CantInit,
+            6 => ReadOnlyReason::Directory,
+            _ => ReadOnlyReason::Other(code),
+        }
+    }
+
+    /// Return the extended result code of this reason.
+    pub fn extended_code(self) -> c_int {
+        let n = match self {
+            ReadOnlyReason::ReadOnly => 0,
+            ReadOnlyReason::Recovery => 1,
+            ReadOnlyReason::CantLock => 2,
+            ReadOnlyReason::Rollback => 3,
+            ReadOnlyReason::DbMoved => 4,
+            ReadOnlyReason::CantInit => 5,
+            ReadOnlyReason::Directory => 6,
+            ReadOnlyReason::Other(code) => return code,
+        };
+        ffi::SQLITE_READONLY | (n << 8)
+    }
+}
+
+impl Error {
+    /// Return the error reported by the operating system (see
+    /// [`sqlite3_system_errno`](https://sqlite.org/c3ref/system_errno.html))
+    /// if this is a `SqliteFailure` of a file operation, e.g. to tell a
+    /// permission error from a full disk.
+    ///
+    /// The OS error is only available with the `system_errno` (or `bundled`)
+    /// feature and SQLite 3.12.0 or later.
+    pub fn os_error(&self) -> Option<io::Error> {
+        match *self {
+            Error::SqliteFailure(ref err, _) if err.system_errno != 0 => {
+                Some(io::Error::from_raw_os_error(err.system_errno))
+            }
+            _ => None,
+        }
+    }
+
+    /// Return why SQLite refused to write to a database, if this is an
+    /// `Error::ReadOnly`.
+    pub fn read_only_reason(&self) -> Option<ReadOnlyReason> {
+        match *self {
+            Error::ReadOnly { reason, .. } => Some(reason),
+            _ => None,
+        }
+    }
 }
 
 impl PartialEq for Error {
     fn eq(&self, other: &Error) -> bool {
         match (self, other) {
             (Error::SqliteFailure(e1, s1), Error::SqliteFailure(e2, s2)) => e1 == e2 && s1 == s2,
+            (Error::Io(i1), Error::Io(i2)) => {
+                i1.kind() == i2.kind() && i1.raw_os_error() == i2.raw_os_error()
+            }
+            (
+                Error::ReadOnly {
+                    reason: r1,
+                    message: m1,
+                },
+                Error::ReadOnly {
+                    reason: r2,
+                    message: m2,
+                },
+            ) => r1 == r2 && m1 == m2,
             (Error::SqliteSingleThreadedMode, Error::SqliteSingleThreadedMode) => true,
             (Error::IntegralValueOutOfRange(i1, n1), Error::IntegralValueOutOfRange(i2, n2)) => {
                 i1 == i2 && n1 == n2
@@ -113,6 +281,11 @@ impl PartialEq for Error {
             (Error::NulError(e1), Error::NulError(e2)) => e1 == e2,
             (Error::InvalidParameterName(n1), Error::InvalidParameterName(n2)) => n1 == n2,
             (Error::InvalidPath(p1), Error::InvalidPath(p2)) => p1 == p2,
+            (Error::InvalidDatabaseName(n1), Error::InvalidDatabaseName(n2)) => n1 == n2,
+            (Error::IncompatibleDatabaseSetting(s1), Error::IncompatibleDatabaseSetting(s2)) => {
+                s1 == s2
+            }
+            (Error::MultipleStatement(s1), Error::MultipleStatement(s2)) => s1 == s2,
             (Error::ExecuteReturnedResults, Error::ExecuteReturnedResults) => true,
             (Error::QueryReturnedNoRows, Error::QueryReturnedNoRows) => true,
             (Error::InvalidColumnIndex(i1), Error::InvalidColumnIndex(i2)) => i1 == i2,
@@ -121,6 +294,10 @@ impl PartialEq for Error {
                 i1 == i2 && t1 == t2
             }
             (Error::StatementChangedRows(n1), Error::StatementChangedRows(n2)) => n1 == n2,
+            #[cfg(feature = "snapshot")]
+            (Error::CommitWaitTimeout(t1), Error::CommitWaitTimeout(t2)) => t1 == t2,
+            #[cfg(feature = "blob")]
+            (Error::BlobOpenFailure(e1), Error::BlobOpenFailure(e2)) => e1 == e2,
             #[cfg(feature = "functions")]
             (
                 Error::InvalidFunctionParameterType(i1, t1),
@@ -132,12 +309,20 @@ impl PartialEq for Error {
                 Error::InvalidFilterParameterType(i2, t2),
             ) => i1 == i2 && t1 == t2,
             (Error::InvalidQuery, Error::InvalidQuery) => true,
+            (Error::DeadlineExceeded, Error::DeadlineExceeded) => true,
+            (Error::NotAutoincrementTable(t1), Error::NotAutoincrementTable(t2)) => t1 == t2,
+            (Error::TempDirectoryInUse, Error::TempDirectoryInUse) => true,
+            (Error::InvalidUrl(s1), Error::InvalidUrl(s2)) => s1 == s2,
+            #[cfg(feature = "tokio")]
+            (Error::AsyncConnectionClosed, Error::AsyncConnectionClosed) => true,
             #[cfg(feature = "vtab")]
             (Error::ModuleError(s1), Error::ModuleError(s2)) => s1 == s2,
             #[cfg(feature = "functions")]
             (Error::UnwindingPanic, Error::UnwindingPanic) => true,
             #[cfg(feature = "functions")]
             (Error::GetAuxWrongType, Error::GetAuxWrongType) => true,
+            #[cfg(all(feature = "functions", feature = "pointer"))]
+            (Error::PointerWrongType(i1), Error::PointerWrongType(i2)) => i1 == i2,
             (_, _) => false,
         }
     }
@@ -158,8 +343,25 @@ impl From<::std::ffi::NulError> for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
-            Error::SqliteFailure(ref err, None) => err.fmt(f),
-            Error::SqliteFailure(_, Some(ref s)) => write!(f, "{}", s),
+            Error::SqliteFailure(ref err, ref s) => {
+                match *s {
+                    None => err.fmt(f)?,
+                    Some(ref s) => write!(f, "{}", s)?,
+                }
+                match self.os_error() {
+                    Some(io_err) => write!(f, ": {}", io_err),
+                    None => Ok(()),
+                }
+            }
+            Error::Io(ref err) => err.fmt(f),
+            Error::ReadOnly {
+                message: Some(ref s),
+                ..
+            } => write!(f, "{}", s),
+            Error::ReadOnly {
+                reason,
+                message: None,
+            } => ffi::Error::new(reason.extended_code()).fmt(f),
             Error::SqliteSingleThreadedMode => write!(
                 f,
                 "SQLite was compiled or configured for single-threaded use only"
@@ -176,6 +378,13 @@ impl fmt::Display for Error {
             Error::NulError(ref err) => err.fmt(f),
             Error::InvalidParameterName(ref name) => write!(f, "Invalid parameter name: {}", name),
             Error::InvalidPath(ref p) => write!(f, "Invalid path: {}", p.to_string_lossy()),
+            Error::InvalidDatabaseName(ref name) => write!(f, "Invalid database name: {}", name),
+            Error::IncompatibleDatabaseSetting(ref desc) => {
+                write!(f, "Incompatible database setting: {}", desc)
+            }
+            Error::MultipleStatement(ref sql) => {
+                write!(f, "Multiple statements provided, trailing SQL: {}", sql)
+            }
             Error::ExecuteReturnedResults => {
                 write!(f, "Execute returned results - did you mean to call query?")
             }
@@ -186,6 +395,8 @@ impl fmt::Display for Error {
                 write!(f, "Invalid column type {} at index: {}", t, i)
             }
             Error::StatementChangedRows(i) => write!(f, "Query changed {} rows", i),
+            #[cfg(feature = "blob")]
+            Error::BlobOpenFailure(ref err) => write!(f, "Cannot open BLOB: {}", err),
 
             #[cfg(feature = "functions")]
             Error::InvalidFunctionParameterType(i, ref t) => {
@@ -199,12 +410,29 @@ impl fmt::Display for Error {
             Error::UserFunctionError(ref err) => err.fmt(f),
             Error::ToSqlConversionFailure(ref err) => err.fmt(f),
             Error::InvalidQuery => write!(f, "Query is not read-only"),
+            Error::DeadlineExceeded => write!(f, "Deadline exceeded"),
+            Error::NotAutoincrementTable(ref table) => {
+                write!(f, "{} is not an AUTOINCREMENT table", table)
+            }
+            Error::TempDirectoryInUse => write!(
+                f,
+                "Cannot change the temp directory while a connection is open"
+            ),
+            #[cfg(feature = "snapshot")]
+            Error::CommitWaitTimeout(token) => write!(f, "Timed out waiting for {:?}", token),
+            Error::InvalidUrl(ref desc) => write!(f, "Invalid connection URL: {}", desc),
+            #[cfg(feature = "tokio")]
+            Error::AsyncConnectionClosed => write!(f, "Async connection closed"),
             #[cfg(feature = "vtab")]
             Error::ModuleError(ref desc) => write!(f, "{}", desc),
             #[cfg(feature = "functions")]
             Error::UnwindingPanic => write!(f, "unwinding panic"),
             #[cfg(feature = "functions")]
             Error::GetAuxWrongType => write!(f, "get_aux called with wrong type"),
+            #[cfg(all(feature = "functions", feature = "pointer"))]
+            Error::PointerWrongType(i) => {
+                write!(f, "Pointer argument {} is not of the requested type", i)
+            }
         }
     }
 }
@@ -214,6 +442,12 @@ impl error::Error for Error {
         match *self {
             Error::SqliteFailure(ref err, None) => err.description(),
             Error::SqliteFailure(_, Some(ref s)) => s,
+            Error::Io(ref err) => err.description(),
+            Error::ReadOnly {
+                message: Some(ref s),
+                ..
+            } => s,
+            Error::ReadOnly { message: None, .. } => "attempt to write a readonly database",
             Error::SqliteSingleThreadedMode => {
                 "SQLite was compiled or configured for single-threaded use only"
             }
@@ -223,6 +457,9 @@ impl error::Error for Error {
             Error::InvalidParameterName(_) => "invalid parameter name",
             Error::NulError(ref err) => err.description(),
             Error::InvalidPath(_) => "invalid path",
+            Error::InvalidDatabaseName(_) => "invalid database name",
+            Error::IncompatibleDatabaseSetting(_) => "incompatible database setting",
+            Error::MultipleStatement(_) => "multiple statements provided",
             Error::ExecuteReturnedResults => {
                 "execute returned results - did you mean to call query?"
             }
@@ -231,6 +468,8 @@ impl error::Error for Error {
             Error::InvalidColumnName(_) => "invalid column name",
             Error::InvalidColumnType(_, _) => "invalid column type",
             Error::StatementChangedRows(_) => "query inserted zero or more than one row",
+            #[cfg(feature = "blob")]
+            Error::BlobOpenFailure(_) => "cannot open BLOB",
 
             #[cfg(feature = "functions")]
             Error::InvalidFunctionParameterType(_, _) => "invalid function parameter type",
@@ -240,33 +479,58 @@ impl error::Error for Error {
             Error::UserFunctionError(ref err) => err.description(),
             Error::ToSqlConversionFailure(ref err) => err.description(),
             Error::InvalidQuery => "query is not read-only",
+            Error::DeadlineExceeded => "deadline exceeded",
+            Error::NotAutoincrementTable(_) => "not an AUTOINCREMENT table",
+            Error::TempDirectoryInUse => "temp directory in use",
+            #[cfg(feature = "snapshot")]
+            Error::CommitWaitTimeout(_) => "timed out waiting for a commit",
+            Error::InvalidUrl(_) => "invalid connection URL",
+            #[cfg(feature = "tokio")]
+            Error::AsyncConnectionClosed => "async connection closed",
             #[cfg(feature = "vtab")]
             Error::ModuleError(ref desc) => desc,
             #[cfg(feature = "functions")]
             Error::UnwindingPanic => "unwinding panic",
             #[cfg(feature = "functions")]
             Error::GetAuxWrongType => "get_aux called with wrong type",
+            #[cfg(all(feature = "functions", feature = "pointer"))]
+            Error::PointerWrongType(_) => "pointer argument of the wrong type",
         }
     }
 
     fn cause(&self) -> Option<&dyn error::Error> {
         match *self {
             Error::SqliteFailure(ref err, _) => Some(err),
+            Error::Io(ref err) => Some(err),
             Error::Utf8Error(ref err) => Some(err),
             Error::NulError(ref err) => Some(err),
 
             Error::IntegralValueOutOfRange(_, _)
+            | Error::ReadOnly { .. }
             | Error::SqliteSingleThreadedMode
             | Error::InvalidParameterName(_)
+            | Error::MultipleStatement(_)
             | Error::ExecuteReturnedResults
             | Error::QueryReturnedNoRows
             | Error::InvalidColumnIndex(_)
             | Error::InvalidColumnName(_)
             | Error::InvalidColumnType(_, _)
             | Error::InvalidPath(_)
+            | Error::InvalidDatabaseName(_)
+            | Error::IncompatibleDatabaseSetting(_)
             | Error::StatementChangedRows(_)
-            | Error::InvalidQuery => None,
-
+            | Error::InvalidQuery
+            | Error::DeadlineExceeded
+            | Error::NotAutoincrementTable(_)
+            | Error::TempDirectoryInUse
+            | Error::InvalidUrl(_) => None,
+
+            #[cfg(feature = "blob")]
+            Error::BlobOpenFailure(_) => None,
+            #[cfg(feature = "snapshot")]
+            Error::CommitWaitTimeout(_) => None,
+            #[cfg(feature = "tokio")]
+            Error::AsyncConnectionClosed => None,
             #[cfg(feature = "functions")]
             Error::InvalidFunctionParameterType(_, _) => None,
             #[cfg(feature = "vtab")]
@@ -286,6 +550,9 @@ impl error::Error for Error {
 
             #[cfg(feature = "functions")]
             Error::GetAuxWrongType => None,
+
+            #[cfg(all(feature = "functions", feature = "pointer"))]
+            Error::PointerWrongType(_) => None,
         }
     }
 }
@@ -293,6 +560,12 @@ impl error::Error for Error {
 // These are public but not re-exported by lib.rs, so only visible within crate.
 
 pub fn error_from_sqlite_code(code: c_int, message: Option<String>) -> Error {
+    if code & 0xff == ffi::SQLITE_READONLY {
+        return Error::ReadOnly {
+            reason: ReadOnlyReason::from_extended_code(code),
+            message,
+        };
+    }
     Error::SqliteFailure(ffi::Error::new(code), message)
 }
 
@@ -302,9 +575,36 @@ pub fn error_from_handle(db: *mut ffi::sqlite3, code: c_int) -> Error {
     } else {
         Some(unsafe { errmsg_to_string(ffi::sqlite3_errmsg(db)) })
     };
+    let primary = code & 0xff;
+    if !db.is_null() && (primary == ffi::SQLITE_IOERR || primary == ffi::SQLITE_CANTOPEN) {
+        let errno = system_errno(db);
+        if errno != 0 {
+            let mut err = ffi::Error::new(code);
+            err.system_errno = errno;
+            return Error::SqliteFailure(err, message);
+        }
+    }
     error_from_sqlite_code(code, message)
 }
 
+/// Return the OS error of the last failed file operation on `db`, or 0 if
+/// there is none or if SQLite is older than 3.12.0.
+#[cfg(any(feature = "system_errno", feature = "bundled"))]
+pub(crate) fn system_errno(db: *mut ffi::sqlite3) -> c_int {
+    if crate::version_number() >= 3_012_000 {
+        unsafe { ffi::sqlite3_system_errno(db) }
+    } else {
+        0
+    }
+}
+
+/// Without the `system_errno` feature, `sqlite3_system_errno` is not
+/// referenced, so that SQLite older than 3.12.0 can be linked.
+#[cfg(not(any(feature = "system_errno", feature = "bundled")))]
+pub(crate) fn system_errno(_db: *mut ffi::sqlite3) -> c_int {
+    0
+}
+
 macro_rules! check {
     ($funcall:expr) => {{
         let rc = $funcall;
@@ -0,0 +1,153 @@
+//! In-memory log of the most recently run statements.
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{Connection, InnerConnection};
+
+/// Outcome of a statement recorded in the event log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StatementOutcome {
+    /// The statement (or batch) completed and changed the given number of
+    /// rows.
+    Executed(usize),
+    /// The query completed (or was abandoned) after returning the given
+    /// number of rows.
+    Queried(usize),
+    /// The statement failed with the given error message.
+    Failed(String),
+}
+
+/// A statement recorded in the event log (see
+/// `Connection::recent_statements`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatementEvent {
+    /// SQL text of the statement (or batch).
+    pub sql: String,
+    /// When the statement started to run.
+    pub started: SystemTime,
+    /// Time elapsed between the first step and the completion of the
+    /// statement (for queries, this includes the time spent by the caller
+    /// between rows).
+    pub duration: Duration,
+    /// Outcome of the statement.
+    pub outcome: StatementOutcome,
+}
+
+/// When a recorded statement started.
+pub(crate) type EventStart = (SystemTime, Instant);
+
+#[derive(Debug)]
+pub(crate) struct EventLog {
+    capacity: usize,
+    events: VecDeque<StatementEvent>,
+}
+
+impl Connection {
+    /// Record the last `capacity` statements run on this connection, with
+    /// their start time, duration and outcome, in an in-memory ring buffer.
+    ///
+    /// The event log is disabled by default, and `capacity` 0 disables it
+    /// (and clears the recorded events). Statements run by `execute` and
+    /// `query` methods are recorded individually, `execute_batch` is recorded
+    /// as a single event.
+    pub fn set_event_log_capacity(&self, capacity: usize) {
+        let mut db = self.db.borrow_mut();
+        if capacity == 0 {
+            db.event_log = None;
+            return;
+        }
+        let log = db.event_log.get_or_insert_with(|| EventLog {
+            capacity,
+            events: VecDeque::with_capacity(capacity),
+        });
+        log.capacity = capacity;
+        while log.events.len() > capacity {
+            log.events.pop_front();
+        }
+    }
+
+    /// Return the statements recorded in the event log, from the oldest to
+    /// the most recent (see `set_event_log_capacity`).
+    pub fn recent_statements(&self) -> Vec<StatementEvent> {
+        match self.db.borrow().event_log {
+            Some(ref log) => log.events.iter().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub(crate) fn event_start(&self) -> Option<EventStart> {
+        self.db.borrow().event_start()
+    }
+
+    pub(crate) fn record_event(&self, start: EventStart, sql: &str, outcome: StatementOutcome) {
+        self.db.borrow_mut().record_event(start, sql, outcome)
+    }
+}
+
+impl InnerConnection {
+    pub(crate) fn event_start(&self) -> Option<EventStart> {
+        self.event_log
+            .as_ref()
+            .map(|_| (SystemTime::now(), Instant::now()))
+    }
+
+    pub(crate) fn record_event(&mut self, start: EventStart, sql: &str, outcome: StatementOutcome) {
+        if let Some(ref mut log) = self.event_log {
+            if log.events.len() == log.capacity {
+                log.events.pop_front();
+            }
+            log.events.push_back(StatementEvent {
+                sql: sql.to_owned(),
+                started: start.0,
+                duration: start.1.elapsed(),
+                outcome,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StatementOutcome;
+    use crate::{Connection, NO_PARAMS};
+
+    #[test]
+    fn test_event_log() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER UNIQUE)")
+            .unwrap();
+        assert!(db.recent_statements().is_empty());
+
+        db.set_event_log_capacity(4);
+        db.execute_batch("INSERT INTO foo VALUES(1); INSERT INTO foo VALUES(2);")
+            .unwrap();
+        db.execute("UPDATE foo SET x = x + 10", NO_PARAMS).unwrap();
+        {
+            let mut stmt = db.prepare("SELECT x FROM foo").unwrap();
+            let mut rows = stmt.query(NO_PARAMS).unwrap();
+            rows.next().unwrap();
+        }
+        assert!(db.execute("INSERT INTO foo VALUES(11)", NO_PARAMS).is_err());
+        // Preparation errors are not recorded.
+        assert!(db.execute("INSERT INTO bar VALUES(1)", NO_PARAMS).is_err());
+        db.query_row("SELECT count(*) FROM foo", NO_PARAMS, |_| Ok(()))
+            .unwrap();
+
+        let events = db.recent_statements();
+        assert_eq!(4, events.len());
+        assert_eq!("UPDATE foo SET x = x + 10", events[0].sql);
+        assert_eq!(StatementOutcome::Executed(2), events[0].outcome);
+        assert_eq!("SELECT x FROM foo", events[1].sql);
+        assert_eq!(StatementOutcome::Queried(1), events[1].outcome);
+        match events[2].outcome {
+            StatementOutcome::Failed(ref msg) => assert!(msg.contains("UNIQUE")),
+            ref outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+        assert_eq!(StatementOutcome::Queried(1), events[3].outcome);
+
+        db.set_event_log_capacity(1);
+        assert_eq!(vec![events[3].clone()], db.recent_statements());
+        db.set_event_log_capacity(0);
+        assert!(db.recent_statements().is_empty());
+    }
+}
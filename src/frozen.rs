@@ -0,0 +1,124 @@
+//! Point-in-time, read-only views of a database.
+use std::ops::Deref;
+use std::path::Path;
+
+use crate::backup::Backup;
+use crate::{Connection, OpenFlags, Result, NO_PARAMS};
+
+/// A read-only connection which sees the state of a database at a fixed
+/// point in time, even while other connections keep committing changes.
+///
+/// See `Connection::freeze`.
+#[derive(Debug)]
+pub struct FrozenDb {
+    conn: Connection,
+    copy: bool,
+}
+
+impl FrozenDb {
+    /// Return `true` if the frozen state is an in-memory copy of the
+    /// database, `false` if it is a read transaction on the database file.
+    pub fn is_copy(&self) -> bool {
+        self.copy
+    }
+}
+
+impl Deref for FrozenDb {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+impl Connection {
+    /// Return a read-only handle on the current state of the main database.
+    ///
+    /// When the database is a file in WAL mode, a new read-only connection
+    /// is opened and a read transaction is started on it: the WAL guarantees
+    /// that it keeps seeing the same snapshot while writers commit, without
+    /// blocking them. Otherwise (in-memory databases, rollback journal
+    /// modes), the database is copied to memory with the online backup API.
+    ///
+    /// Holding a `FrozenDb` on a WAL database prevents checkpoints from
+    /// completing, so it should not be kept longer than necessary.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn freeze(&self) -> Result<FrozenDb> {
+        if let Some(path) = self.wal_file_path()? {
+            let conn = Connection::open_with_flags(
+                path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )?;
+            conn.execute_batch("BEGIN DEFERRED")?;
+            // The snapshot is only taken by the first read.
+            conn.query_row("SELECT count(*) FROM sqlite_master", NO_PARAMS, |_| Ok(()))?;
+            return Ok(FrozenDb { conn, copy: false });
+        }
+
+        let mut conn = Connection::open_in_memory()?;
+        Backup::new(self, &mut conn)?.step(-1)?;
+        conn.execute_batch("PRAGMA query_only = ON")?;
+        Ok(FrozenDb { conn, copy: true })
+    }
+
+    fn wal_file_path(&self) -> Result<Option<&Path>> {
+        let path = match self.path {
+            Some(ref path) if !path.as_os_str().is_empty() && path != Path::new(":memory:") => path,
+            _ => return Ok(None),
+        };
+        let journal_mode: String =
+            self.pragma_query_value(None, "journal_mode", |row| row.get(0))?;
+        if journal_mode.eq_ignore_ascii_case("wal") {
+            Ok(Some(path))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Connection, NO_PARAMS};
+
+    fn count(db: &Connection) -> i64 {
+        db.query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_freeze_wal() {
+        let temp_dir = tempdir::TempDir::new("test_freeze_wal").unwrap();
+        let path = temp_dir.path().join("test.db3");
+
+        let db = Connection::open(&path).unwrap();
+        db.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE foo(x INTEGER);
+             INSERT INTO foo VALUES(1);",
+        )
+        .unwrap();
+
+        let frozen = db.freeze().unwrap();
+        assert!(!frozen.is_copy());
+        db.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
+        assert_eq!(1, count(&frozen));
+        assert_eq!(2, count(&db));
+        assert!(frozen.execute_batch("INSERT INTO foo VALUES(3)").is_err());
+    }
+
+    #[test]
+    fn test_freeze_copy() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES(1);")
+            .unwrap();
+
+        let frozen = db.freeze().unwrap();
+        assert!(frozen.is_copy());
+        db.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
+        assert_eq!(1, count(&frozen));
+        assert!(frozen.execute_batch("INSERT INTO foo VALUES(3)").is_err());
+    }
+}
@@ -62,7 +62,13 @@
 //! }
 //! ```
 use std::error::Error as StdError;
+#[cfg(feature = "pointer")]
+use std::ffi::CStr;
+#[cfg(feature = "tokio")]
+use std::future::Future;
 use std::os::raw::{c_int, c_void};
+#[cfg(feature = "tokio")]
+use std::panic::AssertUnwindSafe;
 use std::panic::{catch_unwind, RefUnwindSafe, UnwindSafe};
 use std::ptr;
 use std::slice;
@@ -72,7 +78,8 @@ use crate::ffi::sqlite3_context;
 use crate::ffi::sqlite3_value;
 
 use crate::context::set_result;
-use crate::types::{FromSql, FromSqlError, ToSql, ValueRef};
+use crate::tagged::Tagged;
+use crate::types::{FromSql, FromSqlError, ToSql, Type, ValueRef};
 
 use crate::{str_to_cstring, Connection, Error, InnerConnection, Result};
 
@@ -92,20 +99,55 @@ unsafe fn report_error(ctx: *mut sqlite3_context, err: &Error) {
 
     match *err {
         Error::SqliteFailure(ref err, ref s) => {
-            ffi::sqlite3_result_error_code(ctx, err.extended_code);
+            // `sqlite3_result_error` resets the error code to SQLITE_ERROR, so
+            // it must be called first.
             if let Some(Ok(cstr)) = s.as_ref().map(|s| str_to_cstring(s)) {
                 ffi::sqlite3_result_error(ctx, cstr.as_ptr(), -1);
             }
+            ffi::sqlite3_result_error_code(ctx, err.extended_code);
         }
         _ => {
-            ffi::sqlite3_result_error_code(ctx, constraint_error_code());
             if let Ok(cstr) = str_to_cstring(err.description()) {
                 ffi::sqlite3_result_error(ctx, cstr.as_ptr(), -1);
             }
+            ffi::sqlite3_result_error_code(ctx, constraint_error_code());
         }
     }
 }
 
+/// Return an error which, when returned by a user-defined function, makes the
+/// calling statement fail with the given (possibly extended) result `code`
+/// and `message`, so that callers can distinguish the errors of the function
+/// from the `Error::SqliteFailure` they get.
+///
+/// ```rust
+/// # use rusqlite::{Connection, Error, Result, NO_PARAMS};
+/// # use rusqlite::functions::error_with_code;
+/// const INVALID_INPUT: i32 = rusqlite::ffi::SQLITE_CONSTRAINT | (100 << 8);
+///
+/// fn check(conn: &Connection) -> Result<()> {
+///     conn.create_scalar_function("check_positive", 1, true, |ctx| {
+///         let x = ctx.get::<i64>(0)?;
+///         if x <= 0 {
+///             return Err(error_with_code(INVALID_INPUT, "not positive"));
+///         }
+///         Ok(x)
+///     })?;
+///     match conn.query_row("SELECT check_positive(-1)", NO_PARAMS, |r| r.get::<_, i64>(0)) {
+///         Err(Error::SqliteFailure(err, Some(msg))) => {
+///             assert_eq!(INVALID_INPUT, err.extended_code);
+///             assert_eq!("not positive", msg);
+///         }
+///         _ => unreachable!(),
+///     }
+///     Ok(())
+/// }
+/// # check(&Connection::open_in_memory().unwrap()).unwrap();
+/// ```
+pub fn error_with_code(code: c_int, message: &str) -> Error {
+    Error::SqliteFailure(ffi::Error::new(code), Some(message.to_owned()))
+}
+
 unsafe extern "C" fn free_boxed_value<T>(p: *mut c_void) {
     drop(Box::from_raw(p as *mut T));
 }
@@ -134,10 +176,9 @@ impl Context<'_> {
     /// Will panic if `idx` is greater than or equal to `self.len()`.
     ///
     /// Will return Err if the underlying SQLite type cannot be converted to a
-    /// `T`.
+    /// `T`, or if the argument is a text which is not valid UTF-8.
     pub fn get<T: FromSql>(&self, idx: usize) -> Result<T> {
-        let arg = self.args[idx];
-        let value = unsafe { ValueRef::from_value(arg) };
+        let value = self.get_raw_checked(idx)?;
         FromSql::column_result(value).map_err(|err| match err {
             FromSqlError::InvalidType => {
                 Error::InvalidFunctionParameterType(idx, value.data_type())
@@ -161,41 +202,94 @@ impl Context<'_> {
     ///
     /// # Failure
     ///
-    /// Will panic if `idx` is greater than or equal to `self.len()`.
+    /// Will panic if `idx` is greater than or equal to `self.len()`, or if the
+    /// argument is a text which is not valid UTF-8.
     pub fn get_raw(&self, idx: usize) -> ValueRef<'_> {
         let arg = self.args[idx];
         unsafe { ValueRef::from_value(arg) }
     }
 
+    /// Like `get_raw`, but fails instead of panicking if the argument is a
+    /// text which is not valid UTF-8.
+    pub(crate) fn get_raw_checked(&self, idx: usize) -> Result<ValueRef<'_>> {
+        let arg = self.args[idx];
+        unsafe { ValueRef::from_value_checked(arg) }
+            .map_err(|err| Error::FromSqlConversionFailure(idx, Type::Text, Box::new(err)))
+    }
+
+    /// Returns the `idx`th argument converted to text by SQLite, as with
+    /// `CAST(arg AS TEXT)` (e.g. `1.0` for the real `1`).
+    ///
+    /// # Failure
+    ///
+    /// Will panic if `idx` is greater than or equal to `self.len()`.
+    #[cfg(feature = "regexp")]
+    pub(crate) fn get_text_raw(&self, idx: usize) -> &[u8] {
+        let arg = self.args[idx];
+        unsafe {
+            let text = ffi::sqlite3_value_text(arg);
+            if text.is_null() {
+                return &[];
+            }
+            // The length must be read after the conversion to text.
+            let len = ffi::sqlite3_value_bytes(arg);
+            slice::from_raw_parts(text, len as usize)
+        }
+    }
+
+    /// Returns the `idx`th argument as a reference to the value bound with
+    /// `Statement::bind_pointer` and the same `type_tag`, or `None` if the
+    /// argument is not such a value.
+    ///
+    /// # Failure
+    ///
+    /// Will panic if `idx` is greater than or equal to `self.len()`.
+    ///
+    /// Will return `Err(Error::PointerWrongType(idx))` if the value is not a
+    /// `T`.
+    #[cfg(feature = "pointer")]
+    pub fn get_pointer_arg<T: 'static>(&self, idx: usize, type_tag: &CStr) -> Result<Option<&T>> {
+        let arg = self.args[idx];
+        let p = unsafe { ffi::sqlite3_value_pointer(arg, type_tag.as_ptr()) };
+        if p.is_null() {
+            return Ok(None);
+        }
+        match unsafe { Tagged::<T>::get(p) } {
+            Some(value) => Ok(Some(value)),
+            None => Err(Error::PointerWrongType(idx)),
+        }
+    }
+
     /// Sets the auxilliary data associated with a particular parameter. See
     /// https://www.sqlite.org/c3ref/get_auxdata.html for a discussion of
-    /// this feature, or the unit tests of this module for an example.
+    /// this feature, or the example of this module.
+    ///
+    /// SQLite keeps the data, e.g. a regular expression compiled from a
+    /// constant argument, for the next rows of the statement, as long as the
+    /// argument does not change; it is dropped afterwards.
     pub fn set_aux<T: 'static>(&self, arg: c_int, value: T) {
-        let boxed = Box::into_raw(Box::new((std::any::TypeId::of::<T>(), value)));
         unsafe {
             ffi::sqlite3_set_auxdata(
                 self.ctx,
                 arg,
-                boxed as *mut c_void,
-                Some(free_boxed_value::<(std::any::TypeId, T)>),
+                Tagged::into_raw(value),
+                Some(Tagged::<T>::free),
             )
         };
     }
 
     /// Gets the auxilliary data that was associated with a given parameter
-    /// via `set_aux`. Returns `Ok(None)` if no data has been associated,
-    /// and .
+    /// via `set_aux`. Returns `Ok(None)` if no data has been associated (or
+    /// if SQLite has discarded it), and `Err(Error::GetAuxWrongType)` if the
+    /// data is not a `T`.
     pub fn get_aux<T: 'static>(&self, arg: c_int) -> Result<Option<&T>> {
-        let p = unsafe { ffi::sqlite3_get_auxdata(self.ctx, arg) as *mut (std::any::TypeId, T) };
+        let p = unsafe { ffi::sqlite3_get_auxdata(self.ctx, arg) };
         if p.is_null() {
             Ok(None)
         } else {
-            let id_val = unsafe { &*p };
-            if std::any::TypeId::of::<T>() != id_val.0 {
-                Err(Error::GetAuxWrongType)
-            } else {
-                Ok(Some(&id_val.1))
-            }
+            unsafe { Tagged::<T>::get(p) }
+                .map(Some)
+                .ok_or(Error::GetAuxWrongType)
         }
     }
 }
@@ -272,6 +366,60 @@ impl Connection {
             .create_scalar_function(fn_name, n_arg, deterministic, x_func)
     }
 
+    /// Attach a user-defined scalar function implemented by a future to this
+    /// database connection.
+    ///
+    /// `x_func` extracts the arguments from the `Context` and returns the
+    /// future computing the result, which is run on the tokio runtime of
+    /// `runtime` (so it can use its timers and I/O). The calling thread is
+    /// blocked until the future completes, like the SQLite statement being
+    /// executed.
+    ///
+    /// Since the thread is blocked, statements calling the function must not
+    /// run on a thread of the runtime, which panics: run them on a blocking
+    /// thread, e.g. through `AsyncConnection`. The timers and I/O of a
+    /// `current_thread` runtime are only driven while it is blocked on by
+    /// `Runtime::block_on`, so `runtime` should be a multi-thread runtime.
+    ///
+    /// See `create_scalar_function` for the other parameters.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use tokio::runtime::Handle;
+    /// # async fn fetch_price(symbol: String) -> Result<f64> { Ok(0.0) }
+    /// fn add_fetch_price(db: &Connection, runtime: Handle) -> Result<()> {
+    ///     db.create_async_scalar_function("fetch_price", 1, false, runtime, |ctx| {
+    ///         let symbol = ctx.get::<String>(0)?;
+    ///         Ok(fetch_price(symbol))
+    ///     })
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return Err if the function could not be attached to the connection.
+    #[cfg(feature = "tokio")]
+    pub fn create_async_scalar_function<F, Fut, T>(
+        &self,
+        fn_name: &str,
+        n_arg: c_int,
+        deterministic: bool,
+        runtime: tokio::runtime::Handle,
+        mut x_func: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&Context<'_>) -> Result<Fut> + Send + UnwindSafe + 'static,
+        Fut: Future<Output = Result<T>>,
+        T: ToSql,
+    {
+        let runtime = AssertUnwindSafe(runtime);
+        self.create_scalar_function(fn_name, n_arg, deterministic, move |ctx| {
+            x_func(ctx).and_then(|fut| runtime.block_on(fut))
+        })
+    }
+
     /// Attach a user-defined aggregate function to this database connection.
     ///
     /// # Failure
@@ -552,6 +700,35 @@ mod test {
         assert!((3f64 - result.unwrap()).abs() < EPSILON);
     }
 
+    #[test]
+    #[cfg(feature = "tokio")]
+    fn test_async_function() {
+        use std::time::Duration;
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_time()
+            .build()
+            .unwrap();
+        let db = Connection::open_in_memory().unwrap();
+        db.create_async_scalar_function("double", 1, true, runtime.handle().clone(), |ctx| {
+            let value = ctx.get::<i64>(0)?;
+            Ok(async move {
+                // Needs the timer of the runtime.
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                Ok(value * 2)
+            })
+        })
+        .unwrap();
+        let result: i64 = db
+            .query_row("SELECT double(21)", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(42, result);
+
+        let result: Result<i64> = db.query_row("SELECT double('a')", NO_PARAMS, |r| r.get(0));
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_remove_function() {
         let db = Connection::open_in_memory().unwrap();
@@ -656,6 +833,20 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_get_invalid_utf8() {
+        let db = Connection::open_in_memory().unwrap();
+        db.create_scalar_function("len", 1, true, |ctx| Ok(ctx.get::<String>(0)?.len() as i64))
+            .unwrap();
+        let len: i64 = db
+            .query_row("SELECT len('ab')", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(2, len);
+        let r: Result<i64> =
+            db.query_row("SELECT len(CAST(x'ff' AS TEXT))", NO_PARAMS, |r| r.get(0));
+        assert!(r.is_err());
+    }
+
     #[test]
     fn test_get_aux_type_checking() {
         let db = Connection::open_in_memory().unwrap();
@@ -681,6 +872,92 @@ mod test {
         assert!(res);
     }
 
+    #[test]
+    fn test_get_aux_cached() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let db = Connection::open_in_memory().unwrap();
+        let compiled = Arc::new(AtomicUsize::new(0));
+        let count = compiled.clone();
+        db.create_scalar_function("prefixed", 2, true, move |ctx| {
+            let prefix = match ctx.get_aux::<String>(0)? {
+                Some(prefix) => prefix.clone(),
+                None => {
+                    count.fetch_add(1, Ordering::SeqCst);
+                    let prefix = ctx.get::<String>(0)?.to_lowercase();
+                    ctx.set_aux(0, prefix.clone());
+                    prefix
+                }
+            };
+            Ok(ctx.get::<String>(1)?.to_lowercase().starts_with(&prefix))
+        })
+        .unwrap();
+
+        db.execute_batch(
+            "CREATE TABLE foo(x TEXT); INSERT INTO foo VALUES('abc'), ('ABD'), ('b');",
+        )
+        .unwrap();
+        let matches: i64 = db
+            .query_row(
+                "SELECT count(*) FROM foo WHERE prefixed('aB', x)",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(2, matches);
+        // Compiled once for the three rows.
+        assert_eq!(1, compiled.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    #[cfg(feature = "pointer")]
+    fn test_get_pointer_arg() {
+        use std::ffi::CStr;
+
+        let tag = CStr::from_bytes_with_nul(b"blocklist\0").unwrap();
+        let db = Connection::open_in_memory().unwrap();
+        db.create_scalar_function("in_blocklist", 2, false, move |ctx| {
+            assert_eq!(
+                ctx.get_pointer_arg::<i64>(0, tag),
+                Err(Error::PointerWrongType(0))
+            );
+            let other_tag = CStr::from_bytes_with_nul(b"other\0").unwrap();
+            assert_eq!(ctx.get_pointer_arg::<Vec<String>>(0, other_tag), Ok(None));
+            let name = ctx.get::<String>(1)?;
+            Ok(match ctx.get_pointer_arg::<Vec<String>>(0, tag)? {
+                Some(blocklist) => blocklist.contains(&name),
+                None => false,
+            })
+        })
+        .unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT in_blocklist(:blocklist, :name)")
+            .unwrap();
+        stmt.bind_pointer(":blocklist", vec!["eve".to_owned()], tag)
+            .unwrap();
+        let blocked: bool = stmt
+            .query_row_named(&[(":name", &"eve")], |r| r.get(0))
+            .unwrap();
+        assert!(blocked);
+        // Still bound.
+        let blocked: bool = stmt
+            .query_row_named(&[(":name", &"bob")], |r| r.get(0))
+            .unwrap();
+        assert!(!blocked);
+        // A pointer looks like NULL to SQL.
+        let is_null: bool = db
+            .prepare("SELECT :p IS NULL")
+            .and_then(|mut stmt| {
+                stmt.bind_pointer(":p", 1i64, tag)?;
+                stmt.query_row_named(&[], |r| r.get(0))
+            })
+            .unwrap();
+        assert!(is_null);
+        assert!(stmt.bind_pointer(":missing", 0, tag).is_err());
+    }
+
     struct Sum;
     struct Count;
 
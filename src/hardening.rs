@@ -0,0 +1,172 @@
+//! Security and durability pragmas.
+use crate::{Connection, Result};
+
+/// Whether deleted content is overwritten (see
+/// [`PRAGMA secure_delete`](https://sqlite.org/pragma.html#pragma_secure_delete)).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecureDelete {
+    /// Deleted content is left in the database file (default).
+    Off,
+    /// Deleted content is overwritten with zeros.
+    On,
+    /// Deleted content is overwritten with zeros when it does not increase
+    /// the I/O (SQLite >= 3.20.0, otherwise behaves as `On`).
+    Fast,
+}
+
+impl SecureDelete {
+    fn as_str(self) -> &'static str {
+        match self {
+            SecureDelete::Off => "OFF",
+            SecureDelete::On => "ON",
+            SecureDelete::Fast => "FAST",
+        }
+    }
+}
+
+/// Security related settings of a connection.
+///
+/// Settings left to `None` are not changed by
+/// `Connection::set_security_options`; `Connection::security_options`
+/// reports all of them.
+///
+/// ```rust,no_run
+/// # use rusqlite::{OpenOptions, Connection, Result, SecureDelete, SecurityOptions};
+/// fn open(path: &str) -> Result<Connection> {
+///     OpenOptions::new()
+///         .security_options(SecurityOptions {
+///             secure_delete: Some(SecureDelete::On),
+///             cell_size_check: Some(true),
+///         })
+///         .open(path)
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SecurityOptions {
+    /// See [`PRAGMA secure_delete`](https://sqlite.org/pragma.html#pragma_secure_delete).
+    pub secure_delete: Option<SecureDelete>,
+    /// Check the b-tree pages for corruption when they are read (see
+    /// [`PRAGMA cell_size_check`](https://sqlite.org/pragma.html#pragma_cell_size_check)).
+    pub cell_size_check: Option<bool>,
+}
+
+/// Durability related settings of a connection.
+///
+/// Settings left to `None` are not changed by
+/// `Connection::set_durability_options`; `Connection::durability_options`
+/// reports all of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DurabilityOptions {
+    /// Use `F_FULLFSYNC` on checkpoints, on the platforms supporting it (see
+    /// [`PRAGMA checkpoint_fullfsync`](https://sqlite.org/pragma.html#pragma_checkpoint_fullfsync)).
+    pub checkpoint_fullfsync: Option<bool>,
+}
+
+impl Connection {
+    /// Return the effective security settings of the main database.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn security_options(&self) -> Result<SecurityOptions> {
+        let secure_delete: i32 =
+            self.pragma_query_value(None, "secure_delete", |row| row.get(0))?;
+        let cell_size_check = self.pragma_query_value(None, "cell_size_check", |row| row.get(0))?;
+        Ok(SecurityOptions {
+            secure_delete: Some(match secure_delete {
+                0 => SecureDelete::Off,
+                1 => SecureDelete::On,
+                _ => SecureDelete::Fast,
+            }),
+            cell_size_check: Some(cell_size_check),
+        })
+    }
+
+    /// Apply the security settings which are not `None`, to all the
+    /// attached databases.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn set_security_options(&self, options: &SecurityOptions) -> Result<()> {
+        if let Some(secure_delete) = options.secure_delete {
+            self.pragma_update(None, "secure_delete", &secure_delete.as_str())?;
+        }
+        if let Some(cell_size_check) = options.cell_size_check {
+            self.pragma_update(None, "cell_size_check", &cell_size_check)?;
+        }
+        Ok(())
+    }
+
+    /// Return the effective durability settings of the connection.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn durability_options(&self) -> Result<DurabilityOptions> {
+        let checkpoint_fullfsync =
+            self.pragma_query_value(None, "checkpoint_fullfsync", |row| row.get(0))?;
+        Ok(DurabilityOptions {
+            checkpoint_fullfsync: Some(checkpoint_fullfsync),
+        })
+    }
+
+    /// Apply the durability settings which are not `None`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn set_durability_options(&self, options: &DurabilityOptions) -> Result<()> {
+        if let Some(checkpoint_fullfsync) = options.checkpoint_fullfsync {
+            self.pragma_update(None, "checkpoint_fullfsync", &checkpoint_fullfsync)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DurabilityOptions, SecureDelete, SecurityOptions};
+    use crate::{Connection, OpenOptions};
+
+    #[test]
+    fn test_security_and_durability_options() {
+        let db = Connection::open_in_memory().unwrap();
+        let defaults = db.security_options().unwrap();
+        assert_eq!(Some(false), defaults.cell_size_check);
+        db.set_security_options(&SecurityOptions::default())
+            .unwrap();
+        assert_eq!(defaults, db.security_options().unwrap());
+
+        let db = OpenOptions::new()
+            .security_options(SecurityOptions {
+                secure_delete: Some(SecureDelete::On),
+                cell_size_check: Some(true),
+            })
+            .durability_options(DurabilityOptions {
+                checkpoint_fullfsync: Some(true),
+            })
+            .open_in_memory()
+            .unwrap();
+        assert_eq!(
+            SecurityOptions {
+                secure_delete: Some(SecureDelete::On),
+                cell_size_check: Some(true),
+            },
+            db.security_options().unwrap()
+        );
+        assert_eq!(
+            Some(true),
+            db.durability_options().unwrap().checkpoint_fullfsync
+        );
+
+        db.set_security_options(&SecurityOptions {
+            secure_delete: Some(SecureDelete::Off),
+            cell_size_check: None,
+        })
+        .unwrap();
+        let options = db.security_options().unwrap();
+        assert_eq!(Some(SecureDelete::Off), options.secure_delete);
+        assert_eq!(Some(true), options.cell_size_check);
+    }
+}
@@ -1,12 +1,13 @@
 //! Commit, Data Change and Rollback Notification Callbacks
 #![allow(non_camel_case_types)]
 
-use std::os::raw::{c_char, c_int, c_void};
+use std::os::raw::{c_int, c_void};
 use std::panic::catch_unwind;
 use std::ptr;
 
 use crate::ffi;
 
+use crate::tx_hooks::{TxBuffer, UpdateHook};
 use crate::{Connection, InnerConnection};
 
 /// Action Codes
@@ -34,7 +35,12 @@ impl Connection {
     /// Register a callback function to be invoked whenever a transaction is
     /// committed.
     ///
-    /// The callback returns `true` to rollback.
+    /// The callback returns `true` to rollback, e.g. to veto a commit; the
+    /// `COMMIT` then fails with `SQLITE_CONSTRAINT_COMMITHOOK`.
+    ///
+    /// The callback replaces the previous one, if any, which is dropped.
+    /// `None` clears the callback. The callback is also dropped when the
+    /// connection is closed.
     pub fn commit_hook<F>(&self, hook: Option<F>)
     where
         F: FnMut() -> bool + Send + 'static,
@@ -43,9 +49,12 @@ impl Connection {
     }
 
     /// Register a callback function to be invoked whenever a transaction is
-    /// committed.
+    /// rolled back (explicitly, or because of an error or a commit vetoed by
+    /// the commit hook).
     ///
-    /// The callback returns `true` to rollback.
+    /// The callback replaces the previous one, if any, which is dropped.
+    /// `None` clears the callback. The callback is also dropped when the
+    /// connection is closed.
     pub fn rollback_hook<F>(&self, hook: Option<F>)
     where
         F: FnMut() + Send + 'static,
@@ -59,7 +68,7 @@ impl Connection {
     /// The callback parameters are:
     ///
     /// - the type of database update (SQLITE_INSERT, SQLITE_UPDATE or
-    /// SQLITE_DELETE),
+    ///   SQLITE_DELETE),
     /// - the name of the database ("main", "temp", ...),
     /// - the name of the table that is updated,
     /// - the ROWID of the row that is updated.
@@ -69,16 +78,79 @@ impl Connection {
     {
         self.db.borrow_mut().update_hook(hook);
     }
+
+    /// Register a callback function to be invoked periodically during long
+    /// running calls like `execute` or `Rows::next`, about every `num_ops`
+    /// virtual machine instructions, e.g. to update a progress indicator.
+    ///
+    /// The callback returns `true` to interrupt the operation, which then
+    /// fails with `SQLITE_INTERRUPT`: this allows runaway queries to be
+    /// aborted without calling an `InterruptHandle` from another thread.
+    ///
+    /// The callback replaces the previous one, if any, which is dropped.
+    /// `None`, or a `num_ops` lower than 1, clears the callback.
+    pub fn progress_handler<F>(&self, num_ops: i32, handler: Option<F>)
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        self.db.borrow_mut().progress_handler(num_ops, handler);
+    }
+
+    /// Record the `(table, rowid)` of the rows inserted in rowid tables, up
+    /// to `max_rows` (see `take_recent_inserts`), or stop recording them if
+    /// `max_rows` is 0.
+    ///
+    /// This is useful to recover the ids generated by a batch of `INSERT`s
+    /// without `RETURNING` (or `last_insert_rowid` after each statement).
+    /// Rows are only returned once the transaction which inserted them is
+    /// committed, and are forgotten if the statement or transaction which
+    /// inserted them fails or is rolled back (also by `ROLLBACK TO`). Once
+    /// `max_rows` rows have been recorded (and not taken), the oldest ones are
+    /// discarded.
+    ///
+    /// This is implemented with the update hook, to which the callback of
+    /// `update_hook`, if any, is chained.
+    pub fn track_inserts(&self, max_rows: usize) {
+        let c = self.db.borrow_mut();
+        *c.tx_hooks.inserts.borrow_mut() = if max_rows == 0 {
+            None
+        } else {
+            Some(TxBuffer::new(max_rows))
+        };
+        c.tx_hooks.sync_update_hook();
+    }
+
+    /// Return, and forget, the `(table, rowid)` of the rows inserted by
+    /// committed transactions since the last call, in insertion order (see
+    /// `track_inserts`).
+    pub fn take_recent_inserts(&self) -> Vec<(String, i64)> {
+        let c = self.db.borrow();
+        let mut inserts = c.tx_hooks.inserts.borrow_mut();
+        match *inserts {
+            Some(ref mut inserts) => inserts.take_committed(),
+            None => Vec::new(),
+        }
+    }
 }
 
 impl InnerConnection {
     pub fn remove_hooks(&mut self) {
-        self.update_hook(None::<fn(Action, &str, &str, i64)>);
         self.commit_hook(None::<fn() -> bool>);
-        self.rollback_hook(None::<fn()>);
+        self.progress_handler(0, None::<fn() -> bool>);
+        #[cfg(feature = "preupdate_hook")]
+        self.capture_changes(None);
+    }
+
+    /// Drop the hooks of a connection which has been closed, without
+    /// unregistering them.
+    pub(crate) fn free_hooks(&mut self) {
+        if let Some((boxed_hook, free_boxed_hook)) = self.commit_hook.take() {
+            free_boxed_hook(boxed_hook);
+        }
+        self.progress_handler = None;
     }
 
-    fn commit_hook<F>(&mut self, hook: Option<F>)
+    pub(crate) fn commit_hook<F>(&mut self, hook: Option<F>)
     where
         F: FnMut() -> bool + Send + 'static,
     {
@@ -98,15 +170,10 @@ impl InnerConnection {
         }
 
         // unlike `sqlite3_create_function_v2`, we cannot specify a `xDestroy` with
-        // `sqlite3_commit_hook`. so we keep the `xDestroy` function in
-        // `InnerConnection.free_boxed_hook`.
-        let free_commit_hook = if hook.is_some() {
-            Some(free_boxed_hook::<F> as fn(*mut c_void))
-        } else {
-            None
-        };
-
-        let previous_hook = match hook {
+        // `sqlite3_commit_hook`. so we keep the `xDestroy` function, with the
+        // closure, in `InnerConnection.commit_hook`.
+        let previous_hook = self.commit_hook.take();
+        match hook {
             Some(hook) => {
                 let boxed_hook: *mut F = Box::into_raw(Box::new(hook));
                 unsafe {
@@ -114,117 +181,76 @@ impl InnerConnection {
                         self.db(),
                         Some(call_boxed_closure::<F>),
                         boxed_hook as *mut _,
-                    )
+                    );
                 }
+                self.commit_hook = Some((boxed_hook as *mut c_void, free_boxed_hook::<F>));
             }
-            _ => unsafe { ffi::sqlite3_commit_hook(self.db(), None, ptr::null_mut()) },
-        };
-        if !previous_hook.is_null() {
-            if let Some(free_boxed_hook) = self.free_commit_hook {
-                free_boxed_hook(previous_hook);
-            }
+            _ => unsafe {
+                ffi::sqlite3_commit_hook(self.db(), None, ptr::null_mut());
+            },
+        }
+        if let Some((boxed_hook, free_boxed_hook)) = previous_hook {
+            free_boxed_hook(boxed_hook);
         }
-        self.free_commit_hook = free_commit_hook;
     }
 
-    fn rollback_hook<F>(&mut self, hook: Option<F>)
+    pub(crate) fn rollback_hook<F>(&mut self, hook: Option<F>)
     where
         F: FnMut() + Send + 'static,
     {
-        unsafe extern "C" fn call_boxed_closure<F>(p_arg: *mut c_void)
+        // Chained by the rollback hook which rusqlite registers on every
+        // connection (see `tx_hooks`).
+        *self.tx_hooks.rollback.borrow_mut() =
+            hook.map(|hook| Box::new(hook) as Box<dyn FnMut() + Send>);
+    }
+
+    fn progress_handler<F>(&mut self, num_ops: c_int, handler: Option<F>)
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        unsafe extern "C" fn call_boxed_closure<F>(p_arg: *mut c_void) -> c_int
         where
-            F: FnMut(),
+            F: FnMut() -> bool,
         {
-            let _ = catch_unwind(|| {
-                let boxed_hook: *mut F = p_arg as *mut F;
-                (*boxed_hook)();
+            let r = catch_unwind(|| {
+                let boxed_handler: *mut F = p_arg as *mut F;
+                (*boxed_handler)()
             });
+            if let Ok(true) = r {
+                1
+            } else {
+                0
+            }
         }
 
-        let free_rollback_hook = if hook.is_some() {
-            Some(free_boxed_hook::<F> as fn(*mut c_void))
-        } else {
-            None
-        };
-
-        let previous_hook = match hook {
-            Some(hook) => {
-                let boxed_hook: *mut F = Box::into_raw(Box::new(hook));
+        match handler {
+            Some(handler) if num_ops > 0 => {
+                // SQLite does not return the previous handler: the closure is
+                // owned by the connection, and dropped once replaced.
+                let mut boxed_handler = Box::new(handler);
                 unsafe {
-                    ffi::sqlite3_rollback_hook(
+                    ffi::sqlite3_progress_handler(
                         self.db(),
+                        num_ops,
                         Some(call_boxed_closure::<F>),
-                        boxed_hook as *mut _,
+                        &mut *boxed_handler as *mut F as *mut _,
                     )
-                }
+                };
+                self.progress_handler = Some(boxed_handler);
             }
-            _ => unsafe { ffi::sqlite3_rollback_hook(self.db(), None, ptr::null_mut()) },
-        };
-        if !previous_hook.is_null() {
-            if let Some(free_boxed_hook) = self.free_rollback_hook {
-                free_boxed_hook(previous_hook);
+            _ => {
+                unsafe { ffi::sqlite3_progress_handler(self.db(), 0, None, ptr::null_mut()) };
+                self.progress_handler = None;
             }
         }
-        self.free_rollback_hook = free_rollback_hook;
     }
 
     fn update_hook<F>(&mut self, hook: Option<F>)
     where
         F: FnMut(Action, &str, &str, i64) + Send + 'static,
     {
-        unsafe extern "C" fn call_boxed_closure<F>(
-            p_arg: *mut c_void,
-            action_code: c_int,
-            db_str: *const c_char,
-            tbl_str: *const c_char,
-            row_id: i64,
-        ) where
-            F: FnMut(Action, &str, &str, i64),
-        {
-            use std::ffi::CStr;
-            use std::str;
-
-            let action = Action::from(action_code);
-            let db_name = {
-                let c_slice = CStr::from_ptr(db_str).to_bytes();
-                str::from_utf8_unchecked(c_slice)
-            };
-            let tbl_name = {
-                let c_slice = CStr::from_ptr(tbl_str).to_bytes();
-                str::from_utf8_unchecked(c_slice)
-            };
-
-            let _ = catch_unwind(|| {
-                let boxed_hook: *mut F = p_arg as *mut F;
-                (*boxed_hook)(action, db_name, tbl_name, row_id);
-            });
-        }
-
-        let free_update_hook = if hook.is_some() {
-            Some(free_boxed_hook::<F> as fn(*mut c_void))
-        } else {
-            None
-        };
-
-        let previous_hook = match hook {
-            Some(hook) => {
-                let boxed_hook: *mut F = Box::into_raw(Box::new(hook));
-                unsafe {
-                    ffi::sqlite3_update_hook(
-                        self.db(),
-                        Some(call_boxed_closure::<F>),
-                        boxed_hook as *mut _,
-                    )
-                }
-            }
-            _ => unsafe { ffi::sqlite3_update_hook(self.db(), None, ptr::null_mut()) },
-        };
-        if !previous_hook.is_null() {
-            if let Some(free_boxed_hook) = self.free_update_hook {
-                free_boxed_hook(previous_hook);
-            }
-        }
-        self.free_update_hook = free_update_hook;
+        *self.tx_hooks.update.borrow_mut() = hook.map(|hook| Box::new(hook) as UpdateHook);
+        self.tx_hooks.sync_update_hook();
     }
 }
 
@@ -236,7 +262,8 @@ fn free_boxed_hook<F>(p: *mut c_void) {
 mod test {
     use super::Action;
     use crate::Connection;
-    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
 
     #[test]
     fn test_commit_hook() {
@@ -282,6 +309,66 @@ mod test {
         assert!(CALLED.load(Ordering::Relaxed));
     }
 
+    #[test]
+    fn test_veto_commit_and_clear_hooks() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (t TEXT)").unwrap();
+
+        let rollbacks = Arc::new(AtomicUsize::new(0));
+        let counter = rollbacks.clone();
+        db.rollback_hook(Some(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }));
+        db.commit_hook(Some(|| true));
+        db.execute("INSERT INTO foo VALUES ('a')", crate::NO_PARAMS)
+            .unwrap_err();
+        assert_eq!(1, rollbacks.load(Ordering::Relaxed));
+
+        // The closures are dropped when the hooks are cleared.
+        db.commit_hook(None::<fn() -> bool>);
+        db.rollback_hook(None::<fn()>);
+        assert_eq!(1, Arc::strong_count(&rollbacks));
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES ('b'); ROLLBACK;")
+            .unwrap();
+        db.execute("INSERT INTO foo VALUES ('c')", crate::NO_PARAMS)
+            .unwrap();
+        assert_eq!(1, rollbacks.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_hooks_kept_when_close_fails() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (t TEXT)").unwrap();
+        let commits = Arc::new(AtomicUsize::new(0));
+        let rollbacks = Arc::new(AtomicUsize::new(0));
+        let counter = commits.clone();
+        db.commit_hook(Some(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+            false
+        }));
+        let counter = rollbacks.clone();
+        db.rollback_hook(Some(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }));
+        db.track_inserts(10);
+
+        // An unfinalized statement prevents the connection from closing.
+        std::mem::forget(db.prepare("SELECT 1").unwrap());
+        let (db, _) = db.close().unwrap_err();
+        db.execute("INSERT INTO foo VALUES ('a')", crate::NO_PARAMS)
+            .unwrap();
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES ('b'); ROLLBACK;")
+            .unwrap();
+        assert_eq!(1, commits.load(Ordering::Relaxed));
+        assert_eq!(1, rollbacks.load(Ordering::Relaxed));
+        assert_eq!(vec![("foo".to_owned(), 1)], db.take_recent_inserts());
+
+        // The closures are dropped once the connection is closed.
+        db.force_close().unwrap();
+        assert_eq!(1, Arc::strong_count(&commits));
+        assert_eq!(1, Arc::strong_count(&rollbacks));
+    }
+
     #[test]
     fn test_update_hook() {
         let db = Connection::open_in_memory().unwrap();
@@ -300,4 +387,102 @@ mod test {
         db.execute_batch("INSERT INTO foo VALUES ('lisa')").unwrap();
         assert!(CALLED.load(Ordering::Relaxed));
     }
+
+    #[test]
+    fn test_track_inserts() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (t TEXT); CREATE TABLE bar (t TEXT);")
+            .unwrap();
+        db.execute_batch("INSERT INTO foo VALUES ('before')")
+            .unwrap();
+
+        db.track_inserts(10);
+        db.execute_batch(
+            "INSERT INTO foo VALUES ('a'), ('b');
+             UPDATE foo SET t = 'c' WHERE rowid = 1;
+             INSERT INTO bar VALUES ('d');",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                ("foo".to_owned(), 2),
+                ("foo".to_owned(), 3),
+                ("bar".to_owned(), 1)
+            ],
+            db.take_recent_inserts()
+        );
+        assert!(db.take_recent_inserts().is_empty());
+
+        db.track_inserts(0);
+        db.execute_batch("INSERT INTO foo VALUES ('e')").unwrap();
+        assert!(db.take_recent_inserts().is_empty());
+    }
+
+    #[test]
+    fn test_track_inserts_transactions() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (t TEXT UNIQUE)")
+            .unwrap();
+        let updates = Arc::new(AtomicUsize::new(0));
+        let counter = updates.clone();
+        db.update_hook(Some(move |_, _: &str, _: &str, _| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }));
+        db.track_inserts(2);
+
+        // not committed yet, then rolled back
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES ('a');")
+            .unwrap();
+        assert!(db.take_recent_inserts().is_empty());
+        db.execute_batch("ROLLBACK").unwrap();
+        assert!(db.take_recent_inserts().is_empty());
+
+        // a failed statement within a committed transaction
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES ('b');")
+            .unwrap();
+        db.execute_batch("INSERT INTO foo VALUES ('c'), ('b')")
+            .unwrap_err();
+        db.execute_batch("COMMIT").unwrap();
+        assert_eq!(vec![("foo".to_owned(), 1)], db.take_recent_inserts());
+
+        // only the most recent rows are kept
+        db.execute_batch("INSERT INTO foo VALUES ('d'), ('e'), ('f')")
+            .unwrap();
+        assert_eq!(
+            vec![("foo".to_owned(), 3), ("foo".to_owned(), 4)],
+            db.take_recent_inserts()
+        );
+        // the update hook is chained
+        assert_eq!(6, updates.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_progress_handler() {
+        let db = Connection::open_in_memory().unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        db.progress_handler(
+            100,
+            Some(move || counted.fetch_add(1, Ordering::SeqCst) >= 10),
+        );
+        let r = db.execute_batch(
+            "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c)
+             SELECT count(*) FROM c",
+        );
+        match r {
+            Err(crate::Error::SqliteFailure(err, _)) => {
+                assert_eq!(crate::ErrorCode::OperationInterrupted, err.code)
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert_eq!(11, calls.load(Ordering::SeqCst));
+
+        db.progress_handler(0, None::<fn() -> bool>);
+        db.execute_batch(
+            "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c LIMIT 10000)
+             SELECT count(*) FROM c",
+        )
+        .unwrap();
+        assert_eq!(11, calls.load(Ordering::SeqCst));
+    }
 }
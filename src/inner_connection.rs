@@ -1,6 +1,6 @@
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::mem;
-use std::os::raw::c_int;
+use std::os::raw::{c_char, c_int};
 #[cfg(feature = "load_extension")]
 use std::path::Path;
 use std::ptr;
@@ -10,10 +10,20 @@ use std::sync::{Arc, Mutex, Once, ONCE_INIT};
 
 use super::ffi;
 use super::{str_for_sqlite, str_to_cstring};
-use super::{Connection, InterruptHandle, OpenFlags, Result};
+use super::{Connection, ErrorCode, InterruptHandle, OpenFlags, Result};
+use crate::authorizer::Authorizers;
+use crate::auto_batch::AutoBatch;
+use crate::busy::{BusyDiagnostics, BusyHandler};
+use crate::decoders::ColumnDecoders;
 use crate::error::{error_from_handle, error_from_sqlite_code, Error};
-use crate::raw_statement::RawStatement;
+use crate::event_log::{EventLog, StatementOutcome};
+use crate::io_counters::IoTotals;
+use crate::raw_statement::{RawStatement, TrackedStatements};
+use crate::sandbox::SandboxLimits;
+use crate::sql_guard::ActiveGuard;
 use crate::statement::Statement;
+use crate::temp_store;
+use crate::tx_hooks::TxHooks;
 use crate::unlock_notify;
 use crate::version::version_number;
 
@@ -26,12 +36,28 @@ pub struct InnerConnection {
     // Otherwise, a long running query would prevent calling interrupt, as
     // interrupt would only acquire the lock after the query's completion.
     interrupt_lock: Arc<Mutex<*mut ffi::sqlite3>>,
+    // Set by `InterruptHandle::interrupt`, so that `execute_batch` stops
+    // between statements (when SQLite itself ignores interrupts).
+    pub(crate) interrupted: Arc<AtomicBool>,
+    // The closure registered with `sqlite3_commit_hook`, and the function
+    // dropping it.
     #[cfg(feature = "hooks")]
-    pub free_commit_hook: Option<fn(*mut ::std::os::raw::c_void)>,
+    pub commit_hook: Option<(*mut ::std::os::raw::c_void, fn(*mut ::std::os::raw::c_void))>,
     #[cfg(feature = "hooks")]
-    pub free_rollback_hook: Option<fn(*mut ::std::os::raw::c_void)>,
-    #[cfg(feature = "hooks")]
-    pub free_update_hook: Option<fn(*mut ::std::os::raw::c_void)>,
+    pub progress_handler: Option<Box<dyn FnMut() -> bool + Send>>,
+    pub(crate) busy_handler: Option<Box<BusyHandler>>,
+    pub(crate) tx_hooks: Box<TxHooks>,
+    pub(crate) busy_context: Option<BusyDiagnostics>,
+    pub(crate) authorizers: Box<Authorizers>,
+    pub(crate) sandbox_limits: SandboxLimits,
+    pub(crate) event_log: Option<EventLog>,
+    pub(crate) auto_batch: Option<AutoBatch>,
+    pub(crate) io_totals: IoTotals,
+    pub(crate) optimize_on_close: bool,
+    pub(crate) decoders: ColumnDecoders,
+    pub(crate) sql_guard: Option<ActiveGuard>,
+    // Statements prepared through `prepare` and not finalized yet
+    statements: TrackedStatements,
     owned: bool,
 }
 
@@ -41,6 +67,19 @@ impl InnerConnection {
         InnerConnection {
             db,
             interrupt_lock: Arc::new(Mutex::new(db)),
+            interrupted: Arc::new(AtomicBool::new(false)),
+            busy_handler: None,
+            tx_hooks: TxHooks::install(db),
+            busy_context: None,
+            authorizers: Box::default(),
+            sandbox_limits: SandboxLimits::default(),
+            event_log: None,
+            auto_batch: None,
+            io_totals: IoTotals::default(),
+            optimize_on_close: false,
+            decoders: ColumnDecoders::new(),
+            sql_guard: None,
+            statements: TrackedStatements::default(),
             owned,
         }
     }
@@ -50,14 +89,30 @@ impl InnerConnection {
         InnerConnection {
             db,
             interrupt_lock: Arc::new(Mutex::new(db)),
-            free_commit_hook: None,
-            free_rollback_hook: None,
-            free_update_hook: None,
+            interrupted: Arc::new(AtomicBool::new(false)),
+            commit_hook: None,
+            progress_handler: None,
+            busy_handler: None,
+            tx_hooks: TxHooks::install(db),
+            busy_context: None,
+            authorizers: Box::default(),
+            sandbox_limits: SandboxLimits::default(),
+            event_log: None,
+            auto_batch: None,
+            io_totals: IoTotals::default(),
+            optimize_on_close: false,
+            decoders: ColumnDecoders::new(),
+            sql_guard: None,
+            statements: TrackedStatements::default(),
             owned,
         }
     }
 
-    pub fn open_with_flags(c_path: &CString, flags: OpenFlags) -> Result<InnerConnection> {
+    pub fn open_with_flags(
+        c_path: &CString,
+        flags: OpenFlags,
+        vfs: Option<&CString>,
+    ) -> Result<InnerConnection> {
         #[cfg(not(feature = "bundled"))]
         ensure_valid_sqlite_version();
         ensure_safe_sqlite_threading_mode()?;
@@ -77,32 +132,46 @@ impl InnerConnection {
             ));
         }
 
-        unsafe {
-            let mut db: *mut ffi::sqlite3 = mem::uninitialized();
-            let r = ffi::sqlite3_open_v2(c_path.as_ptr(), &mut db, flags.bits(), ptr::null());
-            if r != ffi::SQLITE_OK {
-                let e = if db.is_null() {
-                    error_from_sqlite_code(r, None)
-                } else {
-                    let e = error_from_handle(db, r);
-                    ffi::sqlite3_close(db);
-                    e
-                };
-
-                return Err(e);
-            }
-            let r = ffi::sqlite3_busy_timeout(db, 5000);
-            if r != ffi::SQLITE_OK {
+        // Counted before opening, so that the temp directory is not changed
+        // while SQLite may read it.
+        temp_store::connection_opened();
+        let r = unsafe { InnerConnection::open_raw(c_path, flags, vfs) };
+        if r.is_err() {
+            temp_store::connection_closed();
+        }
+        r.map(|db| InnerConnection::new(db, true))
+    }
+
+    unsafe fn open_raw(
+        c_path: &CString,
+        flags: OpenFlags,
+        vfs: Option<&CString>,
+    ) -> Result<*mut ffi::sqlite3> {
+        let mut db: *mut ffi::sqlite3 = mem::uninitialized();
+        let z_vfs = vfs.map_or(ptr::null(), |vfs| vfs.as_ptr());
+        let r = ffi::sqlite3_open_v2(c_path.as_ptr(), &mut db, flags.bits(), z_vfs);
+        if r != ffi::SQLITE_OK {
+            let e = if db.is_null() {
+                error_from_sqlite_code(r, None)
+            } else {
                 let e = error_from_handle(db, r);
                 ffi::sqlite3_close(db);
-                return Err(e);
-            }
-
-            // attempt to turn on extended results code; don't fail if we can't.
-            ffi::sqlite3_extended_result_codes(db, 1);
+                e
+            };
 
-            Ok(InnerConnection::new(db, true))
+            return Err(e);
         }
+        let r = ffi::sqlite3_busy_timeout(db, 5000);
+        if r != ffi::SQLITE_OK {
+            let e = error_from_handle(db, r);
+            ffi::sqlite3_close(db);
+            return Err(e);
+        }
+
+        // attempt to turn on extended results code; don't fail if we can't.
+        ffi::sqlite3_extended_result_codes(db, 1);
+
+        Ok(db)
     }
 
     pub fn db(&self) -> *mut ffi::sqlite3 {
@@ -110,7 +179,11 @@ impl InnerConnection {
     }
 
     pub fn decode_result(&mut self, code: c_int) -> Result<()> {
-        InnerConnection::decode_result_raw(self.db(), code)
+        let r = InnerConnection::decode_result_raw(self.db(), code);
+        if code & 0xff == ffi::SQLITE_BUSY {
+            self.capture_busy_context(code);
+        }
+        r
     }
 
     fn decode_result_raw(db: *mut ffi::sqlite3, code: c_int) -> Result<()> {
@@ -125,13 +198,20 @@ impl InnerConnection {
         if self.db.is_null() {
             return Ok(());
         }
-        self.remove_hooks();
-        let mut shared_handle = self.interrupt_lock.lock().unwrap();
+        if self.owned && self.optimize_on_close {
+            let _ = self.execute_internal("PRAGMA optimize");
+        }
+        // Cloned so that the hooks can be removed while the lock is held.
+        let interrupt_lock = Arc::clone(&self.interrupt_lock);
+        let mut shared_handle = interrupt_lock.lock().unwrap();
         assert!(
             !shared_handle.is_null(),
             "Bug: Somehow interrupt_lock was cleared before the DB was closed"
         );
         if !self.owned {
+            // The handle outlives the hooks.
+            self.remove_hooks();
+            self.tx_hooks.uninstall(self.db);
             self.db = ptr::null_mut();
             return Ok(());
         }
@@ -140,32 +220,107 @@ impl InnerConnection {
             // Need to use _raw because _guard has a reference out, and
             // decode_result takes &mut self.
             let r = InnerConnection::decode_result_raw(self.db, r);
+            // The hooks are kept until the connection is closed, since it is
+            // still usable if closing it fails (e.g. with `SQLITE_BUSY`).
             if r.is_ok() {
                 *shared_handle = ptr::null_mut();
                 self.db = ptr::null_mut();
+                self.free_hooks();
+                self.tx_hooks.clear();
+                temp_store::connection_closed();
+            }
+            r.map_err(|err| match err {
+                // Tell which statements prevent the connection from closing.
+                Error::SqliteFailure(err, msg) if err.code == ErrorCode::DatabaseBusy => {
+                    let unfinalized = self.unfinalized_statements();
+                    let msg = if unfinalized.is_empty() {
+                        msg
+                    } else {
+                        Some(format!(
+                            "{} (unfinalized statements: {})",
+                            msg.as_ref().map_or("database is busy", |m| &m[..]),
+                            unfinalized.join("; ")
+                        ))
+                    };
+                    Error::SqliteFailure(err, msg)
+                }
+                err => err,
+            })
+        }
+    }
+
+    /// Return the SQL of the statements of the connection which have not been
+    /// finalized.
+    fn unfinalized_statements(&self) -> Vec<String> {
+        let mut sqls = Vec::new();
+        unsafe {
+            let mut stmt = ffi::sqlite3_next_stmt(self.db, ptr::null_mut());
+            while !stmt.is_null() {
+                let sql = ffi::sqlite3_sql(stmt);
+                if !sql.is_null() {
+                    sqls.push(CStr::from_ptr(sql).to_string_lossy().into_owned());
+                }
+                stmt = ffi::sqlite3_next_stmt(self.db, stmt);
+            }
+        }
+        sqls
+    }
+
+    /// Finalize the statements prepared by this connection which are still
+    /// open (e.g. leaked with `std::mem::forget`), and return how many there
+    /// were. The statements prepared by other code through the raw handle are
+    /// left alone.
+    pub fn finalize_statements(&mut self) -> usize {
+        if self.db.is_null() {
+            return 0;
+        }
+        let mut tracked = self.statements.lock().unwrap();
+        let mut count = 0;
+        unsafe {
+            let mut stmt = ffi::sqlite3_next_stmt(self.db, ptr::null_mut());
+            while !stmt.is_null() {
+                let next = ffi::sqlite3_next_stmt(self.db, stmt);
+                if tracked.contains(&(stmt as usize)) {
+                    ffi::sqlite3_finalize(stmt);
+                    count += 1;
+                }
+                stmt = next;
             }
-            r
         }
+        tracked.clear();
+        count
     }
 
     pub fn get_interrupt_handle(&self) -> InterruptHandle {
         InterruptHandle {
             db_lock: Arc::clone(&self.interrupt_lock),
+            interrupted: Arc::clone(&self.interrupted),
         }
     }
 
     pub fn execute_batch(&mut self, sql: &str) -> Result<()> {
+        self.check_sql(sql)?;
         let c_sql = str_to_cstring(sql)?;
-        unsafe {
-            let r = ffi::sqlite3_exec(
+        let start = self.event_start();
+        let total_changes = self.total_changes();
+        let r = unsafe {
+            ffi::sqlite3_exec(
                 self.db(),
                 c_sql.as_ptr(),
                 None,
                 ptr::null_mut(),
                 ptr::null_mut(),
-            );
-            self.decode_result(r)
+            )
+        };
+        let r = self.decode_result(r);
+        if let Some(start) = start {
+            let outcome = match r {
+                Ok(()) => StatementOutcome::Executed(self.total_changes() - total_changes),
+                Err(ref err) => StatementOutcome::Failed(err.to_string()),
+            };
+            self.record_event(start, sql, outcome);
         }
+        r
     }
 
     #[cfg(feature = "load_extension")]
@@ -207,19 +362,25 @@ impl InnerConnection {
     }
 
     pub fn prepare<'a>(&mut self, conn: &'a Connection, sql: &str) -> Result<Statement<'a>> {
+        self.prepare_with_tail(conn, sql).map(|(stmt, _)| stmt)
+    }
+
+    /// Prepare the first statement of `sql`, and return it with the byte
+    /// offset of the remaining (unparsed) SQL.
+    pub fn prepare_with_tail<'a>(
+        &mut self,
+        conn: &'a Connection,
+        sql: &str,
+    ) -> Result<(Statement<'a>, usize)> {
+        self.check_sql(sql)?;
         let mut c_stmt: *mut ffi::sqlite3_stmt = unsafe { mem::uninitialized() };
         let (c_sql, len, _) = str_for_sqlite(sql)?;
+        let mut c_tail: *const c_char = c_sql;
         let r = unsafe {
             if cfg!(feature = "unlock_notify") {
                 let mut rc;
                 loop {
-                    rc = ffi::sqlite3_prepare_v2(
-                        self.db(),
-                        c_sql,
-                        len,
-                        &mut c_stmt,
-                        ptr::null_mut(),
-                    );
+                    rc = ffi::sqlite3_prepare_v2(self.db(), c_sql, len, &mut c_stmt, &mut c_tail);
                     if !unlock_notify::is_locked(self.db, rc) {
                         break;
                     }
@@ -230,22 +391,38 @@ impl InnerConnection {
                 }
                 rc
             } else {
-                ffi::sqlite3_prepare_v2(self.db(), c_sql, len, &mut c_stmt, ptr::null_mut())
+                ffi::sqlite3_prepare_v2(self.db(), c_sql, len, &mut c_stmt, &mut c_tail)
             }
         };
-        self.decode_result(r)
-            .map(|_| Statement::new(conn, RawStatement::new(c_stmt)))
+        self.decode_result(r)?;
+        let tail = if c_tail.is_null() {
+            sql.len()
+        } else {
+            c_tail as usize - c_sql as usize
+        };
+        Ok((
+            Statement::new(
+                conn,
+                RawStatement::tracked(c_stmt, &self.statements),
+                &self.decoders,
+            ),
+            tail,
+        ))
     }
 
     pub fn changes(&mut self) -> usize {
         unsafe { ffi::sqlite3_changes(self.db()) as usize }
     }
 
+    pub fn total_changes(&self) -> usize {
+        unsafe { ffi::sqlite3_total_changes(self.db()) as usize }
+    }
+
     pub fn is_autocommit(&self) -> bool {
         unsafe { ffi::sqlite3_get_autocommit(self.db()) != 0 }
     }
 
-    #[cfg(feature = "bundled")] // 3.8.6
+    #[cfg(any(feature = "stmt_busy", feature = "bundled"))]
     pub fn is_busy(&self) -> bool {
         let db = self.db();
         unsafe {
@@ -260,8 +437,31 @@ impl InnerConnection {
         false
     }
 
+    #[cfg(any(feature = "stmt_busy", feature = "bundled"))]
+    pub fn active_statements(&self) -> Vec<String> {
+        let db = self.db();
+        let mut active = Vec::new();
+        unsafe {
+            let mut stmt = ffi::sqlite3_next_stmt(db, ptr::null_mut());
+            while !stmt.is_null() {
+                if ffi::sqlite3_stmt_busy(stmt) != 0 {
+                    let sql = ffi::sqlite3_sql(stmt);
+                    if !sql.is_null() {
+                        let sql = std::ffi::CStr::from_ptr(sql);
+                        active.push(sql.to_string_lossy().into_owned());
+                    }
+                }
+                stmt = ffi::sqlite3_next_stmt(db, stmt);
+            }
+        }
+        active
+    }
+
     #[cfg(not(feature = "hooks"))]
     fn remove_hooks(&mut self) {}
+
+    #[cfg(not(feature = "hooks"))]
+    fn free_hooks(&mut self) {}
 }
 
 impl Drop for InnerConnection {
@@ -0,0 +1,165 @@
+//! Per-connection counters of rows read and written.
+use std::os::raw::c_int;
+
+use crate::ffi;
+use crate::{Connection, InnerConnection, Statement, StatementStatus};
+
+/// Counters of the work done by a connection since it was opened, or since
+/// the last call to `Connection::reset_io_counters`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IoCounters {
+    /// Number of rows returned by queries.
+    pub rows_read: u64,
+    /// Number of rows inserted, updated or deleted (including by triggers,
+    /// see `sqlite3_total_changes`).
+    pub rows_written: u64,
+    /// Number of rows visited by full table scans (see
+    /// `StatementStatus::FullscanStep`).
+    pub fullscan_steps: u64,
+    /// Number of sort operations (see `StatementStatus::Sort`).
+    pub sorts: u64,
+    /// Number of pages found in the page cache (0 before SQLite 3.7.9).
+    pub cache_hits: u64,
+    /// Number of pages read from the database file (0 before SQLite 3.7.9).
+    pub cache_misses: u64,
+    /// Number of pages written to the database file (0 before SQLite
+    /// 3.7.12).
+    pub cache_writes: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct IoTotals {
+    rows_read: u64,
+    fullscan_steps: u64,
+    sorts: u64,
+    // `sqlite3_total_changes` at the last reset
+    changes_base: usize,
+}
+
+impl Connection {
+    /// Return the counters of the rows read and written by this connection
+    /// since it was opened (or since the last `reset_io_counters`), e.g. to
+    /// attribute I/O to specific code paths during load tests.
+    ///
+    /// The rows read, full table scans and sorts of a statement are accounted
+    /// for when its execution completes or is abandoned (when its `Rows` are
+    /// dropped).
+    pub fn io_counters(&self) -> IoCounters {
+        let db = self.db.borrow();
+        IoCounters {
+            rows_read: db.io_totals.rows_read,
+            rows_written: (db.total_changes() - db.io_totals.changes_base) as u64,
+            fullscan_steps: db.io_totals.fullscan_steps,
+            sorts: db.io_totals.sorts,
+            cache_hits: db.db_status(ffi::SQLITE_DBSTATUS_CACHE_HIT, false),
+            cache_misses: db.db_status(ffi::SQLITE_DBSTATUS_CACHE_MISS, false),
+            cache_writes: db.db_status(ffi::SQLITE_DBSTATUS_CACHE_WRITE, false),
+        }
+    }
+
+    /// Reset the counters returned by `io_counters`, returning their values
+    /// before resetting.
+    pub fn reset_io_counters(&self) -> IoCounters {
+        let counters = self.io_counters();
+        let mut db = self.db.borrow_mut();
+        db.io_totals = IoTotals {
+            changes_base: db.total_changes(),
+            ..IoTotals::default()
+        };
+        db.db_status(ffi::SQLITE_DBSTATUS_CACHE_HIT, true);
+        db.db_status(ffi::SQLITE_DBSTATUS_CACHE_MISS, true);
+        db.db_status(ffi::SQLITE_DBSTATUS_CACHE_WRITE, true);
+        counters
+    }
+}
+
+impl InnerConnection {
+    fn db_status(&self, op: c_int, reset: bool) -> u64 {
+        let mut current = 0;
+        let mut highwater = 0;
+        let rc = unsafe {
+            ffi::sqlite3_db_status(self.db(), op, &mut current, &mut highwater, reset as c_int)
+        };
+        if rc == ffi::SQLITE_OK {
+            current as u64
+        } else {
+            0
+        }
+    }
+}
+
+impl Statement<'_> {
+    /// Return the `FullscanStep` and `Sort` counters of this statement.
+    pub(crate) fn io_status(&self) -> (i32, i32) {
+        if self.stmt.is_null() {
+            return (0, 0);
+        }
+        (
+            self.get_status(StatementStatus::FullscanStep),
+            self.get_status(StatementStatus::Sort),
+        )
+    }
+
+    /// Add the rows read, full table scan steps and sorts of the current
+    /// execution of this statement to the connection counters, once.
+    pub(crate) fn collect_io_counters(&self) {
+        if !self.io_pending.get() {
+            return;
+        }
+        let (fullscan_base, sort_base) = self.scan_base.get();
+        let (fullscan, sort) = self.io_status();
+        if let Ok(mut db) = self.conn.db.try_borrow_mut() {
+            db.io_totals.rows_read += self.rows.get() as u64;
+            db.io_totals.fullscan_steps += (fullscan - fullscan_base).max(0) as u64;
+            db.io_totals.sorts += (sort - sort_base).max(0) as u64;
+            self.io_pending.set(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Connection, NO_PARAMS};
+
+    #[test]
+    fn test_io_counters() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER);
+             INSERT INTO foo VALUES(1);
+             INSERT INTO foo VALUES(2);
+             INSERT INTO foo VALUES(3);",
+        )
+        .unwrap();
+        let counters = db.io_counters();
+        assert_eq!(0, counters.rows_read);
+        assert_eq!(3, counters.rows_written);
+
+        db.execute("UPDATE foo SET x = x + 1 WHERE x > ?", [1])
+            .unwrap();
+        {
+            let mut stmt = db.prepare("SELECT x FROM foo ORDER BY x DESC").unwrap();
+            let mut rows = stmt.query(NO_PARAMS).unwrap();
+            assert!(rows.next().unwrap().is_some());
+        }
+        let counters = db.reset_io_counters();
+        assert_eq!(1, counters.rows_read);
+        assert_eq!(5, counters.rows_written);
+        assert!(counters.fullscan_steps > 0);
+        assert_eq!(1, counters.sorts);
+
+        let counters = db.io_counters();
+        assert_eq!(0, counters.rows_read);
+        assert_eq!(0, counters.rows_written);
+        assert_eq!(0, counters.fullscan_steps);
+        assert_eq!(0, counters.sorts);
+
+        let sum: i64 = db
+            .query_row("SELECT sum(x) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(8, sum);
+        let counters = db.io_counters();
+        assert_eq!(1, counters.rows_read);
+        assert!(counters.fullscan_steps > 0);
+    }
+}
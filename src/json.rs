@@ -0,0 +1,164 @@
+//! Query results as JSON.
+use std::io::Write;
+
+use serde_json::{Map, Number, Value};
+
+use crate::types::{Params, ValueRef};
+use crate::{Connection, Error, Result, Row, Statement};
+
+impl Connection {
+    /// Execute a query and return all the rows as a JSON array of objects
+    /// keyed by column name.
+    ///
+    /// `NULL`s are mapped to JSON `null`, and BLOBs to base64-encoded strings.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn people(conn: &Connection) -> Result<String> {
+    ///     let json = conn.query_json("SELECT id, name FROM people WHERE age > ?", &[18])?;
+    ///     Ok(json.to_string())
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails.
+    pub fn query_json<P>(&self, sql: &str, params: P) -> Result<Value>
+    where
+        P: Params,
+    {
+        let mut stmt = self.prepare(sql)?;
+        let names = column_names(&stmt);
+        let mut rows = stmt.query(params)?;
+        let mut array = Vec::new();
+        while let Some(row) = rows.next()? {
+            array.push(row_to_json(&names, row));
+        }
+        Ok(Value::Array(array))
+    }
+
+    /// Execute a query and write each row as a JSON object, one per line
+    /// ([NDJSON](http://ndjson.org/)), to `out`. Returns the number of rows
+    /// written.
+    ///
+    /// Values are converted as by `query_json`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails, or `Err(Error::Io)` if writing
+    /// to `out` fails.
+    pub fn query_ndjson<P, W>(&self, sql: &str, params: P, mut out: W) -> Result<usize>
+    where
+        P: Params,
+        W: Write,
+    {
+        let mut stmt = self.prepare(sql)?;
+        let names = column_names(&stmt);
+        let mut rows = stmt.query(params)?;
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            serde_json::to_writer(&mut out, &row_to_json(&names, row))
+                .map_err(|err| Error::Io(err.into()))?;
+            out.write_all(b"\n").map_err(Error::Io)?;
+            count += 1;
+        }
+        out.flush().map_err(Error::Io)?;
+        Ok(count)
+    }
+}
+
+fn column_names(stmt: &Statement<'_>) -> Vec<String> {
+    stmt.column_names().into_iter().map(String::from).collect()
+}
+
+fn row_to_json(names: &[String], row: &Row<'_>) -> Value {
+    let mut object = Map::new();
+    for (i, name) in names.iter().enumerate() {
+        object.insert(name.clone(), value_to_json(row.get_raw(i)));
+    }
+    Value::Object(object)
+}
+
+fn value_to_json(value: ValueRef<'_>) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::Number(i.into()),
+        ValueRef::Real(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        ValueRef::Text(s) => Value::String(s.to_owned()),
+        ValueRef::Blob(b) => Value::String(base64(b)),
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut s = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).cloned().unwrap_or(0),
+            chunk.get(2).cloned().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod test {
+    use super::base64;
+    use crate::{Connection, Error, NO_PARAMS};
+    use serde_json::json;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!("", base64(b""));
+        assert_eq!("Zg==", base64(b"f"));
+        assert_eq!("Zm8=", base64(b"fo"));
+        assert_eq!("Zm9v", base64(b"foo"));
+        assert_eq!("Zm9vYmFy", base64(b"foobar"));
+    }
+
+    #[test]
+    fn test_query_json() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(i INTEGER, f REAL, t TEXT, b BLOB);
+             INSERT INTO foo VALUES(1, 1.5, 'one', x'666f6f');
+             INSERT INTO foo VALUES(NULL, NULL, NULL, NULL);",
+        )
+        .unwrap();
+
+        let json = db.query_json("SELECT * FROM foo", NO_PARAMS).unwrap();
+        assert_eq!(
+            json!([
+                {"i": 1, "f": 1.5, "t": "one", "b": "Zm9v"},
+                {"i": null, "f": null, "t": null, "b": null},
+            ]),
+            json
+        );
+
+        let mut out = Vec::new();
+        let count = db
+            .query_ndjson("SELECT i, t FROM foo WHERE i = ?", &[1], &mut out)
+            .unwrap();
+        assert_eq!(1, count);
+        assert_eq!("{\"i\":1,\"t\":\"one\"}\n", String::from_utf8(out).unwrap());
+
+        let mut out = [0u8; 4];
+        match db.query_ndjson("SELECT i, t FROM foo", NO_PARAMS, &mut out[..]) {
+            Err(Error::Io(ref err)) => assert_eq!(std::io::ErrorKind::WriteZero, err.kind()),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
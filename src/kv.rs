@@ -0,0 +1,171 @@
+//! Key-value stores
+//!
+//! A `Kv` stores values of any SQLite type by key, in a namespace of the
+//! `rusqlite_kv` table (which is created on demand), e.g. for configuration
+//! or cache storage without writing schema code.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{kv::Kv, Connection, Result};
+//! fn theme(conn: &Connection) -> Result<String> {
+//!     let settings = Kv::open(conn, "settings")?;
+//!     if settings.get::<String>("theme")?.is_none() {
+//!         settings.set("theme", "dark")?;
+//!     }
+//!     Ok(settings.get("theme")?.unwrap())
+//! }
+//! ```
+
+use crate::types::{FromSql, ToSql};
+use crate::{Connection, Result};
+
+/// A namespace of the key-value store of a database.
+pub struct Kv<'conn> {
+    conn: &'conn Connection,
+    namespace: String,
+}
+
+impl Kv<'_> {
+    /// Open the `namespace` key-value store, creating the `rusqlite_kv` table
+    /// if it does not exist.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn open<'conn>(conn: &'conn Connection, namespace: &str) -> Result<Kv<'conn>> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS rusqlite_kv(
+                 namespace TEXT NOT NULL,
+                 key TEXT NOT NULL,
+                 value,
+                 PRIMARY KEY (namespace, key)
+             ) WITHOUT ROWID",
+        )?;
+        Ok(Kv {
+            conn,
+            namespace: namespace.to_owned(),
+        })
+    }
+
+    /// Return the value of `key`, or `None` if it is not set.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the value cannot be converted to `T`, or if the
+    /// underlying SQLite calls fail.
+    pub fn get<T: FromSql>(&self, key: &str) -> Result<Option<T>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT value FROM rusqlite_kv WHERE namespace = ? AND key = ?")?;
+        let mut rows = stmt.query(&[&self.namespace, key])?;
+        match rows.next()? {
+            Some(row) => row.get(0).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Set the value of `key`, replacing its previous value if any.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn set<T: ToSql>(&self, key: &str, value: T) -> Result<()> {
+        let mut stmt = self.conn.prepare_cached(
+            "INSERT OR REPLACE INTO rusqlite_kv(namespace, key, value) VALUES(?, ?, ?)",
+        )?;
+        stmt.execute(&[&self.namespace as &dyn ToSql, &key, &value])?;
+        Ok(())
+    }
+
+    /// Delete `key`, and return `false` if it was not set.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn delete(&self, key: &str) -> Result<bool> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("DELETE FROM rusqlite_kv WHERE namespace = ? AND key = ?")?;
+        Ok(stmt.execute(&[&self.namespace, key])? == 1)
+    }
+
+    /// Return the keys starting with `prefix` (case-sensitive), with their
+    /// values, ordered by key.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a value cannot be converted to `T`, or if the
+    /// underlying SQLite calls fail.
+    pub fn iter_prefix<T: FromSql>(&self, prefix: &str) -> Result<Vec<(String, T)>> {
+        // GLOB (unlike LIKE) is case-sensitive, and can use the primary key.
+        let mut pattern = String::with_capacity(prefix.len() + 1);
+        for c in prefix.chars() {
+            match c {
+                '*' | '?' | '[' => {
+                    pattern.push('[');
+                    pattern.push(c);
+                    pattern.push(']');
+                }
+                _ => pattern.push(c),
+            }
+        }
+        pattern.push('*');
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT key, value FROM rusqlite_kv WHERE namespace = ? AND key GLOB ? ORDER BY key",
+        )?;
+        let rows = stmt.query_map(&[&self.namespace, &pattern], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        rows.collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Kv;
+    use crate::Connection;
+
+    #[test]
+    fn test_kv() {
+        let db = Connection::open_in_memory().unwrap();
+        let kv = Kv::open(&db, "settings").unwrap();
+        let other = Kv::open(&db, "cache").unwrap();
+        assert_eq!(None, kv.get::<String>("theme").unwrap());
+
+        kv.set("theme", "dark").unwrap();
+        kv.set("size", 12).unwrap();
+        other.set("theme", "light").unwrap();
+        assert_eq!(Some("dark".to_owned()), kv.get("theme").unwrap());
+        assert_eq!(Some(12), kv.get("size").unwrap());
+        assert!(kv.get::<i64>("theme").is_err());
+
+        kv.set("theme", "light").unwrap();
+        assert_eq!(Some("light".to_owned()), kv.get("theme").unwrap());
+        assert!(kv.delete("theme").unwrap());
+        assert!(!kv.delete("theme").unwrap());
+        assert_eq!(None, kv.get::<String>("theme").unwrap());
+        assert_eq!(Some("light".to_owned()), other.get("theme").unwrap());
+    }
+
+    #[test]
+    fn test_iter_prefix() {
+        let db = Connection::open_in_memory().unwrap();
+        let kv = Kv::open(&db, "test").unwrap();
+        for (i, key) in ["a/2", "a/1", "A/3", "ab", "a*/4", "a*x"]
+            .iter()
+            .enumerate()
+        {
+            kv.set(key, i as i64).unwrap();
+        }
+        Kv::open(&db, "other").unwrap().set("a/5", 5).unwrap();
+
+        assert_eq!(
+            vec![("a/1".to_owned(), 1), ("a/2".to_owned(), 0)],
+            kv.iter_prefix::<i64>("a/").unwrap()
+        );
+        assert_eq!(
+            vec![("a*/4".to_owned(), 4)],
+            kv.iter_prefix::<i64>("a*/").unwrap()
+        );
+        assert_eq!(6, kv.iter_prefix::<i64>("").unwrap().len());
+    }
+}
@@ -58,13 +58,21 @@
 
 pub use libsqlite3_sys as ffi;
 
+// The prebuilt bindings of libsqlite3-sys predate the pointer passing
+// interfaces.
+#[cfg(all(
+    feature = "pointer",
+    not(any(feature = "bundled", feature = "buildtime_bindgen"))
+))]
+compile_error!("the `pointer` feature requires the `bundled` or `buildtime_bindgen` feature");
+
 #[macro_use]
 extern crate bitflags;
 #[cfg(any(test, feature = "vtab"))]
 #[macro_use]
 extern crate lazy_static;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::convert;
 use std::default::Default;
 use std::ffi::{CStr, CString};
@@ -74,7 +82,7 @@ use std::os::raw::{c_char, c_int};
 use std::path::{Path, PathBuf};
 use std::result;
 use std::str;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
 use crate::cache::StatementCache;
@@ -82,58 +90,145 @@ use crate::inner_connection::{InnerConnection, BYPASS_SQLITE_INIT};
 use crate::raw_statement::RawStatement;
 use crate::types::ValueRef;
 
-pub use crate::cache::CachedStatement;
-pub use crate::column::Column;
-pub use crate::error::Error;
+pub use crate::busy::{BusyCause, BusyDiagnostics};
+pub use crate::cache::{CacheStats, CachedStatement, StatementRegistry, StatementStats};
+#[cfg(feature = "preupdate_hook")]
+pub use crate::changes::ChangeEvent;
+pub use crate::column::{Column, Columns, FromColumns};
+#[cfg(feature = "snapshot")]
+pub use crate::consistency::{CommitToken, CommitTracker};
+pub use crate::error::{Error, ReadOnlyReason};
+pub use crate::event_log::{StatementEvent, StatementOutcome};
 pub use crate::ffi::ErrorCode;
+#[cfg(feature = "backup")]
+pub use crate::frozen::FrozenDb;
+pub use crate::hardening::{DurabilityOptions, SecureDelete, SecurityOptions};
 #[cfg(feature = "hooks")]
 pub use crate::hooks::Action;
+pub use crate::io_counters::IoCounters;
 #[cfg(feature = "load_extension")]
 pub use crate::load_extension_guard::LoadExtensionGuard;
-pub use crate::row::{AndThenRows, MappedRows, Row, RowIndex, Rows};
-pub use crate::statement::{Statement, StatementStatus};
-pub use crate::transaction::{DropBehavior, Savepoint, Transaction, TransactionBehavior};
-pub use crate::types::ToSql;
+pub use crate::locking::LockingMode;
+pub use crate::open_options::{OpenOptions, TextEncoding};
+#[cfg(feature = "bundled")]
+pub use crate::pattern::{str_glob, str_like};
+pub use crate::profile::Profile;
+pub use crate::read_only::ReadOnlyView;
+pub use crate::row::{AndThenRows, FromRow, MappedRows, OwnedRow, Row, RowIndex, Rows};
+pub use crate::row_versioning::RowVersioning;
+pub use crate::sandbox::{Sandbox, SandboxPolicy};
+pub use crate::script::{Batch, ResultSet, ScriptOptions};
+#[cfg(feature = "serialize")]
+pub use crate::serialize::DeserializeMode;
+pub use crate::sql_guard::SqlGuard;
+pub use crate::statement::{BoundParameter, ExecuteResult, Statement, StatementStatus};
+pub use crate::temp_store::{set_temp_directory, TempStore};
+#[cfg(feature = "test_clock")]
+pub use crate::test_clock::TestClock;
+pub use crate::transaction::{
+    DropBehavior, ForeignKeyViolation, Savepoint, Transaction, TransactionBehavior,
+};
+pub use crate::types::{params_from_iter, Params, ParamsFromIter, ToParams, ToSql};
+pub use crate::vacuum::AutoVacuum;
 pub use crate::version::*;
+pub use crate::wal::{CheckpointMode, WalInfo};
+#[cfg(feature = "derive")]
+pub use rusqlite_macros::{FromRow, ToParams};
 
 #[macro_use]
 mod error;
 
+#[cfg(feature = "tokio")]
+pub mod r#async;
+mod authorizer;
+mod auto_batch;
 #[cfg(feature = "backup")]
 pub mod backup;
 #[cfg(feature = "blob")]
 pub mod blob;
 mod busy;
 mod cache;
+#[cfg(feature = "preupdate_hook")]
+mod changes;
+#[cfg(feature = "checkpointer")]
+pub mod checkpointer;
 #[cfg(feature = "collation")]
 mod collation;
 mod column;
 pub mod config;
+#[cfg(feature = "snapshot")]
+mod consistency;
 #[cfg(any(feature = "functions", feature = "vtab"))]
 mod context;
+#[cfg(feature = "polars")]
+mod dataframe;
+mod decoders;
+pub mod digest;
+#[cfg(feature = "doc")]
+pub mod doc;
+mod event_log;
+#[cfg(feature = "backup")]
+mod frozen;
 #[cfg(feature = "functions")]
 pub mod functions;
+mod hardening;
 #[cfg(feature = "hooks")]
 mod hooks;
 mod inner_connection;
+mod io_counters;
+#[cfg(feature = "serde_json")]
+mod json;
+pub mod kv;
 #[cfg(feature = "limits")]
 pub mod limits;
 #[cfg(feature = "load_extension")]
 mod load_extension_guard;
+mod locking;
+mod open_options;
+mod optimize;
+#[cfg(feature = "bundled")] // SQLite >= 3.10.0
+mod pattern;
+mod placeholders;
 mod pragma;
+mod profile;
+pub mod queue;
 mod raw_statement;
+mod read_only;
+#[cfg(feature = "regexp")]
+mod regexp;
 mod row;
+mod row_versioning;
+mod sandbox;
+#[cfg(feature = "scanstatus")]
+pub mod scanstatus;
+pub mod schema;
+mod script;
+mod sequence;
+#[cfg(feature = "serialize")]
+mod serialize;
 #[cfg(feature = "session")]
 pub mod session;
+mod sql_guard;
 mod statement;
+#[cfg(any(feature = "functions", feature = "pointer"))]
+mod tagged;
+mod temp_store;
+#[cfg(feature = "test_clock")]
+mod test_clock;
+#[cfg(feature = "testing")]
+pub mod testing;
 #[cfg(feature = "trace")]
 pub mod trace;
 mod transaction;
+mod tx_hooks;
 pub mod types;
 mod unlock_notify;
+mod update;
+mod vacuum;
 mod version;
 #[cfg(feature = "vtab")]
 pub mod vtab;
+mod wal;
 
 // Number of cached prepared statements we'll hold on to.
 const STATEMENT_CACHE_DEFAULT_CAPACITY: usize = 16;
@@ -284,7 +379,7 @@ fn path_to_cstring(p: &Path) -> Result<CString> {
 }
 
 /// Name for a database within a SQLite connection.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum DatabaseName<'a> {
     /// The main database.
     Main,
@@ -296,8 +391,55 @@ pub enum DatabaseName<'a> {
     Attached(&'a str),
 }
 
-// Currently DatabaseName is only used by the backup and blob mods, so hide
-// this (private) impl to avoid dead code warnings.
+impl<'a> DatabaseName<'a> {
+    /// Return the schema name as used in SQL (e.g. `main`, `temp`).
+    pub fn as_str(&self) -> &'a str {
+        use self::DatabaseName::{Attached, Main, Temp};
+        match *self {
+            Main => "main",
+            Temp => "temp",
+            Attached(s) => s,
+        }
+    }
+
+    /// Check that the schema name is not empty and does not contain any
+    /// quote or nul character.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::InvalidDatabaseName)` if the name is invalid.
+    pub fn validate(&self) -> Result<()> {
+        let name = self.as_str();
+        if name.is_empty() || name.contains(|c| c == '"' || c == '\'' || c == '`' || c == '\0') {
+            Err(Error::InvalidDatabaseName(name.to_owned()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> From<&'a str> for DatabaseName<'a> {
+    /// `"main"` and `"temp"` (case-insensitive) are mapped to `Main` and
+    /// `Temp`, any other name to `Attached`.
+    fn from(name: &'a str) -> DatabaseName<'a> {
+        if name.eq_ignore_ascii_case("main") {
+            DatabaseName::Main
+        } else if name.eq_ignore_ascii_case("temp") {
+            DatabaseName::Temp
+        } else {
+            DatabaseName::Attached(name)
+        }
+    }
+}
+
+impl fmt::Display for DatabaseName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Currently DatabaseName is only used by the backup, blob, serialize and
+// session mods, so hide this (private) impl to avoid dead code warnings.
 #[cfg(any(
     feature = "backup",
     feature = "blob",
@@ -306,12 +448,8 @@ pub enum DatabaseName<'a> {
 ))]
 impl DatabaseName<'_> {
     fn to_cstring(&self) -> Result<CString> {
-        use self::DatabaseName::{Attached, Main, Temp};
-        match *self {
-            Main => str_to_cstring("main"),
-            Temp => str_to_cstring("temp"),
-            Attached(s) => str_to_cstring(s),
-        }
+        self.validate()?;
+        str_to_cstring(self.as_str())
     }
 }
 
@@ -320,12 +458,30 @@ pub struct Connection {
     db: RefCell<InnerConnection>,
     cache: StatementCache,
     path: Option<PathBuf>,
+    // Whether auto-batching is enabled, checked before borrowing `db`.
+    auto_batch: Cell<bool>,
 }
 
 unsafe impl Send for Connection {}
 
 impl Drop for Connection {
     fn drop(&mut self) {
+        let pending = self.pending_batch_len();
+        if pending > 0 {
+            // Drop cannot fail: `close` returns the failure instead
+            if let Err(err) = self.end_batch() {
+                #[cfg(feature = "trace")]
+                trace::log(
+                    ffi::SQLITE_ABORT,
+                    &format!(
+                        "{} statements of the pending batch rolled back on drop: {}",
+                        pending, err
+                    ),
+                );
+                #[cfg(not(feature = "trace"))]
+                let _ = err;
+            }
+        }
         self.flush_prepared_statement_cache();
     }
 }
@@ -368,10 +524,34 @@ impl Connection {
     /// string or if the underlying SQLite open call fails.
     pub fn open_with_flags<P: AsRef<Path>>(path: P, flags: OpenFlags) -> Result<Connection> {
         let c_path = path_to_cstring(path.as_ref())?;
-        InnerConnection::open_with_flags(&c_path, flags).map(|db| Connection {
+        InnerConnection::open_with_flags(&c_path, flags, None).map(|db| Connection {
+            db: RefCell::new(db),
+            cache: StatementCache::with_capacity(STATEMENT_CACHE_DEFAULT_CAPACITY),
+            path: Some(path.as_ref().to_path_buf()),
+            auto_batch: Cell::new(false),
+        })
+    }
+
+    /// Open a new connection to a SQLite database using the specific flags
+    /// and [VFS](https://sqlite.org/vfs.html) name.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `path` or `vfs` cannot be converted to a
+    /// C-compatible string, if there is no such VFS or if the underlying
+    /// SQLite open call fails.
+    pub fn open_with_flags_and_vfs<P: AsRef<Path>>(
+        path: P,
+        flags: OpenFlags,
+        vfs: &str,
+    ) -> Result<Connection> {
+        let c_path = path_to_cstring(path.as_ref())?;
+        let c_vfs = str_to_cstring(vfs)?;
+        InnerConnection::open_with_flags(&c_path, flags, Some(&c_vfs)).map(|db| Connection {
             db: RefCell::new(db),
             cache: StatementCache::with_capacity(STATEMENT_CACHE_DEFAULT_CAPACITY),
             path: Some(path.as_ref().to_path_buf()),
+            auto_batch: Cell::new(false),
         })
     }
 
@@ -385,17 +565,39 @@ impl Connection {
     /// Will return `Err` if the underlying SQLite open call fails.
     pub fn open_in_memory_with_flags(flags: OpenFlags) -> Result<Connection> {
         let c_memory = str_to_cstring(":memory:")?;
-        InnerConnection::open_with_flags(&c_memory, flags).map(|db| Connection {
+        InnerConnection::open_with_flags(&c_memory, flags, None).map(|db| Connection {
+            db: RefCell::new(db),
+            cache: StatementCache::with_capacity(STATEMENT_CACHE_DEFAULT_CAPACITY),
+            path: None,
+            auto_batch: Cell::new(false),
+        })
+    }
+
+    /// Open a new connection to an in-memory SQLite database using the
+    /// specific flags and [VFS](https://sqlite.org/vfs.html) name.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `vfs` cannot be converted to a C-compatible
+    /// string, if there is no such VFS or if the underlying SQLite open call
+    /// fails.
+    pub fn open_in_memory_with_flags_and_vfs(flags: OpenFlags, vfs: &str) -> Result<Connection> {
+        let c_memory = str_to_cstring(":memory:")?;
+        let c_vfs = str_to_cstring(vfs)?;
+        InnerConnection::open_with_flags(&c_memory, flags, Some(&c_vfs)).map(|db| Connection {
             db: RefCell::new(db),
             cache: StatementCache::with_capacity(STATEMENT_CACHE_DEFAULT_CAPACITY),
             path: None,
+            auto_batch: Cell::new(false),
         })
     }
 
     /// Convenience method to run multiple SQL statements (that cannot take any
     /// parameters).
     ///
-    /// Uses [sqlite3_exec](http://www.sqlite.org/c3ref/exec.html) under the hood.
+    /// The statements are prepared and executed one by one (see `batch`), and
+    /// their rows, if any, are discarded. An `InterruptHandle` interrupts the
+    /// statement being executed, or the batch between two statements.
     ///
     /// ## Example
     ///
@@ -414,9 +616,24 @@ impl Connection {
     /// # Failure
     ///
     /// Will return `Err` if `sql` cannot be converted to a C-compatible string
-    /// or if the underlying SQLite call fails.
+    /// or if the underlying SQLite call fails. The message of SQLite errors
+    /// includes the index (from 0) and the text of the failing statement.
     pub fn execute_batch(&self, sql: &str) -> Result<()> {
-        self.db.borrow_mut().execute_batch(sql)
+        self.flush_before(sql)?;
+        self.db.borrow().check_sql(sql)?;
+        let start = self.event_start();
+        let total_changes = self.db.borrow().total_changes();
+        let r = self.run_batch(sql);
+        if let Some(start) = start {
+            let outcome = match r {
+                Ok(()) => {
+                    StatementOutcome::Executed(self.db.borrow().total_changes() - total_changes)
+                }
+                Err(ref err) => StatementOutcome::Failed(err.to_string()),
+            };
+            self.record_event(start, sql, outcome);
+        }
+        r
     }
 
     /// Convenience method to prepare and execute a single SQL statement.
@@ -442,10 +659,38 @@ impl Connection {
     /// or if the underlying SQLite call fails.
     pub fn execute<P>(&self, sql: &str, params: P) -> Result<usize>
     where
-        P: IntoIterator,
-        P::Item: ToSql,
+        P: Params,
     {
-        self.prepare(sql).and_then(|mut stmt| stmt.execute(params))
+        self.batched(sql, |stmt| stmt.execute(params))
+    }
+
+    /// Convenience method to prepare and execute a single DML statement with
+    /// a `RETURNING` clause, and return the result of `f` for each returned
+    /// row (see `Statement::execute_returning`).
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn add_person(conn: &Connection, name: &str) -> Result<Vec<(i64, String)>> {
+    ///     conn.execute_returning(
+    ///         "INSERT INTO people (name) VALUES (?) RETURNING id, created_at",
+    ///         &[name],
+    ///         |row| Ok((row.get(0)?, row.get(1)?)),
+    ///     )
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string,
+    /// if `f` fails, or if the underlying SQLite call fails.
+    pub fn execute_returning<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<Vec<T>>
+    where
+        P: Params,
+        F: FnMut(&Row<'_>) -> Result<T>,
+    {
+        self.batched(sql, |stmt| stmt.execute_returning(params, f))
     }
 
     /// Convenience method to prepare and execute a single SQL statement with
@@ -471,8 +716,32 @@ impl Connection {
     /// Will return `Err` if `sql` cannot be converted to a C-compatible string
     /// or if the underlying SQLite call fails.
     pub fn execute_named(&self, sql: &str, params: &[(&str, &dyn ToSql)]) -> Result<usize> {
-        self.prepare(sql)
-            .and_then(|mut stmt| stmt.execute_named(params))
+        self.batched(sql, |stmt| stmt.execute_named(params))
+    }
+
+    /// Convenience method to prepare and execute a single SQL statement,
+    /// returning both the number of rows changed and the ROWID of the last
+    /// inserted row (see `Statement::run`).
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn insert(conn: &Connection, name: &str) -> Result<i64> {
+    ///     let result = conn.run("INSERT INTO people (name) VALUES (?)", &[name])?;
+    ///     Ok(result.last_insert_rowid)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails.
+    pub fn run<P>(&self, sql: &str, params: P) -> Result<ExecuteResult>
+    where
+        P: Params,
+    {
+        self.batched(sql, |stmt| stmt.run(params))
     }
 
     /// Get the SQLite rowid of the most recent successful INSERT.
@@ -512,14 +781,40 @@ impl Connection {
     /// or if the underlying SQLite call fails.
     pub fn query_row<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<T>
     where
-        P: IntoIterator,
-        P::Item: ToSql,
+        P: Params,
         F: FnOnce(&Row<'_>) -> Result<T>,
     {
         let mut stmt = self.prepare(sql)?;
         stmt.query_row(params, f)
     }
 
+    /// Convenience method to execute a query that is expected to return a
+    /// single row, converted with `FromRow` (e.g. a tuple of column values).
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn person(conn: &Connection, id: i64) -> Result<(String, Option<u32>)> {
+    ///     conn.query_row_as("SELECT name, age FROM people WHERE id = ?", &[id])
+    /// }
+    /// ```
+    ///
+    /// Returns `Err(QueryReturnedNoRows)` if no results are returned.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string,
+    /// if the row cannot be converted or if the underlying SQLite call fails.
+    pub fn query_row_as<T, P>(&self, sql: &str, params: P) -> Result<T>
+    where
+        T: FromRow,
+        P: Params,
+    {
+        let mut stmt = self.prepare(sql)?;
+        stmt.query_row_as(params)
+    }
+
     /// Convenience method to execute a query with named parameter(s) that is
     /// expected to return a single row.
     ///
@@ -569,15 +864,94 @@ impl Connection {
     /// or if the underlying SQLite call fails.
     pub fn query_row_and_then<T, E, P, F>(&self, sql: &str, params: P, f: F) -> result::Result<T, E>
     where
-        P: IntoIterator,
-        P::Item: ToSql,
+        P: Params,
         F: FnOnce(&Row<'_>) -> result::Result<T, E>,
         E: convert::From<Error>,
     {
         let mut stmt = self.prepare(sql)?;
-        let mut rows = stmt.query(params)?;
+        stmt.query_row_and_then(params, f)
+    }
+
+    /// Convenience method to execute a query with named parameter(s) that is
+    /// expected to return a single row, and execute a mapping via `f` on that
+    /// returned row with the possibility of failure. The `Result` type of `f`
+    /// must implement `std::convert::From<Error>`.
+    ///
+    /// If the query returns more than one row, all rows except the first are
+    /// ignored.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails.
+    pub fn query_row_and_then_named<T, E, F>(
+        &self,
+        sql: &str,
+        params: &[(&str, &dyn ToSql)],
+        f: F,
+    ) -> result::Result<T, E>
+    where
+        F: FnOnce(&Row<'_>) -> result::Result<T, E>,
+        E: convert::From<Error>,
+    {
+        let mut stmt = self.prepare(sql)?;
+        stmt.query_row_and_then_named(params, f)
+    }
+
+    /// Convenience method to fetch all the rows of the table (or view)
+    /// `table` in the main database, e.g. a small lookup table, converted
+    /// with `FromRow`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, FromRow, Result, Row};
+    /// struct Setting {
+    ///     key: String,
+    ///     value: String,
+    /// }
+    ///
+    /// impl FromRow for Setting {
+    ///     fn from_row(row: &Row<'_>) -> Result<Setting> {
+    ///         Ok(Setting {
+    ///             key: row.get("key")?,
+    ///             value: row.get("value")?,
+    ///         })
+    ///     }
+    /// }
+    ///
+    /// fn settings(conn: &Connection) -> Result<Vec<Setting>> {
+    ///     conn.query_table("settings")
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if there is no such table, if a row cannot be
+    /// converted or if the underlying SQLite call fails.
+    pub fn query_table<T: FromRow>(&self, table: &str) -> Result<Vec<T>> {
+        self.query_table_where(table, "1", NO_PARAMS)
+    }
 
-        rows.get_expected_row().map_err(E::from).and_then(|r| f(&r))
+    /// Like `query_table`, but only fetch the rows matching the SQL
+    /// expression `clause` (e.g. `"kind = ? ORDER BY name"`), with the
+    /// parameters `params`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if there is no such table, if `clause` is invalid,
+    /// if a row cannot be converted or if the underlying SQLite call fails.
+    pub fn query_table_where<T, P>(&self, table: &str, clause: &str, params: P) -> Result<Vec<T>>
+    where
+        T: FromRow,
+        P: Params,
+    {
+        let mut name = pragma::Sql::new();
+        name.push_identifier(table);
+        let sql = format!("SELECT * FROM {} WHERE {}", name.as_str(), clause);
+        let mut stmt = self.prepare_cached(&sql)?;
+        let rows = stmt.query_map(params, T::from_row)?;
+        rows.collect()
     }
 
     /// Prepare a SQL statement for execution.
@@ -599,6 +973,7 @@ impl Connection {
     /// Will return `Err` if `sql` cannot be converted to a C-compatible string
     /// or if the underlying SQLite call fails.
     pub fn prepare(&self, sql: &str) -> Result<Statement<'_>> {
+        self.flush_before(sql)?;
         self.db.borrow_mut().prepare(self, sql)
     }
 
@@ -610,13 +985,41 @@ impl Connection {
     ///
     /// # Failure
     ///
-    /// Will return `Err` if the underlying SQLite call fails.
+    /// Will return `Err` if the underlying SQLite call fails, e.g. with
+    /// `ErrorCode::DatabaseBusy` if some statements have not been finalized,
+    /// whose SQL is then listed in the error message.
     pub fn close(self) -> std::result::Result<(), (Connection, Error)> {
+        if let Err(err) = self.flush() {
+            return Err((self, err));
+        }
         self.flush_prepared_statement_cache();
         let r = self.db.borrow_mut().close();
         r.map_err(move |err| (self, err))
     }
 
+    /// Close the SQLite connection like `close`, but finalize first the
+    /// statements prepared by this connection which are still open, e.g.
+    /// the ones leaked with `std::mem::forget`.
+    ///
+    /// The statements prepared by other code through the raw `handle` are
+    /// not finalized: they still make closing fail.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn force_close(self) -> std::result::Result<(), (Connection, Error)> {
+        if let Err(err) = self.flush() {
+            return Err((self, err));
+        }
+        self.flush_prepared_statement_cache();
+        let r = {
+            let mut db = self.db.borrow_mut();
+            db.finalize_statements();
+            db.close()
+        };
+        r.map_err(move |err| (self, err))
+    }
+
     /// Enable loading of SQLite extensions. Strongly consider using
     /// `LoadExtensionGuard` instead of this function.
     ///
@@ -710,6 +1113,7 @@ impl Connection {
             db: RefCell::new(db),
             cache: StatementCache::with_capacity(STATEMENT_CACHE_DEFAULT_CAPACITY),
             path: db_path,
+            auto_batch: Cell::new(false),
         })
     }
 
@@ -736,11 +1140,31 @@ impl Connection {
         self.db.borrow().is_autocommit()
     }
 
+    /// Return the error code reported by the operating system for the last
+    /// failed file operation of this connection (`errno` on Unix), or 0 (also
+    /// without the `system_errno` or `bundled` feature, or with SQLite older
+    /// than 3.12.0).
+    ///
+    /// This is also available from `Error::os_error`.
+    pub fn system_errno(&self) -> i32 {
+        error::system_errno(self.db.borrow().db())
+    }
+
     /// Determine if all associated prepared statements have been reset.
-    #[cfg(feature = "bundled")]
+    #[cfg(any(feature = "stmt_busy", feature = "bundled"))]
     pub fn is_busy(&self) -> bool {
         self.db.borrow().is_busy()
     }
+
+    /// Return the SQL text of the prepared statements of this connection that
+    /// have been stepped but not reset (see `Statement::is_busy`).
+    ///
+    /// Such statements may keep a read transaction open, and are a common
+    /// cause of `SQLITE_BUSY` or `SQLITE_LOCKED` errors.
+    #[cfg(any(feature = "stmt_busy", feature = "bundled"))]
+    pub fn active_statements(&self) -> Vec<String> {
+        self.db.borrow().active_statements()
+    }
 }
 
 impl fmt::Debug for Connection {
@@ -813,6 +1237,7 @@ pub unsafe fn bypass_sqlite_version_check() {
 /// Allows interrupting a long-running computation.
 pub struct InterruptHandle {
     db_lock: Arc<Mutex<*mut ffi::sqlite3>>,
+    interrupted: Arc<AtomicBool>,
 }
 
 unsafe impl Send for InterruptHandle {}
@@ -821,7 +1246,11 @@ unsafe impl Sync for InterruptHandle {}
 impl InterruptHandle {
     /// Interrupt the query currently executing on another thread. This will
     /// cause that query to fail with a `SQLITE3_INTERRUPT` error.
+    ///
+    /// An `execute_batch` in progress is also interrupted between two
+    /// statements.
     pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
         let db_handle = self.db_lock.lock().unwrap();
         if !db_handle.is_null() {
             unsafe { ffi::sqlite3_interrupt(*db_handle) }
@@ -980,7 +1409,16 @@ mod test {
         // fail.
         let (db, _) = db.close().unwrap_err();
         let (db, _) = db.close().unwrap_err();
-        let (db, _) = db.close().unwrap_err();
+        let (db, err) = db.close().unwrap_err();
+        match err {
+            Error::SqliteFailure(err, Some(msg)) => {
+                assert_eq!(ErrorCode::DatabaseBusy, err.code);
+                assert!(msg.contains("unfinalized statements: SELECT 1"), "{}", msg);
+            }
+            err => panic!("unexpected error {}", err),
+        }
+        // `force_close` does not finalize statements it did not prepare
+        let (db, _) = db.force_close().unwrap_err();
 
         // finalize the open statement so a final close will succeed
         assert_eq!(ffi::SQLITE_OK, unsafe { ffi::sqlite3_finalize(raw_stmt) });
@@ -988,6 +1426,58 @@ mod test {
         db.close().unwrap();
     }
 
+    #[test]
+    fn test_force_close() {
+        let db = checked_memory_handle();
+        let stmt = db.prepare("SELECT 1").unwrap();
+        std::mem::forget(stmt);
+        let (db, _) = db.close().unwrap_err();
+        db.force_close().unwrap();
+    }
+
+    #[test]
+    #[cfg(any(feature = "system_errno", feature = "bundled"))]
+    fn test_open_system_errno() {
+        let temp_dir = TempDir::new("test_open_system_errno").unwrap();
+        let path = temp_dir.path().join("missing").join("test.db3");
+        let err = match Connection::open(&path) {
+            Err(err) => err,
+            Ok(_) => panic!("{} should not be opened", path.display()),
+        };
+        match err {
+            Error::SqliteFailure(ref e, _) => assert_eq!(ErrorCode::CannotOpen, e.code),
+            ref err => panic!("unexpected error {:?}", err),
+        }
+        let io_err = err.os_error().unwrap();
+        assert_eq!(std::io::ErrorKind::NotFound, io_err.kind());
+        assert!(err.to_string().ends_with(&format!(": {}", io_err)));
+    }
+
+    #[test]
+    fn test_read_only_error() {
+        let temp_dir = TempDir::new("test_read_only_error").unwrap();
+        let path = temp_dir.path().join("test.db3");
+        {
+            let db = Connection::open(&path).unwrap();
+            db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        }
+
+        let db = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+        match db.execute_batch("INSERT INTO foo VALUES(1)") {
+            Err(Error::ReadOnly { reason, .. }) => {
+                assert_eq!(ReadOnlyReason::ReadOnly, reason);
+                assert_eq!(ffi::SQLITE_READONLY, reason.extended_code());
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        let db = Connection::open(&path).unwrap();
+        std::fs::rename(&path, temp_dir.path().join("moved.db3")).unwrap();
+        let err = db.execute_batch("INSERT INTO foo VALUES(1)").unwrap_err();
+        assert_eq!(Some(ReadOnlyReason::DbMoved), err.read_only_reason());
+        assert_eq!(None, Error::QueryReturnedNoRows.read_only_reason());
+    }
+
     #[test]
     fn test_open_with_flags() {
         for bad_flags in &[
@@ -1141,6 +1631,104 @@ mod test {
         assert_eq!(results.unwrap().concat(), "hello, world!");
     }
 
+    #[test]
+    fn test_row_to_owned() {
+        use crate::types::Value;
+
+        let db = checked_memory_handle();
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER, y TEXT); INSERT INTO foo VALUES(1, 'a'), (2, NULL);",
+        )
+        .unwrap();
+
+        let offending = {
+            let mut stmt = db
+                .prepare("SELECT x, y AS Label FROM foo ORDER BY x")
+                .unwrap();
+            let mut rows = stmt.query(NO_PARAMS).unwrap();
+            let mut offending = None;
+            while let Some(row) = rows.next().unwrap() {
+                if row.get::<_, Option<String>>(1).unwrap().is_none() {
+                    offending = Some(row.to_owned());
+                }
+            }
+            offending.unwrap()
+        };
+        assert_eq!(2, offending.column_count());
+        assert_eq!(vec!["x", "Label"], offending.column_names());
+        assert_eq!(&[Value::Integer(2), Value::Null], offending.values());
+        assert_eq!(2, offending.get::<i64>(0).unwrap());
+        assert_eq!(
+            None,
+            offending.get_by_name::<Option<String>>("label").unwrap()
+        );
+        match offending.get::<String>(1).unwrap_err() {
+            Error::InvalidColumnType(1, types::Type::Null) => (),
+            err => panic!("Unexpected error {}", err),
+        }
+        match offending.get::<i64>(2).unwrap_err() {
+            Error::InvalidColumnIndex(2) => (),
+            err => panic!("Unexpected error {}", err),
+        }
+        match offending.get_by_name::<i64>("z").unwrap_err() {
+            Error::InvalidColumnName(ref name) if name == "z" => (),
+            err => panic!("Unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn test_row_debug() {
+        let db = checked_memory_handle();
+        let mut stmt = db
+            .prepare("SELECT 1 AS id, 'x' AS name, NULL AS n, 0.5 AS r, zeroblob(1000) AS b, printf('%.100c', 'a') AS long")
+            .unwrap();
+        let mut rows = stmt.query(NO_PARAMS).unwrap();
+        let row = rows.next().unwrap().unwrap();
+        let long = format!("Text({:?}... (100 bytes))", "a".repeat(64));
+        assert_eq!(
+            format!(
+                "{{\"id\": Integer(1), \"name\": Text(\"x\"), \"n\": Null, \"r\": Real(0.5), \"b\": Blob(1000 bytes), \"long\": {}}}",
+                long
+            ),
+            format!("{:?}", row)
+        );
+        assert!(format!("{:?}", rows).starts_with("Rows { columns: Some([\"id\", \"name\", \"n\", \"r\", \"b\", \"long\"]), row_number: 1"));
+    }
+
+    #[test]
+    fn test_query_table() {
+        struct Setting {
+            key: String,
+            value: i64,
+        }
+
+        impl FromRow for Setting {
+            fn from_row(row: &Row<'_>) -> Result<Setting> {
+                Ok(Setting {
+                    key: row.get("key")?,
+                    value: row.get("value")?,
+                })
+            }
+        }
+
+        let db = checked_memory_handle();
+        db.execute_batch(
+            "CREATE TABLE \"my settings\"(key TEXT, value INTEGER);
+             INSERT INTO \"my settings\" VALUES('a', 1), ('b', 2), ('c', 3);",
+        )
+        .unwrap();
+        let settings: Vec<Setting> = db.query_table("my settings").unwrap();
+        assert_eq!(3, settings.len());
+        assert_eq!("a", settings[0].key);
+        let settings: Vec<Setting> = db
+            .query_table_where("my settings", "value >= ? ORDER BY key DESC", [2])
+            .unwrap();
+        let keys: Vec<&str> = settings.iter().map(|s| s.key.as_str()).collect();
+        assert_eq!(vec!["c", "b"], keys);
+        assert_eq!(5, settings.iter().map(|s| s.value).sum::<i64>());
+        assert!(db.query_table::<Setting>("no_such_table").is_err());
+    }
+
     #[test]
     fn test_query_row() {
         let db = checked_memory_handle();
@@ -1236,6 +1824,20 @@ mod test {
         assert_eq!(db.last_insert_rowid(), 10);
     }
 
+    #[test]
+    fn test_database_name() {
+        assert_eq!(DatabaseName::Main, DatabaseName::from("main"));
+        assert_eq!(DatabaseName::Temp, DatabaseName::from("TEMP"));
+        assert_eq!(DatabaseName::Attached("aux"), DatabaseName::from("aux"));
+        assert_eq!("aux", DatabaseName::Attached("aux").to_string());
+        assert!(DatabaseName::Attached("aux").validate().is_ok());
+        assert!(DatabaseName::Attached("").validate().is_err());
+        assert_eq!(
+            Err(Error::InvalidDatabaseName("a\"b".to_owned())),
+            DatabaseName::Attached("a\"b").validate()
+        );
+    }
+
     #[test]
     fn test_is_autocommit() {
         let db = checked_memory_handle();
@@ -1246,7 +1848,7 @@ mod test {
     }
 
     #[test]
-    #[cfg(feature = "bundled")]
+    #[cfg(any(feature = "stmt_busy", feature = "bundled"))]
     fn test_is_busy() {
         let db = checked_memory_handle();
         assert!(!db.is_busy());
@@ -1262,6 +1864,22 @@ mod test {
         assert!(!db.is_busy());
     }
 
+    #[test]
+    #[cfg(any(feature = "stmt_busy", feature = "bundled"))]
+    fn test_active_statements() {
+        let db = checked_memory_handle();
+        let mut stmt = db.prepare("PRAGMA schema_version").unwrap();
+        assert!(!stmt.is_busy());
+        assert!(db.active_statements().is_empty());
+        {
+            let mut rows = stmt.query(NO_PARAMS).unwrap();
+            rows.next().unwrap();
+            assert_eq!(vec!["PRAGMA schema_version"], db.active_statements());
+        }
+        assert!(!stmt.is_busy());
+        assert!(db.active_statements().is_empty());
+    }
+
     #[test]
     fn test_statement_debugging() {
         let db = checked_memory_handle();
@@ -1600,6 +2218,32 @@ mod test {
             }
         }
 
+        #[test]
+        fn test_query_row_and_then_named_custom_error() {
+            let db = checked_memory_handle();
+            let sql = "BEGIN;
+                       CREATE TABLE foo(x INTEGER, y TEXT);
+                       INSERT INTO foo VALUES(4, \"hello\");
+                       END;";
+            db.execute_batch(sql).unwrap();
+
+            let query = "SELECT y FROM foo WHERE x = :x";
+            let results: CustomResult<String> =
+                db.query_row_and_then_named(query, &[(":x", &4)], |row| {
+                    row.get(0).map_err(CustomError::Sqlite)
+                });
+            assert_eq!(results.unwrap(), "hello");
+
+            let no_rows: CustomResult<String> =
+                db.query_row_and_then_named(query, &[(":x", &5)], |row| {
+                    row.get(0).map_err(CustomError::Sqlite)
+                });
+            match no_rows.unwrap_err() {
+                CustomError::Sqlite(Error::QueryReturnedNoRows) => (),
+                err => panic!("Unexpected error {}", err),
+            }
+        }
+
         #[test]
         fn test_dynamic() {
             let db = checked_memory_handle();
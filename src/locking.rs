@@ -0,0 +1,224 @@
+//! Exclusive access to a database file across processes.
+use std::time::Duration;
+
+use crate::{Connection, Error, ErrorCode, Result, NO_PARAMS};
+
+/// Database connection locking mode (see
+/// [`PRAGMA locking_mode`](https://sqlite.org/pragma.html#pragma_locking_mode)).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockingMode {
+    /// Locks are released at the end of each transaction (default).
+    Normal,
+    /// Once acquired, locks are never released until the connection is
+    /// closed or the locking mode is set back to `Normal`.
+    Exclusive,
+}
+
+impl LockingMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            LockingMode::Normal => "NORMAL",
+            LockingMode::Exclusive => "EXCLUSIVE",
+        }
+    }
+}
+
+impl Connection {
+    /// Return the locking mode of the main database.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn locking_mode(&self) -> Result<LockingMode> {
+        let mode: String = self.pragma_query_value(None, "locking_mode", |row| row.get(0))?;
+        Ok(if mode.eq_ignore_ascii_case("exclusive") {
+            LockingMode::Exclusive
+        } else {
+            LockingMode::Normal
+        })
+    }
+
+    /// Set the locking mode of the main database.
+    ///
+    /// Switching from `Exclusive` to `Normal` only releases the locks the
+    /// next time the database is accessed.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn set_locking_mode(&self, mode: LockingMode) -> Result<()> {
+        self.pragma_update(None, "locking_mode", &mode.as_str())
+    }
+
+    /// Try to acquire, and keep until `release_exclusive` is called or the
+    /// connection is closed, an exclusive lock on the main database file, so
+    /// that no other connection (in this or another process) can read or
+    /// write it.
+    ///
+    /// This is meant for single-writer processes, to detect on startup that
+    /// another instance is already running instead of failing in the middle
+    /// of a transaction. The connection is switched to the `Exclusive`
+    /// locking mode and an exclusive transaction is started and committed;
+    /// other connections are waited for at most `timeout` (a zero `timeout`
+    /// only probes the lock).
+    ///
+    /// Returns `Ok(false)`, with the previous locking mode restored, if the
+    /// lock is held by another connection.
+    ///
+    /// The busy handler of the connection is replaced by a busy timeout
+    /// while waiting, and the previous busy timeout is restored afterwards
+    /// (a busy handler set with `busy_handler` is cleared).
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use std::time::Duration;
+    /// fn open_daemon_db() -> Result<Connection> {
+    ///     let conn = Connection::open("daemon.db")?;
+    ///     if !conn.try_acquire_exclusive(Duration::from_secs(1))? {
+    ///         panic!("another instance is running");
+    ///     }
+    ///     Ok(conn)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the connection is in a transaction or if the
+    /// underlying SQLite calls fail for any other reason than a lock held by
+    /// another connection.
+    pub fn try_acquire_exclusive(&self, timeout: Duration) -> Result<bool> {
+        let mut attempt = ExclusiveAttempt {
+            conn: self,
+            locking_mode: Some(self.locking_mode()?),
+            busy_timeout: self.pragma_query_value(None, "busy_timeout", |row| row.get(0))?,
+            autocommit: self.is_autocommit(),
+        };
+        self.set_locking_mode(LockingMode::Exclusive)?;
+        self.busy_timeout(timeout)?;
+        match self.execute_internal("BEGIN EXCLUSIVE; COMMIT;") {
+            Ok(()) => {
+                attempt.locking_mode = None;
+                Ok(true)
+            }
+            Err(Error::SqliteFailure(ref err, _)) if err.code == ErrorCode::DatabaseBusy => {
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Release the exclusive lock acquired by `try_acquire_exclusive`, and
+    /// restore the `Normal` locking mode.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn release_exclusive(&self) -> Result<()> {
+        self.set_locking_mode(LockingMode::Normal)?;
+        // The lock is only released by the next access to the database.
+        self.query_row("SELECT count(*) FROM sqlite_master", NO_PARAMS, |_| Ok(()))
+    }
+}
+
+/// Restores the busy timeout of a connection, and its locking mode unless
+/// the exclusive lock has been acquired, when `try_acquire_exclusive`
+/// returns.
+struct ExclusiveAttempt<'conn> {
+    conn: &'conn Connection,
+    locking_mode: Option<LockingMode>,
+    busy_timeout: i32,
+    autocommit: bool,
+}
+
+impl Drop for ExclusiveAttempt<'_> {
+    fn drop(&mut self) {
+        if let Some(locking_mode) = self.locking_mode {
+            // `COMMIT` may have failed after `BEGIN EXCLUSIVE`.
+            if self.autocommit && !self.conn.is_autocommit() {
+                let _ = self.conn.execute_internal("ROLLBACK");
+            }
+            let _ = self.conn.set_locking_mode(locking_mode);
+        }
+        let _ = self
+            .conn
+            .pragma_update(None, "busy_timeout", &self.busy_timeout);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    use super::LockingMode;
+    use crate::{Connection, NO_PARAMS};
+
+    #[test]
+    fn test_try_acquire_exclusive() {
+        let temp_dir = TempDir::new("test_try_acquire_exclusive").unwrap();
+        let path = temp_dir.path().join("test.db3");
+
+        let db1 = Connection::open(&path).unwrap();
+        let db2 = Connection::open(&path).unwrap();
+        db2.busy_timeout(Duration::from_millis(50)).unwrap();
+
+        assert!(db1.try_acquire_exclusive(Duration::from_millis(0)).unwrap());
+        assert_eq!(LockingMode::Exclusive, db1.locking_mode().unwrap());
+        assert!(!db2
+            .try_acquire_exclusive(Duration::from_millis(10))
+            .unwrap());
+        assert_eq!(LockingMode::Normal, db2.locking_mode().unwrap());
+        let busy_timeout: i32 = db2
+            .pragma_query_value(None, "busy_timeout", |row| row.get(0))
+            .unwrap();
+        assert_eq!(50, busy_timeout);
+        assert!(db2
+            .query_row("SELECT count(*) FROM sqlite_master", NO_PARAMS, |_| Ok(()))
+            .is_err());
+
+        db1.release_exclusive().unwrap();
+        assert_eq!(LockingMode::Normal, db1.locking_mode().unwrap());
+        assert!(db2.try_acquire_exclusive(Duration::from_millis(0)).unwrap());
+    }
+
+    #[test]
+    fn test_try_acquire_exclusive_restores_locking_mode() {
+        let temp_dir = TempDir::new("test_try_acquire_exclusive_restores").unwrap();
+        let path = temp_dir.path().join("test.db3");
+
+        let db1 = Connection::open(&path).unwrap();
+        let db2 = Connection::open(&path).unwrap();
+        assert!(db1.try_acquire_exclusive(Duration::from_millis(0)).unwrap());
+
+        // Timeout
+        db2.busy_timeout(Duration::from_millis(50)).unwrap();
+        assert!(!db2
+            .try_acquire_exclusive(Duration::from_millis(10))
+            .unwrap());
+        assert_eq!(LockingMode::Normal, db2.locking_mode().unwrap());
+        assert!(db2.is_autocommit());
+        db1.release_exclusive().unwrap();
+        db2.query_row("SELECT count(*) FROM sqlite_master", NO_PARAMS, |_| Ok(()))
+            .unwrap();
+        // The lock is not kept by db2.
+        db1.query_row("SELECT count(*) FROM sqlite_master", NO_PARAMS, |_| Ok(()))
+            .unwrap();
+
+        // Error: already in a transaction, which is kept.
+        db2.execute_batch("BEGIN").unwrap();
+        assert!(db2.try_acquire_exclusive(Duration::from_millis(0)).is_err());
+        assert_eq!(LockingMode::Normal, db2.locking_mode().unwrap());
+        assert!(!db2.is_autocommit());
+        db2.execute_batch("COMMIT").unwrap();
+
+        // The previous locking mode is restored, not `Normal`.
+        db2.set_locking_mode(LockingMode::Exclusive).unwrap();
+        db2.execute_batch("BEGIN").unwrap();
+        assert!(db2.try_acquire_exclusive(Duration::from_millis(0)).is_err());
+        assert_eq!(LockingMode::Exclusive, db2.locking_mode().unwrap());
+        let busy_timeout: i32 = db2
+            .pragma_query_value(None, "busy_timeout", |row| row.get(0))
+            .unwrap();
+        assert_eq!(50, busy_timeout);
+    }
+}
@@ -0,0 +1,558 @@
+//! Open connections with a common initialization.
+use std::fmt;
+use std::path::Path;
+use std::str;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::types::Value;
+use crate::{Connection, DurabilityOptions, Error, OpenFlags, Result, SecurityOptions};
+
+type InitFn = dyn Fn(&Connection) -> Result<()> + Send + Sync;
+
+#[derive(Clone)]
+enum Init {
+    Sql(String),
+    Fn(Arc<InitFn>),
+}
+
+/// Options and initialization steps used to open connections.
+///
+/// The initialization steps (SQL scripts and closures) are run in order right
+/// after each connection is opened, so that every connection gets the same
+/// pragmas, attached databases, functions and collations. `OpenOptions` is
+/// cheap to clone and can be shared between threads, e.g. by a connection
+/// pool.
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, OpenOptions, Result};
+/// fn open(path: &str) -> Result<Connection> {
+///     OpenOptions::new()
+///         .init_sql("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")
+///         .init_fn(|conn| conn.busy_timeout(std::time::Duration::from_secs(5)))
+///         .open(path)
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct OpenOptions {
+    flags: OpenFlags,
+    page_size: Option<u32>,
+    text_encoding: Option<TextEncoding>,
+    init: Vec<Init>,
+}
+
+/// Text encoding of a database (see
+/// [`PRAGMA encoding`](https://sqlite.org/pragma.html#pragma_encoding)).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextEncoding {
+    /// UTF-8 (default).
+    Utf8,
+    /// UTF-16, with the native byte order.
+    Utf16,
+}
+
+impl Connection {
+    /// Return the text encoding of the main database (see
+    /// [`PRAGMA encoding`](https://sqlite.org/pragma.html#pragma_encoding)),
+    /// `Utf16` for both byte orders.
+    ///
+    /// Text is always exchanged with SQLite as UTF-8: the text of a UTF-16
+    /// database is converted by SQLite. As SQLite does not validate text, a
+    /// text value may not be valid UTF-8: `Row::get` and
+    /// `Row::get_raw_checked` then fail, while `Row::get_str_lossy` replaces
+    /// the invalid sequences.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn encoding(&self) -> Result<TextEncoding> {
+        let encoding: String = self.pragma_query_value(None, "encoding", |row| row.get(0))?;
+        // "UTF-8", "UTF-16le" or "UTF-16be"
+        if encoding.starts_with("UTF-16") {
+            Ok(TextEncoding::Utf16)
+        } else {
+            Ok(TextEncoding::Utf8)
+        }
+    }
+}
+
+impl OpenOptions {
+    /// Create options with the default `OpenFlags` and no initialization.
+    pub fn new() -> OpenOptions {
+        OpenOptions::default()
+    }
+
+    /// Set the flags used to open connections.
+    pub fn flags(mut self, flags: OpenFlags) -> OpenOptions {
+        self.flags = flags;
+        self
+    }
+
+    /// Set the page size of new databases, which must be a power of two
+    /// between 512 and 65536.
+    ///
+    /// The page size of a database cannot be changed once it has been
+    /// created (without a `VACUUM`), so opening an existing database with a
+    /// different page size fails with `Error::IncompatibleDatabaseSetting`.
+    pub fn page_size(mut self, page_size: u32) -> OpenOptions {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Set the text encoding of new databases.
+    ///
+    /// The text encoding of a database cannot be changed once it has been
+    /// created, so opening an existing database with a different encoding
+    /// fails with `Error::IncompatibleDatabaseSetting`.
+    pub fn text_encoding(mut self, text_encoding: TextEncoding) -> OpenOptions {
+        self.text_encoding = Some(text_encoding);
+        self
+    }
+
+    /// Add an initialization step applying the security settings which are
+    /// not `None` (see `Connection::set_security_options`).
+    pub fn security_options(self, options: SecurityOptions) -> OpenOptions {
+        self.init_fn(move |conn| conn.set_security_options(&options))
+    }
+
+    /// Add an initialization step applying the durability settings which are
+    /// not `None` (see `Connection::set_durability_options`).
+    pub fn durability_options(self, options: DurabilityOptions) -> OpenOptions {
+        self.init_fn(move |conn| conn.set_durability_options(&options))
+    }
+
+    /// Add SQL statements (that cannot take any parameters) to be run with
+    /// `execute_batch` on each new connection.
+    pub fn init_sql(mut self, sql: &str) -> OpenOptions {
+        self.init.push(Init::Sql(sql.to_owned()));
+        self
+    }
+
+    /// Add a closure to be called on each new connection.
+    pub fn init_fn<F>(mut self, f: F) -> OpenOptions
+    where
+        F: Fn(&Connection) -> Result<()> + Send + Sync + 'static,
+    {
+        self.init.push(Init::Fn(Arc::new(f)));
+        self
+    }
+
+    /// Open a new connection to a SQLite database and initialize it.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `path` cannot be converted to a C-compatible
+    /// string, if the underlying SQLite open call fails or if an
+    /// initialization step fails.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<Connection> {
+        let conn = Connection::open_with_flags(path, self.flags)?;
+        self.initialize(&conn)?;
+        Ok(conn)
+    }
+
+    /// Open a new connection to an in-memory SQLite database and initialize
+    /// it.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite open call fails or if an
+    /// initialization step fails.
+    pub fn open_in_memory(&self) -> Result<Connection> {
+        let conn = Connection::open_in_memory_with_flags(self.flags)?;
+        self.initialize(&conn)?;
+        Ok(conn)
+    }
+
+    /// Apply the page size and text encoding, then run the initialization
+    /// steps, in order, on an already opened connection.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the database already exists with a different page
+    /// size or text encoding, or the error of the first failing step.
+    pub fn initialize(&self, conn: &Connection) -> Result<()> {
+        self.apply_format(conn)?;
+        for init in &self.init {
+            match *init {
+                Init::Sql(ref sql) => conn.execute_batch(sql)?,
+                Init::Fn(ref f) => f(conn)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OpenOptions {
+    /// Set the page size and text encoding, which is only effective before
+    /// the first write to a new database, and check them.
+    fn apply_format(&self, conn: &Connection) -> Result<()> {
+        if let Some(page_size) = self.page_size {
+            conn.pragma_update(None, "page_size", &page_size)?;
+            let actual: u32 = conn.pragma_query_value(None, "page_size", |row| row.get(0))?;
+            if actual != page_size {
+                return Err(Error::IncompatibleDatabaseSetting(format!(
+                    "page_size is {} (expected {})",
+                    actual, page_size
+                )));
+            }
+        }
+        if let Some(text_encoding) = self.text_encoding {
+            let expected = match text_encoding {
+                TextEncoding::Utf8 => "UTF-8",
+                TextEncoding::Utf16 => "UTF-16",
+            };
+            conn.pragma_update(None, "encoding", &expected)?;
+            let actual: String = conn.pragma_query_value(None, "encoding", |row| row.get(0))?;
+            // "UTF-16le" or "UTF-16be"
+            if !actual.starts_with(expected) {
+                return Err(Error::IncompatibleDatabaseSetting(format!(
+                    "encoding is {} (expected {})",
+                    actual, expected
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Connection {
+    /// Open a new connection described by a connection URL, e.g. taken from
+    /// an environment variable:
+    /// `sqlite:///var/lib/app.db?mode=ro&cache=shared&busy_timeout=5000`.
+    ///
+    /// The path follows `sqlite://` (so absolute paths start with a third
+    /// `/`), and `sqlite::memory:` or `sqlite://:memory:` open an in-memory
+    /// database. The path and the values can be percent-encoded. The
+    /// following query parameters are supported:
+    ///
+    /// * `mode`: `ro` (read-only), `rw` (read-write), `rwc` (read-write,
+    ///   creating the database if needed, the default) or `memory` (an
+    ///   in-memory database, which connections opened with `cache=shared`
+    ///   and the same path share),
+    /// * `cache`: `shared` or `private`,
+    /// * `busy_timeout`: in milliseconds, at most `i32::MAX` (see `busy_timeout`),
+    /// * the pragmas `auto_vacuum`, `cache_size`, `foreign_keys`,
+    ///   `journal_mode`, `locking_mode`, `mmap_size`, `recursive_triggers`,
+    ///   `secure_delete`, `synchronous` and `temp_store`, which are set in
+    ///   order (e.g. `journal_mode=WAL&foreign_keys=on`).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the URL is invalid or has an unsupported
+    /// parameter (with `Error::InvalidUrl`), if the underlying SQLite open
+    /// call fails or if a pragma cannot be set.
+    pub fn open_from_url(url: &str) -> Result<Connection> {
+        let (options, path) = parse_url(url)?;
+        match path {
+            Some(path) => options.open(path),
+            None => options.open_in_memory(),
+        }
+    }
+}
+
+/// Parse a connection URL (see `Connection::open_from_url`) into options and
+/// a path (`None` for an in-memory database).
+fn parse_url(url: &str) -> Result<(OpenOptions, Option<String>)> {
+    let rest = match url.strip_prefix("sqlite:") {
+        Some(rest) => rest.strip_prefix("//").unwrap_or(rest),
+        None => return Err(invalid_url(url, "expected a sqlite: URL")),
+    };
+    let (path, query) = match rest.find('?') {
+        Some(i) => (&rest[..i], &rest[i + 1..]),
+        None => (rest, ""),
+    };
+    let path = percent_decode(path).ok_or_else(|| invalid_url(url, "invalid path"))?;
+    let mut memory = path == ":memory:";
+
+    let mut flags = OpenFlags::default();
+    let mut options = OpenOptions::new();
+    for param in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = match param.find('=') {
+            Some(i) => (&param[..i], &param[i + 1..]),
+            None => (param, ""),
+        };
+        let value = percent_decode(value).ok_or_else(|| invalid_url(url, param))?;
+        match key {
+            "mode" => {
+                flags.remove(OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE);
+                match value.as_str() {
+                    "ro" => flags.insert(OpenFlags::SQLITE_OPEN_READ_ONLY),
+                    "rw" => flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE),
+                    "rwc" => flags
+                        .insert(OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE),
+                    "memory" => {
+                        flags.insert(
+                            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
+                        );
+                        memory = true;
+                    }
+                    _ => return Err(invalid_url(url, param)),
+                }
+            }
+            "cache" => match value.as_str() {
+                "shared" => flags.insert(OpenFlags::SQLITE_OPEN_SHARED_CACHE),
+                "private" => flags.insert(OpenFlags::SQLITE_OPEN_PRIVATE_CACHE),
+                _ => return Err(invalid_url(url, param)),
+            },
+            "busy_timeout" => {
+                // `sqlite3_busy_timeout` takes an `int`.
+                let ms = value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|&ms| ms <= i32::max_value() as u32)
+                    .ok_or_else(|| invalid_url(url, param))?;
+                let timeout = Duration::from_millis(u64::from(ms));
+                options = options.init_fn(move |conn| conn.busy_timeout(timeout));
+            }
+            _ => {
+                let pragma = match URL_PRAGMAS.iter().find(|&&pragma| pragma == key) {
+                    Some(&pragma) => pragma,
+                    None => return Err(invalid_url(url, param)),
+                };
+                let value = match value.parse::<i64>() {
+                    Ok(i) => Value::Integer(i),
+                    Err(_) => Value::Text(value),
+                };
+                options = options.init_fn(move |conn| conn.pragma_update(None, pragma, &value));
+            }
+        }
+    }
+    let path = if !memory {
+        Some(path)
+    } else if flags.contains(OpenFlags::SQLITE_OPEN_SHARED_CACHE) {
+        // Only in-memory databases opened by URI filenames can be shared.
+        flags.insert(OpenFlags::SQLITE_OPEN_URI);
+        Some(format!(
+            "file:{}?mode=memory&cache=shared",
+            uri_escape(&path)
+        ))
+    } else {
+        None
+    };
+    Ok((options.flags(flags), path))
+}
+
+/// Pragmas which can be set by a connection URL.
+const URL_PRAGMAS: &[&str] = &[
+    "auto_vacuum",
+    "cache_size",
+    "foreign_keys",
+    "journal_mode",
+    "locking_mode",
+    "mmap_size",
+    "recursive_triggers",
+    "secure_delete",
+    "synchronous",
+    "temp_store",
+];
+
+fn invalid_url(url: &str, reason: &str) -> Error {
+    Error::InvalidUrl(format!("\"{}\": {}", url, reason))
+}
+
+/// Escape the characters of `path` which are special in a URI filename.
+fn uri_escape(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            '?' | '#' | '%' | '&' => escaped.push_str(&format!("%{:02X}", c as u32)),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            // `from_str_radix` would also accept a sign, e.g. in `%+1`
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            decoded.push(u8::from_str_radix(str::from_utf8(hex).ok()?, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+impl fmt::Debug for OpenOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let init: Vec<&str> = self
+            .init
+            .iter()
+            .map(|init| match *init {
+                Init::Sql(ref sql) => sql.as_str(),
+                Init::Fn(_) => "<fn>",
+            })
+            .collect();
+        f.debug_struct("OpenOptions")
+            .field("flags", &self.flags)
+            .field("page_size", &self.page_size)
+            .field("text_encoding", &self.text_encoding)
+            .field("init", &init)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{percent_decode, OpenOptions, TextEncoding};
+    use crate::{Connection, Error, OpenFlags, NO_PARAMS};
+
+    #[test]
+    fn test_init() {
+        let options = OpenOptions::new()
+            .init_sql("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES(1);")
+            .init_fn(|conn| conn.execute_batch("INSERT INTO foo VALUES(2)"));
+        for _ in 0..2 {
+            let db = options.open_in_memory().unwrap();
+            let sum: i64 = db
+                .query_row("SELECT sum(x) FROM foo", NO_PARAMS, |r| r.get(0))
+                .unwrap();
+            assert_eq!(3, sum);
+        }
+    }
+
+    #[test]
+    fn test_init_failure() {
+        let options = OpenOptions::new()
+            .flags(OpenFlags::default())
+            .init_fn(|_| Err(Error::QueryReturnedNoRows));
+        match options.open_in_memory() {
+            Err(Error::QueryReturnedNoRows) => (),
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_page_size_and_text_encoding() {
+        let temp_dir = tempdir::TempDir::new("test_page_size_and_text_encoding").unwrap();
+        let path = temp_dir.path().join("test.db3");
+
+        let options = OpenOptions::new()
+            .page_size(8192)
+            .text_encoding(TextEncoding::Utf16)
+            .init_sql("CREATE TABLE IF NOT EXISTS foo(x TEXT)");
+        {
+            let db = options.open(&path).unwrap();
+            let page_size: u32 = db
+                .pragma_query_value(None, "page_size", |row| row.get(0))
+                .unwrap();
+            assert_eq!(8192, page_size);
+            assert_eq!(TextEncoding::Utf16, db.encoding().unwrap());
+            db.execute("INSERT INTO foo VALUES(?)", &["h\u{e9}\u{1f600}"])
+                .unwrap();
+            let x: String = db
+                .query_row("SELECT x FROM foo", NO_PARAMS, |row| row.get(0))
+                .unwrap();
+            assert_eq!("h\u{e9}\u{1f600}", x);
+        }
+        // Same settings
+        options.open(&path).unwrap();
+
+        match OpenOptions::new().page_size(4096).open(&path) {
+            Err(Error::IncompatibleDatabaseSetting(_)) => (),
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+        match OpenOptions::new()
+            .text_encoding(TextEncoding::Utf8)
+            .open(&path)
+        {
+            Err(Error::IncompatibleDatabaseSetting(_)) => (),
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+        assert!(OpenOptions::new().page_size(1000).open_in_memory().is_err());
+    }
+
+    #[test]
+    fn test_open_from_url() {
+        let temp_dir = tempdir::TempDir::new("test_open_from_url").unwrap();
+        let path = temp_dir.path().join("test db.db3");
+        let url = format!(
+            "sqlite://{}?busy_timeout=1234&journal_mode=WAL&foreign_keys=on",
+            path.to_str().unwrap().replace(' ', "%20")
+        );
+        {
+            let db = Connection::open_from_url(&url).unwrap();
+            let busy_timeout: i64 = db
+                .pragma_query_value(None, "busy_timeout", |row| row.get(0))
+                .unwrap();
+            assert_eq!(1234, busy_timeout);
+            let journal_mode: String = db
+                .pragma_query_value(None, "journal_mode", |row| row.get(0))
+                .unwrap();
+            assert_eq!("wal", journal_mode);
+            let foreign_keys: bool = db
+                .pragma_query_value(None, "foreign_keys", |row| row.get(0))
+                .unwrap();
+            assert!(foreign_keys);
+            db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        }
+        assert!(path.exists());
+
+        let ro_url = url.replace("?", "?mode=ro&cache=shared&");
+        let db = Connection::open_from_url(&ro_url).unwrap();
+        db.query_row("SELECT count(*) FROM foo", NO_PARAMS, |_| Ok(()))
+            .unwrap();
+        assert!(db.execute_batch("INSERT INTO foo VALUES(1)").is_err());
+
+        for url in &[
+            "sqlite::memory:",
+            "sqlite://:memory:",
+            "sqlite://x.db?mode=memory",
+        ] {
+            let db = Connection::open_from_url(url).unwrap();
+            db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        }
+        for url in &[
+            "postgres://localhost/db",
+            "sqlite::memory:?mode=foo",
+            "sqlite::memory:?busy_timeout=x",
+            "sqlite::memory:?busy_timeout=-1",
+            "sqlite::memory:?busy_timeout=3000000000",
+            "sqlite://%zz.db",
+            "sqlite://%+1.db",
+            "sqlite://%-1.db",
+            "sqlite://x.db%2",
+            "sqlite::memory:?no_such;pragma=1",
+            "sqlite::memory:?user_version=1",
+        ] {
+            match Connection::open_from_url(url) {
+                Err(Error::InvalidUrl(_)) => (),
+                r => panic!("{}: unexpected result {:?}", url, r.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(Some("a b%".to_owned()), percent_decode("a%20b%25"));
+        assert_eq!(Some("\u{e9}".to_owned()), percent_decode("%c3%A9"));
+        for s in &["%+1", "%-1", "% 1", "%1", "%", "%zz", "%e9"] {
+            assert_eq!(None, percent_decode(s), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_open_shared_memory_from_url() {
+        let url = "sqlite://test_open_shared_memory_from_url?mode=memory&cache=shared";
+        let db1 = Connection::open_from_url(url).unwrap();
+        db1.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        let db2 = Connection::open_from_url(url).unwrap();
+        db2.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
+
+        // without a shared cache, in-memory databases are private
+        let db3 =
+            Connection::open_from_url("sqlite://test_open_shared_memory_from_url?mode=memory")
+                .unwrap();
+        assert!(db3.execute_batch("INSERT INTO foo VALUES(1)").is_err());
+    }
+}
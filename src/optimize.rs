@@ -0,0 +1,108 @@
+//! Query planner statistics management.
+use crate::pragma::Sql;
+use crate::{Connection, Result};
+
+impl Connection {
+    /// Run [`PRAGMA optimize`](https://sqlite.org/pragma.html#pragma_optimize)
+    /// on all attached databases, which runs `ANALYZE` on the tables whose
+    /// statistics are missing or out of date.
+    ///
+    /// SQLite recommends running it periodically on long-lived connections,
+    /// and before closing short-lived ones (see `set_optimize_on_close`).
+    /// This is a no-op before SQLite 3.18.0.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn optimize(&self) -> Result<()> {
+        self.execute_internal("PRAGMA optimize")
+    }
+
+    /// Run `PRAGMA optimize` with the given `mask` selecting the
+    /// optimizations to perform (e.g. `0x10002` to also analyze the tables
+    /// which have not been queried by this connection, see the SQLite
+    /// documentation).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn optimize_with_mask(&self, mask: u32) -> Result<()> {
+        self.pragma_update(None, "optimize", &mask)
+    }
+
+    /// Gather statistics about the indexes of `table` (or of all tables in
+    /// all attached databases for `None`) for the query planner, with
+    /// [`ANALYZE`](https://sqlite.org/lang_analyze.html).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `table` does not exist or if the underlying
+    /// SQLite call fails.
+    pub fn analyze(&self, table: Option<&str>) -> Result<()> {
+        let mut sql = Sql::new();
+        sql.push_keyword("ANALYZE")?;
+        if let Some(table) = table {
+            sql.push_space();
+            sql.push_identifier(table);
+        }
+        self.execute_batch(&sql)
+    }
+
+    /// Run `PRAGMA optimize` when the connection is closed or dropped
+    /// (disabled by default).
+    ///
+    /// Errors of `PRAGMA optimize` are ignored, as the statistics are only
+    /// used to choose query plans.
+    pub fn set_optimize_on_close(&self, enabled: bool) {
+        self.db.borrow_mut().optimize_on_close = enabled;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Connection, NO_PARAMS};
+
+    fn stat_count(db: &Connection) -> i64 {
+        db.query_row("SELECT count(*) FROM sqlite_stat1", NO_PARAMS, |r| r.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_analyze() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER);
+             CREATE INDEX foo_x ON foo(x);
+             INSERT INTO foo VALUES(1);
+             INSERT INTO foo VALUES(2);
+             CREATE TABLE \"b\"\"ar\"(y INTEGER);
+             CREATE INDEX bar_y ON \"b\"\"ar\"(y);
+             INSERT INTO \"b\"\"ar\" VALUES(1);",
+        )
+        .unwrap();
+        db.analyze(Some("foo")).unwrap();
+        assert_eq!(1, stat_count(&db));
+        db.analyze(Some("b\"ar")).unwrap();
+        assert_eq!(2, stat_count(&db));
+        db.analyze(None).unwrap();
+        assert!(db.analyze(Some("baz")).is_err());
+
+        db.optimize().unwrap();
+        db.optimize_with_mask(0x10002).unwrap();
+    }
+
+    #[test]
+    fn test_optimize_on_close() {
+        let temp_dir = tempdir::TempDir::new("test_optimize_on_close").unwrap();
+        let path = temp_dir.path().join("test.db");
+        let db = Connection::open(&path).unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER);
+             CREATE INDEX foo_x ON foo(x);
+             INSERT INTO foo VALUES(1);",
+        )
+        .unwrap();
+        db.set_optimize_on_close(true);
+        db.close().unwrap();
+    }
+}
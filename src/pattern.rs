@@ -0,0 +1,69 @@
+//! `LIKE` and `GLOB` matching outside of SQL statements.
+use std::os::raw::c_uint;
+
+use crate::ffi;
+use crate::{str_to_cstring, Result};
+
+/// Return `true` if `text` matches the `LIKE` `pattern`, with the same
+/// semantics as the SQL `text LIKE pattern ESCAPE escape` expression: `%`
+/// matches any sequence of characters, `_` any single character, and the
+/// match is case-insensitive for ASCII characters only.
+///
+/// This is useful to filter values on the client side consistently with
+/// `LIKE` queries. Note that the `case_sensitive_like` pragma is ignored.
+///
+/// See [`sqlite3_strlike()`](https://www.sqlite.org/c3ref/strlike.html).
+///
+/// # Failure
+///
+/// Will return `Err` if `pattern` or `text` contains a nul byte.
+pub fn str_like(pattern: &str, text: &str, escape: Option<char>) -> Result<bool> {
+    let pattern = str_to_cstring(pattern)?;
+    let text = str_to_cstring(text)?;
+    let escape = escape.map_or(0, |c| c as c_uint);
+    let r = unsafe { ffi::sqlite3_strlike(pattern.as_ptr(), text.as_ptr(), escape) };
+    Ok(r == 0)
+}
+
+/// Return `true` if `text` matches the `GLOB` `pattern`, with the same
+/// semantics as the SQL `text GLOB pattern` expression: `*` matches any
+/// sequence of characters, `?` any single character, `[...]` a character
+/// class, and the match is case-sensitive.
+///
+/// See [`sqlite3_strglob()`](https://www.sqlite.org/c3ref/strglob.html).
+///
+/// # Failure
+///
+/// Will return `Err` if `pattern` or `text` contains a nul byte.
+pub fn str_glob(pattern: &str, text: &str) -> Result<bool> {
+    let pattern = str_to_cstring(pattern)?;
+    let text = str_to_cstring(text)?;
+    let r = unsafe { ffi::sqlite3_strglob(pattern.as_ptr(), text.as_ptr()) };
+    Ok(r == 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{str_glob, str_like};
+
+    #[test]
+    fn test_str_like() {
+        assert!(str_like("ab%", "ABC", None).unwrap());
+        assert!(str_like("a_c", "abc", None).unwrap());
+        assert!(!str_like("a_c", "abbc", None).unwrap());
+        assert!(str_like("100\\%", "100%", Some('\\')).unwrap());
+        assert!(!str_like("100\\%", "1000", Some('\\')).unwrap());
+        // Only ASCII characters are case-folded, like the SQL operator.
+        assert!(!str_like("é", "É", None).unwrap());
+        assert!(str_like("a\0", "a", None).is_err());
+    }
+
+    #[test]
+    fn test_str_glob() {
+        assert!(str_glob("ab*", "abc").unwrap());
+        assert!(!str_glob("ab*", "ABC").unwrap());
+        assert!(str_glob("a?c", "abc").unwrap());
+        assert!(str_glob("[a-c]x", "bx").unwrap());
+        assert!(!str_glob("[a-c]x", "dx").unwrap());
+    }
+}
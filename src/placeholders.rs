@@ -0,0 +1,145 @@
+//! Placeholder lists for statements with a variable number of parameters.
+use std::fmt::Write;
+use std::os::raw::c_int;
+
+use crate::ffi;
+use crate::{Connection, Error, Result};
+
+impl Connection {
+    /// Return a list of `n` anonymous placeholders (`?,?,?`), e.g. for an
+    /// `IN` clause matching a list of values.
+    ///
+    /// Parameters can be bound from any iterator of `ToSql` values.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn names(conn: &Connection, ids: &[i64]) -> Result<Vec<String>> {
+    ///     let sql = format!(
+    ///         "SELECT name FROM people WHERE id IN ({})",
+    ///         conn.placeholders(ids.len())?
+    ///     );
+    ///     let mut stmt = conn.prepare(&sql)?;
+    ///     let rows = stmt.query_map(ids, |row| row.get(0))?;
+    ///     rows.collect()
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `n` exceeds the maximum number of parameters of a
+    /// statement (`Limit::SQLITE_LIMIT_VARIABLE_NUMBER`).
+    pub fn placeholders(&self, n: usize) -> Result<String> {
+        self.check_variable_number(n)?;
+        let mut sql = String::with_capacity(2 * n);
+        for i in 0..n {
+            if i > 0 {
+                sql.push(',');
+            }
+            sql.push('?');
+        }
+        Ok(sql)
+    }
+
+    /// Return a list of `n` named placeholders made of `prefix` followed by
+    /// their index (`:p0,:p1,:p2` for the `:p` prefix).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `prefix` does not start with `:`, `@` or `$`
+    /// followed by ASCII letters, digits or underscores, or if `n` exceeds the
+    /// maximum number of parameters of a statement
+    /// (`Limit::SQLITE_LIMIT_VARIABLE_NUMBER`).
+    pub fn named_placeholders(&self, prefix: &str, n: usize) -> Result<String> {
+        let mut chars = prefix.chars();
+        let valid = match chars.next() {
+            Some(':') | Some('@') | Some('$') => {
+                chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+            }
+            _ => false,
+        };
+        if !valid {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some(format!("invalid parameter prefix: {:?}", prefix)),
+            ));
+        }
+        self.check_variable_number(n)?;
+        let mut sql = String::new();
+        for i in 0..n {
+            if i > 0 {
+                sql.push(',');
+            }
+            write!(sql, "{}{}", prefix, i).unwrap();
+        }
+        Ok(sql)
+    }
+
+    fn check_variable_number(&self, n: usize) -> Result<()> {
+        let max = unsafe {
+            ffi::sqlite3_limit(
+                self.db.borrow().db(),
+                ffi::Limit::SQLITE_LIMIT_VARIABLE_NUMBER as c_int,
+                -1,
+            )
+        };
+        if n > max as usize {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_RANGE),
+                Some(format!("too many SQL variables: {} > {}", n, max)),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::ToSql;
+    use crate::{Connection, Error, ErrorCode};
+
+    #[test]
+    fn test_placeholders() {
+        let db = Connection::open_in_memory().unwrap();
+        assert_eq!("", db.placeholders(0).unwrap());
+        assert_eq!("?", db.placeholders(1).unwrap());
+        assert_eq!("?,?,?", db.placeholders(3).unwrap());
+
+        let ids = vec![1, 3, 5];
+        let sql = format!(
+            "SELECT count(*) FROM (SELECT 1 AS x UNION SELECT 3 UNION SELECT 4) WHERE x IN ({})",
+            db.placeholders(ids.len()).unwrap()
+        );
+        let count: i64 = db.query_row(&sql, &ids, |r| r.get(0)).unwrap();
+        assert_eq!(2, count);
+
+        match db.placeholders(1_000_000) {
+            Err(Error::SqliteFailure(err, _)) => {
+                assert_eq!(ErrorCode::ParameterOutOfRange, err.code)
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_named_placeholders() {
+        let db = Connection::open_in_memory().unwrap();
+        assert_eq!("", db.named_placeholders(":p", 0).unwrap());
+        let sql = db.named_placeholders(":p", 2).unwrap();
+        assert_eq!(":p0,:p1", sql);
+        let sum: i64 = db
+            .query_row_named(
+                &format!("SELECT {}", sql.replace(',', " + ")),
+                &[(":p0", &1 as &dyn ToSql), (":p1", &2)],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(3, sum);
+
+        assert_eq!("@id_0", db.named_placeholders("@id_", 1).unwrap());
+        assert!(db.named_placeholders("p", 1).is_err());
+        assert!(db.named_placeholders(":p'", 1).is_err());
+        assert!(db.named_placeholders(":p", 1_000_000).is_err());
+    }
+}
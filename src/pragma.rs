@@ -24,7 +24,7 @@ impl Sql {
         self.push_keyword("PRAGMA")?;
         self.push_space();
         if let Some(schema_name) = schema_name {
-            self.push_schema_name(schema_name);
+            self.push_schema_name(schema_name)?;
             self.push_dot();
         }
         self.push_keyword(pragma_name)
@@ -42,12 +42,10 @@ impl Sql {
         }
     }
 
-    pub fn push_schema_name(&mut self, schema_name: DatabaseName<'_>) {
-        match schema_name {
-            DatabaseName::Main => self.buf.push_str("main"),
-            DatabaseName::Temp => self.buf.push_str("temp"),
-            DatabaseName::Attached(s) => self.push_identifier(s),
-        };
+    pub fn push_schema_name(&mut self, schema_name: DatabaseName<'_>) -> Result<()> {
+        schema_name.validate()?;
+        self.push_identifier(schema_name.as_str());
+        Ok(())
     }
 
     pub fn push_identifier(&mut self, s: &str) {
@@ -63,6 +61,8 @@ impl Sql {
         let value = match value {
             ToSqlOutput::Borrowed(v) => v,
             ToSqlOutput::Owned(ref v) => ValueRef::from(v),
+            #[cfg(feature = "borrowed_blob")]
+            ToSqlOutput::BorrowedBlob(b) => ValueRef::Blob(b.as_bytes()),
             #[cfg(feature = "blob")]
             ToSqlOutput::ZeroBlob(_) => {
                 return Err(Error::SqliteFailure(
@@ -122,6 +122,16 @@ impl Sql {
         self.buf.push('=');
     }
 
+    pub fn push_comma(&mut self) {
+        self.buf.push(',');
+    }
+
+    /// Push a numbered parameter (`?NNN`).
+    pub fn push_parameter(&mut self, index: usize) {
+        self.buf.push('?');
+        self.buf.push_str(&index.to_string());
+    }
+
     pub fn open_brace(&mut self) {
         self.buf.push('(');
     }
@@ -417,10 +427,15 @@ mod test {
     }
 
     #[test]
-    fn double_quote() {
+    fn schema_name() {
+        let mut sql = Sql::new();
+        sql.push_schema_name(DatabaseName::Attached("my schema"))
+            .unwrap();
+        assert_eq!(r#""my schema""#, sql.as_str());
         let mut sql = Sql::new();
-        sql.push_schema_name(DatabaseName::Attached(r#"schema";--"#));
-        assert_eq!(r#""schema"";--""#, sql.as_str());
+        assert!(sql
+            .push_schema_name(DatabaseName::Attached(r#"schema";--"#))
+            .is_err());
     }
 
     #[test]
@@ -0,0 +1,174 @@
+//! Engine tuning profiles.
+use crate::types::Value;
+use crate::{Connection, Error, Result};
+
+/// A vetted set of pragmas tuning a connection for a common workload (see
+/// `Connection::apply_profile`).
+///
+/// Only per-connection settings and the journal mode (which is persistent
+/// for WAL) are changed: each connection must apply the profile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    /// Frequent writes, concurrent with reads:
+    ///
+    /// * `journal_mode = WAL`: readers do not block the writer (and vice
+    ///   versa), and commits only append to the WAL,
+    /// * `synchronous = NORMAL`: the WAL is not synced on each commit, which
+    ///   is still safe from corruption in WAL mode, but the last transactions
+    ///   may be rolled back after a power loss,
+    /// * `cache_size = -65536`: a 64 MiB page cache,
+    /// * `temp_store = MEMORY`: temporary tables and indices are kept in
+    ///   memory.
+    WriteHeavy,
+    /// Small memory footprint, e.g. for embedded devices:
+    ///
+    /// * `cache_size = -512`: a 512 KiB page cache,
+    /// * `temp_store = FILE`: temporary tables and indices are written to
+    ///   disk,
+    /// * `mmap_size = 0`: the database file is not memory-mapped,
+    /// * `cache_spill = ON`: dirty pages may be written to the database file
+    ///   before the transaction commits, to free the cache.
+    LowMemory,
+    /// No committed transaction lost, even after a power loss:
+    ///
+    /// * `journal_mode = WAL`,
+    /// * `synchronous = FULL`: the WAL is synced on each commit,
+    /// * `fullfsync = ON` and `checkpoint_fullfsync = ON`: `F_FULLFSYNC` is
+    ///   used instead of `fsync` on the platforms supporting it (macOS), where
+    ///   `fsync` does not flush the disk cache.
+    Durable,
+}
+
+impl Profile {
+    /// Return the pragmas of the profile, in the order they are applied.
+    pub fn pragmas(self) -> Vec<(&'static str, Value)> {
+        let text = |s: &str| Value::Text(s.to_owned());
+        match self {
+            Profile::WriteHeavy => vec![
+                ("journal_mode", text("WAL")),
+                ("synchronous", text("NORMAL")),
+                ("cache_size", Value::Integer(-65536)),
+                ("temp_store", text("MEMORY")),
+            ],
+            Profile::LowMemory => vec![
+                ("cache_size", Value::Integer(-512)),
+                ("temp_store", text("FILE")),
+                ("mmap_size", Value::Integer(0)),
+                ("cache_spill", text("ON")),
+            ],
+            Profile::Durable => vec![
+                ("journal_mode", text("WAL")),
+                ("synchronous", text("FULL")),
+                ("fullfsync", text("ON")),
+                ("checkpoint_fullfsync", text("ON")),
+            ],
+        }
+    }
+}
+
+impl Connection {
+    /// Set the given pragmas of the main database, in order, and return
+    /// their effective values, which may differ from the requested ones
+    /// (e.g. the journal mode of an in-memory database is always `memory`).
+    ///
+    /// Effective values are returned as reported by SQLite, e.g.
+    /// `Value::Integer(1)` for `synchronous = NORMAL`, or `Value::Null` if
+    /// SQLite does not report any (e.g. for `mmap_size` when memory-mapped
+    /// I/O is not supported, or for an unknown pragma, which SQLite silently
+    /// ignores).
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use rusqlite::types::Value;
+    /// fn tune(conn: &Connection) -> Result<()> {
+    ///     let effective = conn.run_pragma_script(&[
+    ///         ("journal_mode", Value::Text("WAL".to_owned())),
+    ///         ("cache_size", Value::Integer(-16384)),
+    ///     ])?;
+    ///     for (pragma, value) in effective {
+    ///         println!("{} = {:?}", pragma, value);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a pragma name is not a valid identifier, or if the
+    /// underlying SQLite calls fail. The pragmas set before the failing one
+    /// are not reverted.
+    pub fn run_pragma_script(&self, pragmas: &[(&str, Value)]) -> Result<Vec<(String, Value)>> {
+        let mut effective = Vec::with_capacity(pragmas.len());
+        for &(name, ref value) in pragmas {
+            self.pragma_update(None, name, value)?;
+            let value = match self.pragma_query_value(None, name, |row| row.get(0)) {
+                Err(Error::QueryReturnedNoRows) => Value::Null,
+                r => r?,
+            };
+            effective.push((name.to_owned(), value));
+        }
+        Ok(effective)
+    }
+
+    /// Apply the pragmas of `profile` (see `run_pragma_script`), and return
+    /// their effective values.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn apply_profile(&self, profile: Profile) -> Result<Vec<(String, Value)>> {
+        self.run_pragma_script(&profile.pragmas())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Profile;
+    use crate::types::Value;
+    use crate::Connection;
+
+    #[test]
+    fn test_apply_profile() {
+        let temp_dir = tempdir::TempDir::new("test_apply_profile").unwrap();
+        let db = Connection::open(temp_dir.path().join("test.db3")).unwrap();
+        let effective = db.apply_profile(Profile::WriteHeavy).unwrap();
+        assert_eq!(
+            vec![
+                ("journal_mode".to_owned(), Value::Text("wal".to_owned())),
+                ("synchronous".to_owned(), Value::Integer(1)),
+                ("cache_size".to_owned(), Value::Integer(-65536)),
+                ("temp_store".to_owned(), Value::Integer(2)),
+            ],
+            effective
+        );
+
+        let db = Connection::open_in_memory().unwrap();
+        let effective = db.apply_profile(Profile::Durable).unwrap();
+        assert_eq!(
+            ("journal_mode".to_owned(), Value::Text("memory".to_owned())),
+            effective[0]
+        );
+        assert_eq!(("synchronous".to_owned(), Value::Integer(2)), effective[1]);
+        db.apply_profile(Profile::LowMemory).unwrap();
+    }
+
+    #[test]
+    fn test_run_pragma_script() {
+        let db = Connection::open_in_memory().unwrap();
+        let effective = db
+            .run_pragma_script(&[("user_version", Value::Integer(3))])
+            .unwrap();
+        assert_eq!(
+            vec![("user_version".to_owned(), Value::Integer(3))],
+            effective
+        );
+        assert_eq!(
+            vec![("no_such_pragma".to_owned(), Value::Null)],
+            db.run_pragma_script(&[("no_such_pragma", Value::Integer(1))])
+                .unwrap()
+        );
+        assert!(db
+            .run_pragma_script(&[("user_version; DROP TABLE x", Value::Integer(1))])
+            .is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Durable message queues stored in a table
+//!
+//! A `Queue` delivers its messages by decreasing priority, then in the order
+//! they were pushed. A message is claimed by a consumer for a visibility
+//! timeout, and must be acknowledged (deleted) before the timeout expires;
+//! otherwise `requeue_expired` makes it available again, e.g. if the
+//! consumer crashed. Messages are thus delivered at least once.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{queue::Queue, Connection, Result};
+//! # use std::time::Duration;
+//! fn send_emails(conn: &Connection) -> Result<()> {
+//!     let outbox = Queue::open(conn, "outbox")?;
+//!     outbox.requeue_expired()?;
+//!     while let Some(message) = outbox.claim(Duration::from_secs(60))? {
+//!         // send message.payload...
+//!         outbox.ack(&message)?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::pragma::Sql;
+use crate::types::{ToSql, Value};
+use crate::{Connection, Result, NO_PARAMS};
+
+/// A message claimed from a `Queue`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    /// Identifier of the message.
+    pub id: i64,
+    /// Payload of the message.
+    pub payload: Value,
+    /// Priority of the message.
+    pub priority: i64,
+    /// Number of times the message has been claimed, including this one
+    /// (which identifies the claim when the message is acknowledged).
+    pub attempts: u32,
+}
+
+/// A queue stored in a table of a database.
+pub struct Queue<'conn> {
+    conn: &'conn Connection,
+    table: String,
+}
+
+impl Queue<'_> {
+    /// Open the queue stored in `table`, creating the table (and its index)
+    /// if it does not exist.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn open<'conn>(conn: &'conn Connection, table: &str) -> Result<Queue<'conn>> {
+        let mut quoted = Sql::new();
+        quoted.push_identifier(table);
+        let mut index = Sql::new();
+        index.push_identifier(&format!("{}_ready", table));
+        conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {table}(
+                 id INTEGER PRIMARY KEY,
+                 payload,
+                 priority INTEGER NOT NULL DEFAULT 0,
+                 attempts INTEGER NOT NULL DEFAULT 0,
+                 claimed_until INTEGER
+             );
+             CREATE INDEX IF NOT EXISTS {index} ON {table}(claimed_until, priority DESC, id);",
+            table = quoted.as_str(),
+            index = index.as_str(),
+        ))?;
+        Ok(Queue {
+            conn,
+            table: quoted.as_str().to_owned(),
+        })
+    }
+
+    /// Add a message with `payload` and `priority` (higher priorities are
+    /// delivered first) to the queue, and return its identifier.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn push<T: ToSql>(&self, payload: T, priority: i64) -> Result<i64> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "INSERT INTO {}(payload, priority) VALUES(?, ?)",
+            self.table
+        ))?;
+        stmt.insert(&[&payload as &dyn ToSql, &priority])
+    }
+
+    /// Claim the next available message, which is not delivered again until
+    /// it is acknowledged, or `visibility_timeout` has elapsed and
+    /// `requeue_expired` is called. Return `None` if no message is
+    /// available.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn claim(&self, visibility_timeout: Duration) -> Result<Option<Message>> {
+        let timeout = i64::try_from(visibility_timeout.as_millis()).unwrap_or(i64::MAX);
+        let until = now_millis().saturating_add(timeout);
+        self.write(|| {
+            let message = {
+                let mut stmt = self.conn.prepare_cached(&format!(
+                    "SELECT id, payload, priority, attempts + 1 FROM {}
+                     WHERE claimed_until IS NULL ORDER BY priority DESC, id LIMIT 1",
+                    self.table
+                ))?;
+                let mut rows = stmt.query(NO_PARAMS)?;
+                match rows.next()? {
+                    Some(row) => Message {
+                        id: row.get(0)?,
+                        payload: row.get(1)?,
+                        priority: row.get(2)?,
+                        attempts: row.get(3)?,
+                    },
+                    None => return Ok(None),
+                }
+            };
+            let mut stmt = self.conn.prepare_cached(&format!(
+                "UPDATE {} SET claimed_until = ?, attempts = ? WHERE id = ?",
+                self.table
+            ))?;
+            stmt.execute(&[&until as &dyn ToSql, &message.attempts, &message.id])?;
+            Ok(Some(message))
+        })
+    }
+
+    /// Acknowledge the claimed `message`, deleting it from the queue. Return
+    /// `false` if the message is not there or has been claimed again since
+    /// (e.g. its visibility timeout expired and another consumer claimed it),
+    /// in which case it is left to the latest claim.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn ack(&self, message: &Message) -> Result<bool> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "DELETE FROM {} WHERE id = ? AND attempts = ?",
+            self.table
+        ))?;
+        Ok(stmt.execute(&[&message.id as &dyn ToSql, &message.attempts])? == 1)
+    }
+
+    /// Make the claimed messages whose visibility timeout has expired
+    /// available again, and return their number.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn requeue_expired(&self) -> Result<usize> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "UPDATE {} SET claimed_until = NULL WHERE claimed_until <= ?",
+            self.table
+        ))?;
+        stmt.execute([now_millis()])
+    }
+
+    /// Return the number of messages in the queue, claimed or not.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn len(&self) -> Result<usize> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!("SELECT count(*) FROM {}", self.table))?;
+        let count: i64 = stmt.query_row(NO_PARAMS, |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    /// Return `true` if the queue holds no message, claimed or not.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Run `f` in a write transaction (or in a savepoint if a transaction is
+    /// already active), so that concurrent consumers cannot claim the same
+    /// message.
+    fn write<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T>,
+    {
+        let (begin, rollback, commit) = if self.conn.is_autocommit() {
+            ("BEGIN IMMEDIATE", "ROLLBACK", "COMMIT")
+        } else {
+            (
+                "SAVEPOINT _rusqlite_queue",
+                "ROLLBACK TO _rusqlite_queue; RELEASE _rusqlite_queue",
+                "RELEASE _rusqlite_queue",
+            )
+        };
+        self.conn.execute_internal(begin)?;
+        // A failed rollback must not hide the error which caused it.
+        let r = f().and_then(|v| self.conn.execute_internal(commit).map(|_| v));
+        if r.is_err() {
+            let _ = self.conn.execute_internal(rollback);
+        }
+        r
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::Queue;
+    use crate::types::Value;
+    use crate::Connection;
+
+    #[test]
+    fn test_queue() {
+        let db = Connection::open_in_memory().unwrap();
+        let queue = Queue::open(&db, "jobs").unwrap();
+        assert!(queue.is_empty().unwrap());
+        assert_eq!(None, queue.claim(Duration::from_secs(60)).unwrap());
+
+        let a = queue.push("a", 0).unwrap();
+        let b = queue.push("b", 0).unwrap();
+        let c = queue.push(vec![1u8, 2], 10).unwrap();
+        assert_eq!(3, queue.len().unwrap());
+
+        let m = queue.claim(Duration::from_secs(60)).unwrap().unwrap();
+        assert_eq!(
+            (c, Value::Blob(vec![1, 2]), 10, 1),
+            (m.id, m.payload, m.priority, m.attempts)
+        );
+        let m = queue.claim(Duration::from_secs(60)).unwrap().unwrap();
+        assert_eq!((a, &Value::Text("a".to_owned())), (m.id, &m.payload));
+        assert!(queue.ack(&m).unwrap());
+        assert!(!queue.ack(&m).unwrap());
+
+        // Expired claim
+        let m = queue.claim(Duration::from_secs(0)).unwrap().unwrap();
+        assert_eq!(b, m.id);
+        assert_eq!(None, queue.claim(Duration::from_secs(60)).unwrap());
+        assert_eq!(1, queue.requeue_expired().unwrap());
+        let expired = m;
+        let m = queue.claim(Duration::from_secs(60)).unwrap().unwrap();
+        assert_eq!((b, 2), (m.id, m.attempts));
+        assert_eq!(0, queue.requeue_expired().unwrap());
+        // The expired claim cannot acknowledge the message claimed again.
+        assert!(!queue.ack(&expired).unwrap());
+        assert_eq!(2, queue.len().unwrap());
+
+        // Huge timeouts do not overflow
+        queue.push("e", -1).unwrap();
+        let m = queue.claim(Duration::from_secs(u64::MAX)).unwrap().unwrap();
+        assert!(queue.ack(&m).unwrap());
+
+        // Reopen an existing queue, in a transaction
+        db.execute_batch("BEGIN").unwrap();
+        let queue = Queue::open(&db, "jobs").unwrap();
+        assert_eq!(2, queue.len().unwrap());
+        queue.push("d", 0).unwrap();
+        assert!(queue.claim(Duration::from_secs(60)).unwrap().is_some());
+        db.execute_batch("COMMIT").unwrap();
+    }
+}
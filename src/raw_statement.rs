@@ -1,23 +1,42 @@
 use super::ffi;
 use super::unlock_notify;
 use super::StatementStatus;
+use std::collections::HashSet;
 use std::ffi::CStr;
 use std::os::raw::c_int;
 use std::ptr;
+use std::sync::{Arc, Mutex};
+
+// Addresses of the statements prepared by a connection which have not been
+// finalized yet (see `Connection::force_close`).
+pub type TrackedStatements = Arc<Mutex<HashSet<usize>>>;
 
 // Private newtype for raw sqlite3_stmts that finalize themselves when dropped.
 #[derive(Debug)]
-pub struct RawStatement(*mut ffi::sqlite3_stmt);
+pub struct RawStatement(*mut ffi::sqlite3_stmt, Option<TrackedStatements>);
 
 impl RawStatement {
     pub fn new(stmt: *mut ffi::sqlite3_stmt) -> RawStatement {
-        RawStatement(stmt)
+        RawStatement(stmt, None)
+    }
+
+    // Register `stmt` in `tracked` until it is finalized.
+    pub fn tracked(stmt: *mut ffi::sqlite3_stmt, tracked: &TrackedStatements) -> RawStatement {
+        if stmt.is_null() {
+            return RawStatement::new(stmt);
+        }
+        tracked.lock().unwrap().insert(stmt as usize);
+        RawStatement(stmt, Some(Arc::clone(tracked)))
     }
 
     pub unsafe fn ptr(&self) -> *mut ffi::sqlite3_stmt {
         self.0
     }
 
+    pub fn is_null(&self) -> bool {
+        self.0.is_null()
+    }
+
     pub fn column_count(&self) -> usize {
         unsafe { ffi::sqlite3_column_count(self.0) as usize }
     }
@@ -78,6 +97,17 @@ impl RawStatement {
         }
     }
 
+    pub fn bind_parameter_name(&self, index: usize) -> Option<&CStr> {
+        unsafe {
+            let name = ffi::sqlite3_bind_parameter_name(self.0, index as c_int);
+            if name.is_null() {
+                None
+            } else {
+                Some(CStr::from_ptr(name))
+            }
+        }
+    }
+
     pub fn clear_bindings(&self) -> c_int {
         unsafe { ffi::sqlite3_clear_bindings(self.0) }
     }
@@ -91,6 +121,9 @@ impl RawStatement {
     }
 
     fn finalize_(&mut self) -> c_int {
+        if let Some(tracked) = self.1.take() {
+            tracked.lock().unwrap().remove(&(self.0 as usize));
+        }
         let r = unsafe { ffi::sqlite3_finalize(self.0) };
         self.0 = ptr::null_mut();
         r
@@ -101,6 +134,11 @@ impl RawStatement {
         unsafe { ffi::sqlite3_stmt_readonly(self.0) != 0 }
     }
 
+    #[cfg(any(feature = "stmt_busy", feature = "bundled"))]
+    pub fn is_busy(&self) -> bool {
+        unsafe { ffi::sqlite3_stmt_busy(self.0) != 0 }
+    }
+
     #[cfg(feature = "bundled")]
     pub fn expanded_sql(&self) -> Option<&CStr> {
         unsafe {
@@ -117,6 +155,10 @@ impl RawStatement {
         assert!(!self.0.is_null());
         unsafe { ffi::sqlite3_stmt_status(self.0, status as i32, reset as i32) }
     }
+
+    pub fn memory_used(&self) -> usize {
+        self.get_status(StatementStatus::MemUsed, false).max(0) as usize
+    }
 }
 
 impl Drop for RawStatement {
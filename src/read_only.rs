@@ -0,0 +1,244 @@
+//! Read-only access to a connection.
+use std::{convert, result};
+
+use crate::authorizer::{Action, AuthorizerGuard};
+use crate::ffi;
+use crate::types::{Params, ToSql};
+use crate::{Connection, Error, Result, Row, Statement};
+
+/// Pragmas which only return a value when called without argument.
+const QUERY_PRAGMAS: &[&str] = &[
+    "application_id",
+    "auto_vacuum",
+    "collation_list",
+    "compile_options",
+    "data_version",
+    "database_list",
+    "encoding",
+    "foreign_keys",
+    "freelist_count",
+    "function_list",
+    "journal_mode",
+    "module_list",
+    "page_count",
+    "page_size",
+    "pragma_list",
+    "schema_version",
+    "user_version",
+];
+
+/// Pragmas which only return a value, whatever their argument (a table or
+/// index name).
+const SCHEMA_PRAGMAS: &[&str] = &[
+    "foreign_key_list",
+    "index_info",
+    "index_list",
+    "index_xinfo",
+    "table_info",
+    "table_xinfo",
+];
+
+/// A read-only view of a connection.
+///
+/// Only query methods are exposed, and while the view exists, an
+/// [authorizer](https://sqlite.org/c3ref/set_authorizer.html) is installed
+/// on the connection which denies any statement that could modify a
+/// database or the state of the connection (`INSERT`, `UPDATE`, `DELETE`,
+/// DDL, `ATTACH`, `BEGIN`/`COMMIT`, `PRAGMA`s other than a few introspection
+/// ones, ...). Statements are checked when they are prepared, so statements
+/// prepared before the view was created (e.g. from the statement cache) are
+/// not restricted, and statements prepared through the view cannot outlive
+/// it.
+///
+/// The authorizer applies to the whole connection, and is removed when the
+/// view is dropped. Views can be nested, and combined with a `Sandbox`: a
+/// statement must then be allowed by all of them.
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result, NO_PARAMS};
+/// fn count_people(conn: &Connection) -> Result<i64> {
+///     let view = conn.read_only_view()?;
+///     view.query_row("SELECT count(*) FROM people", NO_PARAMS, |row| row.get(0))
+/// }
+/// ```
+pub struct ReadOnlyView<'conn> {
+    conn: &'conn Connection,
+    _authorizer: AuthorizerGuard<'conn>,
+}
+
+impl Connection {
+    /// Return a read-only view of this connection.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn read_only_view(&self) -> Result<ReadOnlyView<'_>> {
+        let authorizer = self.push_authorizer(allows_read)?;
+        Ok(ReadOnlyView {
+            conn: self,
+            _authorizer: authorizer,
+        })
+    }
+}
+
+impl<'conn> ReadOnlyView<'conn> {
+    /// Prepare a read-only SQL statement for execution.
+    ///
+    /// The returned statement is not cached.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string,
+    /// if the statement may modify a database (with a `SQLITE_AUTH` error
+    /// code) or if the underlying SQLite call fails.
+    pub fn prepare<'view>(&'view self, sql: &str) -> Result<Statement<'view>> {
+        self.conn.prepare(sql)
+    }
+
+    /// Convenience method to execute a read-only query that is expected to
+    /// return a single row (see `Connection::query_row`).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the statement may modify a database or if the
+    /// underlying SQLite call fails.
+    pub fn query_row<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<T>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> Result<T>,
+    {
+        self.conn.query_row(sql, params, f)
+    }
+
+    /// Convenience method to execute a read-only query with named
+    /// parameter(s) that is expected to return a single row (see
+    /// `Connection::query_row_named`).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the statement may modify a database or if the
+    /// underlying SQLite call fails.
+    pub fn query_row_named<T, F>(&self, sql: &str, params: &[(&str, &dyn ToSql)], f: F) -> Result<T>
+    where
+        F: FnOnce(&Row<'_>) -> Result<T>,
+    {
+        self.conn.query_row_named(sql, params, f)
+    }
+
+    /// Convenience method to execute a read-only query that is expected to
+    /// return a single row, with a fallible mapping (see
+    /// `Connection::query_row_and_then`).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the statement may modify a database or if the
+    /// underlying SQLite call fails.
+    pub fn query_row_and_then<T, E, P, F>(&self, sql: &str, params: P, f: F) -> result::Result<T, E>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> result::Result<T, E>,
+        E: convert::From<Error>,
+    {
+        self.conn.query_row_and_then(sql, params, f)
+    }
+
+    /// Convenience method to execute a read-only query with named
+    /// parameter(s) that is expected to return a single row, with a fallible
+    /// mapping (see `Connection::query_row_and_then_named`).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the statement may modify a database or if the
+    /// underlying SQLite call fails.
+    pub fn query_row_and_then_named<T, E, F>(
+        &self,
+        sql: &str,
+        params: &[(&str, &dyn ToSql)],
+        f: F,
+    ) -> result::Result<T, E>
+    where
+        F: FnOnce(&Row<'_>) -> result::Result<T, E>,
+        E: convert::From<Error>,
+    {
+        self.conn.query_row_and_then_named(sql, params, f)
+    }
+}
+
+fn allows_read(action: &Action<'_>) -> bool {
+    match action.code {
+        ffi::SQLITE_SELECT | ffi::SQLITE_READ | ffi::SQLITE_FUNCTION | ffi::SQLITE_RECURSIVE => {
+            true
+        }
+        ffi::SQLITE_PRAGMA => match action.arg1 {
+            Some(pragma) => {
+                let pragma = pragma.to_ascii_lowercase();
+                (action.arg2.is_none() && QUERY_PRAGMAS.contains(&&pragma[..]))
+                    || SCHEMA_PRAGMAS.contains(&&pragma[..])
+            }
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Connection, Error, ErrorCode, NO_PARAMS};
+
+    #[test]
+    fn test_read_only_view() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES(42);")
+            .unwrap();
+        {
+            let view = db.read_only_view().unwrap();
+            let x: i64 = view
+                .query_row("SELECT x FROM foo", NO_PARAMS, |r| r.get(0))
+                .unwrap();
+            assert_eq!(42, x);
+            let _: i64 = view
+                .query_row("PRAGMA user_version", NO_PARAMS, |r| r.get(0))
+                .unwrap();
+            let _: String = view
+                .query_row("PRAGMA table_info(foo)", NO_PARAMS, |r| r.get(1))
+                .unwrap();
+
+            for sql in &[
+                "INSERT INTO foo VALUES(1)",
+                "UPDATE foo SET x = 1",
+                "DELETE FROM foo",
+                "CREATE TABLE bar(x)",
+                "DROP TABLE foo",
+                "PRAGMA user_version = 1",
+                "PRAGMA journal_mode = WAL",
+                "PRAGMA wal_checkpoint",
+                "PRAGMA optimize",
+                "BEGIN",
+                "SAVEPOINT sp",
+            ] {
+                match view.prepare(sql) {
+                    Err(Error::SqliteFailure(err, _)) => {
+                        assert_eq!(ErrorCode::AuthorizationForStatementDenied, err.code)
+                    }
+                    _ => panic!("{} should have been denied", sql),
+                }
+            }
+        }
+        db.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
+    }
+
+    #[test]
+    fn test_nested_views() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        let outer = db.read_only_view().unwrap();
+        {
+            let inner = db.read_only_view().unwrap();
+            assert!(inner.prepare("DELETE FROM foo").is_err());
+        }
+        // dropping the inner view does not remove the outer one
+        assert!(outer.prepare("DELETE FROM foo").is_err());
+        drop(outer);
+        db.execute_batch("DELETE FROM foo").unwrap();
+    }
+}
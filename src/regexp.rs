@@ -0,0 +1,131 @@
+//! `REGEXP` operator backed by the `regex` crate.
+
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::functions::Context;
+use crate::types::ValueRef;
+use crate::{Connection, Error, Result};
+
+impl Connection {
+    /// Register the `regexp(pattern, text)` SQL function, which the
+    /// [`REGEXP`](https://sqlite.org/lang_expr.html#the_like_glob_regexp_and_match_operators)
+    /// operator calls (`text REGEXP pattern`), and its case-insensitive
+    /// variant `regexpi(pattern, text)`.
+    ///
+    /// Patterns use the syntax of the [`regex`](https://docs.rs/regex/) crate
+    /// (e.g. `(?i)` also makes a pattern case-insensitive), and match
+    /// anywhere in the text unless anchored. A constant pattern is compiled
+    /// once per statement. Both functions return `NULL` if the pattern or the
+    /// text is `NULL`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// # use rusqlite::{Connection, Result, NO_PARAMS};
+    /// fn main() -> Result<()> {
+    ///     let db = Connection::open_in_memory()?;
+    ///     db.register_regexp()?;
+    ///     let is_match: bool =
+    ///         db.query_row("SELECT 'rusqlite' REGEXP '^ru.*e$'", NO_PARAMS, |r| r.get(0))?;
+    ///     assert!(is_match);
+    ///     let is_match: bool =
+    ///         db.query_row("SELECT regexpi('^RU', 'rusqlite')", NO_PARAMS, |r| r.get(0))?;
+    ///     assert!(is_match);
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the functions cannot be registered. Calling them
+    /// with an invalid pattern fails the statement.
+    pub fn register_regexp(&self) -> Result<()> {
+        self.create_scalar_function("regexp", 2, true, |ctx| regexp(ctx, false))?;
+        self.create_scalar_function("regexpi", 2, true, |ctx| regexp(ctx, true))
+    }
+}
+
+fn regexp(ctx: &Context<'_>, case_insensitive: bool) -> Result<Option<bool>> {
+    let text = match ctx.get_raw_checked(1) {
+        Ok(ValueRef::Null) => return Ok(None),
+        Ok(ValueRef::Text(s)) => s.as_bytes(),
+        Ok(ValueRef::Blob(b)) => b,
+        // Formatted by SQLite, like a number stored in a TEXT column, or a
+        // text which is not valid UTF-8, matched as bytes.
+        Ok(ValueRef::Integer(_)) | Ok(ValueRef::Real(_)) | Err(_) => ctx.get_text_raw(1),
+    };
+    if let Some(re) = ctx.get_aux::<Regex>(0)? {
+        return Ok(Some(re.is_match(text)));
+    }
+
+    let pattern = match ctx.get_raw_checked(0)? {
+        ValueRef::Null => return Ok(None),
+        ValueRef::Text(s) => s,
+        _ => {
+            return Err(Error::UserFunctionError(
+                "regexp pattern must be a string".into(),
+            ))
+        }
+    };
+    let re = RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(|err| Error::UserFunctionError(err.into()))?;
+    let is_match = re.is_match(text);
+    ctx.set_aux(0, re);
+    Ok(Some(is_match))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Connection, NO_PARAMS};
+
+    #[test]
+    fn test_regexp() {
+        let db = Connection::open_in_memory().unwrap();
+        db.register_regexp().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(x);
+             INSERT INTO foo VALUES('lisa'), ('LISA'), ('lXsi'), (42), (NULL), (1.0);",
+        )
+        .unwrap();
+        let count = |sql: &str| -> i64 { db.query_row(sql, NO_PARAMS, |r| r.get(0)).unwrap() };
+
+        assert_eq!(
+            2,
+            count("SELECT count(*) FROM foo WHERE x REGEXP 'l.s[aeiouy]'")
+        );
+        assert_eq!(
+            3,
+            count("SELECT count(*) FROM foo WHERE regexpi('l.s[aeiouy]', x)")
+        );
+        assert_eq!(
+            2,
+            count("SELECT count(*) FROM foo WHERE x REGEXP '(?i)^lisa$'")
+        );
+        assert_eq!(1, count("SELECT count(*) FROM foo WHERE x REGEXP '^\\d+$'"));
+        assert_eq!(
+            1,
+            count("SELECT count(*) FROM foo WHERE x REGEXP '^1\\.0$'")
+        );
+        assert_eq!(
+            1,
+            count("SELECT count(*) FROM foo WHERE regexp('a', x) IS NULL")
+        );
+        assert_eq!(
+            6,
+            count("SELECT count(*) FROM foo WHERE regexp(NULL, x) IS NULL")
+        );
+
+        let r: crate::Result<bool> = db.query_row("SELECT 'a' REGEXP '('", NO_PARAMS, |r| r.get(0));
+        assert!(r.is_err());
+
+        // Text which is not valid UTF-8 is matched as bytes.
+        assert_eq!(1, count("SELECT CAST(x'61ff' AS TEXT) REGEXP '^a'"));
+        let r: crate::Result<bool> =
+            db.query_row("SELECT 'a' REGEXP CAST(x'ff' AS TEXT)", NO_PARAMS, |r| {
+                r.get(0)
+            });
+        assert!(r.is_err());
+    }
+}
@@ -1,14 +1,18 @@
 use fallible_iterator::FallibleIterator;
 use fallible_streaming_iterator::FallibleStreamingIterator;
-use std::{convert, result};
+use std::borrow::Cow;
+use std::{convert, fmt, result};
 
 use super::{Error, Result, Statement};
-use crate::types::{FromSql, FromSqlError, ValueRef};
+use crate::event_log::{EventStart, StatementOutcome};
+use crate::types::{FromSql, FromSqlError, Type, Value, ValueRef};
 
 /// An handle for the resulting rows of a query.
 pub struct Rows<'stmt> {
     pub(crate) stmt: Option<&'stmt Statement<'stmt>>,
     row: Option<Row<'stmt>>,
+    row_number: usize,
+    started: Option<EventStart>,
 }
 
 impl<'stmt> Rows<'stmt> {
@@ -35,6 +39,23 @@ impl<'stmt> Rows<'stmt> {
         Ok((*self).get())
     }
 
+    fn record_event(&mut self, err: Option<&Error>) {
+        if let (Some(stmt), Some(start)) = (self.stmt, self.started.take()) {
+            let outcome = match err {
+                Some(err) => StatementOutcome::Failed(err.to_string()),
+                None => StatementOutcome::Queried(self.row_number),
+            };
+            stmt.record_event(start, outcome);
+        }
+    }
+
+    /// Return the 1-based number of the current row, i.e. the number of rows
+    /// retrieved so far. Returns 0 before the first call to `next`, and the
+    /// total number of rows once all rows have been retrieved.
+    pub fn row_number(&self) -> usize {
+        self.row_number
+    }
+
     pub fn map<F, B>(self, f: F) -> Map<'stmt, F>
     where
         F: FnMut(&Row<'_>) -> Result<B>,
@@ -48,6 +69,8 @@ impl<'stmt> Rows<'stmt> {
         Rows {
             stmt: Some(stmt),
             row: None,
+            row_number: 0,
+            started: None,
         }
     }
 
@@ -61,6 +84,7 @@ impl<'stmt> Rows<'stmt> {
 
 impl Drop for Rows<'_> {
     fn drop(&mut self) {
+        self.record_event(None);
         self.reset();
     }
 }
@@ -153,22 +177,38 @@ impl<'stmt> FallibleStreamingIterator for Rows<'stmt> {
 
     fn advance(&mut self) -> Result<()> {
         match self.stmt {
-            Some(ref stmt) => match stmt.step() {
-                Ok(true) => {
-                    self.row = Some(Row { stmt });
-                    Ok(())
+            Some(stmt) => {
+                if self.row_number == 0 && self.started.is_none() {
+                    self.started = stmt.event_start();
                 }
-                Ok(false) => {
-                    self.reset();
-                    self.row = None;
-                    Ok(())
+                match stmt.step() {
+                    Ok(true) => match stmt.decode_row() {
+                        Ok(decoded) => {
+                            self.row = Some(Row { stmt, decoded });
+                            self.row_number += 1;
+                            Ok(())
+                        }
+                        Err(e) => {
+                            self.record_event(Some(&e));
+                            self.reset();
+                            self.row = None;
+                            Err(e)
+                        }
+                    },
+                    Ok(false) => {
+                        self.record_event(None);
+                        self.reset();
+                        self.row = None;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        self.record_event(Some(&e));
+                        self.reset();
+                        self.row = None;
+                        Err(e)
+                    }
                 }
-                Err(e) => {
-                    self.reset();
-                    self.row = None;
-                    Err(e)
-                }
-            },
+            }
             None => {
                 self.row = None;
                 Ok(())
@@ -184,6 +224,35 @@ impl<'stmt> FallibleStreamingIterator for Rows<'stmt> {
 /// A single result row of a query.
 pub struct Row<'stmt> {
     pub(crate) stmt: &'stmt Statement<'stmt>,
+    // Values of the columns which have a decoder, or empty
+    decoded: Vec<Option<Value>>,
+}
+
+/// Show the column names and (truncated) values of the row.
+impl fmt::Debug for Row<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = self.stmt.column_names();
+        f.debug_map()
+            .entries(names.iter().enumerate().map(
+                |(i, name)| -> (&str, Box<dyn fmt::Debug + '_>) {
+                    match self.value_ref(i) {
+                        Ok(value) => (name, Box::new(value)),
+                        Err(err) => (name, Box::new(err)),
+                    }
+                },
+            ))
+            .finish()
+    }
+}
+
+impl fmt::Debug for Rows<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rows")
+            .field("columns", &self.column_names())
+            .field("row_number", &self.row_number)
+            .field("row", &self.row)
+            .finish()
+    }
 }
 
 impl<'stmt> Row<'stmt> {
@@ -219,20 +288,12 @@ impl<'stmt> Row<'stmt> {
     /// If the result type is i128 (which requires the `i128_blob` feature to be
     /// enabled), and the underlying SQLite column is a blob whose size is not
     /// 16 bytes, `Error::InvalidColumnType` will also be returned.
+    ///
+    /// Returns an `Error::FromSqlConversionFailure` if the underlying SQLite
+    /// column is a text which is not valid UTF-8 (see `get_str_lossy`).
     pub fn get<I: RowIndex, T: FromSql>(&self, idx: I) -> Result<T> {
         let idx = idx.idx(self.stmt)?;
-        let value = self.stmt.value_ref(idx);
-        FromSql::column_result(value).map_err(|err| match err {
-            FromSqlError::InvalidType => Error::InvalidColumnType(idx, value.data_type()),
-            FromSqlError::OutOfRange(i) => Error::IntegralValueOutOfRange(idx, i),
-            FromSqlError::Other(err) => {
-                Error::FromSqlConversionFailure(idx as usize, value.data_type(), err)
-            }
-            #[cfg(feature = "i128_blob")]
-            FromSqlError::InvalidI128Size(_) => Error::InvalidColumnType(idx, value.data_type()),
-            #[cfg(feature = "uuid")]
-            FromSqlError::InvalidUuidSize(_) => Error::InvalidColumnType(idx, value.data_type()),
-        })
+        column_result(idx, self.value_ref(idx)?)
     }
 
     /// Get the value of a particular column of the result row as a `ValueRef`,
@@ -250,13 +311,15 @@ impl<'stmt> Row<'stmt> {
     ///
     /// Returns an `Error::InvalidColumnName` if `idx` is not a valid column
     /// name for this row.
+    ///
+    /// Returns an `Error::FromSqlConversionFailure` if the underlying SQLite
+    /// column is a text which is not valid UTF-8 (see `get_str_lossy`).
     pub fn get_raw_checked<I: RowIndex>(&self, idx: I) -> Result<ValueRef<'_>> {
         let idx = idx.idx(self.stmt)?;
         // Narrowing from `ValueRef<'stmt>` (which `self.stmt.value_ref(idx)`
         // returns) to `ValueRef<'a>` is needed because it's only valid until
         // the next call to sqlite3_step.
-        let val_ref = self.stmt.value_ref(idx);
-        Ok(val_ref)
+        self.value_ref(idx)
     }
 
     /// Get the value of a particular column of the result row as a `ValueRef`,
@@ -274,11 +337,189 @@ impl<'stmt> Row<'stmt> {
     ///
     /// * If `idx` is outside the range of columns in the returned query.
     /// * If `idx` is not a valid column name for this row.
+    /// * If the value is a text which is not valid UTF-8.
     pub fn get_raw<I: RowIndex>(&self, idx: I) -> ValueRef<'_> {
         self.get_raw_checked(idx).unwrap()
     }
+
+    /// Get the value of a text column of the result row, with the invalid
+    /// UTF-8 sequences replaced by `U+FFFD REPLACEMENT CHARACTER`.
+    ///
+    /// SQLite does not validate text: a text value is not valid UTF-8 if
+    /// invalid text has been stored (e.g. a blob cast to text, or a UTF-16
+    /// string with an unpaired surrogate in a UTF-16 database), in which case
+    /// `get` and `get_raw_checked` fail.
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::InvalidColumnType` if the underlying SQLite column
+    /// type is not text.
+    ///
+    /// Returns an `Error::InvalidColumnIndex` if `idx` is outside the valid
+    /// column range for this row.
+    ///
+    /// Returns an `Error::InvalidColumnName` if `idx` is not a valid column
+    /// name for this row.
+    pub fn get_str_lossy<I: RowIndex>(&self, idx: I) -> Result<Cow<'_, str>> {
+        let idx = idx.idx(self.stmt)?;
+        match self.value_ref(idx) {
+            Ok(ValueRef::Text(s)) => Ok(Cow::Borrowed(s)),
+            Ok(value) => Err(Error::InvalidColumnType(idx, value.data_type())),
+            // Only a text which has not been decoded can be invalid.
+            Err(_) => Ok(String::from_utf8_lossy(self.stmt.text_bytes(idx))),
+        }
+    }
+
+    /// Copy the column names and values of this row into an `OwnedRow`,
+    /// which can be kept after the iteration continues or the statement is
+    /// dropped (e.g. to report the offending row of an error).
+    ///
+    /// A text which is not valid UTF-8 is converted like `get_str_lossy`
+    /// does.
+    pub fn to_owned(&self) -> OwnedRow {
+        let n = self.stmt.column_count();
+        OwnedRow {
+            names: self
+                .stmt
+                .column_names()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            values: (0..n)
+                .map(|i| match self.value_ref(i) {
+                    Ok(value) => value.into(),
+                    Err(_) => {
+                        Value::Text(String::from_utf8_lossy(self.stmt.text_bytes(i)).into_owned())
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Return the value of the column `idx`, decoded if it has a decoder.
+    fn value_ref(&self, idx: usize) -> Result<ValueRef<'_>> {
+        match self.decoded.get(idx) {
+            Some(Some(value)) => Ok(value.into()),
+            _ => self.stmt.value_ref(idx),
+        }
+    }
+}
+
+/// A result row detached from its statement (see `Row::to_owned`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnedRow {
+    names: Vec<String>,
+    values: Vec<Value>,
 }
 
+impl OwnedRow {
+    /// Return the number of columns of the row.
+    pub fn column_count(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Return the column names of the row.
+    pub fn column_names(&self) -> Vec<&str> {
+        self.names.iter().map(String::as_str).collect()
+    }
+
+    /// Return the column index for a given column name (ignoring ASCII case,
+    /// like `Statement::column_index`).
+    ///
+    /// # Failure
+    ///
+    /// Will return an `Error::InvalidColumnName` when there is no column with
+    /// the specified `name`.
+    pub fn column_index(&self, name: &str) -> Result<usize> {
+        self.names
+            .iter()
+            .position(|n| n.eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::InvalidColumnName(String::from(name)))
+    }
+
+    /// Return the values of the row.
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// Get the value of a particular column of the row.
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::InvalidColumnIndex` if `idx` is outside the valid
+    /// column range for this row, and the same errors as `Row::get` if the
+    /// value cannot be converted to `T`.
+    pub fn get<T: FromSql>(&self, idx: usize) -> Result<T> {
+        let value = self.values.get(idx).ok_or(Error::InvalidColumnIndex(idx))?;
+        column_result(idx, value.into())
+    }
+
+    /// Get the value of a column of the row by name (see `column_index`).
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::InvalidColumnName` if `name` is not a valid column
+    /// name for this row, and the same errors as `Row::get` if the value
+    /// cannot be converted to `T`.
+    pub fn get_by_name<T: FromSql>(&self, name: &str) -> Result<T> {
+        self.get(self.column_index(name)?)
+    }
+}
+
+fn column_result<T: FromSql>(idx: usize, value: ValueRef<'_>) -> Result<T> {
+    FromSql::column_result(value).map_err(|err| column_error(idx, value.data_type(), err))
+}
+
+pub(crate) fn column_error(idx: usize, data_type: Type, err: FromSqlError) -> Error {
+    match err {
+        FromSqlError::InvalidType => Error::InvalidColumnType(idx, data_type),
+        FromSqlError::OutOfRange(i) => Error::IntegralValueOutOfRange(idx, i),
+        FromSqlError::Other(err) => Error::FromSqlConversionFailure(idx, data_type, err),
+        #[cfg(feature = "i128_blob")]
+        FromSqlError::InvalidI128Size(_) => Error::InvalidColumnType(idx, data_type),
+        #[cfg(feature = "uuid")]
+        FromSqlError::InvalidUuidSize(_) => Error::InvalidColumnType(idx, data_type),
+    }
+}
+
+/// A trait implemented by types that can be built from a result row (see
+/// `Statement::query_as`, `Connection::query_row_as` and
+/// `Connection::query_table`).
+///
+/// Implemented for tuples of up to 16 `FromSql` values, taken from the
+/// columns in order.
+pub trait FromRow: Sized {
+    /// Convert `row`.
+    fn from_row(row: &Row<'_>) -> Result<Self>;
+}
+
+macro_rules! from_row_tuple {
+    ($($t:ident: $idx:tt),+) => {
+        impl<$($t: FromSql),+> FromRow for ($($t,)+) {
+            fn from_row(row: &Row<'_>) -> Result<Self> {
+                Ok(($(row.get($idx)?,)+))
+            }
+        }
+    };
+}
+
+from_row_tuple!(A: 0);
+from_row_tuple!(A: 0, B: 1);
+from_row_tuple!(A: 0, B: 1, C: 2);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13, O: 14);
+from_row_tuple!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13, O: 14, P: 15);
+
 /// A trait implemented by types that can index into columns of a row.
 pub trait RowIndex {
     /// Returns the index of the appropriate column, or `None` if no such
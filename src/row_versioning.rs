@@ -0,0 +1,355 @@
+//! `updated_at`, `deleted_at` and version columns maintained by triggers.
+use crate::pragma::Sql;
+use crate::{Connection, Result};
+
+const NOW: &str = "strftime('%Y-%m-%d %H:%M:%f', 'now')";
+
+/// Columns maintained by `Connection::install_row_versioning`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RowVersioning {
+    /// Set the `updated_at` column (`TEXT`, UTC `YYYY-MM-DD HH:MM:SS.SSS`) to
+    /// the current time when a row is inserted without one, or updated.
+    pub updated_at: bool,
+    /// Increment the `version` column (`INTEGER NOT NULL DEFAULT 1`) when a
+    /// row is updated.
+    pub version: bool,
+    /// Turn the deletion of a row into setting its `deleted_at` column
+    /// (`TEXT`) to the current time. Deleting a row whose `deleted_at` is
+    /// already set deletes it for good.
+    pub soft_delete: bool,
+}
+
+impl RowVersioning {
+    fn columns(&self) -> Vec<(&'static str, &'static str)> {
+        let mut columns = Vec::new();
+        if self.updated_at {
+            columns.push(("updated_at", "TEXT"));
+        }
+        if self.version {
+            columns.push(("version", "INTEGER NOT NULL DEFAULT 1"));
+        }
+        if self.soft_delete {
+            columns.push(("deleted_at", "TEXT"));
+        }
+        columns
+    }
+
+    /// Return the names and definitions of the triggers on `table`.
+    fn triggers(&self, table: &str) -> Vec<(String, Option<String>)> {
+        let name = |suffix: &str| format!("{}_row_versioning_{}", table, suffix);
+        let table = identifier(table);
+
+        let insert = if self.updated_at {
+            Some(format!(
+                "CREATE TRIGGER {} AFTER INSERT ON {} FOR EACH ROW \
+                 WHEN NEW.updated_at IS NULL BEGIN \
+                 UPDATE {} SET updated_at = {} WHERE rowid = NEW.rowid; END",
+                identifier(&name("insert")),
+                table,
+                table,
+                NOW
+            ))
+        } else {
+            None
+        };
+
+        let mut conditions = Vec::new();
+        let mut assignments = Vec::new();
+        if self.version {
+            conditions.push("NEW.version IS OLD.version".to_owned());
+            assignments.push("version = OLD.version + 1".to_owned());
+        }
+        if self.updated_at {
+            if !self.version {
+                conditions.push("NEW.updated_at IS OLD.updated_at".to_owned());
+            }
+            // Skip the update made by the insert trigger.
+            conditions.push("OLD.updated_at IS NOT NULL".to_owned());
+            assignments.push(format!("updated_at = {}", NOW));
+        }
+        let update = if assignments.is_empty() {
+            None
+        } else {
+            Some(format!(
+                "CREATE TRIGGER {} AFTER UPDATE ON {} FOR EACH ROW \
+                 WHEN {} BEGIN \
+                 UPDATE {} SET {} WHERE rowid = NEW.rowid; END",
+                identifier(&name("update")),
+                table,
+                conditions.join(" AND "),
+                table,
+                assignments.join(", ")
+            ))
+        };
+
+        let delete = if self.soft_delete {
+            Some(format!(
+                "CREATE TRIGGER {} BEFORE DELETE ON {} FOR EACH ROW \
+                 WHEN OLD.deleted_at IS NULL BEGIN \
+                 UPDATE {} SET deleted_at = {} WHERE rowid = OLD.rowid; \
+                 SELECT RAISE(IGNORE); END",
+                identifier(&name("delete")),
+                table,
+                table,
+                NOW
+            ))
+        } else {
+            None
+        };
+
+        vec![
+            (name("insert"), insert),
+            (name("update"), update),
+            (name("delete"), delete),
+        ]
+    }
+}
+
+fn identifier(s: &str) -> String {
+    let mut sql = Sql::new();
+    sql.push_identifier(s);
+    sql.as_str().to_owned()
+}
+
+impl Connection {
+    /// Add the columns selected by `versioning` to `table` if they are
+    /// missing, and (re)create the triggers maintaining them. Triggers of
+    /// columns which are not selected are dropped, but the columns are kept.
+    ///
+    /// Only tables with a `rowid` are supported. The triggers are named
+    /// `<table>_row_versioning_insert`, `_update` and `_delete`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, RowVersioning};
+    /// fn init(conn: &Connection) -> Result<()> {
+    ///     conn.execute_batch("CREATE TABLE IF NOT EXISTS notes(body TEXT)")?;
+    ///     conn.install_row_versioning(
+    ///         "notes",
+    ///         RowVersioning {
+    ///             updated_at: true,
+    ///             soft_delete: true,
+    ///             ..Default::default()
+    ///         },
+    ///     )
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `table` does not exist or has no `rowid`, or if
+    /// the underlying SQLite calls fail; the schema is then left unchanged.
+    pub fn install_row_versioning(&self, table: &str, versioning: RowVersioning) -> Result<()> {
+        let existing = self.row_versioning_columns(table)?;
+        self.execute_internal("SAVEPOINT _rusqlite_row_versioning")?;
+        let r = self.install_row_versioning_(table, &versioning, &existing);
+        if r.is_err() {
+            self.execute_internal("ROLLBACK TO _rusqlite_row_versioning")?;
+        }
+        self.execute_internal("RELEASE _rusqlite_row_versioning")?;
+        r
+    }
+
+    fn install_row_versioning_(
+        &self,
+        table: &str,
+        versioning: &RowVersioning,
+        existing: &[String],
+    ) -> Result<()> {
+        let quoted = identifier(table);
+        for (column, definition) in versioning.columns() {
+            if !existing.iter().any(|c| c.eq_ignore_ascii_case(column)) {
+                self.execute_batch(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    quoted, column, definition
+                ))?;
+            }
+        }
+        if versioning.updated_at {
+            self.execute_batch(&format!(
+                "UPDATE {} SET updated_at = {} WHERE updated_at IS NULL",
+                quoted, NOW
+            ))?;
+        }
+        for (name, sql) in versioning.triggers(table) {
+            self.execute_batch(&format!("DROP TRIGGER IF EXISTS {}", identifier(&name)))?;
+            if let Some(sql) = sql {
+                self.execute_batch(&sql)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that `table` has the columns and the triggers that
+    /// `install_row_versioning` would create for `versioning`, and return
+    /// the differences found (an empty list if the table is up to date).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `table` does not exist or has no `rowid`, or if
+    /// the underlying SQLite calls fail.
+    pub fn verify_row_versioning(
+        &self,
+        table: &str,
+        versioning: RowVersioning,
+    ) -> Result<Vec<String>> {
+        let existing = self.row_versioning_columns(table)?;
+        let mut problems = Vec::new();
+        for (column, _) in versioning.columns() {
+            if !existing.iter().any(|c| c.eq_ignore_ascii_case(column)) {
+                problems.push(format!("missing column {}", column));
+            }
+        }
+        let mut stmt =
+            self.prepare("SELECT sql FROM sqlite_master WHERE type = 'trigger' AND name = ?")?;
+        for (name, expected) in versioning.triggers(table) {
+            let actual: Option<String> = {
+                let mut rows = stmt.query(&[&name])?;
+                match rows.next()? {
+                    Some(row) => Some(row.get(0)?),
+                    None => None,
+                }
+            };
+            match (expected, actual) {
+                (Some(_), None) => problems.push(format!("missing trigger {}", name)),
+                (None, Some(_)) => problems.push(format!("unexpected trigger {}", name)),
+                (Some(ref expected), Some(ref actual)) if expected != actual => {
+                    problems.push(format!("outdated trigger {}", name))
+                }
+                _ => {}
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Return the column names of `table`, checking that it has a `rowid`.
+    fn row_versioning_columns(&self, table: &str) -> Result<Vec<String>> {
+        let stmt = self.prepare(&format!("SELECT rowid, * FROM {}", identifier(table)))?;
+        Ok(stmt
+            .column_names()
+            .iter()
+            .skip(1)
+            .map(|c| (*c).to_owned())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RowVersioning;
+    use crate::{Connection, NO_PARAMS};
+
+    const ALL: RowVersioning = RowVersioning {
+        updated_at: true,
+        version: true,
+        soft_delete: true,
+    };
+
+    #[test]
+    fn test_row_versioning() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE \"no\"\"tes\"(id INTEGER PRIMARY KEY, body TEXT);
+             INSERT INTO \"no\"\"tes\"(body) VALUES('old');",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                "missing column updated_at",
+                "missing column version",
+                "missing column deleted_at",
+                "missing trigger no\"tes_row_versioning_insert",
+                "missing trigger no\"tes_row_versioning_update",
+                "missing trigger no\"tes_row_versioning_delete",
+            ],
+            db.verify_row_versioning("no\"tes", ALL).unwrap()
+        );
+        db.install_row_versioning("no\"tes", ALL).unwrap();
+        assert!(db.verify_row_versioning("no\"tes", ALL).unwrap().is_empty());
+        // Idempotent
+        db.install_row_versioning("no\"tes", ALL).unwrap();
+
+        let row = |id: i64| -> (i64, bool, bool) {
+            db.query_row(
+                "SELECT version, updated_at IS NOT NULL, deleted_at IS NOT NULL
+                 FROM \"no\"\"tes\" WHERE id = ?",
+                [id],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap()
+        };
+        // Existing rows are backfilled.
+        assert_eq!((1, true, false), row(1));
+
+        db.execute("INSERT INTO \"no\"\"tes\"(body) VALUES('new')", NO_PARAMS)
+            .unwrap();
+        assert_eq!((1, true, false), row(2));
+        db.execute(
+            "UPDATE \"no\"\"tes\" SET body = 'edited' WHERE id = 2",
+            NO_PARAMS,
+        )
+        .unwrap();
+        db.execute(
+            "UPDATE \"no\"\"tes\" SET body = 'edited' WHERE id = 2",
+            NO_PARAMS,
+        )
+        .unwrap();
+        assert_eq!((3, true, false), row(2));
+
+        // Soft delete, then purge
+        db.execute("DELETE FROM \"no\"\"tes\" WHERE id = 2", NO_PARAMS)
+            .unwrap();
+        assert_eq!((4, true, true), row(2));
+        db.execute("DELETE FROM \"no\"\"tes\" WHERE id = 2", NO_PARAMS)
+            .unwrap();
+        let count: i64 = db
+            .query_row("SELECT count(*) FROM \"no\"\"tes\"", NO_PARAMS, |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(1, count);
+    }
+
+    #[test]
+    fn test_verify_row_versioning() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x)").unwrap();
+        let versioning = RowVersioning {
+            updated_at: true,
+            ..Default::default()
+        };
+        db.install_row_versioning("foo", ALL).unwrap();
+        assert_eq!(
+            vec![
+                "outdated trigger foo_row_versioning_update",
+                "unexpected trigger foo_row_versioning_delete",
+            ],
+            db.verify_row_versioning("foo", versioning).unwrap()
+        );
+        db.install_row_versioning("foo", versioning).unwrap();
+        assert!(db
+            .verify_row_versioning("foo", versioning)
+            .unwrap()
+            .is_empty());
+
+        // Updates without version counter
+        db.execute("INSERT INTO foo(x) VALUES(1)", NO_PARAMS)
+            .unwrap();
+        // An explicit value is kept.
+        db.execute("UPDATE foo SET updated_at = '2000-01-01'", NO_PARAMS)
+            .unwrap();
+        let updated_at = || -> String {
+            db.query_row("SELECT updated_at FROM foo", NO_PARAMS, |r| r.get(0))
+                .unwrap()
+        };
+        assert_eq!("2000-01-01", updated_at());
+        db.execute("UPDATE foo SET x = 2", NO_PARAMS).unwrap();
+        assert!(updated_at().as_str() > "2000-01-01");
+
+        assert!(db.install_row_versioning("bar", versioning).is_err());
+        db.execute_batch("CREATE TABLE baz(x PRIMARY KEY) WITHOUT ROWID")
+            .unwrap();
+        assert!(db.install_row_versioning("baz", versioning).is_err());
+    }
+}
@@ -0,0 +1,416 @@
+//! Restricted execution of untrusted queries.
+use std::collections::HashSet;
+use std::os::raw::c_int;
+
+use crate::authorizer::{Action, AuthorizerGuard};
+use crate::ffi;
+use crate::types::Params;
+use crate::{Connection, InnerConnection, Result, Row, Statement};
+
+/// What queries run through a `Sandbox` are allowed to do.
+///
+/// Everything is denied by default: tables (or views) must be made readable
+/// with `allow_tables`, and functions (including built-in ones like `count`
+/// or `lower`) made callable with `allow_functions`. Statements which may
+/// modify a database are always denied.
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result, SandboxPolicy};
+/// fn policy() -> SandboxPolicy {
+///     SandboxPolicy::new()
+///         .allow_tables(&["orders", "customers"])
+///         .allow_functions(&["count", "sum", "avg", "date"])
+///         .max_sql_length(10_000)
+///         .max_expr_depth(100)
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SandboxPolicy {
+    tables: HashSet<String>,
+    functions: HashSet<String>,
+    pragmas: HashSet<String>,
+    attach: bool,
+    max_sql_length: Option<i32>,
+    max_expr_depth: Option<i32>,
+}
+
+impl SandboxPolicy {
+    /// Create a policy denying everything but `SELECT` statements which do
+    /// not read any table nor call any function.
+    pub fn new() -> SandboxPolicy {
+        SandboxPolicy::default()
+    }
+
+    /// Allow reading the given tables or views (in any attached database).
+    /// Names are case-insensitive.
+    ///
+    /// Allowing a view allows reading the tables it selects from, but only
+    /// through the view.
+    pub fn allow_tables(mut self, tables: &[&str]) -> SandboxPolicy {
+        self.tables
+            .extend(tables.iter().map(|t| t.to_ascii_lowercase()));
+        self
+    }
+
+    /// Allow calling the given SQL functions. Names are case-insensitive.
+    pub fn allow_functions(mut self, functions: &[&str]) -> SandboxPolicy {
+        self.functions
+            .extend(functions.iter().map(|f| f.to_ascii_lowercase()));
+        self
+    }
+
+    /// Allow running the given pragmas, e.g. `table_info`. Names are
+    /// case-insensitive.
+    ///
+    /// The pragmas are allowed whatever their argument, so pragmas setting a
+    /// value (like `user_version`) should not be allowed.
+    pub fn allow_pragmas(mut self, pragmas: &[&str]) -> SandboxPolicy {
+        self.pragmas
+            .extend(pragmas.iter().map(|p| p.to_ascii_lowercase()));
+        self
+    }
+
+    /// Allow (or deny, by default) `ATTACH` and `DETACH` statements.
+    pub fn allow_attach(mut self, allow: bool) -> SandboxPolicy {
+        self.attach = allow;
+        self
+    }
+
+    /// Set the maximum length in bytes of a SQL statement (see
+    /// `Limit::SQLITE_LIMIT_SQL_LENGTH`). The limit of the connection is
+    /// only lowered, never raised.
+    pub fn max_sql_length(mut self, max: i32) -> SandboxPolicy {
+        self.max_sql_length = Some(max);
+        self
+    }
+
+    /// Set the maximum depth of the parse tree of an expression (see
+    /// `Limit::SQLITE_LIMIT_EXPR_DEPTH`). The limit of the connection is only
+    /// lowered, never raised.
+    pub fn max_expr_depth(mut self, max: i32) -> SandboxPolicy {
+        self.max_expr_depth = Some(max);
+        self
+    }
+
+    fn allows(&self, action: &Action<'_>) -> bool {
+        match action.code {
+            ffi::SQLITE_SELECT | ffi::SQLITE_RECURSIVE => true,
+            ffi::SQLITE_READ => {
+                contains(&self.tables, action.arg1) || contains(&self.tables, action.view)
+            }
+            ffi::SQLITE_FUNCTION => contains(&self.functions, action.arg2),
+            ffi::SQLITE_PRAGMA => contains(&self.pragmas, action.arg1),
+            ffi::SQLITE_ATTACH | ffi::SQLITE_DETACH => self.attach,
+            _ => false,
+        }
+    }
+}
+
+fn contains(names: &HashSet<String>, name: Option<&str>) -> bool {
+    match name {
+        Some(name) => names.contains(&name.to_ascii_lowercase()),
+        None => false,
+    }
+}
+
+/// A connection restricted by a `SandboxPolicy`, to run user-provided (e.g.
+/// analytics) queries.
+///
+/// While the sandbox exists, an
+/// [authorizer](https://sqlite.org/c3ref/set_authorizer.html) checking the
+/// policy is installed on the connection, and its limits are lowered
+/// according to the policy. Statements are checked when they are prepared,
+/// so statements prepared before the sandbox was created (e.g. from the
+/// statement cache) are not restricted; the statements prepared through the
+/// sandbox are never cached, and cannot outlive it.
+///
+/// The authorizer and limits apply to the whole connection, and are restored
+/// when the sandbox is dropped. Sandboxes can be nested, and combined with a
+/// `ReadOnlyView`: a statement must then be allowed by all of them, and the
+/// lowest limits apply. They can be dropped in any order.
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result, SandboxPolicy, NO_PARAMS};
+/// fn run_report(conn: &Connection, sql: &str) -> Result<i64> {
+///     let policy = SandboxPolicy::new()
+///         .allow_tables(&["orders"])
+///         .allow_functions(&["count", "sum"]);
+///     let sandbox = conn.sandbox(policy)?;
+///     sandbox.query_row(sql, NO_PARAMS, |row| row.get(0))
+/// }
+/// ```
+pub struct Sandbox<'conn> {
+    conn: &'conn Connection,
+    policy: SandboxPolicy,
+    limits_id: u64,
+    _authorizer: AuthorizerGuard<'conn>,
+}
+
+impl Connection {
+    /// Restrict this connection according to `policy`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn sandbox(&self, policy: SandboxPolicy) -> Result<Sandbox<'_>> {
+        let checked = policy.clone();
+        let authorizer = self.push_authorizer(move |action| checked.allows(action))?;
+
+        let limits = [
+            (
+                ffi::Limit::SQLITE_LIMIT_SQL_LENGTH as c_int,
+                policy.max_sql_length,
+            ),
+            (
+                ffi::Limit::SQLITE_LIMIT_EXPR_DEPTH as c_int,
+                policy.max_expr_depth,
+            ),
+        ]
+        .iter()
+        .filter_map(|&(limit, max)| max.map(|max| (limit, max)))
+        .collect();
+        let limits_id = self.db.borrow_mut().push_sandbox_limits(limits);
+        Ok(Sandbox {
+            conn: self,
+            policy,
+            limits_id,
+            _authorizer: authorizer,
+        })
+    }
+}
+
+impl<'conn> Sandbox<'conn> {
+    /// Return the policy enforced by this sandbox.
+    pub fn policy(&self) -> &SandboxPolicy {
+        &self.policy
+    }
+
+    /// Prepare a SQL statement allowed by the policy for execution.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string,
+    /// if the statement is not allowed (with a `SQLITE_AUTH` error code, or
+    /// a `SQLITE_ERROR` one for functions), if it exceeds a limit or if the
+    /// underlying SQLite call fails.
+    pub fn prepare<'sandbox>(&'sandbox self, sql: &str) -> Result<Statement<'sandbox>> {
+        self.conn.prepare(sql)
+    }
+
+    /// Convenience method to execute a query allowed by the policy that is
+    /// expected to return a single row (see `Connection::query_row`).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the statement is not allowed, if it exceeds a
+    /// limit or if the underlying SQLite call fails.
+    pub fn query_row<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<T>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> Result<T>,
+    {
+        let mut stmt = self.prepare(sql)?;
+        stmt.query_row(params, f)
+    }
+}
+
+impl Drop for Sandbox<'_> {
+    fn drop(&mut self) {
+        self.conn
+            .db
+            .borrow_mut()
+            .remove_sandbox_limits(self.limits_id);
+    }
+}
+
+/// The limits lowered by the active sandboxes of a connection, so that each
+/// sandbox can be removed, whatever the order, when it is dropped.
+#[derive(Default)]
+pub(crate) struct SandboxLimits {
+    next_id: u64,
+    /// The value of each lowered limit before the first active sandbox.
+    saved: Vec<(c_int, c_int)>,
+    /// The maximum of each limit lowered by each active sandbox.
+    active: Vec<(u64, Vec<(c_int, c_int)>)>,
+}
+
+impl InnerConnection {
+    fn push_sandbox_limits(&mut self, limits: Vec<(c_int, c_int)>) -> u64 {
+        for &(limit, _) in &limits {
+            if !self.sandbox_limits.saved.iter().any(|&(l, _)| l == limit) {
+                let previous = unsafe { ffi::sqlite3_limit(self.db(), limit, -1) };
+                self.sandbox_limits.saved.push((limit, previous));
+            }
+        }
+        let id = self.sandbox_limits.next_id;
+        self.sandbox_limits.next_id += 1;
+        self.sandbox_limits.active.push((id, limits.clone()));
+        for (limit, _) in limits {
+            self.apply_sandbox_limit(limit);
+        }
+        id
+    }
+
+    fn remove_sandbox_limits(&mut self, id: u64) {
+        let i = match self
+            .sandbox_limits
+            .active
+            .iter()
+            .position(|&(i, _)| i == id)
+        {
+            Some(i) => i,
+            None => return,
+        };
+        let (_, limits) = self.sandbox_limits.active.remove(i);
+        if self.db.is_null() {
+            return;
+        }
+        for (limit, _) in limits {
+            self.apply_sandbox_limit(limit);
+        }
+    }
+
+    /// Set `limit` to the lowest of its saved value and of its maximums in
+    /// the active sandboxes, or restore it if no sandbox lowers it anymore.
+    fn apply_sandbox_limit(&mut self, limit: c_int) {
+        let limits = &mut self.sandbox_limits;
+        let i = match limits.saved.iter().position(|&(l, _)| l == limit) {
+            Some(i) => i,
+            None => return,
+        };
+        let saved = limits.saved[i].1;
+        let lowest = limits
+            .active
+            .iter()
+            .flat_map(|(_, maxes)| maxes)
+            .filter(|&&(l, _)| l == limit)
+            .map(|&(_, max)| max)
+            .min();
+        let value = match lowest {
+            Some(max) => max.min(saved),
+            None => {
+                limits.saved.remove(i);
+                saved
+            }
+        };
+        unsafe { ffi::sqlite3_limit(self.db(), limit, value) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SandboxPolicy;
+    use crate::{Connection, Error, ErrorCode, NO_PARAMS};
+
+    #[test]
+    fn test_sandbox() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE orders(amount INTEGER, customer TEXT);
+             CREATE TABLE secrets(x INTEGER);
+             CREATE VIEW totals AS SELECT customer, sum(amount) AS total FROM orders
+                GROUP BY customer;
+             INSERT INTO orders VALUES(10, 'a'), (20, 'b'), (12, 'a');
+             INSERT INTO secrets VALUES(42);",
+        )
+        .unwrap();
+        {
+            let policy = SandboxPolicy::new()
+                .allow_tables(&["ORDERS", "totals"])
+                .allow_functions(&["sum"])
+                .max_expr_depth(10);
+            let sandbox = db.sandbox(policy).unwrap();
+            let sum = sandbox.query_row("SELECT sum(amount) FROM orders", NO_PARAMS, |r| {
+                r.get::<_, i64>(0)
+            });
+            assert_eq!(42, sum.unwrap());
+            let total =
+                sandbox.query_row("SELECT total FROM totals WHERE customer = ?", &["a"], |r| {
+                    r.get::<_, i64>(0)
+                });
+            assert_eq!(22, total.unwrap());
+
+            match sandbox.prepare("SELECT x FROM secrets") {
+                Err(Error::SqliteFailure(err, _)) => {
+                    assert_eq!(ErrorCode::AuthorizationForStatementDenied, err.code)
+                }
+                _ => panic!("secrets should not be readable"),
+            }
+            for sql in &[
+                "SELECT count(*) FROM orders",
+                "UPDATE orders SET amount = 0",
+                "PRAGMA table_info(orders)",
+                "ATTACH ':memory:' AS other",
+            ] {
+                assert!(sandbox.prepare(sql).is_err(), "{} should be denied", sql);
+            }
+            let deep = format!("SELECT 1{}", " + 1".repeat(20));
+            assert!(sandbox.prepare(&deep).is_err());
+        }
+        let x: i64 = db
+            .query_row("SELECT x FROM secrets", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(42, x);
+        let deep = format!("SELECT 1{}", " + 1".repeat(20));
+        db.query_row(&deep, NO_PARAMS, |r| r.get::<_, i64>(0))
+            .unwrap();
+
+        let sandbox = db
+            .sandbox(SandboxPolicy::new().allow_pragmas(&["TABLE_INFO"]))
+            .unwrap();
+        sandbox
+            .query_row("PRAGMA table_info(orders)", NO_PARAMS, |_| Ok(()))
+            .unwrap();
+        assert!(sandbox.prepare("PRAGMA user_version = 1").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "limits")]
+    fn test_sandbox_limits() {
+        use crate::limits::Limit;
+
+        let db = Connection::open_in_memory().unwrap();
+        db.set_limit(Limit::SQLITE_LIMIT_SQL_LENGTH, 100);
+        {
+            let _sandbox = db
+                .sandbox(
+                    SandboxPolicy::new()
+                        .max_sql_length(1_000)
+                        .max_expr_depth(10),
+                )
+                .unwrap();
+            assert_eq!(100, db.limit(Limit::SQLITE_LIMIT_SQL_LENGTH));
+            assert_eq!(10, db.limit(Limit::SQLITE_LIMIT_EXPR_DEPTH));
+        }
+        assert_eq!(100, db.limit(Limit::SQLITE_LIMIT_SQL_LENGTH));
+        assert_eq!(1000, db.limit(Limit::SQLITE_LIMIT_EXPR_DEPTH));
+
+        // dropped out of order, the remaining sandbox still applies
+        let outer = db.sandbox(SandboxPolicy::new().max_expr_depth(10)).unwrap();
+        let inner = db.sandbox(SandboxPolicy::new().max_expr_depth(20)).unwrap();
+        assert_eq!(10, db.limit(Limit::SQLITE_LIMIT_EXPR_DEPTH));
+        drop(outer);
+        assert_eq!(20, db.limit(Limit::SQLITE_LIMIT_EXPR_DEPTH));
+        drop(inner);
+        assert_eq!(1000, db.limit(Limit::SQLITE_LIMIT_EXPR_DEPTH));
+    }
+
+    #[test]
+    fn test_nested_sandboxes() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        let view = db.read_only_view().unwrap();
+        let sandbox = db
+            .sandbox(SandboxPolicy::new().allow_pragmas(&["user_version"]))
+            .unwrap();
+        // allowed by the sandbox, but not by the view
+        assert!(sandbox.prepare("PRAGMA user_version = 1").is_err());
+        drop(view);
+        sandbox.prepare("PRAGMA user_version = 1").unwrap();
+        // the sandbox is removed even if it is dropped first
+        let view = db.read_only_view().unwrap();
+        assert!(view.prepare("SELECT x FROM foo").is_err());
+        drop(sandbox);
+        view.prepare("SELECT x FROM foo").unwrap();
+    }
+}
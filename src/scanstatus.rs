@@ -0,0 +1,140 @@
+//! Query plan loop counters, to compare the estimated and actual number of
+//! rows visited by each loop of a statement.
+//!
+//! This requires SQLite to be compiled with `SQLITE_ENABLE_STMT_SCANSTATUS`,
+//! which system libraries usually are not: the `scanstatus` feature enables
+//! the `bundled` feature, which compiles SQLite with it.
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+use crate::ffi;
+use crate::Statement;
+
+/// Counters of one loop (a table or index scan) of the query plan of a
+/// statement, accumulated since it was prepared or since
+/// `Statement::reset_scan_status`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanStatus {
+    /// Number of times the loop has run.
+    pub loops: i64,
+    /// Number of rows visited by the loop, over all its runs.
+    pub rows_visited: i64,
+    /// Number of rows the query planner estimated each run would visit.
+    pub estimated_rows: f64,
+    /// Name of the table or index scanned.
+    pub name: Option<String>,
+    /// `EXPLAIN QUERY PLAN` description of the loop.
+    pub explain: Option<String>,
+    /// Id of the `EXPLAIN QUERY PLAN` entry of the loop.
+    pub select_id: i32,
+}
+
+impl ScanStatus {
+    /// Return the average number of rows visited per run of the loop.
+    pub fn rows_per_loop(&self) -> f64 {
+        if self.loops == 0 {
+            0.0
+        } else {
+            self.rows_visited as f64 / self.loops as f64
+        }
+    }
+}
+
+impl Statement<'_> {
+    /// Return the counters of each loop of the query plan of this statement.
+    ///
+    /// Comparing `ScanStatus::rows_per_loop` to `ScanStatus::estimated_rows`
+    /// shows where the query planner is wrong, and checking `rows_visited`
+    /// in tests detects query plan regressions (e.g. a full table scan
+    /// instead of an index lookup).
+    pub fn scan_status(&self) -> Vec<ScanStatus> {
+        let stmt = unsafe { self.stmt.ptr() };
+        let mut status = Vec::new();
+        for idx in 0.. {
+            let mut loops: i64 = 0;
+            if unsafe { scan_status_value(stmt, idx, ffi::SQLITE_SCANSTAT_NLOOP, &mut loops) } {
+                break;
+            }
+            let mut rows_visited: i64 = 0;
+            let mut estimated_rows: f64 = 0.0;
+            let mut name: *const c_char = ptr::null();
+            let mut explain: *const c_char = ptr::null();
+            let mut select_id: c_int = 0;
+            unsafe {
+                scan_status_value(stmt, idx, ffi::SQLITE_SCANSTAT_NVISIT, &mut rows_visited);
+                scan_status_value(stmt, idx, ffi::SQLITE_SCANSTAT_EST, &mut estimated_rows);
+                scan_status_value(stmt, idx, ffi::SQLITE_SCANSTAT_NAME, &mut name);
+                scan_status_value(stmt, idx, ffi::SQLITE_SCANSTAT_EXPLAIN, &mut explain);
+                scan_status_value(stmt, idx, ffi::SQLITE_SCANSTAT_SELECTID, &mut select_id);
+            }
+            status.push(ScanStatus {
+                loops,
+                rows_visited,
+                estimated_rows,
+                name: to_string(name),
+                explain: to_string(explain),
+                select_id,
+            });
+        }
+        status
+    }
+
+    /// Reset the counters returned by `scan_status` to zero.
+    pub fn reset_scan_status(&self) {
+        unsafe { ffi::sqlite3_stmt_scanstatus_reset(self.stmt.ptr()) }
+    }
+}
+
+/// Read one counter of loop `idx` into `out`, and return `true` if there is
+/// no such loop.
+unsafe fn scan_status_value<T>(
+    stmt: *mut ffi::sqlite3_stmt,
+    idx: c_int,
+    op: c_int,
+    out: &mut T,
+) -> bool {
+    ffi::sqlite3_stmt_scanstatus(stmt, idx, op, out as *mut T as *mut c_void) != 0
+}
+
+fn to_string(s: *const c_char) -> Option<String> {
+    if s.is_null() {
+        None
+    } else {
+        Some(unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Connection, NO_PARAMS};
+
+    #[test]
+    fn test_scan_status() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER, y INTEGER);
+             CREATE INDEX foo_x ON foo(x);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100)
+             INSERT INTO foo SELECT i, i FROM n;",
+        )
+        .unwrap();
+
+        let mut stmt = db.prepare("SELECT y FROM foo WHERE y = 50").unwrap();
+        assert!(stmt.scan_status().iter().all(|s| s.loops == 0));
+        assert_eq!(1, stmt.query_map(NO_PARAMS, |_| Ok(())).unwrap().count());
+        let status = stmt.scan_status();
+        assert_eq!(1, status.len());
+        assert_eq!(Some("foo"), status[0].name.as_ref().map(String::as_str));
+        assert_eq!(1, status[0].loops);
+        assert_eq!(100, status[0].rows_visited);
+
+        let mut stmt = db.prepare("SELECT y FROM foo WHERE x = ?").unwrap();
+        assert_eq!(1, stmt.query_map([50], |_| Ok(())).unwrap().count());
+        let status = stmt.scan_status();
+        assert_eq!(Some("foo_x"), status[0].name.as_ref().map(String::as_str));
+        assert!(status[0].rows_visited < 10);
+        stmt.reset_scan_status();
+        assert_eq!(0, stmt.scan_status()[0].loops);
+    }
+}
@@ -0,0 +1,991 @@
+//! Declarative schema migrations
+//!
+//! `plan_migration` compares the schema of a database with a desired schema
+//! and returns the statements which transform the former into the latter:
+//! objects (tables, indexes, views and triggers) are matched by name and
+//! compared by their SQL text (ignoring whitespace differences). Columns
+//! appended to a table are added with `ALTER TABLE ... ADD COLUMN` when
+//! SQLite allows it, other table changes are done by rebuilding the table
+//! (see [Making Other Kinds Of Table Schema
+//! Changes](https://sqlite.org/lang_altertable.html#otheralter)), copying
+//! the data of the columns common to both definitions.
+//!
+//! The statements should be run in a single transaction with foreign key
+//! enforcement disabled, followed by `PRAGMA foreign_key_check`:
+//!
+//! ```rust,no_run
+//! # use rusqlite::{schema, Connection, Result};
+//! const SCHEMA: &str = "
+//!     CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT NOT NULL, email TEXT);
+//!     CREATE INDEX person_name ON person(name);
+//! ";
+//!
+//! fn migrate(conn: &mut Connection) -> Result<()> {
+//!     conn.pragma_update(None, "foreign_keys", &false)?;
+//!     let tx = conn.transaction()?;
+//!     for sql in schema::plan_migration(&tx, SCHEMA)? {
+//!         tx.execute_batch(&sql)?;
+//!     }
+//!     tx.commit()?;
+//!     conn.pragma_update(None, "foreign_keys", &true)
+//! }
+//! ```
+//!
+//! `table_columns` and `index_columns` introspect the columns of all the
+//! tables and indexes matching a filter in a single query, including
+//! generated columns and indexed expressions.
+
+use crate::pragma::Sql;
+use crate::types::Params;
+use crate::{Connection, Result, NO_PARAMS};
+
+/// The schema a database should be migrated to.
+#[derive(Clone, Copy)]
+pub enum DesiredSchema<'a> {
+    /// `CREATE` statements, run on an empty in-memory database.
+    Sql(&'a str),
+    /// The schema of the main database of another connection.
+    Connection(&'a Connection),
+}
+
+impl<'a> From<&'a str> for DesiredSchema<'a> {
+    fn from(sql: &'a str) -> DesiredSchema<'a> {
+        DesiredSchema::Sql(sql)
+    }
+}
+
+impl<'a> From<&'a Connection> for DesiredSchema<'a> {
+    fn from(conn: &'a Connection) -> DesiredSchema<'a> {
+        DesiredSchema::Connection(conn)
+    }
+}
+
+/// Return the statements which migrate the schema of the main database of
+/// `conn` to `desired` (see the module documentation). The returned
+/// statements are empty if the schemas are identical.
+///
+/// # Failure
+///
+/// Will return `Err` if the desired schema SQL is invalid or if the
+/// underlying SQLite calls fail.
+pub fn plan_migration<'a, D: Into<DesiredSchema<'a>>>(
+    conn: &Connection,
+    desired: D,
+) -> Result<Vec<String>> {
+    match desired.into() {
+        DesiredSchema::Sql(sql) => {
+            let db = Connection::open_in_memory()?;
+            db.execute_batch(sql)?;
+            plan(conn, &db)
+        }
+        DesiredSchema::Connection(db) => plan(conn, db),
+    }
+}
+
+/// How the value of a [generated column](https://sqlite.org/gencol.html) is
+/// stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GeneratedColumn {
+    /// The value is computed when the column is read.
+    Virtual,
+    /// The value is computed when the row is written, and stored.
+    Stored,
+}
+
+impl GeneratedColumn {
+    /// Convert the `hidden` column of `PRAGMA table_xinfo`.
+    fn from_hidden(hidden: i32) -> Option<GeneratedColumn> {
+        match hidden {
+            2 => Some(GeneratedColumn::Virtual),
+            3 => Some(GeneratedColumn::Stored),
+            _ => None,
+        }
+    }
+}
+
+/// A column of a table, as returned by `table_columns` (see
+/// [`PRAGMA table_xinfo`](https://sqlite.org/pragma.html#pragma_table_xinfo)).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableColumn {
+    /// Name of the table.
+    pub table: String,
+    /// Index of the column in the table.
+    pub cid: i32,
+    /// Name of the column.
+    pub name: String,
+    /// Declared type of the column (empty if none).
+    pub decl_type: String,
+    /// Whether the column has a `NOT NULL` constraint.
+    pub not_null: bool,
+    /// SQL text of the default value of the column.
+    pub default: Option<String>,
+    /// Index of the column in the primary key (starting at 1), or 0 if it is
+    /// not part of the primary key.
+    pub pk: i32,
+    /// How the column is generated, or `None` if it is an ordinary column.
+    pub generated: Option<GeneratedColumn>,
+}
+
+/// A column of an index, as returned by `index_columns` (see
+/// [`PRAGMA index_xinfo`](https://sqlite.org/pragma.html#pragma_index_xinfo)).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexColumn {
+    /// Name of the table.
+    pub table: String,
+    /// Name of the index.
+    pub index: String,
+    /// Rank of the column in the index.
+    pub seqno: i32,
+    /// Index of the column in the table (-1 for the rowid, -2 for an
+    /// expression).
+    pub cid: i32,
+    /// Name of the column (`None` for the rowid or an expression).
+    pub name: Option<String>,
+    /// Whether the column is sorted in descending order.
+    pub desc: bool,
+    /// Name of the collating sequence of the column.
+    pub collation: String,
+    /// Whether the column is a key column, rather than an auxiliary column
+    /// (like the rowid) stored in the index.
+    pub key: bool,
+    /// SQL text of the indexed expression (with its `COLLATE` clause if
+    /// any) if `cid` is -2.
+    pub expression: Option<String>,
+}
+
+/// Return the columns of the tables of the main database, ordered by table
+/// name and column index, in a single query of the `pragma_table_info`
+/// table-valued function.
+///
+/// `filter` is an SQL expression selecting the columns to return, whose
+/// terms are the names of the `TableColumn` fields (`"table"` must be
+/// quoted, and `generated` is `'VIRTUAL'`, `'STORED'` or `NULL`), e.g.
+/// `"\"table\" LIKE 'log_%' AND pk > 0"`; pass `"1"` to return all the
+/// columns. This is much faster than running one `PRAGMA table_info` per
+/// table on large schemas.
+///
+/// This requires SQLite 3.16.0 or later; generated columns (SQLite 3.31.0)
+/// are only returned with SQLite 3.26.0 or later.
+///
+/// # Failure
+///
+/// Will return `Err` if `filter` is invalid or if the underlying SQLite
+/// calls fail.
+pub fn table_columns<P>(conn: &Connection, filter: &str, params: P) -> Result<Vec<TableColumn>>
+where
+    P: Params,
+{
+    let (pragma, generated) = if crate::version_number() >= 3_026_000 {
+        (
+            "pragma_table_xinfo(m.name) AS p WHERE p.hidden <> 1 AND",
+            "CASE p.hidden WHEN 2 THEN 'VIRTUAL' WHEN 3 THEN 'STORED' END",
+        )
+    } else {
+        ("pragma_table_info(m.name) AS p WHERE", "NULL")
+    };
+    let sql = format!(
+        "SELECT * FROM (SELECT m.name AS \"table\", p.cid AS cid, p.name AS name, \
+         p.type AS decl_type, p.\"notnull\" AS not_null, p.dflt_value AS \"default\", \
+         p.pk AS pk, {} AS generated FROM sqlite_master AS m, {} \
+         m.type = 'table') WHERE {} ORDER BY \"table\", cid",
+        generated, pragma, filter
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params, |row| {
+        Ok(TableColumn {
+            table: row.get(0)?,
+            cid: row.get(1)?,
+            name: row.get(2)?,
+            decl_type: row.get(3)?,
+            not_null: row.get(4)?,
+            default: row.get(5)?,
+            pk: row.get(6)?,
+            generated: match row.get_raw(7).as_str().ok() {
+                Some("VIRTUAL") => Some(GeneratedColumn::Virtual),
+                Some("STORED") => Some(GeneratedColumn::Stored),
+                _ => None,
+            },
+        })
+    })?;
+    rows.collect()
+}
+
+/// Return the columns of the indexes of the main database, ordered by index
+/// name and rank, in a single query of the `pragma_index_xinfo`
+/// table-valued function.
+///
+/// `filter` is an SQL expression selecting the columns to return, whose
+/// terms are the names of the `IndexColumn` fields (`"table"`, `"index"`,
+/// `"desc"` and `"key"` must be quoted, `expression` cannot be used), e.g.
+/// `"\"table\" = 'person' AND \"key\""`; pass `"1"` to return all the
+/// columns.
+///
+/// This requires SQLite 3.16.0 or later.
+///
+/// # Failure
+///
+/// Will return `Err` if `filter` is invalid or if the underlying SQLite
+/// calls fail.
+pub fn index_columns<P>(conn: &Connection, filter: &str, params: P) -> Result<Vec<IndexColumn>>
+where
+    P: Params,
+{
+    let sql = format!(
+        "SELECT * FROM (SELECT m.tbl_name AS \"table\", m.name AS \"index\", \
+         p.seqno AS seqno, p.cid AS cid, p.name AS name, p.\"desc\" AS \"desc\", \
+         p.coll AS collation, p.\"key\" AS \"key\", m.sql AS sql \
+         FROM sqlite_master AS m, pragma_index_xinfo(m.name) AS p \
+         WHERE m.type = 'index') WHERE {} ORDER BY \"index\", seqno",
+        filter
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params, |row| {
+        let seqno = row.get(2)?;
+        let cid = row.get(3)?;
+        Ok(IndexColumn {
+            table: row.get(0)?,
+            index: row.get(1)?,
+            seqno,
+            cid,
+            name: row.get(4)?,
+            desc: row.get(5)?,
+            collation: row.get(6)?,
+            key: row.get(7)?,
+            expression: if cid == -2 {
+                let sql: Option<String> = row.get(8)?;
+                sql.and_then(|sql| indexed_expression(&sql, seqno))
+            } else {
+                None
+            },
+        })
+    })?;
+    rows.collect()
+}
+
+/// Return the SQL text of the term `seqno` of a `CREATE INDEX` statement,
+/// without its sort order.
+fn indexed_expression(sql: &str, seqno: i32) -> Option<String> {
+    let (_, terms, _) = split_definitions(sql)?;
+    let term = normalize_whitespace(terms.get(seqno as usize)?);
+    let upper = term.to_ascii_uppercase();
+    let len = if upper.ends_with(" ASC") {
+        term.len() - 4
+    } else if upper.ends_with(" DESC") {
+        term.len() - 5
+    } else {
+        term.len()
+    };
+    Some(term[..len].to_owned())
+}
+
+struct Object {
+    kind: String,
+    name: String,
+    tbl_name: String,
+    sql: String,
+}
+
+impl Object {
+    fn is(&self, kind: &str) -> bool {
+        self.kind == kind
+    }
+
+    fn is_virtual(&self) -> bool {
+        self.is("table") && self.sql.to_ascii_uppercase().starts_with("CREATE VIRTUAL")
+    }
+
+    fn same_sql(&self, other: &Object) -> bool {
+        if self.is("table") && !self.is_virtual() && !other.is_virtual() {
+            // Ignore the quoting of the table name, which is changed by renaming.
+            if let (Some((_, defs, suffix)), Some((_, other_defs, other_suffix))) =
+                (split_definitions(&self.sql), split_definitions(&other.sql))
+            {
+                return same_definitions(&defs, &other_defs)
+                    && normalize_whitespace(suffix)
+                        .eq_ignore_ascii_case(&normalize_whitespace(other_suffix));
+            }
+        }
+        normalize_whitespace(&self.sql) == normalize_whitespace(&other.sql)
+    }
+
+    /// Virtual tables cannot be altered or rebuilt, only dropped and created
+    /// again.
+    fn needs_replace(&self, other: &Object) -> bool {
+        (self.is_virtual() || other.is_virtual()) && !self.same_sql(other)
+    }
+}
+
+fn plan(conn: &Connection, desired: &Connection) -> Result<Vec<String>> {
+    let current_objects = load_objects(conn)?;
+    let desired_objects = load_objects(desired)?;
+    let find = |objects: &'_ [Object], name: &str| -> Option<usize> {
+        objects
+            .iter()
+            .position(|o| o.name.eq_ignore_ascii_case(name))
+    };
+
+    // Tables to create, alter (with the columns to add) or rebuild.
+    let mut created = Vec::new();
+    let mut altered = Vec::new();
+    let mut rebuilt = Vec::new();
+    for (i, table) in desired_objects.iter().enumerate() {
+        if !table.is("table") {
+            continue;
+        }
+        match find(&current_objects, &table.name) {
+            Some(j)
+                if current_objects[j].is("table") && !current_objects[j].needs_replace(table) =>
+            {
+                let current = &current_objects[j];
+                if table.same_sql(current) {
+                    continue;
+                }
+                match added_columns(conn, current, desired, table)? {
+                    Some(columns) => altered.push((i, columns)),
+                    None => rebuilt.push(i),
+                }
+            }
+            _ => created.push(i),
+        }
+    }
+    let dropped_tables: Vec<&Object> = current_objects
+        .iter()
+        .filter(|o| o.is("table"))
+        .filter(|o| match find(&desired_objects, &o.name) {
+            Some(i) => !desired_objects[i].is("table") || o.needs_replace(&desired_objects[i]),
+            None => true,
+        })
+        .collect();
+    let is_rebuilt = |table: &str| {
+        rebuilt
+            .iter()
+            .any(|&i| desired_objects[i].name.eq_ignore_ascii_case(table))
+    };
+    let is_dropped = |table: &str| {
+        dropped_tables
+            .iter()
+            .any(|o| o.name.eq_ignore_ascii_case(table))
+    };
+    // Views and triggers may refer to dropped or rebuilt tables, which would
+    // make the table renaming fail: recreate all of them in that case.
+    let recreate_all = !rebuilt.is_empty() || !dropped_tables.is_empty();
+    let unchanged = |o: &Object| match find(&desired_objects, &o.name) {
+        Some(i) => desired_objects[i].is(&o.kind) && desired_objects[i].same_sql(o),
+        None => false,
+    };
+
+    let mut plan = Vec::new();
+    for o in &current_objects {
+        let kind = match o.kind.as_str() {
+            "view" | "trigger" if recreate_all || !unchanged(o) => o.kind.to_ascii_uppercase(),
+            // Indexes of dropped or rebuilt tables are dropped with them.
+            "index" if !unchanged(o) && !is_dropped(&o.tbl_name) && !is_rebuilt(&o.tbl_name) => {
+                o.kind.to_ascii_uppercase()
+            }
+            _ => continue,
+        };
+        plan.push(format!("DROP {} {}", kind, quote(&o.name)));
+    }
+    for o in &dropped_tables {
+        plan.push(format!("DROP TABLE {}", quote(&o.name)));
+    }
+    for &i in &created {
+        plan.push(desired_objects[i].sql.clone());
+    }
+    for &(i, ref columns) in &altered {
+        for column in columns {
+            plan.push(format!(
+                "ALTER TABLE {} ADD COLUMN {}",
+                quote(&desired_objects[i].name),
+                column
+            ));
+        }
+    }
+    for &i in &rebuilt {
+        let table = &desired_objects[i];
+        let current = &current_objects[find(&current_objects, &table.name).unwrap()];
+        plan.extend(rebuild_table(conn, current, desired, table)?);
+    }
+    for o in &desired_objects {
+        let create = match o.kind.as_str() {
+            "view" | "trigger" => recreate_all || !has_same(&current_objects, o),
+            "index" => {
+                !has_same(&current_objects, o) || is_rebuilt(&o.tbl_name) || is_dropped(&o.tbl_name)
+            }
+            _ => false,
+        };
+        if create {
+            plan.push(o.sql.clone());
+        }
+    }
+    Ok(plan)
+}
+
+fn has_same(objects: &[Object], o: &Object) -> bool {
+    objects
+        .iter()
+        .any(|c| c.name.eq_ignore_ascii_case(&o.name) && c.kind == o.kind && c.same_sql(o))
+}
+
+fn load_objects(conn: &Connection) -> Result<Vec<Object>> {
+    let mut stmt = conn.prepare(
+        "SELECT type, name, tbl_name, sql FROM sqlite_master \
+         WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' ORDER BY rowid",
+    )?;
+    let objects = stmt
+        .query_map(NO_PARAMS, |row| {
+            Ok(Object {
+                kind: row.get(0)?,
+                name: row.get(1)?,
+                tbl_name: row.get(2)?,
+                sql: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    // The shadow tables of virtual tables are managed by their module.
+    let virtual_tables: Vec<String> = objects
+        .iter()
+        .filter(|o| o.is_virtual())
+        .map(|o| format!("{}_", o.name.to_ascii_lowercase()))
+        .collect();
+    Ok(objects
+        .into_iter()
+        .filter(|o| {
+            let name = o.name.to_ascii_lowercase();
+            !virtual_tables.iter().any(|prefix| name.starts_with(prefix))
+        })
+        .collect())
+}
+
+struct ColumnInfo {
+    name: String,
+    not_null: bool,
+    default: Option<String>,
+    pk: bool,
+    generated: Option<GeneratedColumn>,
+}
+
+fn table_info(conn: &Connection, table: &str) -> Result<Vec<ColumnInfo>> {
+    // Generated columns are only listed by `table_xinfo`.
+    let xinfo = crate::version_number() >= 3_026_000;
+    let pragma = if xinfo { "table_xinfo" } else { "table_info" };
+    let mut columns = Vec::new();
+    conn.pragma(None, pragma, &table, |row| {
+        let hidden = if xinfo { row.get(6)? } else { 0 };
+        if hidden != 1 {
+            columns.push(ColumnInfo {
+                name: row.get(1)?,
+                not_null: row.get(3)?,
+                default: row.get(4)?,
+                pk: row.get::<_, i32>(5)? != 0,
+                generated: GeneratedColumn::from_hidden(hidden),
+            });
+        }
+        Ok(())
+    })?;
+    Ok(columns)
+}
+
+/// Return the definitions of the columns appended to `current` by `table`,
+/// if `table` only differs from `current` by these columns and if they can
+/// be added with `ALTER TABLE ... ADD COLUMN`.
+fn added_columns(
+    conn: &Connection,
+    current: &Object,
+    desired: &Connection,
+    table: &Object,
+) -> Result<Option<Vec<String>>> {
+    let (current_defs, current_suffix) = match split_definitions(&current.sql) {
+        Some((_, defs, suffix)) => (defs, suffix),
+        None => return Ok(None),
+    };
+    let (defs, suffix) = match split_definitions(&table.sql) {
+        Some((_, defs, suffix)) => (defs, suffix),
+        None => return Ok(None),
+    };
+    if !normalize_whitespace(current_suffix).eq_ignore_ascii_case(&normalize_whitespace(suffix)) {
+        return Ok(None);
+    }
+    let (current_columns, current_constraints): (Vec<&str>, Vec<&str>) = current_defs
+        .into_iter()
+        .partition(|d| !is_table_constraint(d));
+    let (columns, constraints): (Vec<&str>, Vec<&str>) =
+        defs.into_iter().partition(|d| !is_table_constraint(d));
+    if columns.len() <= current_columns.len()
+        || !same_definitions(&current_columns, &columns[..current_columns.len()])
+        || !same_definitions(&current_constraints, &constraints)
+    {
+        return Ok(None);
+    }
+    let info = table_info(desired, &table.name)?;
+    let current_info = table_info(conn, &current.name)?;
+    let added = &columns[current_columns.len()..];
+    if info.len() != current_info.len() + added.len() {
+        return Ok(None);
+    }
+    for (def, column) in added.iter().zip(&info[current_info.len()..]) {
+        let default = column
+            .default
+            .as_ref()
+            .map(|d| d.to_ascii_uppercase())
+            .filter(|d| d != "NULL");
+        // See the restrictions of https://sqlite.org/lang_altertable.html#altertabaddcol
+        let not_addable = column.pk
+            || column.generated == Some(GeneratedColumn::Stored)
+            || has_keyword(def, "UNIQUE")
+            || has_keyword(def, "PRIMARY")
+            || (column.not_null && default.is_none())
+            || (has_keyword(def, "REFERENCES") && default.is_some())
+            || default
+                .iter()
+                .any(|d| d.starts_with('(') || d.starts_with("CURRENT_"));
+        if not_addable {
+            return Ok(None);
+        }
+    }
+    Ok(Some(
+        added.iter().map(|d| normalize_whitespace(d)).collect(),
+    ))
+}
+
+/// Return the statements rebuilding `current` as `table`.
+fn rebuild_table(
+    conn: &Connection,
+    current: &Object,
+    desired: &Connection,
+    table: &Object,
+) -> Result<Vec<String>> {
+    let current_columns = table_info(conn, &current.name)?;
+    // Generated columns cannot be inserted into.
+    let columns: Vec<String> = table_info(desired, &table.name)?
+        .into_iter()
+        .filter(|c| {
+            c.generated.is_none()
+                && current_columns
+                    .iter()
+                    .any(|o| o.name.eq_ignore_ascii_case(&c.name))
+        })
+        .map(|c| quote(&c.name))
+        .collect();
+    let columns = columns.join(", ");
+    let new_name = quote(&format!("new_{}", table.name));
+    let body = match split_definitions(&table.sql) {
+        Some((start, _, _)) => &table.sql[start..],
+        None => unreachable!("table without column definitions"),
+    };
+    Ok(vec![
+        format!("CREATE TABLE {} {}", new_name, body),
+        format!(
+            "INSERT INTO {} ({}) SELECT {} FROM {}",
+            new_name,
+            columns,
+            columns,
+            quote(&current.name)
+        ),
+        format!("DROP TABLE {}", quote(&current.name)),
+        format!("ALTER TABLE {} RENAME TO {}", new_name, quote(&table.name)),
+    ])
+}
+
+fn quote(name: &str) -> String {
+    let mut sql = Sql::new();
+    sql.push_identifier(name);
+    sql.as_str().to_owned()
+}
+
+fn normalize_whitespace(sql: &str) -> String {
+    sql.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn same_definitions(a: &[&str], b: &[&str]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(a, b)| normalize_whitespace(a) == normalize_whitespace(b))
+}
+
+/// Whether `keyword` is one of the words of `def` (and not only a part of a
+/// name like `primary_email`).
+fn has_keyword(def: &str, keyword: &str) -> bool {
+    def.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+        .any(|word| word.eq_ignore_ascii_case(keyword))
+}
+
+fn is_table_constraint(def: &str) -> bool {
+    // The first word is a whole keyword, not a prefix of a column name like
+    // `check_date`
+    let end = def
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+        .unwrap_or_else(|| def.len());
+    let (keyword, rest) = def.split_at(end);
+    ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"]
+        .iter()
+        .any(|k| keyword.eq_ignore_ascii_case(k))
+        && rest.starts_with(|c: char| c.is_whitespace() || c == '(')
+}
+
+/// Split the definitions of a `CREATE TABLE` statement at the top-level
+/// commas. Return the offset of the opening parenthesis, the trimmed
+/// definitions and what follows the closing parenthesis.
+fn split_definitions(sql: &str) -> Option<(usize, Vec<&str>, &str)> {
+    let bytes = sql.as_bytes();
+    let mut open = None;
+    let mut depth = 0;
+    let mut defs = Vec::new();
+    let mut def_start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            q @ b'\'' | q @ b'"' | q @ b'`' | q @ b'[' => {
+                let close = if q == b'[' { b']' } else { q };
+                i += 1;
+                while i < bytes.len() && bytes[i] != close {
+                    i += 1;
+                }
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    i += 1;
+                }
+                i += 1;
+            }
+            b'(' => {
+                if open.is_none() {
+                    open = Some(i);
+                    def_start = i + 1;
+                }
+                depth += 1;
+            }
+            b')' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    defs.push(sql[def_start..i].trim());
+                    return open.map(|open| (open, defs, &sql[i + 1..]));
+                }
+            }
+            b',' if depth == 1 => {
+                defs.push(sql[def_start..i].trim());
+                def_start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        index_columns, is_table_constraint, plan_migration, table_columns, GeneratedColumn,
+        IndexColumn, TableColumn,
+    };
+    use crate::{Connection, NO_PARAMS};
+
+    fn migrate(db: &mut Connection, desired: &str) -> Vec<String> {
+        let plan = plan_migration(db, desired).unwrap();
+        let tx = db.transaction().unwrap();
+        for sql in &plan {
+            tx.execute_batch(sql).unwrap();
+        }
+        tx.commit().unwrap();
+        assert!(plan_migration(db, desired).unwrap().is_empty());
+        plan
+    }
+
+    #[test]
+    fn test_create_and_drop() {
+        let mut db = Connection::open_in_memory().unwrap();
+        let plan = migrate(
+            &mut db,
+            "CREATE TABLE foo(x INTEGER);
+             CREATE INDEX foo_x ON foo(x);
+             CREATE VIEW v AS SELECT x FROM foo;",
+        );
+        assert_eq!(
+            vec![
+                "CREATE TABLE foo(x INTEGER)",
+                "CREATE INDEX foo_x ON foo(x)",
+                "CREATE VIEW v AS SELECT x FROM foo",
+            ],
+            plan
+        );
+
+        let plan = migrate(
+            &mut db,
+            "CREATE TABLE \"bar baz\"(x  INTEGER, y TEXT UNIQUE)",
+        );
+        assert_eq!(
+            vec![
+                "DROP VIEW v",
+                "DROP TABLE foo",
+                "CREATE TABLE \"bar baz\"(x  INTEGER, y TEXT UNIQUE)",
+            ],
+            plan
+        );
+        // Whitespace differences are ignored.
+        assert!(
+            plan_migration(&db, "CREATE TABLE \"bar baz\"(x INTEGER,\n y TEXT UNIQUE)")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_add_column() {
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER, CHECK (x > 0));
+             CREATE INDEX foo_x ON foo(x);
+             INSERT INTO foo VALUES(1);",
+        )
+        .unwrap();
+        let plan = migrate(
+            &mut db,
+            "CREATE TABLE foo(x INTEGER, y TEXT NOT NULL DEFAULT 'a,b', z, CHECK (x > 0));
+             CREATE INDEX foo_x ON foo(x, y);",
+        );
+        assert_eq!(
+            vec![
+                "DROP INDEX foo_x",
+                "ALTER TABLE foo ADD COLUMN y TEXT NOT NULL DEFAULT 'a,b'",
+                "ALTER TABLE foo ADD COLUMN z",
+                "CREATE INDEX foo_x ON foo(x, y)",
+            ],
+            plan
+        );
+        let y: String = db
+            .query_row("SELECT y FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!("a,b", y);
+    }
+
+    #[test]
+    fn test_is_table_constraint() {
+        for def in &[
+            "CHECK (x > 0)",
+            "check(x > 0)",
+            "UNIQUE (x, y)",
+            "PRIMARY KEY (x)",
+            "FOREIGN KEY (x) REFERENCES bar(id)",
+            "CONSTRAINT positive CHECK (x > 0)",
+        ] {
+            assert!(is_table_constraint(def), "{}", def);
+        }
+        for def in &[
+            "check_date TEXT",
+            "unique_code TEXT",
+            "primary_email TEXT",
+            "constraints",
+            "\"check\" TEXT",
+        ] {
+            assert!(!is_table_constraint(def), "{}", def);
+        }
+    }
+
+    #[test]
+    fn test_add_column_after_keyword_prefixed_column() {
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(check_date TEXT, unique_code TEXT)")
+            .unwrap();
+        let plan = migrate(
+            &mut db,
+            "CREATE TABLE foo(check_date TEXT, unique_code TEXT, primary_email TEXT)",
+        );
+        assert_eq!(vec!["ALTER TABLE foo ADD COLUMN primary_email TEXT"], plan);
+    }
+
+    #[test]
+    fn test_rebuild_table() {
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER, y TEXT, z BLOB);
+             CREATE INDEX foo_y ON foo(y);
+             CREATE TABLE bar(a);
+             CREATE VIEW v AS SELECT a FROM bar;
+             INSERT INTO foo VALUES(1, 'one', x'00');",
+        )
+        .unwrap();
+        let desired = "CREATE TABLE foo(x INTEGER PRIMARY KEY, y TEXT NOT NULL, w);
+                       CREATE INDEX foo_y ON foo(y);
+                       CREATE TABLE bar(a);
+                       CREATE VIEW v AS SELECT a FROM bar;";
+        let plan = migrate(&mut db, desired);
+        assert_eq!(
+            vec![
+                "DROP VIEW v",
+                "CREATE TABLE new_foo (x INTEGER PRIMARY KEY, y TEXT NOT NULL, w)",
+                "INSERT INTO new_foo (x, y) SELECT x, y FROM foo",
+                "DROP TABLE foo",
+                "ALTER TABLE new_foo RENAME TO foo",
+                "CREATE INDEX foo_y ON foo(y)",
+                "CREATE VIEW v AS SELECT a FROM bar",
+            ],
+            plan
+        );
+        let row: (i64, String, Option<i64>) = db
+            .query_row("SELECT x, y, w FROM foo", NO_PARAMS, |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })
+            .unwrap();
+        assert_eq!((1, "one".to_owned(), None), row);
+
+        // Desired schema of another connection
+        let other = Connection::open_in_memory().unwrap();
+        other.execute_batch(desired).unwrap();
+        assert!(plan_migration(&db, &other).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_table_and_index_columns() {
+        if crate::version_number() < 3_016_000 {
+            return;
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT NOT NULL DEFAULT '');
+             CREATE TABLE log_a (msg TEXT);
+             CREATE TABLE log_b (msg TEXT, level INTEGER);
+             CREATE INDEX person_name ON person (name DESC);",
+        )
+        .unwrap();
+
+        let columns = table_columns(&db, "1", NO_PARAMS).unwrap();
+        assert_eq!(5, columns.len());
+        assert_eq!(
+            TableColumn {
+                table: "person".to_owned(),
+                cid: 1,
+                name: "name".to_owned(),
+                decl_type: "TEXT".to_owned(),
+                not_null: true,
+                default: Some("''".to_owned()),
+                pk: 0,
+                generated: None,
+            },
+            columns[4]
+        );
+        let columns = table_columns(&db, "\"table\" LIKE ? AND cid > 0", ["log%"]).unwrap();
+        let names: Vec<(&str, &str)> = columns
+            .iter()
+            .map(|c| (c.table.as_str(), c.name.as_str()))
+            .collect();
+        assert_eq!(vec![("log_b", "level")], names);
+        assert!(table_columns(&db, "no_such_column", NO_PARAMS).is_err());
+
+        let columns = index_columns(&db, "\"index\" = ?", ["person_name"]).unwrap();
+        assert_eq!(2, columns.len());
+        assert_eq!(
+            IndexColumn {
+                table: "person".to_owned(),
+                index: "person_name".to_owned(),
+                seqno: 0,
+                cid: 1,
+                name: Some("name".to_owned()),
+                desc: true,
+                collation: "BINARY".to_owned(),
+                key: true,
+                expression: None,
+            },
+            columns[0]
+        );
+        assert_eq!(
+            (-1, None, false),
+            (columns[1].cid, columns[1].name.clone(), columns[1].key)
+        );
+    }
+
+    #[test]
+    fn test_generated_columns() {
+        if crate::version_number() < 3_031_000 {
+            return;
+        }
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER, y AS (x * 2) STORED);
+             INSERT INTO foo(x) VALUES(1);",
+        )
+        .unwrap();
+        let columns = table_columns(&db, "generated IS NOT NULL", NO_PARAMS).unwrap();
+        assert_eq!(1, columns.len());
+        assert_eq!(
+            ("y", Some(GeneratedColumn::Stored)),
+            (columns[0].name.as_str(), columns[0].generated)
+        );
+
+        // Virtual columns can be added...
+        let plan = migrate(
+            &mut db,
+            "CREATE TABLE foo(x INTEGER, y AS (x * 2) STORED, z AS (x + 1));",
+        );
+        assert_eq!(vec!["ALTER TABLE foo ADD COLUMN z AS (x + 1)"], plan);
+        let columns = table_columns(&db, "name = 'z'", NO_PARAMS).unwrap();
+        assert_eq!(Some(GeneratedColumn::Virtual), columns[0].generated);
+
+        // ...stored ones require a rebuild, without copying generated values.
+        let plan = migrate(
+            &mut db,
+            "CREATE TABLE foo(x INTEGER, y AS (x * 2) STORED, z AS (x + 1), \
+             w AS (x * 3) STORED);",
+        );
+        assert_eq!("INSERT INTO new_foo (x) SELECT x FROM foo", plan[1]);
+        let row: (i64, i64, i64) = db
+            .query_row("SELECT y, z, w FROM foo", NO_PARAMS, |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })
+            .unwrap();
+        assert_eq!((2, 2, 3), row);
+    }
+
+    #[test]
+    fn test_expression_index() {
+        if crate::version_number() < 3_016_000 {
+            return;
+        }
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE person(name TEXT, email TEXT)")
+            .unwrap();
+        let plan = migrate(
+            &mut db,
+            "CREATE TABLE person(name TEXT, email TEXT);
+             CREATE INDEX person_email ON person(lower(email) DESC, name,
+                 substr(name, 1, 2) COLLATE NOCASE);",
+        );
+        assert_eq!(1, plan.len());
+
+        let columns = index_columns(&db, "\"key\"", NO_PARAMS).unwrap();
+        let terms: Vec<(i32, Option<&str>, Option<&str>, bool)> = columns
+            .iter()
+            .map(|c| (c.cid, c.name.as_deref(), c.expression.as_deref(), c.desc))
+            .collect();
+        assert_eq!(
+            vec![
+                (-2, None, Some("lower(email)"), true),
+                (0, Some("name"), None, false),
+                (-2, None, Some("substr(name, 1, 2) COLLATE NOCASE"), false),
+            ],
+            terms
+        );
+        assert_eq!("NOCASE", columns[2].collation);
+
+        // Changing the expression recreates the index.
+        let plan = migrate(
+            &mut db,
+            "CREATE TABLE person(name TEXT, email TEXT);
+             CREATE INDEX person_email ON person(upper(email));",
+        );
+        assert_eq!(
+            vec![
+                "DROP INDEX person_email",
+                "CREATE INDEX person_email ON person(upper(email))",
+            ],
+            plan
+        );
+    }
+}
@@ -0,0 +1,597 @@
+//! Execution of SQL scripts statement by statement.
+use std::sync::atomic::Ordering;
+
+use crate::types::ToSql;
+use crate::{ffi, Connection, Error, OwnedRow, Result, Statement};
+
+/// Options for `Connection::execute_script`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScriptOptions {
+    /// Run the whole script atomically: if a statement fails, the changes made
+    /// by the previous ones are rolled back.
+    pub transaction: bool,
+    /// Only validate the script: each statement is prepared (which checks
+    /// its syntax and the objects it references) but not executed, except
+    /// `CREATE`, `DROP` and `ALTER` statements so that the following
+    /// statements can be validated against the new schema. All schema changes
+    /// are rolled back at the end.
+    pub dry_run: bool,
+}
+
+/// The columns and rows returned by a statement of `Connection::query_multi`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResultSet {
+    /// The column names (empty for statements which return no data, e.g.
+    /// `INSERT`).
+    pub columns: Vec<String>,
+    /// The rows.
+    pub rows: Vec<OwnedRow>,
+}
+
+/// An iterator over the statements of a SQL script (see `Connection::batch`).
+pub struct Batch<'conn, 'sql> {
+    conn: &'conn Connection,
+    sql: &'sql str,
+    /// Text of the last statement (or of the remaining SQL, if it failed to
+    /// prepare).
+    current: &'sql str,
+}
+
+impl<'conn> Iterator for Batch<'conn, '_> {
+    type Item = Result<Statement<'conn>>;
+
+    /// Prepare the next statement, skipping empty statements. After an
+    /// error, the iteration stops.
+    fn next(&mut self) -> Option<Result<Statement<'conn>>> {
+        loop {
+            self.sql = skip_empty_statements(self.sql);
+            if self.sql.is_empty() {
+                return None;
+            }
+            self.current = self.sql;
+            match self.conn.prepare_with_tail(self.sql) {
+                Ok((stmt, tail)) => {
+                    self.current = self.sql[..tail].trim_end();
+                    self.sql = &self.sql[tail..];
+                    if !stmt.stmt.is_null() {
+                        return Some(Ok(stmt));
+                    }
+                }
+                Err(err) => {
+                    self.sql = "";
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+impl Connection {
+    /// Return an iterator preparing the statements of `sql` one by one, so
+    /// that parameters can be bound to each statement and its rows read,
+    /// unlike with `execute_batch`.
+    ///
+    /// Each statement is prepared when the iterator is advanced, so a
+    /// statement can refer to a table created by a previous one if it has
+    /// been executed first.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn run(conn: &Connection, user_id: i64) -> Result<()> {
+    ///     let script = "UPDATE users SET seen = 1 WHERE id = :id;
+    ///                   SELECT name FROM users WHERE id = :id;";
+    ///     for stmt in conn.batch(script) {
+    ///         let mut stmt = stmt?;
+    ///         let mut rows = stmt.query_named(&[(":id", &user_id)])?;
+    ///         while let Some(row) = rows.next()? {
+    ///             println!("{}", row.get::<_, String>(0)?);
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn batch<'conn, 'sql>(&'conn self, sql: &'sql str) -> Batch<'conn, 'sql> {
+        Batch {
+            conn: self,
+            sql,
+            current: "",
+        }
+    }
+
+    /// Execute the statements of `sql`, discarding their rows (see
+    /// `execute_batch`).
+    pub(crate) fn run_batch(&self, sql: &str) -> Result<()> {
+        let interrupted = self.db.borrow().interrupted.clone();
+        interrupted.store(false, Ordering::SeqCst);
+        let mut batch = self.batch(sql);
+        let mut index = 0;
+        while let Some(stmt) = batch.next() {
+            let r = stmt.and_then(|stmt| {
+                if interrupted.load(Ordering::SeqCst) {
+                    return Err(Error::SqliteFailure(
+                        ffi::Error::new(ffi::SQLITE_INTERRUPT),
+                        None,
+                    ));
+                }
+                while stmt.step()? {}
+                stmt.reset();
+                Ok(())
+            });
+            if let Err(err) = r {
+                return Err(in_statement(err, index, batch.current));
+            }
+            index += 1;
+        }
+        Ok(())
+    }
+
+    /// Execute the statements of `sql` one by one, and return the number of
+    /// statements executed (or validated, in dry-run mode).
+    ///
+    /// Unlike `execute_batch`, statements are prepared individually so a
+    /// script can be validated without modifying the database (see
+    /// `ScriptOptions::dry_run`). Rows returned by statements are ignored.
+    ///
+    /// When `transaction` or `dry_run` is set, the script runs in a
+    /// savepoint, so it must not contain transaction control statements.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, ScriptOptions};
+    /// fn check_migration(conn: &Connection, sql: &str) -> Result<usize> {
+    ///     conn.execute_script(
+    ///         sql,
+    ///         ScriptOptions {
+    ///             dry_run: true,
+    ///             ..Default::default()
+    ///         },
+    ///     )
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if a statement fails to prepare or execute.
+    pub fn execute_script(&self, sql: &str, options: ScriptOptions) -> Result<usize> {
+        if !options.transaction && !options.dry_run {
+            return self.execute_script_(sql, false);
+        }
+        self.execute_internal("SAVEPOINT _rusqlite_script")?;
+        let r = self.execute_script_(sql, options.dry_run);
+        if r.is_err() || options.dry_run {
+            self.execute_internal("ROLLBACK TO _rusqlite_script")?;
+        }
+        self.execute_internal("RELEASE _rusqlite_script")?;
+        r
+    }
+
+    /// Execute the statements of `sql` one by one, the `i`th one with the
+    /// parameters `params_per_stmt[i]` (or no parameters if there are fewer
+    /// parameter lists than statements), and return the rows of each
+    /// statement, in order.
+    ///
+    /// All the statements are prepared before the first one is executed, so
+    /// a statement cannot refer to a table created by a previous one (use
+    /// `batch` instead).
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, ResultSet};
+    /// fn dashboard(conn: &Connection, user_id: i64) -> Result<Vec<ResultSet>> {
+    ///     conn.query_multi(
+    ///         "SELECT count(*) FROM orders WHERE user_id = ?;
+    ///          SELECT id, title FROM messages WHERE user_id = ? AND unread;",
+    ///         &[&[&user_id], &[&user_id]],
+    ///     )
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if there are more parameter lists than statements
+    /// (before executing any statement), if `sql` cannot be converted to a C-compatible string or if a
+    /// statement fails to prepare or execute.
+    pub fn query_multi(
+        &self,
+        mut sql: &str,
+        params_per_stmt: &[&[&dyn ToSql]],
+    ) -> Result<Vec<ResultSet>> {
+        // Prepare all the statements first, so that nothing is executed if
+        // the parameter lists do not match them.
+        let mut stmts = Vec::new();
+        loop {
+            sql = skip_empty_statements(sql);
+            if sql.is_empty() {
+                break;
+            }
+            let (stmt, tail) = self.prepare_with_tail(sql)?;
+            sql = &sql[tail..];
+            if !stmt.stmt.is_null() {
+                stmts.push(stmt);
+            }
+        }
+        if params_per_stmt.len() > stmts.len() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some(format!(
+                    "{} parameter lists for {} statements",
+                    params_per_stmt.len(),
+                    stmts.len()
+                )),
+            ));
+        }
+        let mut result_sets = Vec::with_capacity(stmts.len());
+        for (i, mut stmt) in stmts.into_iter().enumerate() {
+            let params = params_per_stmt.get(i).cloned().unwrap_or_default();
+            let columns = stmt.column_names().into_iter().map(String::from).collect();
+            let mut rows = Vec::new();
+            {
+                let mut query = stmt.query(params)?;
+                while let Some(row) = query.next()? {
+                    rows.push(row.to_owned());
+                }
+            }
+            result_sets.push(ResultSet { columns, rows });
+        }
+        Ok(result_sets)
+    }
+
+    /// Prepare a single SQL statement, like `prepare`, but fail if `sql`
+    /// contains anything else than whitespace, comments or semicolons after
+    /// the first statement (which `prepare` silently ignores).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::MultipleStatement)` with the trailing SQL if
+    /// `sql` contains more than one statement, or `Err` if `sql` cannot be
+    /// converted to a C-compatible string or if the underlying SQLite call
+    /// fails.
+    pub fn prepare_single(&self, sql: &str) -> Result<Statement<'_>> {
+        let (stmt, tail) = self.prepare_with_tail(sql)?;
+        let trailing = skip_empty_statements(&sql[tail..]);
+        if !trailing.is_empty() {
+            return Err(Error::MultipleStatement(trailing.to_owned()));
+        }
+        Ok(stmt)
+    }
+
+    /// Prepare all the SQL statements of `sql`, skipping empty statements.
+    ///
+    /// Statements are only prepared, not executed, so a statement cannot
+    /// refer to a table created by a previous one: use `execute_script` to
+    /// run such scripts.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if a statement fails to prepare.
+    pub fn prepare_multi(&self, mut sql: &str) -> Result<Vec<Statement<'_>>> {
+        let mut stmts = Vec::new();
+        loop {
+            sql = skip_empty_statements(sql);
+            if sql.is_empty() {
+                return Ok(stmts);
+            }
+            let (stmt, tail) = self.prepare_with_tail(sql)?;
+            if !stmt.stmt.is_null() {
+                stmts.push(stmt);
+            }
+            sql = &sql[tail..];
+        }
+    }
+
+    fn prepare_with_tail(&self, sql: &str) -> Result<(Statement<'_>, usize)> {
+        self.flush_before(sql)?;
+        self.db.borrow_mut().prepare_with_tail(self, sql)
+    }
+
+    fn execute_script_(&self, mut sql: &str, dry_run: bool) -> Result<usize> {
+        let mut count = 0;
+        while !sql.is_empty() {
+            let (stmt, tail) = self.db.borrow_mut().prepare_with_tail(self, sql)?;
+            if !stmt.stmt.is_null() {
+                if !dry_run || is_schema_change(&sql[..tail]) {
+                    while stmt.step()? {}
+                    stmt.reset();
+                }
+                count += 1;
+            }
+            sql = &sql[tail..];
+        }
+        Ok(count)
+    }
+}
+
+/// Add the index and the text of the failing statement of a batch to the
+/// message of a SQLite error.
+fn in_statement(err: Error, index: usize, sql: &str) -> Error {
+    let context = |message: Option<String>, code: &dyn std::fmt::Display| {
+        Some(format!(
+            "{} (in statement {}: {})",
+            message.unwrap_or_else(|| code.to_string()),
+            index,
+            sql
+        ))
+    };
+    match err {
+        Error::SqliteFailure(code, message) => {
+            let message = context(message, &code);
+            Error::SqliteFailure(code, message)
+        }
+        Error::ReadOnly { reason, message } => {
+            let message = context(message, &ffi::Error::new(reason.extended_code()));
+            Error::ReadOnly { reason, message }
+        }
+        err => err,
+    }
+}
+
+/// Skip leading whitespace and comments (and semicolons, if `semicolons` is
+/// set).
+pub(crate) fn skip_comments(mut sql: &str, semicolons: bool) -> &str {
+    loop {
+        sql = sql.trim_start();
+        if sql.starts_with("--") {
+            sql = sql.find('\n').map_or("", |i| &sql[i..]);
+        } else if sql.starts_with("/*") {
+            sql = sql.find("*/").map_or("", |i| &sql[i + 2..]);
+        } else if semicolons && sql.starts_with(';') {
+            sql = &sql[1..];
+        } else {
+            return sql;
+        }
+    }
+}
+
+fn skip_empty_statements(sql: &str) -> &str {
+    skip_comments(sql, true)
+}
+
+/// Check if `sql` starts with `CREATE`, `DROP` or `ALTER`, ignoring leading
+/// whitespace and comments.
+fn is_schema_change(sql: &str) -> bool {
+    let sql = skip_comments(sql, false);
+    let keyword: String = sql.chars().take_while(char::is_ascii_alphabetic).collect();
+    ["CREATE", "DROP", "ALTER"]
+        .iter()
+        .any(|k| keyword.eq_ignore_ascii_case(k))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_schema_change, ScriptOptions};
+    use crate::{Connection, Error, ErrorCode, NO_PARAMS};
+
+    const SCRIPT: &str = "CREATE TABLE foo(x INTEGER);
+        -- comment
+        INSERT INTO foo VALUES(1);
+        /* comment */ CREATE INDEX foo_x ON foo(x);
+        INSERT INTO foo VALUES(2);";
+
+    fn count(db: &Connection) -> i64 {
+        db.query_row(
+            "SELECT count(*) FROM sqlite_master WHERE name LIKE 'foo%'",
+            NO_PARAMS,
+            |r| r.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_is_schema_change() {
+        assert!(is_schema_change("create table foo(x)"));
+        assert!(is_schema_change(" -- c\n /* c */ DROP TABLE foo"));
+        assert!(!is_schema_change("INSERT INTO foo VALUES(1)"));
+        assert!(!is_schema_change("CREATED"));
+    }
+
+    #[test]
+    fn test_execute_script() {
+        let db = Connection::open_in_memory().unwrap();
+        assert_eq!(
+            4,
+            db.execute_script(SCRIPT, ScriptOptions::default()).unwrap()
+        );
+        let sum: i64 = db
+            .query_row("SELECT sum(x) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(3, sum);
+        assert_eq!(
+            0,
+            db.execute_script("  ", ScriptOptions::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_execute_script_transaction() {
+        let db = Connection::open_in_memory().unwrap();
+        let options = ScriptOptions {
+            transaction: true,
+            ..Default::default()
+        };
+        assert!(db
+            .execute_script("CREATE TABLE foo(x); INSERT INTO bar VALUES(1);", options)
+            .is_err());
+        assert_eq!(0, count(&db));
+        assert!(db.is_autocommit());
+    }
+
+    #[test]
+    fn test_execute_script_dry_run() {
+        let db = Connection::open_in_memory().unwrap();
+        let options = ScriptOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        assert_eq!(4, db.execute_script(SCRIPT, options).unwrap());
+        assert_eq!(0, count(&db));
+
+        assert!(db
+            .execute_script(
+                "CREATE TABLE foo(x); INSERT INTO foo(y) VALUES(1);",
+                options
+            )
+            .is_err());
+        assert!(db.execute_script("CREATE TABLE (", options).is_err());
+        assert_eq!(0, count(&db));
+    }
+
+    #[test]
+    fn test_prepare_single() {
+        let db = Connection::open_in_memory().unwrap();
+        db.prepare_single("SELECT 1").unwrap();
+        db.prepare_single("SELECT 1; -- comment\n ; /* comment */")
+            .unwrap();
+        match db.prepare_single("SELECT 1; SELECT 2;").map(|_| ()) {
+            Err(Error::MultipleStatement(trailing)) => assert_eq!("SELECT 2;", trailing),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_prepare_multi() {
+        let db = Connection::open_in_memory().unwrap();
+        let stmts = db
+            .prepare_multi("SELECT 1; ; -- comment\n SELECT 2")
+            .unwrap();
+        let values: Vec<i64> = stmts
+            .into_iter()
+            .map(|mut stmt| stmt.query_row(NO_PARAMS, |r| r.get(0)).unwrap())
+            .collect();
+        assert_eq!(vec![1, 2], values);
+        assert!(db.prepare_multi(" -- nothing").unwrap().is_empty());
+        assert!(db.prepare_multi("SELECT 1; SELEC 2").is_err());
+    }
+
+    #[test]
+    fn test_query_multi() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER, y TEXT);")
+            .unwrap();
+        // Too many parameter lists: nothing is executed
+        assert!(db
+            .query_multi(
+                "INSERT INTO foo VALUES(?, 'a'); SELECT 1;",
+                &[&[&1], &[], &[]],
+            )
+            .is_err());
+        let result_sets = db
+            .query_multi(
+                "INSERT INTO foo VALUES(?, 'a'), (?, 'b');
+                 SELECT x, y FROM foo WHERE x > ? ORDER BY x; -- comment
+                 SELECT count(*) AS n FROM foo;",
+                &[&[&1, &2], &[&0]],
+            )
+            .unwrap();
+        assert_eq!(3, result_sets.len());
+        assert!(result_sets[0].columns.is_empty());
+        assert!(result_sets[0].rows.is_empty());
+        assert_eq!(vec!["x", "y"], result_sets[1].columns);
+        let rows: Vec<(i64, String)> = result_sets[1]
+            .rows
+            .iter()
+            .map(|row| (row.get(0).unwrap(), row.get(1).unwrap()))
+            .collect();
+        assert_eq!(vec![(1, "a".to_owned()), (2, "b".to_owned())], rows);
+        assert_eq!(Ok(2), result_sets[2].rows[0].get_by_name::<i64>("n"));
+
+        assert!(db.query_multi("SELECT 1", &[&[], &[]]).is_err());
+        assert!(db.query_multi("SELECT 1; SELEC 2", &[]).is_err());
+    }
+
+    #[test]
+    fn test_batch() {
+        let db = Connection::open_in_memory().unwrap();
+        let mut batch = db.batch(
+            "CREATE TABLE foo(x INTEGER); ; -- comment
+             INSERT INTO foo VALUES(?);
+             SELECT sum(x) FROM foo WHERE x > ?;
+             SELECT * FROM bar;
+             SELECT 1;",
+        );
+        batch.next().unwrap().unwrap().execute(NO_PARAMS).unwrap();
+        let mut insert = batch.next().unwrap().unwrap();
+        insert.execute([1]).unwrap();
+        insert.execute([2]).unwrap();
+        let sum: i64 = batch
+            .next()
+            .unwrap()
+            .unwrap()
+            .query_row([1], |r| r.get(0))
+            .unwrap();
+        assert_eq!(2, sum);
+        assert!(batch.next().unwrap().is_err());
+        assert!(batch.next().is_none());
+
+        assert_eq!(0, db.batch(" ; -- comment").count());
+    }
+
+    #[test]
+    fn test_execute_batch_error_context() {
+        let db = Connection::open_in_memory().unwrap();
+        let err = db
+            .execute_batch(
+                "CREATE TABLE foo(x INTEGER UNIQUE); SELECT 1;
+                 INSERT INTO foo VALUES(1); INSERT INTO foo VALUES(1);
+                 INSERT INTO foo VALUES(2);",
+            )
+            .unwrap_err();
+        match err {
+            Error::SqliteFailure(err, Some(msg)) => {
+                assert_eq!(ErrorCode::ConstraintViolation, err.code);
+                assert!(
+                    msg.ends_with("(in statement 3: INSERT INTO foo VALUES(1);)"),
+                    "{}",
+                    msg
+                );
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+        let count: i64 = db
+            .query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(1, count);
+
+        let msg = db
+            .execute_batch("SELECT 1; SELEC 2")
+            .unwrap_err()
+            .to_string();
+        assert!(msg.contains("(in statement 1: SELEC 2)"), "{}", msg);
+    }
+
+    #[test]
+    #[cfg(feature = "functions")]
+    fn test_execute_batch_interrupt() {
+        let db = Connection::open_in_memory().unwrap();
+        let handle = db.get_interrupt_handle();
+        db.create_scalar_function("interrupt", 0, false, move |_| {
+            handle.interrupt();
+            Ok(0)
+        })
+        .unwrap();
+        let err = db
+            .execute_batch(
+                "CREATE TABLE foo(x INTEGER); SELECT interrupt();
+                 INSERT INTO foo VALUES(1);",
+            )
+            .unwrap_err();
+        match err {
+            Error::SqliteFailure(err, Some(msg)) => {
+                assert_eq!(ErrorCode::OperationInterrupted, err.code);
+                assert!(msg.contains("(in statement 1: "), "{}", msg);
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+        // A previous interrupt does not affect the next batch.
+        db.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
+        let count: i64 = db
+            .query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(1, count);
+    }
+}
@@ -0,0 +1,163 @@
+//! `AUTOINCREMENT` sequences.
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+use crate::ffi;
+use crate::types::ToSql;
+use crate::{str_to_cstring, Connection, Error, OptionalExtension, Result, NO_PARAMS};
+
+impl Connection {
+    /// Return the largest rowid ever used by the `AUTOINCREMENT` table
+    /// `table` of the main database (see
+    /// [`sqlite_sequence`](https://sqlite.org/autoinc.html)), or `None` if no
+    /// row has been inserted in it yet (or if it has no `AUTOINCREMENT`
+    /// column).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn current_sequence(&self, table: &str) -> Result<Option<i64>> {
+        if !self.has_sequence_table()? {
+            return Ok(None);
+        }
+        self.query_row(
+            "SELECT seq FROM main.sqlite_sequence WHERE name = ?1 COLLATE NOCASE",
+            &[table],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    /// Set the largest rowid used by the `AUTOINCREMENT` table `table` of the
+    /// main database, so that the next row inserted without a rowid gets
+    /// `value + 1` (or the largest rowid of the table plus one, if greater),
+    /// e.g. to restart ids after clearing a table in tests, or to preserve id
+    /// ranges when importing data.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::NotAutoincrementTable)` if `table` is not an
+    /// `AUTOINCREMENT` table of the main database, and `Err` if the
+    /// underlying SQLite calls fail.
+    pub fn reset_sequence(&self, table: &str, value: i64) -> Result<()> {
+        // The name as declared, which is the one in `sqlite_sequence`.
+        let name: Option<String> = if self.has_sequence_table()? {
+            self.query_row(
+                "SELECT name FROM main.sqlite_master
+                 WHERE type = 'table' AND name = ?1 COLLATE NOCASE",
+                &[table],
+                |row| row.get(0),
+            )
+            .optional()?
+        } else {
+            None
+        };
+        let name = match name {
+            Some(ref name) if self.is_autoincrement(name)? => name.clone(),
+            _ => return Err(Error::NotAutoincrementTable(table.to_owned())),
+        };
+        self.execute_internal("SAVEPOINT _rusqlite_sequence")?;
+        let r = self.reset_sequence_(&name, value);
+        if r.is_err() {
+            self.execute_internal("ROLLBACK TO _rusqlite_sequence")?;
+        }
+        self.execute_internal("RELEASE _rusqlite_sequence")?;
+        r
+    }
+
+    fn reset_sequence_(&self, name: &str, value: i64) -> Result<()> {
+        let updated = self.execute(
+            "UPDATE main.sqlite_sequence SET seq = ?2 WHERE name = ?1",
+            &[&name as &dyn ToSql, &value],
+        )?;
+        if updated == 0 {
+            self.execute(
+                "INSERT INTO main.sqlite_sequence(name, seq) VALUES(?1, ?2)",
+                &[&name as &dyn ToSql, &value],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Whether the `INTEGER PRIMARY KEY` of `table`, in the main database, is
+    /// declared `AUTOINCREMENT`.
+    fn is_autoincrement(&self, table: &str) -> Result<bool> {
+        let c_table = str_to_cstring(table)?;
+        let c = self.db.borrow();
+        // The rowid aliases designate the `INTEGER PRIMARY KEY`, if any,
+        // unless a column has the same name.
+        for alias in &[&b"rowid\0"[..], b"oid\0", b"_rowid_\0"] {
+            let mut autoinc: c_int = 0;
+            let r = unsafe {
+                ffi::sqlite3_table_column_metadata(
+                    c.db(),
+                    b"main\0".as_ptr() as *const c_char,
+                    c_table.as_ptr(),
+                    alias.as_ptr() as *const c_char,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    &mut autoinc,
+                )
+            };
+            if r == ffi::SQLITE_OK && autoinc != 0 {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// `sqlite_sequence` is created with the first `AUTOINCREMENT` table.
+    fn has_sequence_table(&self) -> Result<bool> {
+        self.query_row(
+            "SELECT count(*) FROM main.sqlite_master WHERE name = 'sqlite_sequence'",
+            NO_PARAMS,
+            |row| row.get::<_, i64>(0),
+        )
+        .map(|count| count > 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Connection, Error, NO_PARAMS};
+
+    #[test]
+    fn test_sequence() {
+        let db = Connection::open_in_memory().unwrap();
+        assert_eq!(None, db.current_sequence("foo").unwrap());
+        assert!(db.reset_sequence("foo", 1).is_err());
+
+        db.execute_batch(
+            "CREATE TABLE Foo(id INTEGER PRIMARY KEY AUTOINCREMENT, x TEXT);
+             CREATE TABLE bar(id INTEGER PRIMARY KEY, x TEXT);",
+        )
+        .unwrap();
+        assert_eq!(None, db.current_sequence("foo").unwrap());
+        assert_eq!(
+            Err(Error::NotAutoincrementTable("bar".to_owned())),
+            db.reset_sequence("bar", 1)
+        );
+
+        db.reset_sequence("foo", 100).unwrap();
+        assert_eq!(Some(100), db.current_sequence("foo").unwrap());
+        db.execute("INSERT INTO foo(x) VALUES('a')", NO_PARAMS)
+            .unwrap();
+        assert_eq!(101, db.last_insert_rowid());
+        assert_eq!(Some(101), db.current_sequence("FOO").unwrap());
+
+        // A table whose SQL mentions AUTOINCREMENT elsewhere.
+        db.execute_batch(
+            "CREATE TABLE baz(id INTEGER PRIMARY KEY, note TEXT DEFAULT 'AUTOINCREMENT')",
+        )
+        .unwrap();
+        assert!(db.reset_sequence("baz", 1).is_err());
+
+        db.execute_batch("DELETE FROM foo").unwrap();
+        db.reset_sequence("foo", 0).unwrap();
+        db.execute("INSERT INTO foo(x) VALUES('b')", NO_PARAMS)
+            .unwrap();
+        assert_eq!(1, db.last_insert_rowid());
+    }
+}
@@ -0,0 +1,164 @@
+//! Serialize a database into a byte buffer, and restore it.
+//!
+//! This requires SQLite to be compiled with `SQLITE_ENABLE_DESERIALIZE`
+//! (SQLite 3.23.0 or later): the `serialize` feature implies `bundled`,
+//! which does.
+use std::os::raw::{c_uint, c_void};
+use std::ptr;
+use std::slice;
+
+use crate::ffi;
+use crate::{Connection, DatabaseName, Error, Result};
+
+/// How a database restored by `Connection::deserialize` can be modified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeserializeMode {
+    /// The database is read-only (`SQLITE_DESERIALIZE_READONLY`).
+    ReadOnly,
+    /// The database can be modified, but cannot grow beyond its initial size.
+    Fixed,
+    /// The database can be modified and can grow
+    /// (`SQLITE_DESERIALIZE_RESIZEABLE`).
+    Resizeable,
+}
+
+impl DeserializeMode {
+    fn flags(self) -> c_uint {
+        let flags = match self {
+            DeserializeMode::ReadOnly => ffi::SQLITE_DESERIALIZE_READONLY,
+            DeserializeMode::Fixed => 0,
+            DeserializeMode::Resizeable => ffi::SQLITE_DESERIALIZE_RESIZEABLE,
+        };
+        (flags | ffi::SQLITE_DESERIALIZE_FREEONCLOSE) as c_uint
+    }
+}
+
+impl Connection {
+    /// Return the content of the database `schema` as it would be stored in a
+    /// database file, e.g. to snapshot an in-memory database.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `schema` is not attached, or if the underlying
+    /// SQLite call fails.
+    pub fn serialize(&self, schema: DatabaseName<'_>) -> Result<Vec<u8>> {
+        let schema = schema.to_cstring()?;
+        let db = self.db.borrow();
+        let mut size: ffi::sqlite3_int64 = 0;
+        let data = unsafe { ffi::sqlite3_serialize(db.db(), schema.as_ptr(), &mut size, 0) };
+        if data.is_null() {
+            // An empty database has no content.
+            return if size == 0 {
+                Ok(Vec::new())
+            } else {
+                Err(Error::SqliteFailure(
+                    ffi::Error::new(if size < 0 {
+                        ffi::SQLITE_ERROR
+                    } else {
+                        ffi::SQLITE_NOMEM
+                    }),
+                    Some("cannot serialize the database".to_owned()),
+                ))
+            };
+        }
+        let content = unsafe { slice::from_raw_parts(data, size as usize) }.to_vec();
+        unsafe { ffi::sqlite3_free(data as *mut c_void) };
+        Ok(content)
+    }
+
+    /// Replace the database `schema` by an in-memory database holding a copy
+    /// of `data`, the content of a database file (e.g. returned by
+    /// `serialize`).
+    ///
+    /// The prepared statement cache is flushed: the statements of the
+    /// previous database must not be in use.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, DatabaseName, DeserializeMode, Result};
+    /// fn clone_in_memory(src: &Connection) -> Result<Connection> {
+    ///     let data = src.serialize(DatabaseName::Main)?;
+    ///     let db = Connection::open_in_memory()?;
+    ///     db.deserialize(DatabaseName::Main, &data, DeserializeMode::Resizeable)?;
+    ///     Ok(db)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `schema` is not attached, if the connection is
+    /// busy reading or writing it, or if the underlying SQLite call fails.
+    pub fn deserialize(
+        &self,
+        schema: DatabaseName<'_>,
+        data: &[u8],
+        mode: DeserializeMode,
+    ) -> Result<()> {
+        let schema = schema.to_cstring()?;
+        self.flush_prepared_statement_cache();
+        let mut db = self.db.borrow_mut();
+        let size = data.len() as ffi::sqlite3_int64;
+        // SQLite frees the buffer when the database is closed (or on failure).
+        let buf = unsafe { ffi::sqlite3_malloc64(data.len().max(1) as ffi::sqlite3_uint64) };
+        if buf.is_null() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_NOMEM),
+                None,
+            ));
+        }
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), buf as *mut u8, data.len());
+        }
+        let r = unsafe {
+            ffi::sqlite3_deserialize(
+                db.db(),
+                schema.as_ptr(),
+                buf as *mut u8,
+                size,
+                size,
+                mode.flags(),
+            )
+        };
+        db.decode_result(r)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DeserializeMode;
+    use crate::{Connection, DatabaseName, NO_PARAMS};
+
+    #[test]
+    fn test_serialize_deserialize() {
+        let src = Connection::open_in_memory().unwrap();
+        assert!(src.serialize(DatabaseName::Main).unwrap().is_empty());
+        src.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES(42);")
+            .unwrap();
+        let data = src.serialize(DatabaseName::Main).unwrap();
+        assert!(data.starts_with(b"SQLite format 3\0"));
+
+        let db = Connection::open_in_memory().unwrap();
+        db.deserialize(DatabaseName::Main, &data, DeserializeMode::Resizeable)
+            .unwrap();
+        let x: i64 = db
+            .query_row("SELECT x FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(42, x);
+        db.execute_batch("INSERT INTO foo SELECT x FROM foo; INSERT INTO foo SELECT x FROM foo;")
+            .unwrap();
+
+        let db = Connection::open_in_memory().unwrap();
+        db.deserialize(DatabaseName::Main, &data, DeserializeMode::ReadOnly)
+            .unwrap();
+        assert!(db.execute("INSERT INTO foo VALUES(1)", NO_PARAMS).is_err());
+
+        assert!(db
+            .deserialize(
+                DatabaseName::Attached("nope"),
+                &data,
+                DeserializeMode::Fixed
+            )
+            .is_err());
+    }
+}
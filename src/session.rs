@@ -15,8 +15,8 @@ use fallible_streaming_iterator::FallibleStreamingIterator;
 use crate::error::error_from_sqlite_code;
 use crate::ffi;
 use crate::hooks::Action;
-use crate::types::ValueRef;
-use crate::{errmsg_to_string, str_to_cstring, Connection, DatabaseName, Result};
+use crate::types::{Type, ValueRef};
+use crate::{errmsg_to_string, str_to_cstring, Connection, DatabaseName, Error, Result};
 
 // https://sqlite.org/session.html
 
@@ -100,15 +100,36 @@ impl Session<'_> {
 
     /// Attach a table. `None` means all tables.
     pub fn attach(&mut self, table: Option<&str>) -> Result<()> {
-        let table = if let Some(table) = table {
-            str_to_cstring(table)?.as_ptr()
-        } else {
-            ptr::null()
+        let table = match table {
+            Some(table) => Some(str_to_cstring(table)?),
+            None => None,
         };
+        let table = table.as_ref().map_or(ptr::null(), |table| table.as_ptr());
         unsafe { check!(ffi::sqlite3session_attach(self.s, table)) };
         Ok(())
     }
 
+    /// Attach all the tables whose name matches one of the `GLOB` patterns
+    /// (e.g. `"sync_*"`), including tables created later.
+    ///
+    /// This replaces the table filter (see `table_filter`).
+    pub fn attach_patterns(&mut self, patterns: &[&str]) -> Result<()> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| str_to_cstring(pattern))
+            .collect::<Result<Vec<_>>>()?;
+        self.table_filter(Some(move |table: &str| {
+            let table = match str_to_cstring(table) {
+                Ok(table) => table,
+                Err(_) => return false,
+            };
+            patterns.iter().any(|pattern| unsafe {
+                ffi::sqlite3_strglob(pattern.as_ptr(), table.as_ptr()) == 0
+            })
+        }));
+        self.attach(None)
+    }
+
     /// Generate a Changeset
     pub fn changeset(&mut self) -> Result<Changeset> {
         let mut n = 0;
@@ -267,6 +288,7 @@ impl Changeset {
             phantom: PhantomData,
             it,
             item: None,
+            input: None,
         })
     }
 
@@ -277,6 +299,55 @@ impl Changeset {
         check!(unsafe { ffi::sqlite3changeset_concat(a.n, a.cs, b.n, b.cs, &mut n, &mut cs) });
         Ok(Changeset { cs, n })
     }
+
+    /// Size of the changeset in bytes, i.e. the number of bytes to send to
+    /// replicate it.
+    pub fn len_bytes(&self) -> usize {
+        self.n as usize
+    }
+
+    /// Number of operations (inserted, updated or deleted rows) in the
+    /// changeset.
+    pub fn op_count(&self) -> Result<usize> {
+        Ok(self
+            .table_summaries()?
+            .iter()
+            .map(TableChanges::op_count)
+            .sum())
+    }
+
+    /// Number of operations in the changeset for each table, in the order the
+    /// tables appear in the changeset.
+    ///
+    /// Only the operation of each change is decoded, not its values.
+    pub fn table_summaries(&self) -> Result<Vec<TableChanges>> {
+        let mut summaries: Vec<TableChanges> = Vec::new();
+        let mut iter = self.iter()?;
+        while let Some(item) = iter.next()? {
+            let op = item.op()?;
+            // Changes are grouped by table.
+            let new_table = match summaries.last() {
+                Some(last) => last.table != op.table_name(),
+                None => true,
+            };
+            if new_table {
+                summaries.push(TableChanges {
+                    table: op.table_name().to_owned(),
+                    inserts: 0,
+                    updates: 0,
+                    deletes: 0,
+                });
+            }
+            let summary = summaries.last_mut().unwrap();
+            match op.code() {
+                Action::SQLITE_INSERT => summary.inserts += 1,
+                Action::SQLITE_UPDATE => summary.updates += 1,
+                Action::SQLITE_DELETE => summary.deletes += 1,
+                Action::UNKNOWN => {}
+            }
+        }
+        Ok(summaries)
+    }
 }
 
 impl Drop for Changeset {
@@ -287,29 +358,54 @@ impl Drop for Changeset {
     }
 }
 
+/// Number of operations of each kind on one table in a changeset (see
+/// `Changeset::table_summaries`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableChanges {
+    /// Name of the table.
+    pub table: String,
+    /// Number of inserted rows.
+    pub inserts: usize,
+    /// Number of updated rows.
+    pub updates: usize,
+    /// Number of deleted rows.
+    pub deletes: usize,
+}
+
+impl TableChanges {
+    /// Total number of operations on the table.
+    pub fn op_count(&self) -> usize {
+        self.inserts + self.updates + self.deletes
+    }
+}
+
 /// Cursor for iterating over the elements of a changeset or patchset.
 pub struct ChangesetIter<'changeset> {
     phantom: PhantomData<&'changeset ()>,
     it: *mut ffi::sqlite3_changeset_iter,
     item: Option<ChangesetItem>,
+    // The input of `start_strm`, read by `x_input` until the iterator is
+    // finalized: boxed for a stable address.
+    input: Option<Box<&'changeset mut dyn Read>>,
 }
 
 impl ChangesetIter<'_> {
     /// Create an iterator on `input`
     pub fn start_strm<'input>(input: &'input mut dyn Read) -> Result<ChangesetIter<'input>> {
-        let input_ref = &input;
+        let mut input = Box::new(input);
         let mut it: *mut ffi::sqlite3_changeset_iter = unsafe { mem::uninitialized() };
         check!(unsafe {
             ffi::sqlite3changeset_start_strm(
                 &mut it,
                 Some(x_input),
-                input_ref as *const &mut dyn Read as *mut c_void,
+                &mut *input as *mut &mut dyn Read as *mut c_void,
             )
         });
         Ok(ChangesetIter {
             phantom: PhantomData,
             it,
             item: None,
+            input: Some(input),
         })
     }
 }
@@ -391,7 +487,8 @@ impl ChangesetItem {
                 col as i32,
                 &mut p_value
             ));
-            Ok(ValueRef::from_value(p_value))
+            ValueRef::from_value_checked(p_value)
+                .map_err(|err| Error::FromSqlConversionFailure(col, Type::Text, Box::new(err)))
         }
     }
 
@@ -415,7 +512,8 @@ impl ChangesetItem {
         unsafe {
             let mut p_value: *mut ffi::sqlite3_value = mem::uninitialized();
             check!(ffi::sqlite3changeset_new(self.it, col as i32, &mut p_value));
-            Ok(ValueRef::from_value(p_value))
+            ValueRef::from_value_checked(p_value)
+                .map_err(|err| Error::FromSqlConversionFailure(col, Type::Text, Box::new(err)))
         }
     }
 
@@ -427,7 +525,8 @@ impl ChangesetItem {
         unsafe {
             let mut p_value: *mut ffi::sqlite3_value = mem::uninitialized();
             check!(ffi::sqlite3changeset_old(self.it, col as i32, &mut p_value));
-            Ok(ValueRef::from_value(p_value))
+            ValueRef::from_value_checked(p_value)
+                .map_err(|err| Error::FromSqlConversionFailure(col, Type::Text, Box::new(err)))
         }
     }
 
@@ -535,6 +634,11 @@ impl Drop for Changegroup {
 
 impl Connection {
     /// Apply a changeset to a database
+    ///
+    /// If `filter` is not `None`, it is called with the name of each table
+    /// which has changes in the changeset, and only the changes of the tables
+    /// for which it returns `true` are applied (e.g. to skip local-only
+    /// tables). `conflict` is called to resolve each conflict.
     pub fn apply<F, C>(&self, cs: &Changeset, filter: Option<F>, conflict: C) -> Result<()>
     where
         F: Fn(&str) -> bool + Send + RefUnwindSafe + 'static,
@@ -568,7 +672,8 @@ impl Connection {
         Ok(())
     }
 
-    /// Apply a changeset to a database
+    /// Apply a changeset read from `input` to a database (see `apply` for
+    /// `filter` and `conflict`)
     pub fn apply_strm<F, C>(
         &self,
         input: &mut dyn Read,
@@ -689,7 +794,7 @@ unsafe extern "C" fn x_input(p_in: *mut c_void, data: *mut c_void, len: *mut c_i
     if p_in.is_null() {
         return ffi::SQLITE_MISUSE;
     }
-    let bytes: &mut [u8] = from_raw_parts_mut(data as *mut u8, len as usize);
+    let bytes: &mut [u8] = from_raw_parts_mut(data as *mut u8, *len as usize);
     let input = p_in as *mut &mut dyn Read;
     match (*input).read(bytes) {
         Ok(n) => {
@@ -720,9 +825,9 @@ mod test {
     use fallible_streaming_iterator::FallibleStreamingIterator;
     use std::sync::atomic::{AtomicBool, Ordering};
 
-    use super::{Changeset, ChangesetIter, ConflictAction, ConflictType, Session};
+    use super::{Changeset, ChangesetIter, ConflictAction, ConflictType, Session, TableChanges};
     use crate::hooks::Action;
-    use crate::Connection;
+    use crate::{Connection, NO_PARAMS};
 
     fn one_changeset() -> Changeset {
         let db = Connection::open_in_memory().unwrap();
@@ -777,6 +882,53 @@ mod test {
         assert_eq!(Ok("bar"), new_value.as_str());
     }
 
+    #[test]
+    fn test_changeset_summary() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(t TEXT PRIMARY KEY NOT NULL);
+             CREATE TABLE bar(i INTEGER PRIMARY KEY, x TEXT);
+             INSERT INTO bar VALUES(1, 'a'), (2, 'b');",
+        )
+        .unwrap();
+
+        let mut session = Session::new(&db).unwrap();
+        session.attach(None).unwrap();
+        db.execute_batch(
+            "INSERT INTO foo VALUES('x'), ('y');
+             UPDATE bar SET x = 'c' WHERE i = 1;
+             DELETE FROM bar WHERE i = 2;
+             INSERT INTO bar VALUES(3, 'd');",
+        )
+        .unwrap();
+        let changeset = session.changeset().unwrap();
+
+        assert_eq!(5, changeset.op_count().unwrap());
+        let mut summaries = changeset.table_summaries().unwrap();
+        summaries.sort_by(|a, b| a.table.cmp(&b.table));
+        assert_eq!(
+            vec![
+                TableChanges {
+                    table: "bar".to_owned(),
+                    inserts: 1,
+                    updates: 1,
+                    deletes: 1,
+                },
+                TableChanges {
+                    table: "foo".to_owned(),
+                    inserts: 2,
+                    updates: 0,
+                    deletes: 0,
+                },
+            ],
+            summaries
+        );
+
+        let mut output = Vec::new();
+        session.changeset_strm(&mut output).unwrap();
+        assert_eq!(output.len(), changeset.len_bytes());
+    }
+
     #[test]
     fn test_changeset_strm() {
         let output = one_changeset_strm();
@@ -834,6 +986,41 @@ mod test {
         assert!(CALLED.load(Ordering::Relaxed));
     }
 
+    #[test]
+    fn test_changeset_apply_filter() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = "CREATE TABLE foo(t TEXT PRIMARY KEY NOT NULL);
+                   CREATE TABLE bar(t TEXT PRIMARY KEY NOT NULL);";
+        db.execute_batch(sql).unwrap();
+        let changeset = {
+            let mut session = Session::new(&db).unwrap();
+            session.attach(None).unwrap();
+            db.execute_batch("INSERT INTO foo VALUES('a'); INSERT INTO bar VALUES('b');")
+                .unwrap();
+            session.changeset().unwrap()
+        };
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(sql).unwrap();
+        db.apply(
+            &changeset,
+            Some(|table: &str| table != "bar"),
+            |_conflict_type, _item| ConflictAction::SQLITE_CHANGESET_ABORT,
+        )
+        .unwrap();
+
+        let count = |table: &str| -> i64 {
+            db.query_row(
+                &format!("SELECT count(*) FROM {}", table),
+                NO_PARAMS,
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(1, count("foo"));
+        assert_eq!(0, count("bar"));
+    }
+
     #[test]
     fn test_changeset_apply_strm() {
         let output = one_changeset_strm();
@@ -857,6 +1044,31 @@ mod test {
         assert_eq!(1, check);
     }
 
+    #[test]
+    fn test_attach_patterns() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE sync_foo(t TEXT PRIMARY KEY NOT NULL);
+             CREATE TABLE local_foo(t TEXT PRIMARY KEY NOT NULL);",
+        )
+        .unwrap();
+
+        let mut session = Session::new(&db).unwrap();
+        session.attach_patterns(&["sync_*"]).unwrap();
+        db.execute("INSERT INTO local_foo (t) VALUES (?);", &["bar"])
+            .unwrap();
+        assert!(session.is_empty());
+        db.execute("INSERT INTO sync_foo (t) VALUES (?);", &["bar"])
+            .unwrap();
+        assert!(!session.is_empty());
+
+        let changeset = session.changeset().unwrap();
+        let mut iter = changeset.iter().unwrap();
+        while let Some(item) = iter.next().unwrap() {
+            assert_eq!("sync_foo", item.op().unwrap().table_name());
+        }
+    }
+
     #[test]
     fn test_session_empty() {
         let db = Connection::open_in_memory().unwrap();
@@ -0,0 +1,355 @@
+//! Validation of untrusted SQL before it is prepared.
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::os::raw::c_int;
+
+use crate::ffi;
+use crate::inner_connection::InnerConnection;
+use crate::{Connection, Error, Result};
+
+/// Checks run on the SQL of a connection handling untrusted input, before
+/// it reaches `sqlite3_prepare` (see `Connection::set_sql_guard`).
+///
+/// This complements an authorizer (e.g. a `Sandbox`): oversized or deeply
+/// nested SQL is rejected without being parsed, and blocked keywords are
+/// rejected wherever they appear outside of literals, quoted identifiers
+/// and comments (even as the name of a column). Blocking `PRAGMA` also
+/// blocks the `pragma_*` table-valued functions (e.g.
+/// `SELECT * FROM pragma_table_info('t')`).
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, SqlGuard};
+/// fn untrusted(conn: &Connection) {
+///     conn.set_sql_guard(Some(
+///         SqlGuard::new()
+///             .max_length(10_000)
+///             .max_depth(20)
+///             .max_expr_depth(100)
+///             .block_keywords(&["ATTACH", "DETACH", "PRAGMA"]),
+///     ));
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SqlGuard {
+    max_length: Option<usize>,
+    max_depth: Option<usize>,
+    max_expr_depth: Option<usize>,
+    keywords: HashSet<String>,
+}
+
+impl SqlGuard {
+    /// Create a guard accepting any SQL.
+    pub fn new() -> SqlGuard {
+        SqlGuard::default()
+    }
+
+    /// Reject SQL longer than `max` bytes. `SQLITE_LIMIT_SQL_LENGTH` is
+    /// also lowered while the guard is set.
+    pub fn max_length(mut self, max: usize) -> SqlGuard {
+        self.max_length = Some(max);
+        self
+    }
+
+    /// Reject SQL with parentheses nested more than `max` levels deep,
+    /// before it is parsed.
+    pub fn max_depth(mut self, max: usize) -> SqlGuard {
+        self.max_depth = Some(max);
+        self
+    }
+
+    /// Lower `SQLITE_LIMIT_EXPR_DEPTH`, the maximum depth of the parse tree
+    /// of an expression (which is not the nesting of parentheses: e.g.
+    /// `1 + 2 + 3` is 3 levels deep), to `max` while the guard is set.
+    pub fn max_expr_depth(mut self, max: usize) -> SqlGuard {
+        self.max_expr_depth = Some(max);
+        self
+    }
+
+    /// Reject SQL containing any of the given keywords (case-insensitive),
+    /// e.g. `ATTACH` or `PRAGMA` (which also rejects the `pragma_*`
+    /// table-valued functions).
+    pub fn block_keywords(mut self, keywords: &[&str]) -> SqlGuard {
+        self.keywords
+            .extend(keywords.iter().map(|k| k.to_ascii_uppercase()));
+        self
+    }
+
+    /// Check `sql` against the guard.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with a `SQLITE_TOOBIG` code if `sql` is too long or
+    /// too deeply nested, or with a `SQLITE_AUTH` code if it contains a
+    /// blocked keyword.
+    pub fn check(&self, sql: &str) -> Result<()> {
+        if let Some(max) = self.max_length {
+            if sql.len() > max {
+                return Err(rejected(
+                    ffi::SQLITE_TOOBIG,
+                    format!("SQL length {} exceeds {}", sql.len(), max),
+                ));
+            }
+        }
+        let mut depth = 0;
+        let mut chars = sql.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '(' => {
+                    depth += 1;
+                    if let Some(max) = self.max_depth {
+                        if depth > max {
+                            return Err(rejected(
+                                ffi::SQLITE_TOOBIG,
+                                format!("SQL nesting exceeds {}", max),
+                            ));
+                        }
+                    }
+                }
+                ')' => depth = depth.saturating_sub(1),
+                '\'' | '"' | '`' | '[' => {
+                    let end = if c == '[' { ']' } else { c };
+                    // A doubled quote is an escaped one: it is skipped as the
+                    // end of a literal followed by the start of another.
+                    for (_, c) in chars.by_ref() {
+                        if c == end {
+                            break;
+                        }
+                    }
+                }
+                '-' if sql[i..].starts_with("--") => {
+                    for (_, c) in chars.by_ref() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                '/' if sql[i..].starts_with("/*") => {
+                    chars.next();
+                    let mut star = false;
+                    for (_, c) in chars.by_ref() {
+                        if star && c == '/' {
+                            break;
+                        }
+                        star = c == '*';
+                    }
+                }
+                c if c.is_alphabetic() || c == '_' => {
+                    let mut end = sql.len();
+                    while let Some(&(j, c)) = chars.peek() {
+                        if !(c.is_alphanumeric() || c == '_' || c == '$') {
+                            end = j;
+                            break;
+                        }
+                        chars.next();
+                    }
+                    let word = sql[i..end].to_ascii_uppercase();
+                    if self.keywords.contains(&word)
+                        || (word.starts_with("PRAGMA_") && self.keywords.contains("PRAGMA"))
+                    {
+                        return Err(rejected(
+                            ffi::SQLITE_AUTH,
+                            format!("SQL keyword {} is not allowed", word),
+                        ));
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn limits(&self) -> Vec<(c_int, c_int)> {
+        let clamp = |max: usize| max.min(c_int::MAX as usize) as c_int;
+        let mut limits = Vec::new();
+        if let Some(max) = self.max_length {
+            limits.push((ffi::Limit::SQLITE_LIMIT_SQL_LENGTH as c_int, clamp(max)));
+        }
+        if let Some(max) = self.max_expr_depth {
+            limits.push((ffi::Limit::SQLITE_LIMIT_EXPR_DEPTH as c_int, clamp(max)));
+        }
+        limits
+    }
+}
+
+/// A guard set on a connection, with the limits it replaced.
+pub(crate) struct ActiveGuard {
+    pub(crate) guard: SqlGuard,
+    previous_limits: Vec<(c_int, c_int)>,
+    // SQL issued by this crate, being executed without being checked
+    internal_sql: RefCell<Option<String>>,
+}
+
+impl InnerConnection {
+    /// Check `sql` against the guard of the connection, if any.
+    pub(crate) fn check_sql(&self, sql: &str) -> Result<()> {
+        match self.sql_guard {
+            Some(ref active) => {
+                // The statements of the internal SQL are prepared one by one,
+                // from the start of each one to the end of the SQL.
+                if let Some(ref internal) = *active.internal_sql.borrow() {
+                    if internal.ends_with(sql) {
+                        return Ok(());
+                    }
+                }
+                active.guard.check(sql)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Execute `sql`, issued by this crate itself (e.g. transaction control
+    /// statements), without checking it against the guard.
+    pub(crate) fn execute_internal(&mut self, sql: &str) -> Result<()> {
+        let previous = self.exempt_sql(Some(sql.to_owned()));
+        let r = self.execute_batch(sql);
+        self.exempt_sql(previous);
+        r
+    }
+
+    fn exempt_sql(&self, sql: Option<String>) -> Option<String> {
+        match self.sql_guard {
+            Some(ref active) => active.internal_sql.replace(sql),
+            None => None,
+        }
+    }
+}
+
+fn rejected(code: c_int, msg: String) -> Error {
+    Error::SqliteFailure(ffi::Error::new(code), Some(msg))
+}
+
+impl Connection {
+    /// Execute `sql`, issued by this crate itself (e.g. transaction control
+    /// statements), like `execute_batch` but without checking it against the
+    /// guard.
+    pub(crate) fn execute_internal(&self, sql: &str) -> Result<()> {
+        let previous = self.db.borrow().exempt_sql(Some(sql.to_owned()));
+        let r = self.execute_batch(sql);
+        self.db.borrow().exempt_sql(previous);
+        r
+    }
+
+    /// Set (or clear, with `None`) the guard checking all the SQL prepared or
+    /// executed on this connection, e.g. a connection running user-provided
+    /// queries.
+    ///
+    /// The guard also applies to the SQL built from the arguments of methods
+    /// like `pragma_query_value`, and to the statements of the statement
+    /// cache when they are first prepared; statements already cached are
+    /// flushed. The fixed SQL issued by this crate itself (e.g. the
+    /// `SAVEPOINT` statements of `Connection::savepoint`) is not checked.
+    ///
+    /// The limits lowered by the guard are restored when it is cleared or
+    /// replaced. Limits above the hard limits of SQLite are truncated.
+    pub fn set_sql_guard(&self, guard: Option<SqlGuard>) {
+        self.flush_prepared_statement_cache();
+        let mut c = self.db.borrow_mut();
+        if let Some(active) = c.sql_guard.take() {
+            for &(limit, previous) in &active.previous_limits {
+                unsafe { ffi::sqlite3_limit(c.db(), limit, previous) };
+            }
+        }
+        if let Some(guard) = guard {
+            let previous_limits = guard
+                .limits()
+                .into_iter()
+                .map(|(limit, max)| (limit, unsafe { ffi::sqlite3_limit(c.db(), limit, max) }))
+                .collect();
+            c.sql_guard = Some(ActiveGuard {
+                guard,
+                previous_limits,
+                internal_sql: RefCell::new(None),
+            });
+        }
+    }
+
+    /// Return the guard set with `set_sql_guard`, if any.
+    pub fn sql_guard(&self) -> Option<SqlGuard> {
+        self.db
+            .borrow()
+            .sql_guard
+            .as_ref()
+            .map(|active| active.guard.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SqlGuard;
+    use crate::{Connection, ErrorCode, NO_PARAMS};
+
+    fn code(r: crate::Result<()>) -> ErrorCode {
+        match r {
+            Err(crate::Error::SqliteFailure(err, _)) => err.code,
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let guard = SqlGuard::new()
+            .max_length(100)
+            .max_depth(2)
+            .block_keywords(&["attach", "pragma"]);
+        guard.check("SELECT ((1)) -- PRAGMA").unwrap();
+        guard
+            .check("SELECT 'attach', \"pragma\", [pragma] /* ATTACH */, 'a''(('")
+            .unwrap();
+        assert_eq!(
+            ErrorCode::AuthorizationForStatementDenied,
+            code(guard.check("select 1; Pragma user_version"))
+        );
+        assert_eq!(
+            ErrorCode::AuthorizationForStatementDenied,
+            code(guard.check("SELECT 'it''s'; ATTACH 'x' AS y"))
+        );
+        assert_eq!(ErrorCode::TooBig, code(guard.check("SELECT (((1)))")));
+        assert_eq!(
+            ErrorCode::AuthorizationForStatementDenied,
+            code(guard.check("SELECT * FROM Pragma_Table_Info('t')"))
+        );
+        assert_eq!(
+            ErrorCode::TooBig,
+            code(guard.check(&format!("SELECT '{}'", "x".repeat(100))))
+        );
+        guard.check("SELECT attachment, pragmas FROM t").unwrap();
+    }
+
+    #[test]
+    fn test_set_sql_guard() {
+        let mut db = Connection::open_in_memory().unwrap();
+        db.set_sql_guard(Some(
+            SqlGuard::new()
+                .max_depth(3)
+                .max_expr_depth(10)
+                .block_keywords(&["ATTACH", "SAVEPOINT", "BEGIN", "COMMIT"]),
+        ));
+        assert!(db.sql_guard().is_some());
+        db.execute_batch("CREATE TABLE foo(x)").unwrap();
+        assert!(db.execute_batch("ATTACH ':memory:' AS bar").is_err());
+        assert!(db.prepare("ATTACH ':memory:' AS bar").is_err());
+        let depth = crate::ffi::Limit::SQLITE_LIMIT_EXPR_DEPTH as i32;
+        let limit = unsafe { crate::ffi::sqlite3_limit(db.handle(), depth, -1) };
+        assert_eq!(10, limit);
+        // The depth of expressions is checked by SQLite.
+        db.execute_batch("SELECT ((1))").unwrap();
+        assert!(db
+            .execute_batch("SELECT 1+1+1+1+1+1+1+1+1+1+1+1+1+1+1")
+            .is_err());
+        // SQL issued by the crate itself is not checked.
+        assert!(db.execute_batch("BEGIN").is_err());
+        let sp = db.savepoint().unwrap();
+        sp.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
+        sp.commit().unwrap();
+        let tx = db.transaction().unwrap();
+        tx.commit().unwrap();
+        assert!(db.execute_batch("SAVEPOINT x").is_err());
+
+        db.set_sql_guard(None);
+        assert!(db.sql_guard().is_none());
+        assert_ne!(10, unsafe {
+            crate::ffi::sqlite3_limit(db.handle(), depth, -1)
+        });
+        db.execute("ATTACH ':memory:' AS bar", NO_PARAMS).unwrap();
+    }
+}
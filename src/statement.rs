@@ -1,6 +1,8 @@
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+#[cfg(feature = "pointer")]
 use std::ffi::CStr;
-use std::iter::IntoIterator;
-use std::os::raw::{c_char, c_int, c_void};
+use std::os::raw::{c_int, c_void};
 #[cfg(feature = "array")]
 use std::rc::Rc;
 use std::slice::from_raw_parts;
@@ -9,16 +11,59 @@ use std::{convert, fmt, mem, ptr, result, str};
 use super::ffi;
 use super::{len_as_c_int, str_for_sqlite, str_to_cstring};
 use super::{
-    AndThenRows, Connection, Error, MappedRows, RawStatement, Result, Row, Rows, ValueRef,
+    AndThenRows, Connection, Error, FromRow, MappedRows, RawStatement, Result, Row, Rows, ValueRef,
 };
-use crate::types::{ToSql, ToSqlOutput};
+use crate::decoders::{self, ColumnDecoders, StatementDecoders};
+use crate::event_log::{EventStart, StatementOutcome};
+#[cfg(feature = "pointer")]
+use crate::tagged::Tagged;
+use crate::types::{Params, ToSql, ToSqlOutput, Type};
 #[cfg(feature = "array")]
 use crate::vtab::array::{free_array, ARRAY_TYPE};
 
 /// A prepared statement.
 pub struct Statement<'conn> {
-    conn: &'conn Connection,
+    pub(crate) conn: &'conn Connection,
     pub(crate) stmt: RawStatement,
+    parameter_report: RefCell<Option<Vec<BoundParameter>>>,
+    changes: Cell<usize>,
+    pub(crate) rows: Cell<usize>,
+    // `FullscanStep` and `Sort` counters at the start of the execution
+    pub(crate) scan_base: Cell<(i32, i32)>,
+    // The rows and counters of the current execution have not been added to
+    // the connection `IoCounters` yet
+    pub(crate) io_pending: Cell<bool>,
+    // Clear the bindings before binding named parameters
+    null_missing_named: bool,
+    // Decoders of the columns, resolved once (see `register_column_decoder`)
+    pub(crate) decoders: StatementDecoders,
+}
+
+/// A parameter bound to a statement, as recorded by
+/// `Statement::record_parameters`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoundParameter {
+    /// 1-based index of the parameter.
+    pub index: usize,
+    /// Name of the parameter (e.g. `:name` or `?1`), if any.
+    pub name: Option<String>,
+    /// Name of the Rust type of the bound value (see `ToSql::type_name`).
+    pub rust_type: &'static str,
+    /// SQLite storage class of the bound value.
+    pub storage_class: Type,
+}
+
+/// Result of a DML statement executed by `Statement::run` or
+/// `Connection::run`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExecuteResult {
+    /// Number of rows that were changed or inserted or deleted (via
+    /// `sqlite3_changes`).
+    pub rows_affected: usize,
+    /// ROWID of the most recent successful INSERT on the connection (via
+    /// `sqlite3_last_insert_rowid`), read right after the execution. It is
+    /// only meaningful if the statement is an INSERT which inserted a row.
+    pub last_insert_rowid: i64,
 }
 
 impl Statement<'_> {
@@ -48,8 +93,7 @@ impl Statement<'_> {
     /// underling SQLite call fails.
     pub fn execute<P>(&mut self, params: P) -> Result<usize>
     where
-        P: IntoIterator,
-        P::Item: ToSql,
+        P: Params,
     {
         self.bind_parameters(params)?;
         self.execute_with_bound_parameters()
@@ -95,6 +139,36 @@ impl Statement<'_> {
         self.execute_with_bound_parameters()
     }
 
+    /// Execute the prepared statement, and return both the number of rows
+    /// changed and the ROWID of the last inserted row, without a separate
+    /// call to `Connection::last_insert_rowid`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn insert(conn: &Connection, name: &str) -> Result<i64> {
+    ///     let mut stmt = conn.prepare("INSERT INTO people (name) VALUES (?)")?;
+    ///     Ok(stmt.run(&[name])?.last_insert_rowid)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails, the executed statement
+    /// returns rows (in which case `query` should be used instead), or the
+    /// underling SQLite call fails.
+    pub fn run<P>(&mut self, params: P) -> Result<ExecuteResult>
+    where
+        P: Params,
+    {
+        let rows_affected = self.execute(params)?;
+        Ok(ExecuteResult {
+            rows_affected,
+            last_insert_rowid: self.conn.last_insert_rowid(),
+        })
+    }
+
     /// Execute an INSERT and return the ROWID.
     ///
     /// # Note
@@ -110,8 +184,7 @@ impl Statement<'_> {
     /// Will return `Err` if no row is inserted or many rows are inserted.
     pub fn insert<P>(&mut self, params: P) -> Result<i64>
     where
-        P: IntoIterator,
-        P::Item: ToSql,
+        P: Params,
     {
         let changes = self.execute(params)?;
         match changes {
@@ -120,6 +193,40 @@ impl Statement<'_> {
         }
     }
 
+    /// Execute a DML statement with a `RETURNING` clause (SQLite 3.35.0 or
+    /// later), e.g. `INSERT ... RETURNING id`, and return the result of `f`
+    /// for each returned row, so that generated ids and default values are
+    /// retrieved in one round trip (`execute` fails with
+    /// `ExecuteReturnedResults` on such statements).
+    ///
+    /// All the rows are read, so that the statement runs to completion.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn insert_people(conn: &Connection, names: &[&str]) -> Result<Vec<i64>> {
+    ///     let mut stmt = conn.prepare("INSERT INTO people (name) VALUES (?) RETURNING id")?;
+    ///     let mut ids = Vec::new();
+    ///     for name in names {
+    ///         ids.extend(stmt.execute_returning(&[name], |row| row.get::<_, i64>(0))?);
+    ///     }
+    ///     Ok(ids)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails, if `f` fails, or if the
+    /// underlying SQLite call fails.
+    pub fn execute_returning<T, P, F>(&mut self, params: P, f: F) -> Result<Vec<T>>
+    where
+        P: Params,
+        F: FnMut(&Row<'_>) -> Result<T>,
+    {
+        self.query_map(params, f)?.collect()
+    }
+
     /// Execute the prepared statement, returning a handle to the resulting
     /// rows.
     ///
@@ -149,8 +256,7 @@ impl Statement<'_> {
     /// Will return `Err` if binding parameters fails.
     pub fn query<P>(&mut self, params: P) -> Result<Rows<'_>>
     where
-        P: IntoIterator,
-        P::Item: ToSql,
+        P: Params,
     {
         self.check_readonly()?;
         self.bind_parameters(params)?;
@@ -226,14 +332,80 @@ impl Statement<'_> {
     /// Will return `Err` if binding parameters fails.
     pub fn query_map<T, P, F>(&mut self, params: P, f: F) -> Result<MappedRows<'_, F>>
     where
-        P: IntoIterator,
-        P::Item: ToSql,
+        P: Params,
         F: FnMut(&Row<'_>) -> Result<T>,
     {
         let rows = self.query(params)?;
         Ok(MappedRows::new(rows, f))
     }
 
+    /// Like `query_map`, but the mapping function also receives the 0-based
+    /// index of each row.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, NO_PARAMS};
+    /// fn get_names(conn: &Connection) -> Result<Vec<String>> {
+    ///     let mut stmt = conn.prepare("SELECT name FROM people")?;
+    ///     let rows = stmt.query_map_indexed(NO_PARAMS, |i, row| {
+    ///         if i % 1000 == 0 {
+    ///             println!("{} names", i);
+    ///         }
+    ///         row.get(0)
+    ///     })?;
+    ///     rows.collect()
+    /// }
+    /// ```
+    ///
+    /// ## Failure
+    ///
+    /// Will return `Err` if binding parameters fails.
+    pub fn query_map_indexed<T, P, F>(
+        &mut self,
+        params: P,
+        mut f: F,
+    ) -> Result<MappedRows<'_, impl FnMut(&Row<'_>) -> Result<T>>>
+    where
+        P: Params,
+        F: FnMut(usize, &Row<'_>) -> Result<T>,
+    {
+        let mut index = 0;
+        self.query_map(params, move |row| {
+            let r = f(index, row);
+            index += 1;
+            r
+        })
+    }
+
+    /// Execute the prepared statement, returning an iterator over the rows
+    /// converted with `FromRow` (e.g. a tuple of column values).
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, NO_PARAMS};
+    /// fn get_people(conn: &Connection) -> Result<Vec<(i64, String)>> {
+    ///     let mut stmt = conn.prepare("SELECT id, name FROM people")?;
+    ///     let rows = stmt.query_as::<(i64, String), _>(NO_PARAMS)?;
+    ///     rows.collect()
+    /// }
+    /// ```
+    ///
+    /// ## Failure
+    ///
+    /// Will return `Err` if binding parameters fails.
+    pub fn query_as<T, P>(
+        &mut self,
+        params: P,
+    ) -> Result<MappedRows<'_, impl FnMut(&Row<'_>) -> Result<T>>>
+    where
+        T: FromRow,
+        P: Params,
+    {
+        self.query_map(params, T::from_row)
+    }
+
     /// Execute the prepared statement with named parameter(s), returning an
     /// iterator over the result of calling the mapping function over the
     /// query's rows. If any parameters that were in the prepared statement
@@ -282,8 +454,7 @@ impl Statement<'_> {
     /// Will return `Err` if binding parameters fails.
     pub fn query_and_then<T, E, P, F>(&mut self, params: P, f: F) -> Result<AndThenRows<'_, F>>
     where
-        P: IntoIterator,
-        P::Item: ToSql,
+        P: Params,
         E: convert::From<Error>,
         F: FnMut(&Row<'_>) -> result::Result<T, E>,
     {
@@ -346,8 +517,7 @@ impl Statement<'_> {
     /// or more rows and `false` if the SQL returns an empty set.
     pub fn exists<P>(&mut self, params: P) -> Result<bool>
     where
-        P: IntoIterator,
-        P::Item: ToSql,
+        P: Params,
     {
         let mut rows = self.query(params)?;
         let exists = rows.next()?.is_some();
@@ -369,8 +539,7 @@ impl Statement<'_> {
     /// Will return `Err` if the underlying SQLite call fails.
     pub fn query_row<T, P, F>(&mut self, params: P, f: F) -> Result<T>
     where
-        P: IntoIterator,
-        P::Item: ToSql,
+        P: Params,
         F: FnOnce(&Row<'_>) -> Result<T>,
     {
         let mut rows = self.query(params)?;
@@ -378,6 +547,23 @@ impl Statement<'_> {
         rows.get_expected_row().and_then(|r| f(&r))
     }
 
+    /// Convenience method to execute a query that is expected to return a
+    /// single row, converted with `FromRow`.
+    ///
+    /// Returns `Err(QueryReturnedNoRows)` if no results are returned.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the row cannot be converted or if the underlying
+    /// SQLite call fails.
+    pub fn query_row_as<T, P>(&mut self, params: P) -> Result<T>
+    where
+        T: FromRow,
+        P: Params,
+    {
+        self.query_row(params, T::from_row)
+    }
+
     /// Convenience method to execute a query with named parameter(s) that is
     /// expected to return a single row.
     ///
@@ -401,6 +587,54 @@ impl Statement<'_> {
         rows.get_expected_row().and_then(|r| f(&r))
     }
 
+    /// Convenience method to execute a query that is expected to return a
+    /// single row, and execute a mapping via `f` on that returned row with
+    /// the possibility of failure. The `Result` type of `f` must implement
+    /// `std::convert::From<Error>`.
+    ///
+    /// If the query returns more than one row, all rows except the first are
+    /// ignored.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn query_row_and_then<T, E, P, F>(&mut self, params: P, f: F) -> result::Result<T, E>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> result::Result<T, E>,
+        E: convert::From<Error>,
+    {
+        let mut rows = self.query(params)?;
+
+        rows.get_expected_row().map_err(E::from).and_then(f)
+    }
+
+    /// Convenience method to execute a query with named parameter(s) that is
+    /// expected to return a single row, and execute a mapping via `f` on that
+    /// returned row with the possibility of failure. The `Result` type of `f`
+    /// must implement `std::convert::From<Error>`.
+    ///
+    /// If the query returns more than one row, all rows except the first are
+    /// ignored.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails or if the underlying
+    /// SQLite call fails.
+    pub fn query_row_and_then_named<T, E, F>(
+        &mut self,
+        params: &[(&str, &dyn ToSql)],
+        f: F,
+    ) -> result::Result<T, E>
+    where
+        F: FnOnce(&Row<'_>) -> result::Result<T, E>,
+        E: convert::From<Error>,
+    {
+        let mut rows = self.query_named(params)?;
+
+        rows.get_expected_row().map_err(E::from).and_then(f)
+    }
+
     /// Consumes the statement.
     ///
     /// Functionally equivalent to the `Drop` implementation, but allows
@@ -424,20 +658,150 @@ impl Statement<'_> {
         Ok(self.stmt.bind_parameter_index(&c_name))
     }
 
+    /// Bind `value` to the parameter `name` (e.g. `:obj`, or `?1` for a
+    /// numbered parameter) with the
+    /// [pointer passing interface](https://sqlite.org/bindptr.html), so that
+    /// application-defined functions can get it back with
+    /// `Context::get_pointer_arg` and the same `type_tag`, without
+    /// serializing it. To SQL, the parameter looks like a `NULL`.
+    ///
+    /// The value is dropped when the parameter is bound again, or when the
+    /// statement is finalized. Other parameters must be bound with the
+    /// `_named` methods, which leave this one bound.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use std::ffi::CStr;
+    /// const TAG: &[u8] = b"blocklist\0";
+    ///
+    /// fn is_blocked(conn: &Connection, blocklist: Vec<String>, name: &str) -> Result<bool> {
+    ///     let mut stmt = conn.prepare("SELECT in_blocklist(:blocklist, :name)")?;
+    ///     stmt.bind_pointer(":blocklist", blocklist, CStr::from_bytes_with_nul(TAG).unwrap())?;
+    ///     stmt.query_row_named(&[(":name", &name)], |row| row.get(0))
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `name` is not a parameter of the statement, or if
+    /// the underlying SQLite call fails.
+    #[cfg(feature = "pointer")]
+    pub fn bind_pointer<T: 'static>(
+        &mut self,
+        name: &str,
+        value: T,
+        type_tag: &'static CStr,
+    ) -> Result<()> {
+        let index = match self.parameter_index(name)? {
+            Some(index) => index,
+            None => return Err(Error::InvalidParameterName(name.into())),
+        };
+        // The value is dropped by SQLite, even if the binding fails.
+        self.conn.decode_result(unsafe {
+            ffi::sqlite3_bind_pointer(
+                self.stmt.ptr(),
+                index as c_int,
+                Tagged::into_raw(value),
+                type_tag.as_ptr(),
+                Some(Tagged::<T>::free),
+            )
+        })
+    }
+
+    /// Reset all the parameters of this statement to `NULL`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn clear_bindings(&mut self) -> Result<()> {
+        self.conn.decode_result(self.stmt.clear_bindings())
+    }
+
+    /// Enable or disable binding `NULL` to the named parameters which are
+    /// not given to `execute_named`, `query_named` and the like (disabled by
+    /// default).
+    ///
+    /// By default, a parameter which is not given keeps the value bound by
+    /// the previous execution of the statement, so reusing a statement with
+    /// different subsets of its parameters requires binding all of them each
+    /// time (or calling `clear_bindings`). Once enabled, the bindings are
+    /// cleared before the given parameters are bound.
+    pub fn set_missing_named_params_null(&mut self, enabled: bool) {
+        self.null_missing_named = enabled
+    }
+
+    /// Enable or disable the recording of the parameters bound to this
+    /// statement (disabled by default).
+    ///
+    /// This is a debugging aid: once enabled, each time parameters are bound,
+    /// the Rust type and the SQLite storage class of each parameter is
+    /// recorded and can be retrieved with `parameter_report`, e.g. to find an
+    /// `f64` bound where an `INTEGER` is expected.
+    pub fn record_parameters(&mut self, enabled: bool) {
+        *self.parameter_report.get_mut() = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Return the parameters bound by the last execution of this statement,
+    /// ordered by index, if their recording is enabled (see
+    /// `record_parameters`).
+    pub fn parameter_report(&self) -> Vec<BoundParameter> {
+        self.parameter_report.borrow().clone().unwrap_or_default()
+    }
+
+    /// Reset the per-execution state, before binding new parameters.
+    fn begin_execution(&mut self) {
+        self.collect_io_counters();
+        if let Some(ref mut report) = *self.parameter_report.get_mut() {
+            report.clear();
+        }
+        self.changes.set(0);
+        self.rows.set(0);
+        self.scan_base.set(self.io_status());
+    }
+
+    fn record_parameter(&self, param: &dyn ToSql, index: usize, value: &ToSqlOutput<'_>) {
+        if let Some(ref mut report) = *self.parameter_report.borrow_mut() {
+            let storage_class = match *value {
+                ToSqlOutput::Borrowed(v) => v.data_type(),
+                ToSqlOutput::Owned(ref v) => ValueRef::from(v).data_type(),
+                #[cfg(feature = "borrowed_blob")]
+                ToSqlOutput::BorrowedBlob(_) => Type::Blob,
+                #[cfg(feature = "blob")]
+                ToSqlOutput::ZeroBlob(_) => Type::Blob,
+                // A pointer is seen as NULL by SQL functions.
+                #[cfg(feature = "array")]
+                ToSqlOutput::Array(_) => Type::Null,
+            };
+            report.retain(|p| p.index != index);
+            report.push(BoundParameter {
+                index,
+                name: self
+                    .stmt
+                    .bind_parameter_name(index)
+                    .map(|name| name.to_string_lossy().into_owned()),
+                rust_type: param.type_name(),
+                storage_class,
+            });
+            report.sort_by_key(|p| p.index);
+        }
+    }
+
     fn bind_parameters<P>(&mut self, params: P) -> Result<()>
     where
-        P: IntoIterator,
-        P::Item: ToSql,
+        P: Params,
     {
+        self.begin_execution();
         let expected = self.stmt.bind_parameter_count();
         let mut index = 0;
-        for p in params.into_iter() {
+        params.try_for_each_param(|p| {
             index += 1; // The leftmost SQL parameter has an index of 1.
             if index > expected {
-                break;
+                return Ok(());
             }
-            self.bind_parameter(&p, index)?;
-        }
+            self.bind_parameter(p, index)
+        })?;
         assert_eq!(
             index, expected,
             "incorrect number of parameters: expected {}, got {}",
@@ -448,6 +812,10 @@ impl Statement<'_> {
     }
 
     fn bind_parameters_named(&mut self, params: &[(&str, &dyn ToSql)]) -> Result<()> {
+        self.begin_execution();
+        if self.null_missing_named {
+            self.clear_bindings()?;
+        }
         for &(name, value) in params {
             if let Some(i) = self.parameter_index(name)? {
                 self.bind_parameter(value, i)?;
@@ -460,11 +828,32 @@ impl Statement<'_> {
 
     fn bind_parameter(&self, param: &dyn ToSql, col: usize) -> Result<()> {
         let value = param.to_sql()?;
+        self.record_parameter(param, col, &value);
 
         let ptr = unsafe { self.stmt.ptr() };
         let value = match value {
             ToSqlOutput::Borrowed(v) => v,
             ToSqlOutput::Owned(ref v) => ValueRef::from(v),
+            #[cfg(feature = "borrowed_blob")]
+            ToSqlOutput::BorrowedBlob(b) => {
+                let b = b.as_bytes();
+                let length = len_as_c_int(b.len())?;
+                return self.conn.decode_result(unsafe {
+                    if length == 0 {
+                        ffi::sqlite3_bind_zeroblob(ptr, col as c_int, 0)
+                    } else {
+                        // The caller of `BorrowedBlob::new` guarantees that `b`
+                        // outlives this binding.
+                        ffi::sqlite3_bind_blob(
+                            ptr,
+                            col as c_int,
+                            b.as_ptr() as *const c_void,
+                            length,
+                            ffi::SQLITE_STATIC(),
+                        )
+                    }
+                });
+            }
 
             #[cfg(feature = "blob")]
             ToSqlOutput::ZeroBlob(len) => {
@@ -511,8 +900,33 @@ impl Statement<'_> {
     }
 
     fn execute_with_bound_parameters(&mut self) -> Result<usize> {
-        let r = self.stmt.step();
-        self.stmt.reset();
+        let start = self.conn.event_start();
+        let r = self.step_raw();
+        self.reset();
+        let result = self.decode_execute_result(r);
+        if let Ok(changes) = result {
+            self.changes.set(changes);
+        }
+        if let Some(start) = start {
+            let outcome = match result {
+                Ok(changes) => StatementOutcome::Executed(changes),
+                Err(ref err) => StatementOutcome::Failed(err.to_string()),
+            };
+            self.record_event(start, outcome);
+        }
+        result
+    }
+
+    pub(crate) fn event_start(&self) -> Option<EventStart> {
+        self.conn.event_start()
+    }
+
+    pub(crate) fn record_event(&self, start: EventStart, outcome: StatementOutcome) {
+        let sql = self.stmt.sql().to_string_lossy();
+        self.conn.record_event(start, &sql, outcome);
+    }
+
+    fn decode_execute_result(&self, r: c_int) -> Result<usize> {
         match r {
             ffi::SQLITE_DONE => {
                 if self.column_count() == 0 {
@@ -527,9 +941,12 @@ impl Statement<'_> {
     }
 
     fn finalize_(&mut self) -> Result<()> {
+        self.collect_io_counters();
         let mut stmt = RawStatement::new(ptr::null_mut());
         mem::swap(&mut stmt, &mut self.stmt);
-        self.conn.decode_result(stmt.finalize())
+        let rc = stmt.finalize();
+        self.conn.db.borrow().tx_hooks.reset(rc);
+        self.conn.decode_result(rc)
     }
 
     #[cfg(not(feature = "bundled"))]
@@ -558,6 +975,55 @@ impl Statement<'_> {
         }
     }
 
+    /// Return the SQL text of the statement.
+    pub(crate) fn sql(&self) -> Cow<'_, str> {
+        self.stmt.sql().to_string_lossy()
+    }
+
+    /// Return `true` if the statement may write to the database file (always
+    /// `true` without `sqlite3_stmt_readonly`, i.e. before SQLite 3.7.4).
+    #[cfg(feature = "bundled")]
+    pub(crate) fn may_write(&self) -> bool {
+        !self.stmt.readonly()
+    }
+
+    #[cfg(not(feature = "bundled"))]
+    pub(crate) fn may_write(&self) -> bool {
+        true
+    }
+
+    /// Return `true` if the statement has been stepped at least once but has
+    /// not run to completion nor been reset.
+    #[cfg(any(feature = "stmt_busy", feature = "bundled"))]
+    pub fn is_busy(&self) -> bool {
+        self.stmt.is_busy()
+    }
+
+    /// Return the number of rows modified, inserted or deleted by the most
+    /// recent execution of this statement (0 if it is not an `INSERT`,
+    /// `UPDATE` or `DELETE` statement, or if it has not been run to
+    /// completion).
+    ///
+    /// Unlike `sqlite3_changes` (which is connection-wide), the result is not
+    /// affected by other statements run on the connection since.
+    pub fn changes(&self) -> usize {
+        self.changes.get()
+    }
+
+    /// Return an estimate of the number of rows processed by the most recent
+    /// (or current) execution of this statement.
+    ///
+    /// This is the largest of the number of rows returned so far and of the
+    /// increments of the `FullscanStep` and `Sort` status counters during the
+    /// execution (rows visited by full table scans and sorted). It is a lower
+    /// bound: rows visited through an index and filtered out are not counted.
+    pub fn row_count_estimate(&self) -> usize {
+        let (fullscan_base, sort_base) = self.scan_base.get();
+        let fullscan = self.get_status(StatementStatus::FullscanStep) - fullscan_base;
+        let sort = self.get_status(StatementStatus::Sort) - sort_base;
+        self.rows.get().max(fullscan.max(sort).max(0) as usize)
+    }
+
     /// Get the value for one of the status counters for this statement.
     pub fn get_status(&self, status: StatementStatus) -> i32 {
         self.stmt.get_status(status, false)
@@ -568,6 +1034,13 @@ impl Statement<'_> {
     pub fn reset_status(&self, status: StatementStatus) -> i32 {
         self.stmt.get_status(status, true)
     }
+
+    /// Return the approximate number of bytes of heap memory used by this
+    /// prepared statement (`SQLITE_STMTSTATUS_MEMUSED`, which is always 0
+    /// before SQLite 3.20.0).
+    pub fn memory_used(&self) -> usize {
+        self.stmt.memory_used()
+    }
 }
 
 impl Into<RawStatement> for Statement<'_> {
@@ -597,14 +1070,35 @@ impl Drop for Statement<'_> {
 }
 
 impl Statement<'_> {
-    pub(crate) fn new(conn: &Connection, stmt: RawStatement) -> Statement<'_> {
-        Statement { conn, stmt }
+    pub(crate) fn new<'conn>(
+        conn: &'conn Connection,
+        stmt: RawStatement,
+        decoders: &ColumnDecoders,
+    ) -> Statement<'conn> {
+        let decoders = decoders::resolve(decoders, &stmt);
+        let stmt = Statement {
+            conn,
+            stmt,
+            parameter_report: RefCell::new(None),
+            changes: Cell::new(0),
+            rows: Cell::new(0),
+            scan_base: Cell::new((0, 0)),
+            io_pending: Cell::new(false),
+            null_missing_named: false,
+            decoders,
+        };
+        // Cached statements keep their status counters.
+        stmt.scan_base.set(stmt.io_status());
+        stmt
     }
 
-    pub(crate) fn value_ref(&self, col: usize) -> ValueRef<'_> {
+    /// Return the value of the column `col`, or
+    /// `Err(Error::FromSqlConversionFailure)` if it is a text which is not
+    /// valid UTF-8: SQLite does not validate text (e.g. a blob cast to text).
+    pub(crate) fn value_ref(&self, col: usize) -> Result<ValueRef<'_>> {
         let raw = unsafe { self.stmt.ptr() };
 
-        match self.stmt.column_type(col) {
+        Ok(match self.stmt.column_type(col) {
             ffi::SQLITE_NULL => ValueRef::Null,
             ffi::SQLITE_INTEGER => {
                 ValueRef::Integer(unsafe { ffi::sqlite3_column_int64(raw, col as c_int) })
@@ -613,20 +1107,9 @@ impl Statement<'_> {
                 ValueRef::Real(unsafe { ffi::sqlite3_column_double(raw, col as c_int) })
             }
             ffi::SQLITE_TEXT => {
-                let s = unsafe {
-                    let text = ffi::sqlite3_column_text(raw, col as c_int);
-                    assert!(
-                        !text.is_null(),
-                        "unexpected SQLITE_TEXT column type with NULL data"
-                    );
-                    CStr::from_ptr(text as *const c_char)
-                };
-
-                // sqlite3_column_text returns UTF8 data, so our unwrap here should be fine.
-                let s = s
-                    .to_str()
-                    .expect("sqlite3_column_text returned invalid UTF-8");
-                ValueRef::Text(s)
+                ValueRef::Text(str::from_utf8(self.text_bytes(col)).map_err(|err| {
+                    Error::FromSqlConversionFailure(col, Type::Text, Box::new(err))
+                })?)
             }
             ffi::SQLITE_BLOB => {
                 let (blob, len) = unsafe {
@@ -653,19 +1136,63 @@ impl Statement<'_> {
                 }
             }
             _ => unreachable!("sqlite3_column_type returned invalid value"),
-        }
+        })
+    }
+
+    /// Return the bytes of the text of the column `col`, which must be of
+    /// type `SQLITE_TEXT`.
+    pub(crate) fn text_bytes(&self, col: usize) -> &[u8] {
+        let raw = unsafe { self.stmt.ptr() };
+        let (text, len) = unsafe {
+            let text = ffi::sqlite3_column_text(raw, col as c_int);
+            // The length must be read after the conversion to UTF-8.
+            (text, ffi::sqlite3_column_bytes(raw, col as c_int))
+        };
+        assert!(
+            !text.is_null(),
+            "unexpected SQLITE_TEXT column type with NULL data"
+        );
+        assert!(
+            len >= 0,
+            "unexpected negative return from sqlite3_column_bytes"
+        );
+        unsafe { from_raw_parts(text, len as usize) }
+    }
+
+    // Step the statement, and tell the transaction hooks how it went.
+    fn step_raw(&self) -> c_int {
+        self.io_pending.set(true);
+        let mark = self.conn.db.borrow().tx_hooks.mark();
+        let rc = self.stmt.step();
+        self.conn
+            .db
+            .borrow()
+            .tx_hooks
+            .stepped(unsafe { self.stmt.ptr() }, mark, rc);
+        rc
     }
 
     pub(crate) fn step(&self) -> Result<bool> {
-        match self.stmt.step() {
-            ffi::SQLITE_ROW => Ok(true),
-            ffi::SQLITE_DONE => Ok(false),
+        match self.step_raw() {
+            ffi::SQLITE_ROW => {
+                self.rows.set(self.rows.get() + 1);
+                Ok(true)
+            }
+            ffi::SQLITE_DONE => {
+                if self.column_count() == 0 {
+                    self.changes.set(self.conn.changes());
+                }
+                Ok(false)
+            }
             code => Err(self.conn.decode_result(code).unwrap_err()),
         }
     }
 
     pub(crate) fn reset(&self) -> c_int {
-        self.stmt.reset()
+        let rc = self.stmt.reset();
+        self.collect_io_counters();
+        self.conn.db.borrow().tx_hooks.reset(rc);
+        rc
     }
 }
 
@@ -698,7 +1225,7 @@ pub enum StatementStatus {
 #[cfg(test)]
 mod test {
     use crate::types::ToSql;
-    use crate::{Connection, Error, Result, NO_PARAMS};
+    use crate::{params_from_iter, Connection, Error, Result, NO_PARAMS};
 
     #[test]
     fn test_execute_named() {
@@ -790,6 +1317,80 @@ mod test {
         assert_eq!(2, doubled_id);
     }
 
+    #[test]
+    fn test_query_map_indexed() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES(10), (20), (30);")
+            .unwrap();
+
+        let mut stmt = db.prepare("SELECT x FROM foo ORDER BY x").unwrap();
+        let rows: Vec<(usize, i64)> = stmt
+            .query_map_indexed(NO_PARAMS, |i, row| Ok((i, row.get(0)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(vec![(0, 10), (1, 20), (2, 30)], rows);
+
+        let mut rows = stmt.query(NO_PARAMS).unwrap();
+        assert_eq!(0, rows.row_number());
+        rows.next().unwrap();
+        assert_eq!(1, rows.row_number());
+        while rows.next().unwrap().is_some() {}
+        assert_eq!(3, rows.row_number());
+    }
+
+    #[test]
+    fn test_query_as() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo(x INTEGER, y TEXT); INSERT INTO foo VALUES(1, 'a'), (2, NULL);",
+        )
+        .unwrap();
+
+        let mut stmt = db.prepare("SELECT x, y FROM foo ORDER BY x").unwrap();
+        let rows: Vec<(i64, Option<String>)> = stmt
+            .query_as(NO_PARAMS)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(vec![(1, Some("a".to_owned())), (2, None)], rows);
+        assert!(stmt.query_row_as::<(i64, String), _>(NO_PARAMS).is_ok());
+
+        let (x,): (i64,) = db
+            .query_row_as("SELECT x FROM foo WHERE y IS NULL", NO_PARAMS)
+            .unwrap();
+        assert_eq!(2, x);
+        type Wide = (
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+        );
+        let wide: Wide = db
+            .query_row_as(
+                "SELECT 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16",
+                NO_PARAMS,
+            )
+            .unwrap();
+        assert_eq!(16, wide.15);
+        match db.query_row_as::<(i64, i64), _>("SELECT x FROM foo", NO_PARAMS) {
+            Err(Error::InvalidColumnIndex(1)) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
     #[test]
     fn test_query_and_then_named() {
         let db = Connection::open_in_memory().unwrap();
@@ -846,6 +1447,94 @@ mod test {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_missing_named_params_null() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE test (x TEXT, y TEXT)")
+            .unwrap();
+
+        let mut stmt = db
+            .prepare("INSERT INTO test (x, y) VALUES (:x, :y)")
+            .unwrap();
+        stmt.set_missing_named_params_null(true);
+        stmt.execute_named(&[(":x", &"one"), (":y", &"two")])
+            .unwrap();
+        stmt.execute_named(&[(":x", &"three")]).unwrap();
+        stmt.execute_named(&[(":y", &"four")]).unwrap();
+
+        stmt.set_missing_named_params_null(false);
+        stmt.execute_named(&[(":x", &"five")]).unwrap();
+        stmt.clear_bindings().unwrap();
+        stmt.execute_named(&[(":y", &"six")]).unwrap();
+
+        let mut query = db.prepare("SELECT x, y FROM test ORDER BY rowid").unwrap();
+        let rows: Vec<(Option<String>, Option<String>)> = query
+            .query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        let some = |s: &str| Some(s.to_owned());
+        assert_eq!(
+            vec![
+                (some("one"), some("two")),
+                (some("three"), None),
+                (None, some("four")),
+                (some("five"), some("four")),
+                (None, some("six")),
+            ],
+            rows
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "borrowed_blob")]
+    fn test_borrowed_blob() {
+        use crate::types::BorrowedBlob;
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE test (b BLOB)").unwrap();
+
+        let data: Vec<u8> = (0..=255).collect();
+        {
+            let mut stmt = db.prepare("INSERT INTO test (b) VALUES (?)").unwrap();
+            let blob = unsafe { BorrowedBlob::new(&data) };
+            stmt.execute(&[blob]).unwrap();
+            let empty = unsafe { BorrowedBlob::new(&[]) };
+            stmt.execute(&[empty]).unwrap();
+        }
+
+        let mut query = db.prepare("SELECT b FROM test ORDER BY rowid").unwrap();
+        let blobs: Vec<Vec<u8>> = query
+            .query_map(NO_PARAMS, |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(vec![data, vec![]], blobs);
+    }
+
+    #[test]
+    #[cfg(feature = "borrowed_blob")]
+    fn test_borrowed_blob_cleared_in_cache() {
+        use crate::types::{BorrowedBlob, Value};
+
+        let db = Connection::open_in_memory().unwrap();
+        let sql = "SELECT :b";
+        {
+            let data = vec![1u8, 2, 3];
+            let mut stmt = db.prepare_cached(sql).unwrap();
+            let blob = unsafe { BorrowedBlob::new(&data) };
+            let value: Vec<u8> = stmt
+                .query_row_named(&[(":b", &blob)], |r| r.get(0))
+                .unwrap();
+            assert_eq!(data, value);
+        }
+        // The statement returned to the cache no longer points to the dropped
+        // BLOB: its bindings were cleared.
+        let mut stmt = db.prepare_cached(sql).unwrap();
+        let value: Value = stmt.query_row_named(&[], |r| r.get(0)).unwrap();
+        assert_eq!(Value::Null, value);
+    }
+
     #[test]
     fn test_unbound_parameters_are_reused() {
         let db = Connection::open_in_memory().unwrap();
@@ -889,6 +1578,64 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_execute_returning() {
+        if crate::version_number() < 3_035_000 {
+            return;
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(id INTEGER PRIMARY KEY, x INTEGER, t TEXT DEFAULT 'a')")
+            .unwrap();
+        let sql = "INSERT INTO foo (x) VALUES (?), (?) RETURNING id, t";
+        let mut stmt = db.prepare(sql).unwrap();
+        let rows: Vec<(i64, String)> = stmt
+            .execute_returning([1, 2], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap();
+        assert_eq!(vec![(1, "a".to_owned()), (2, "a".to_owned())], rows);
+
+        let xs: Vec<i64> = db
+            .execute_returning("UPDATE foo SET x = x + 10 RETURNING x", NO_PARAMS, |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(vec![11, 12], xs);
+        let count: i64 = db
+            .query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(2, count);
+        assert_eq!(
+            Err(Error::ExecuteReturnedResults),
+            db.execute("DELETE FROM foo RETURNING id", NO_PARAMS)
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER UNIQUE)")
+            .unwrap();
+        let mut stmt = db
+            .prepare("INSERT OR IGNORE INTO foo (x) VALUES (?)")
+            .unwrap();
+        let result = stmt.run([5]).unwrap();
+        assert_eq!(1, result.rows_affected);
+        assert_eq!(1, result.last_insert_rowid);
+        assert_eq!(0, stmt.run([5]).unwrap().rows_affected);
+
+        let result = db
+            .run("INSERT INTO foo (x) SELECT 3 UNION ALL SELECT 4", NO_PARAMS)
+            .unwrap();
+        assert_eq!(2, result.rows_affected);
+        assert_eq!(3, result.last_insert_rowid);
+        assert_eq!(
+            2,
+            db.run("DELETE FROM foo WHERE x < 5", NO_PARAMS)
+                .unwrap()
+                .rows_affected
+        );
+        assert!(db.run("SELECT x FROM foo", NO_PARAMS).is_err());
+    }
+
     #[test]
     fn test_insert_different_tables() {
         // Test for https://github.com/jgallagher/rusqlite/issues/171
@@ -1013,7 +1760,64 @@ mod test {
         let data = [0; 3];
         db.query_row("SELECT ?1, ?2, ?3", &data, |row| row.get::<_, u8>(0))
             .unwrap();
-        db.query_row("SELECT ?1, ?2, ?3", data.iter(), |row| row.get::<_, u8>(0))
+        db.query_row("SELECT ?1, ?2, ?3", params_from_iter(data.iter()), |row| {
+            row.get::<_, u8>(0)
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_parameter_report() {
+        use crate::types::Type;
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER, y TEXT)")
+            .unwrap();
+        let mut stmt = db.prepare("INSERT INTO foo VALUES(:x, :y)").unwrap();
+        stmt.execute_named(&[(":x", &1.5f64), (":y", &"a")])
             .unwrap();
+        assert!(stmt.parameter_report().is_empty());
+
+        stmt.record_parameters(true);
+        stmt.execute_named(&[(":y", &None::<String>), (":x", &2.5f64)])
+            .unwrap();
+        let report = stmt.parameter_report();
+        assert_eq!(2, report.len());
+        assert_eq!(1, report[0].index);
+        assert_eq!(Some(":x"), report[0].name.as_ref().map(|s| &s[..]));
+        assert_eq!("f64", report[0].rust_type);
+        assert_eq!(Type::Real, report[0].storage_class);
+        assert_eq!(Type::Null, report[1].storage_class);
+
+        let mut stmt = db.prepare("SELECT ?").unwrap();
+        stmt.record_parameters(true);
+        stmt.query_row(&[42i64], |_| Ok(())).unwrap();
+        let report = stmt.parameter_report();
+        assert_eq!(None, report[0].name);
+        assert_eq!("i64", report[0].rust_type);
+        assert_eq!(Type::Integer, report[0].storage_class);
+    }
+
+    #[test]
+    fn test_changes_and_row_count_estimate() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES(1), (2), (3);")
+            .unwrap();
+        let mut update = db.prepare("UPDATE foo SET x = x + 1 WHERE x > ?").unwrap();
+        assert_eq!(0, update.changes());
+        update.execute([1]).unwrap();
+        db.execute("DELETE FROM foo WHERE x = 4", NO_PARAMS)
+            .unwrap();
+        assert_eq!(1, db.changes());
+        assert_eq!(2, update.changes());
+
+        let mut stmt = db.prepare("SELECT x FROM foo ORDER BY x").unwrap();
+        for _ in 0..2 {
+            let mut rows = stmt.query(NO_PARAMS).unwrap();
+            while rows.next().unwrap().is_some() {}
+            drop(rows);
+            assert_eq!(0, stmt.changes());
+            assert_eq!(2, stmt.row_count_estimate());
+        }
     }
 }
@@ -0,0 +1,44 @@
+//! Boxed values tagged with their type, given to SQLite as `void *`.
+use std::any::TypeId;
+use std::os::raw::c_void;
+
+/// A value tagged with its type, so that it is not read as another type
+/// (e.g. auxiliary data set by another function, or a pointer bound with
+/// the same type tag). The layout is fixed so that the tag can be checked
+/// before the value is known to be a `T`.
+#[repr(C)]
+pub(crate) struct Tagged<T> {
+    type_id: TypeId,
+    value: T,
+}
+
+impl<T: 'static> Tagged<T> {
+    /// Box `value`, which must be dropped with `free`.
+    pub(crate) fn into_raw(value: T) -> *mut c_void {
+        Box::into_raw(Box::new(Tagged {
+            type_id: TypeId::of::<T>(),
+            value,
+        })) as *mut c_void
+    }
+
+    /// Return the value boxed by `into_raw` at `p`, or `None` if it is not a
+    /// `T`.
+    ///
+    /// `p` must be a pointer returned by `into_raw`, for any type, and not
+    /// freed yet.
+    #[cfg(feature = "functions")]
+    pub(crate) unsafe fn get<'a>(p: *const c_void) -> Option<&'a T> {
+        // `type_id` is the first field of `Tagged`, whatever `T` is.
+        let type_id = &*(p as *const TypeId);
+        if *type_id == TypeId::of::<T>() {
+            Some(&(*(p as *const Tagged<T>)).value)
+        } else {
+            None
+        }
+    }
+
+    /// Drop the value boxed by `into_raw` at `p`.
+    pub(crate) unsafe extern "C" fn free(p: *mut c_void) {
+        drop(Box::from_raw(p as *mut Tagged<T>));
+    }
+}
@@ -0,0 +1,184 @@
+//! Storage of temporary tables and indices.
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::ffi;
+use crate::{path_to_cstring, Connection, Error, Result};
+
+/// Where temporary tables and indices are stored (see
+/// [`PRAGMA temp_store`](https://sqlite.org/pragma.html#pragma_temp_store)).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TempStore {
+    /// The default chosen at compile time (`SQLITE_TEMP_STORE`), usually
+    /// files.
+    Default,
+    /// Files, in the temp directory (see `set_temp_directory`).
+    File,
+    /// Memory.
+    Memory,
+}
+
+impl Connection {
+    /// Return where temporary tables and indices are stored.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn temp_store(&self) -> Result<TempStore> {
+        let mode: i32 = self.pragma_query_value(None, "temp_store", |row| row.get(0))?;
+        Ok(match mode {
+            1 => TempStore::File,
+            2 => TempStore::Memory,
+            _ => TempStore::Default,
+        })
+    }
+
+    /// Set where temporary tables and indices are stored, e.g. `Memory` when
+    /// the temp directory is not writable.
+    ///
+    /// Changing it immediately drops all the temporary tables, indices,
+    /// triggers and views of the connection.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn set_temp_store(&self, temp_store: TempStore) -> Result<()> {
+        let mode = match temp_store {
+            TempStore::Default => 0,
+            TempStore::File => 1,
+            TempStore::Memory => 2,
+        };
+        self.pragma_update(None, "temp_store", &mode)
+    }
+}
+
+/// Number of connections opened by rusqlite and not closed yet, or
+/// `SETTING_TEMP_DIRECTORY` while `set_temp_directory` runs.
+static OPEN_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+const SETTING_TEMP_DIRECTORY: usize = usize::MAX;
+
+pub(crate) fn connection_opened() {
+    let mut open = OPEN_CONNECTIONS.load(Ordering::SeqCst);
+    loop {
+        if open == SETTING_TEMP_DIRECTORY {
+            // Wait for the directory to be set, which is quick.
+            thread::yield_now();
+            open = OPEN_CONNECTIONS.load(Ordering::SeqCst);
+            continue;
+        }
+        match OPEN_CONNECTIONS.compare_exchange_weak(
+            open,
+            open + 1,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(_) => return,
+            Err(current) => open = current,
+        }
+    }
+}
+
+pub(crate) fn connection_closed() {
+    OPEN_CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// Set the directory where SQLite creates temporary files (see
+/// [`sqlite3_temp_directory`](https://sqlite.org/c3ref/temp_directory.html)),
+/// or restore the default one with `None`, e.g. on Android or in sandboxes
+/// where the default locations (`/var/tmp`, `/tmp`, ...) are not writable.
+///
+/// SQLite requires the directory not to change while a connection is open,
+/// so this must be called before opening connections, or while all of them
+/// are closed. This is checked for the connections opened by rusqlite (but
+/// not for the ones opened by other libraries of the process). A connection
+/// which fails to close when it is dropped (see `Connection::close`) is
+/// still open, and still counted.
+///
+/// # Failure
+///
+/// Will return `Err(Error::TempDirectoryInUse)` if a connection is open, and
+/// `Err` if `path` cannot be converted to a C-compatible string, or if the
+/// memory for it cannot be allocated.
+pub fn set_temp_directory(path: Option<&Path>) -> Result<()> {
+    let c_path = match path {
+        Some(path) => Some(path_to_cstring(path)?),
+        None => None,
+    };
+    // Claimed until the directory is set, so that no connection is opened
+    // meanwhile.
+    if OPEN_CONNECTIONS
+        .compare_exchange(
+            0,
+            SETTING_TEMP_DIRECTORY,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        )
+        .is_err()
+    {
+        return Err(Error::TempDirectoryInUse);
+    }
+    let r = unsafe { replace_temp_directory(c_path) };
+    OPEN_CONNECTIONS.store(0, Ordering::SeqCst);
+    r
+}
+
+unsafe fn replace_temp_directory(c_path: Option<CString>) -> Result<()> {
+    // The directory must be allocated by SQLite, which frees it with
+    // `sqlite3_free` when `PRAGMA temp_store_directory` changes it.
+    let dir = match c_path {
+        Some(c_path) => {
+            let dir = ffi::sqlite3_mprintf(b"%s\0".as_ptr() as *const c_char, c_path.as_ptr());
+            if dir.is_null() {
+                return Err(Error::SqliteFailure(
+                    ffi::Error::new(ffi::SQLITE_NOMEM),
+                    None,
+                ));
+            }
+            dir
+        }
+        None => ptr::null_mut(),
+    };
+    ffi::sqlite3_free(ffi::sqlite3_temp_directory as *mut _);
+    ffi::sqlite3_temp_directory = dir;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{set_temp_directory, TempStore};
+    use crate::{Connection, Error, NO_PARAMS};
+
+    #[test]
+    fn test_temp_store() {
+        let db = Connection::open_in_memory().unwrap();
+        db.set_temp_store(TempStore::Memory).unwrap();
+        assert_eq!(TempStore::Memory, db.temp_store().unwrap());
+        db.execute_batch("CREATE TEMP TABLE foo(x INTEGER)")
+            .unwrap();
+
+        // Temporary tables are dropped.
+        db.set_temp_store(TempStore::File).unwrap();
+        assert_eq!(TempStore::File, db.temp_store().unwrap());
+        let count: i64 = db
+            .query_row("SELECT count(*) FROM temp.sqlite_master", NO_PARAMS, |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(0, count);
+        db.set_temp_store(TempStore::Default).unwrap();
+        assert_eq!(TempStore::Default, db.temp_store().unwrap());
+    }
+
+    #[test]
+    fn test_set_temp_directory_while_open() {
+        let _db = Connection::open_in_memory().unwrap();
+        match set_temp_directory(None) {
+            Err(Error::TempDirectoryInUse) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Deterministic time source for the date and time SQL functions.
+use std::ffi::CString;
+use std::fmt;
+use std::os::raw::c_int;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::ffi;
+use crate::{Connection, Error, OpenFlags, Result};
+
+/// Julian day number of the Unix epoch.
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2_440_587.5;
+const MILLIS_PER_DAY: f64 = 86_400_000.0;
+
+/// A clock controlling the current time seen by the connections opened with
+/// it: `CURRENT_TIMESTAMP`, `date('now')`, etc. (with a millisecond
+/// precision), so that time-dependent SQL can be tested deterministically.
+///
+/// Each clock registers a [VFS](https://sqlite.org/vfs.html), delegating to
+/// the default one except for the current time, which is never unregistered:
+/// clocks are meant to be used in tests.
+///
+/// ```rust,no_run
+/// # use rusqlite::{Result, TestClock, NO_PARAMS};
+/// # use std::time::{Duration, UNIX_EPOCH};
+/// fn test_expiry() -> Result<()> {
+///     let clock = TestClock::new(UNIX_EPOCH + Duration::from_secs(1_000_000_000))?;
+///     let db = clock.open_in_memory()?;
+///     clock.advance(Duration::from_secs(3600));
+///     let now: String = db.query_row("SELECT datetime('now')", NO_PARAMS, |r| r.get(0))?;
+///     assert_eq!("2001-09-09 02:46:40", now);
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct TestClock {
+    vfs: *mut ClockVfs,
+}
+
+unsafe impl Send for TestClock {}
+unsafe impl Sync for TestClock {}
+
+// `base` must be the first field: SQLite calls `current_time` with a pointer
+// to it.
+#[repr(C)]
+struct ClockVfs {
+    base: ffi::sqlite3_vfs,
+    name: CString,
+    /// Milliseconds since the Unix epoch.
+    now: AtomicI64,
+}
+
+impl TestClock {
+    /// Create a clock set to `now`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if there is no default VFS, or if the VFS cannot be
+    /// registered.
+    pub fn new(now: SystemTime) -> Result<TestClock> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "rusqlite_test_clock_{}",
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        );
+        let default = unsafe { ffi::sqlite3_vfs_find(ptr::null()) };
+        if default.is_null() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_ERROR),
+                Some("no default VFS".to_owned()),
+            ));
+        }
+        let mut base = unsafe { *default };
+        // Only the version 1 methods are used, so that SQLite asks for the
+        // time with `xCurrentTime` (and not `xCurrentTimeInt64`).
+        base.iVersion = 1;
+        base.pNext = ptr::null_mut();
+        base.xCurrentTime = Some(current_time);
+        let vfs = Box::into_raw(Box::new(ClockVfs {
+            base,
+            name: CString::new(name).unwrap(),
+            now: AtomicI64::new(millis(now)),
+        }));
+        unsafe {
+            (*vfs).base.zName = (*vfs).name.as_ptr();
+            let r = ffi::sqlite3_vfs_register(vfs as *mut ffi::sqlite3_vfs, 0);
+            if r != ffi::SQLITE_OK {
+                drop(Box::from_raw(vfs));
+                return Err(Error::SqliteFailure(ffi::Error::new(r), None));
+            }
+        }
+        Ok(TestClock { vfs })
+    }
+
+    /// Return the current time of the clock.
+    pub fn now(&self) -> SystemTime {
+        let now = self.vfs().now.load(Ordering::SeqCst);
+        if now >= 0 {
+            UNIX_EPOCH + Duration::from_millis(now as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_millis(now.wrapping_neg() as u64)
+        }
+    }
+
+    /// Set the current time of the clock.
+    pub fn set(&self, now: SystemTime) {
+        self.vfs().now.store(millis(now), Ordering::SeqCst);
+    }
+
+    /// Move the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.vfs()
+            .now
+            .fetch_add(duration.as_millis() as i64, Ordering::SeqCst);
+    }
+
+    /// Return the name of the VFS of the clock, e.g. to open connections
+    /// with `Connection::open_with_flags_and_vfs`.
+    pub fn vfs_name(&self) -> &str {
+        self.vfs().name.to_str().unwrap()
+    }
+
+    /// Open a new connection to a SQLite database, using this clock.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `path` cannot be converted to a C-compatible
+    /// string or if the underlying SQLite open call fails.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<Connection> {
+        Connection::open_with_flags_and_vfs(path, OpenFlags::default(), self.vfs_name())
+    }
+
+    /// Open a new connection to an in-memory SQLite database, using this
+    /// clock.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite open call fails.
+    pub fn open_in_memory(&self) -> Result<Connection> {
+        Connection::open_in_memory_with_flags_and_vfs(OpenFlags::default(), self.vfs_name())
+    }
+
+    fn vfs(&self) -> &ClockVfs {
+        // The VFS is never freed once registered.
+        unsafe { &*self.vfs }
+    }
+}
+
+impl fmt::Debug for TestClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestClock")
+            .field("vfs", &self.vfs_name())
+            .field("now", &self.now())
+            .finish()
+    }
+}
+
+fn millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
+}
+
+unsafe extern "C" fn current_time(vfs: *mut ffi::sqlite3_vfs, julian_day: *mut f64) -> c_int {
+    let clock = &*(vfs as *const ClockVfs);
+    let now = clock.now.load(Ordering::SeqCst) as f64;
+    // SQLite truncates the time to milliseconds: add half a millisecond so
+    // that rounding errors do not move it to the previous one.
+    *julian_day = UNIX_EPOCH_JULIAN_DAY + (now + 0.5) / MILLIS_PER_DAY;
+    ffi::SQLITE_OK
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::TestClock;
+    use crate::{Connection, NO_PARAMS};
+
+    fn now(db: &Connection) -> String {
+        db.query_row(
+            "SELECT strftime('%Y-%m-%d %H:%M:%f', 'now')",
+            NO_PARAMS,
+            |r| r.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_clock() {
+        let clock = TestClock::new(UNIX_EPOCH + Duration::from_secs(86400)).unwrap();
+        let db = clock.open_in_memory().unwrap();
+        assert_eq!("1970-01-02 00:00:00.000", now(&db));
+        clock.advance(Duration::from_millis(90_001));
+        assert_eq!("1970-01-02 00:01:30.001", now(&db));
+        let ts: String = db
+            .query_row("SELECT CURRENT_TIMESTAMP", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!("1970-01-02 00:01:30", ts);
+        assert_eq!(UNIX_EPOCH + Duration::from_millis(86_490_001), clock.now());
+
+        clock.set(UNIX_EPOCH + Duration::from_secs(1_577_836_800));
+        assert_eq!("2020-01-01 00:00:00.000", now(&db));
+
+        // Another clock is independent
+        let other = TestClock::new(UNIX_EPOCH).unwrap();
+        assert_ne!(clock.vfs_name(), other.vfs_name());
+        assert_eq!(
+            "1970-01-01 00:00:00.000",
+            now(&other.open_in_memory().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_clock_file() {
+        let temp_dir = tempdir::TempDir::new("test_clock_file").unwrap();
+        let clock = TestClock::new(UNIX_EPOCH + Duration::from_secs(60)).unwrap();
+        let db = clock.open(temp_dir.path().join("test.db")).unwrap();
+        db.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE foo(t TEXT DEFAULT CURRENT_TIMESTAMP);
+             INSERT INTO foo DEFAULT VALUES;",
+        )
+        .unwrap();
+        let t: String = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!("1970-01-01 00:01:00", t);
+    }
+}
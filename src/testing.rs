@@ -0,0 +1,164 @@
+//! Helpers to test the `SQLITE_BUSY` handling of an application, e.g. with
+//! a lock held from another connection.
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::ffi;
+use crate::{Connection, Error, Result};
+
+/// An exclusive lock held on a database file by a connection of a helper
+/// thread, so that the `SQLITE_BUSY` retry logic of other connections can be
+/// tested deterministically.
+///
+/// The lock is released when its duration has elapsed, when `release` is
+/// called or when the `LockHolder` is dropped.
+///
+/// ```rust,no_run
+/// # use rusqlite::testing::LockHolder;
+/// # use rusqlite::{Connection, Result};
+/// # use std::time::Duration;
+/// fn test_retry(path: &str) -> Result<()> {
+///     let lock = LockHolder::exclusive_for(path, Duration::from_millis(200))?;
+///     let conn = Connection::open(path)?;
+///     conn.busy_timeout(Duration::from_secs(1))?;
+///     // succeeds after about 200ms
+///     conn.execute_batch("INSERT INTO foo VALUES(1)")?;
+///     lock.release()
+/// }
+/// ```
+pub struct LockHolder {
+    release: Option<SyncSender<()>>,
+    handle: Option<JoinHandle<Result<()>>>,
+}
+
+impl LockHolder {
+    /// Open a connection to the database at `path` in a helper thread, and
+    /// hold an exclusive lock on it (in an exclusive transaction) for
+    /// `duration`. Return once the lock is acquired.
+    ///
+    /// In WAL mode, an exclusive transaction only prevents other
+    /// connections from writing.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the database cannot be opened, or if the lock
+    /// cannot be acquired (e.g. it is held by another connection for more
+    /// than the default 5 seconds busy timeout).
+    pub fn exclusive_for<P: AsRef<Path>>(path: P, duration: Duration) -> Result<LockHolder> {
+        let path = path.as_ref().to_path_buf();
+        let (ready_tx, ready_rx) = sync_channel(0);
+        let (release_tx, release_rx) = sync_channel::<()>(0);
+        let handle = thread::spawn(move || {
+            let conn = match Connection::open(&path)
+                .and_then(|conn| conn.execute_batch("BEGIN EXCLUSIVE").map(|_| conn))
+            {
+                Ok(conn) => conn,
+                Err(err) => {
+                    let _ = ready_tx.send(Err(err));
+                    return Ok(());
+                }
+            };
+            let _ = ready_tx.send(Ok(()));
+            // Returns early when the `LockHolder` is released or dropped.
+            let _ = release_rx.recv_timeout(duration);
+            conn.execute_batch("ROLLBACK")
+        });
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(LockHolder {
+                release: Some(release_tx),
+                handle: Some(handle),
+            }),
+            Ok(Err(err)) => {
+                let _ = handle.join();
+                Err(err)
+            }
+            Err(_) => Err(helper_panicked()),
+        }
+    }
+
+    /// Release the lock now (if it is still held), and wait for the helper
+    /// thread to finish.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the transaction holding the lock cannot be
+    /// rolled back.
+    pub fn release(mut self) -> Result<()> {
+        self.release_()
+    }
+
+    fn release_(&mut self) -> Result<()> {
+        // Dropping the sender wakes up the helper thread.
+        self.release.take();
+        match self.handle.take() {
+            Some(handle) => handle.join().unwrap_or_else(|_| Err(helper_panicked())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for LockHolder {
+    fn drop(&mut self) {
+        let _ = self.release_();
+    }
+}
+
+fn helper_panicked() -> Error {
+    Error::SqliteFailure(
+        ffi::Error::new(ffi::SQLITE_ERROR),
+        Some("lock holder thread panicked".to_owned()),
+    )
+}
+
+/// Suspend the current thread for at least `duration` using
+/// [`sqlite3_sleep`](https://sqlite.org/c3ref/sleep.html) (with the
+/// resolution of the VFS, usually a millisecond), and return the time
+/// actually requested from the operating system.
+pub fn sleep(duration: Duration) -> Duration {
+    let ms = duration.as_millis().min(i32::MAX as u128) as i32;
+    let slept = unsafe { ffi::sqlite3_sleep(ms) };
+    Duration::from_millis(slept.max(0) as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{sleep, LockHolder};
+    use crate::{Connection, ErrorCode};
+
+    #[test]
+    fn test_sleep() {
+        let start = Instant::now();
+        assert!(sleep(Duration::from_millis(20)) >= Duration::from_millis(20));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_lock_holder() {
+        let temp_dir = tempdir::TempDir::new("test_lock_holder").unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = Connection::open(&path).unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+
+        let lock = LockHolder::exclusive_for(&path, Duration::from_secs(60)).unwrap();
+        db.busy_timeout(Duration::from_millis(0)).unwrap();
+        match db.execute_batch("INSERT INTO foo VALUES(1)") {
+            Err(crate::Error::SqliteFailure(err, _)) => {
+                assert_eq!(ErrorCode::DatabaseBusy, err.code)
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+        lock.release().unwrap();
+        db.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
+
+        // The lock expires while the busy handler retries.
+        let start = Instant::now();
+        let _lock = LockHolder::exclusive_for(&path, Duration::from_millis(100)).unwrap();
+        db.busy_timeout(Duration::from_secs(10)).unwrap();
+        db.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
@@ -1,5 +1,12 @@
-use crate::{Connection, Result};
+use crate::busy::BusyHandler;
+use crate::pragma::Sql;
+use crate::{CachedStatement, Connection, DatabaseName, Error, ErrorCode, Result, Row, NO_PARAMS};
+use std::collections::HashMap;
+use std::fmt;
 use std::ops::Deref;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Options for transaction behavior. See [BEGIN
 /// TRANSACTION](http://www.sqlite.org/lang_transaction.html) for details.
@@ -54,6 +61,12 @@ pub enum DropBehavior {
 pub struct Transaction<'conn> {
     conn: &'conn Connection,
     drop_behavior: DropBehavior,
+    cache_statements: bool,
+    // Busy timeout to restore when the transaction ends.
+    previous_busy_timeout: Option<Duration>,
+    // `sqlite3_total_changes` when the transaction began.
+    total_changes_base: i64,
+    tracks_tables: bool,
 }
 
 /// Represents a savepoint on a database connection.
@@ -85,23 +98,113 @@ pub struct Savepoint<'conn> {
     depth: u32,
     drop_behavior: DropBehavior,
     committed: bool,
+    // Whether the savepoint started the transaction (in autocommit mode).
+    outermost: bool,
+    cache_statements: bool,
+    retry: BusyRetry,
 }
 
-impl Transaction<'_> {
+/// How many times, and after which delays, savepoint statements failing
+/// with `SQLITE_BUSY` are retried.
+#[derive(Clone, Copy, Debug, Default)]
+struct BusyRetry {
+    attempts: u32,
+    backoff: Duration,
+}
+
+impl BusyRetry {
+    fn execute(self, conn: &Connection, sql: &str) -> Result<()> {
+        let mut backoff = self.backoff;
+        let mut attempts = self.attempts;
+        loop {
+            match conn.execute_internal(sql) {
+                Err(Error::SqliteFailure(ref err, _))
+                    if err.code == ErrorCode::DatabaseBusy && attempts > 0 =>
+                {
+                    attempts -= 1;
+                    thread::sleep(backoff);
+                    // Keep the last delay once doubling it would overflow.
+                    backoff = backoff.checked_mul(2).unwrap_or(backoff);
+                }
+                r => return r,
+            }
+        }
+    }
+}
+
+/// A row violating a foreign key constraint, as reported by
+/// `Transaction::check_constraints`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForeignKeyViolation {
+    /// Name of the table containing the row.
+    pub table: String,
+    /// Rowid of the row (`None` for a `WITHOUT ROWID` table).
+    pub rowid: Option<i64>,
+    /// Name of the table referenced by the foreign key.
+    pub parent: String,
+    /// Columns of the foreign key in `table`.
+    pub columns: Vec<String>,
+}
+
+impl fmt::Display for ForeignKeyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.table)?;
+        if let Some(rowid) = self.rowid {
+            write!(f, " (rowid {})", rowid)?;
+        }
+        write!(
+            f,
+            ": ({}) references a missing row of {}",
+            self.columns.join(", "),
+            self.parent
+        )
+    }
+}
+
+impl<'conn> Transaction<'conn> {
     /// Begin a new transaction. Cannot be nested; see `savepoint` for nested
     /// transactions.
     /// Even though we don't mutate the connection, we take a `&mut Connection`
     /// so as to prevent nested or concurrent transactions on the same
     /// connection.
     pub fn new(conn: &mut Connection, behavior: TransactionBehavior) -> Result<Transaction<'_>> {
+        Transaction::begin(conn, behavior, None)
+    }
+
+    fn with_busy_timeout(
+        conn: &mut Connection,
+        behavior: TransactionBehavior,
+        timeout: Duration,
+    ) -> Result<Transaction<'_>> {
+        let previous = conn.get_busy_timeout()?;
+        conn.busy_timeout(timeout)?;
+        Transaction::begin(conn, behavior, Some(previous))
+    }
+
+    fn begin(
+        conn: &Connection,
+        behavior: TransactionBehavior,
+        previous_busy_timeout: Option<Duration>,
+    ) -> Result<Transaction<'_>> {
         let query = match behavior {
             TransactionBehavior::Deferred => "BEGIN DEFERRED",
             TransactionBehavior::Immediate => "BEGIN IMMEDIATE",
             TransactionBehavior::Exclusive => "BEGIN EXCLUSIVE",
         };
-        conn.execute_batch(query).map(move |_| Transaction {
+        if let Err(err) = conn.execute_internal(query) {
+            if let Some(timeout) = previous_busy_timeout {
+                conn.busy_timeout(timeout)?;
+            }
+            return Err(err);
+        }
+        let total_changes_base = conn.db.borrow().tx_hooks.total_changes();
+        Ok(Transaction {
             conn,
             drop_behavior: DropBehavior::Rollback,
+            cache_statements: true,
+            previous_busy_timeout,
+            total_changes_base,
+            tracks_tables: false,
         })
     }
 
@@ -133,12 +236,23 @@ impl Transaction<'_> {
     /// }
     /// ```
     pub fn savepoint(&mut self) -> Result<Savepoint<'_>> {
-        Savepoint::with_depth(self.conn, 1)
+        Savepoint::with_depth(self.conn, 1, BusyRetry::default())
     }
 
     /// Create a new savepoint with a custom savepoint name. See `savepoint()`.
     pub fn savepoint_with_name<T: Into<String>>(&mut self, name: T) -> Result<Savepoint<'_>> {
-        Savepoint::with_depth_and_name(self.conn, 1, name)
+        Savepoint::with_depth_and_name(self.conn, 1, name, BusyRetry::default())
+    }
+
+    /// Create a new savepoint whose statements are retried, at most
+    /// `attempts` times, if they fail with `SQLITE_BUSY`. See
+    /// `Savepoint::savepoint_with_retry`.
+    pub fn savepoint_with_retry(
+        &mut self,
+        attempts: u32,
+        backoff: Duration,
+    ) -> Result<Savepoint<'_>> {
+        Savepoint::with_retry(self.conn, 1, attempts, backoff)
     }
 
     /// Get the current setting for what happens to the transaction when it is
@@ -153,23 +267,210 @@ impl Transaction<'_> {
         self.drop_behavior = drop_behavior
     }
 
+    /// Prepare a SQL statement for execution within the transaction.
+    ///
+    /// By default, this is equivalent to `Connection::prepare_cached`: the
+    /// statement cache is shared with the connection, so statements prepared
+    /// here remain cached after the transaction is committed (they are
+    /// invalidated if it is rolled back). If caching has been disabled with
+    /// `set_statement_caching(false)`, the statement is prepared from scratch
+    /// and finalized when dropped.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails.
+    pub fn prepare_cached(&self, sql: &str) -> Result<CachedStatement<'conn>> {
+        if self.cache_statements {
+            self.conn.prepare_cached(sql)
+        } else {
+            self.conn.prepare_uncached(sql)
+        }
+    }
+
+    /// Enable or disable the statement cache for `prepare_cached` calls made
+    /// through this transaction, e.g. to avoid filling the cache with one-off
+    /// statements during a DDL-heavy migration.
+    pub fn set_statement_caching(&mut self, enabled: bool) {
+        self.cache_statements = enabled
+    }
+
+    /// Record the tables changed by this transaction, so that
+    /// `check_constraints` only checks them. It must be called before the
+    /// first change: otherwise all the tables are checked.
+    ///
+    /// The tables are recorded by an update hook, invoked for each changed
+    /// row: it is only registered by this method, and unregistered when the
+    /// transaction ends (the update hook set with `Connection::update_hook`
+    /// is still invoked meanwhile).
+    pub fn track_changed_tables(&mut self) {
+        if !self.tracks_tables {
+            self.tracks_tables = true;
+            self.conn
+                .db
+                .borrow()
+                .tx_hooks
+                .track_tables(Some(self.total_changes_base));
+        }
+    }
+
+    /// Return the rows of the main database, in the tables changed by this
+    /// transaction (and the tables referencing them), which currently
+    /// violate a foreign key constraint.
+    ///
+    /// Deferred foreign key constraints (and all of them once `PRAGMA
+    /// defer_foreign_keys` is on) are only enforced at `COMMIT`, which then
+    /// fails with an opaque `FOREIGN KEY constraint failed` error. Calling
+    /// this before `commit` tells which rows are to blame, so that they can
+    /// be fixed or reported. Violations are reported even when foreign key
+    /// enforcement is disabled.
+    ///
+    /// All the tables are checked unless `track_changed_tables` was called.
+    /// The changed tables are recorded by an update hook, which does not see
+    /// every change (e.g. to a `WITHOUT ROWID` table): all the tables are
+    /// checked as well when some changes were missed. Schema changes other
+    /// than dropped tables are not taken into account, see
+    /// `check_table_constraints`.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn import(conn: &mut Connection, sql: &str) -> Result<()> {
+    ///     let mut tx = conn.transaction()?;
+    ///     tx.track_changed_tables();
+    ///     tx.execute_batch("PRAGMA defer_foreign_keys = ON")?;
+    ///     tx.execute_batch(sql)?;
+    ///     for violation in tx.check_constraints()? {
+    ///         eprintln!("{}", violation);
+    ///     }
+    ///     tx.commit()
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn check_constraints(&self) -> Result<Vec<ForeignKeyViolation>> {
+        let touched = self.conn.db.borrow().tx_hooks.touched_tables();
+        match touched {
+            Some(touched) => {
+                let tables = self.conn.referencing_tables(touched)?;
+                let tables: Vec<&str> = tables.iter().map(String::as_str).collect();
+                self.check_table_constraints(&tables)
+            }
+            None => self.conn.foreign_key_check(None),
+        }
+    }
+
+    /// Like `check_constraints`, but only check the rows of `tables`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a table does not exist or if the underlying
+    /// SQLite calls fail.
+    pub fn check_table_constraints(&self, tables: &[&str]) -> Result<Vec<ForeignKeyViolation>> {
+        let mut violations = Vec::new();
+        for table in tables {
+            violations.extend(self.conn.foreign_key_check(Some(table))?);
+        }
+        Ok(violations)
+    }
+
     /// A convenience method which consumes and commits a transaction.
     pub fn commit(mut self) -> Result<()> {
         self.commit_()
     }
 
     fn commit_(&mut self) -> Result<()> {
-        self.conn.execute_batch("COMMIT")?;
+        self.conn.execute_internal("COMMIT")?;
         Ok(())
     }
 
+    /// Consume and commit the transaction, giving up at `deadline`, e.g. to
+    /// bound the latency of a request handler.
+    ///
+    /// While the database is locked by another connection, the commit is
+    /// retried (with a busy handler sleeping at most until the deadline),
+    /// until the deadline. The transaction is then rolled back (or finished
+    /// according to `drop_behavior`). Once the locks are acquired, the
+    /// commit is not interrupted: SQLite cannot interrupt the writing of the
+    /// changes, which may still end after the deadline.
+    ///
+    /// The busy timeout (see `get_busy_timeout`), or the busy handler set
+    /// with `busy_handler` or `set_busy_handler`, is restored afterwards.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Error, Result};
+    /// # use std::time::{Duration, Instant};
+    /// fn save(conn: &mut Connection) -> Result<bool> {
+    ///     let tx = conn.transaction()?;
+    ///     tx.execute_batch("UPDATE counters SET hits = hits + 1")?;
+    ///     match tx.commit_with_deadline(Instant::now() + Duration::from_millis(50)) {
+    ///         Ok(()) => Ok(true),
+    ///         Err(Error::DeadlineExceeded) => Ok(false),
+    ///         Err(err) => Err(err),
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::DeadlineExceeded)` if the commit failed
+    /// because the database was still locked at the deadline, or `Err` if
+    /// the underlying SQLite call fails.
+    pub fn commit_with_deadline(mut self, deadline: Instant) -> Result<()> {
+        let previous_timeout = match self.previous_busy_timeout.take() {
+            Some(timeout) => timeout,
+            None => self.conn.get_busy_timeout()?,
+        };
+        let handler: BusyHandler = Box::new(move |attempts: i32| {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            let backoff = Duration::from_millis(1 << attempts.min(5));
+            thread::sleep(backoff.min(deadline - now));
+            true
+        });
+        let previous_handler = self
+            .conn
+            .db
+            .borrow_mut()
+            .replace_busy_handler(Some(Box::new(handler)))?;
+
+        let r = self.commit_();
+        // The outcome of the commit matters more than a failure to restore
+        // the busy handler, which SQLite only reports for a closed
+        // connection.
+        let _ = match previous_handler {
+            Some(handler) => self
+                .conn
+                .db
+                .borrow_mut()
+                .replace_busy_handler(Some(handler))
+                .map(|_| ()),
+            None => self.conn.busy_timeout(previous_timeout),
+        };
+
+        match r {
+            Err(Error::SqliteFailure(ref err, _))
+                if err.code == ErrorCode::DatabaseBusy && Instant::now() >= deadline =>
+            {
+                Err(Error::DeadlineExceeded)
+            }
+            r => r,
+        }
+    }
+
     /// A convenience method which consumes and rolls back a transaction.
     pub fn rollback(mut self) -> Result<()> {
         self.rollback_()
     }
 
     fn rollback_(&mut self) -> Result<()> {
-        self.conn.execute_batch("ROLLBACK")?;
+        self.conn.execute_internal("ROLLBACK")?;
+        self.conn.invalidate_cached_statements();
         Ok(())
     }
 
@@ -207,49 +508,131 @@ impl Deref for Transaction<'_> {
 impl Drop for Transaction<'_> {
     fn drop(&mut self) {
         self.finish_();
+        if self.tracks_tables {
+            self.conn.db.borrow().tx_hooks.track_tables(None);
+        }
+        if let Some(timeout) = self.previous_busy_timeout {
+            self.conn.busy_timeout(timeout);
+        }
     }
 }
 
-impl Savepoint<'_> {
+impl<'conn> Savepoint<'conn> {
     fn with_depth_and_name<T: Into<String>>(
         conn: &Connection,
         depth: u32,
         name: T,
+        retry: BusyRetry,
     ) -> Result<Savepoint<'_>> {
-        let name = name.into();
-        conn.execute_batch(&format!("SAVEPOINT {}", name))
-            .map(|_| Savepoint {
-                conn,
-                name,
-                depth,
-                drop_behavior: DropBehavior::Rollback,
-                committed: false,
-            })
+        Savepoint::begin(conn, depth, name.into(), retry)
+    }
+
+    fn with_retry(
+        conn: &Connection,
+        depth: u32,
+        attempts: u32,
+        backoff: Duration,
+    ) -> Result<Savepoint<'_>> {
+        let name = format!("_rusqlite_sp_{}", depth);
+        Savepoint::begin(conn, depth, name, BusyRetry { attempts, backoff })
+    }
+
+    fn begin(
+        conn: &Connection,
+        depth: u32,
+        name: String,
+        retry: BusyRetry,
+    ) -> Result<Savepoint<'_>> {
+        let mut sp = Savepoint {
+            conn,
+            name,
+            depth,
+            drop_behavior: DropBehavior::Rollback,
+            committed: false,
+            outermost: conn.is_autocommit(),
+            cache_statements: true,
+            retry,
+        };
+        let r = sp.execute(&format!("SAVEPOINT {}", sp.name));
+        if r.is_err() {
+            // There is nothing to roll back.
+            sp.committed = true;
+        }
+        r.map(|_| sp)
+    }
+
+    /// Run a savepoint statement, retrying it according to `retry`, and add
+    /// the name and depth of the savepoint to the error message.
+    fn execute(&self, sql: &str) -> Result<()> {
+        self.retry.execute(self.conn, sql).map_err(|err| match err {
+            Error::SqliteFailure(err, msg) => {
+                let msg = msg.unwrap_or_else(|| err.to_string());
+                Error::SqliteFailure(
+                    err,
+                    Some(format!(
+                        "{} (savepoint {} at depth {})",
+                        msg, self.name, self.depth
+                    )),
+                )
+            }
+            err => err,
+        })
     }
 
-    fn with_depth(conn: &Connection, depth: u32) -> Result<Savepoint<'_>> {
+    fn with_depth(conn: &Connection, depth: u32, retry: BusyRetry) -> Result<Savepoint<'_>> {
         let name = format!("_rusqlite_sp_{}", depth);
-        Savepoint::with_depth_and_name(conn, depth, name)
+        Savepoint::with_depth_and_name(conn, depth, name, retry)
     }
 
     /// Begin a new savepoint. Can be nested.
     pub fn new(conn: &mut Connection) -> Result<Savepoint<'_>> {
-        Savepoint::with_depth(conn, 0)
+        Savepoint::with_depth(conn, 0, BusyRetry::default())
     }
 
     /// Begin a new savepoint with a user-provided savepoint name.
     pub fn with_name<T: Into<String>>(conn: &mut Connection, name: T) -> Result<Savepoint<'_>> {
-        Savepoint::with_depth_and_name(conn, 0, name)
+        Savepoint::with_depth_and_name(conn, 0, name, BusyRetry::default())
     }
 
-    /// Begin a nested savepoint.
+    /// Begin a nested savepoint, whose statements are retried like the ones
+    /// of this savepoint (see `savepoint_with_retry`).
     pub fn savepoint(&mut self) -> Result<Savepoint<'_>> {
-        Savepoint::with_depth(self.conn, self.depth + 1)
+        Savepoint::with_depth(self.conn, self.depth + 1, self.retry)
     }
 
-    /// Begin a nested savepoint with a user-provided savepoint name.
+    /// Begin a nested savepoint with a user-provided savepoint name. See
+    /// `savepoint()`.
     pub fn savepoint_with_name<T: Into<String>>(&mut self, name: T) -> Result<Savepoint<'_>> {
-        Savepoint::with_depth_and_name(self.conn, self.depth + 1, name)
+        Savepoint::with_depth_and_name(self.conn, self.depth + 1, name, self.retry)
+    }
+
+    /// Begin a nested savepoint whose statements (`SAVEPOINT`, `RELEASE` and
+    /// `ROLLBACK TO`) are retried, at most `attempts` times, if they fail
+    /// with `SQLITE_BUSY`, waiting `backoff` before the first retry and
+    /// twice as long before each of the next ones. The savepoints nested in
+    /// it are retried the same way.
+    ///
+    /// This is useful for the outermost savepoint, whose `RELEASE` commits
+    /// the transaction, when the busy handler of the connection is not
+    /// enough.
+    pub fn savepoint_with_retry(
+        &mut self,
+        attempts: u32,
+        backoff: Duration,
+    ) -> Result<Savepoint<'_>> {
+        Savepoint::with_retry(self.conn, self.depth + 1, attempts, backoff)
+    }
+
+    /// Return the name of the savepoint.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the nesting depth of the savepoint (0 for a savepoint started
+    /// on a connection, 1 for a savepoint started in a transaction or
+    /// another savepoint of depth 0, etc.).
+    pub fn depth(&self) -> u32 {
+        self.depth
     }
 
     /// Get the current setting for what happens to the savepoint when it is
@@ -264,17 +647,66 @@ impl Savepoint<'_> {
         self.drop_behavior = drop_behavior
     }
 
+    /// Prepare a SQL statement for execution within the savepoint.
+    ///
+    /// By default, this is equivalent to `Connection::prepare_cached`: the
+    /// statement cache is shared with the connection, so statements prepared
+    /// here remain cached after the savepoint is committed (they are
+    /// invalidated if it is rolled back). If caching has been disabled with
+    /// `set_statement_caching(false)`, the statement is prepared from scratch
+    /// and finalized when dropped.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails.
+    pub fn prepare_cached(&self, sql: &str) -> Result<CachedStatement<'conn>> {
+        if self.cache_statements {
+            self.conn.prepare_cached(sql)
+        } else {
+            self.conn.prepare_uncached(sql)
+        }
+    }
+
+    /// Enable or disable the statement cache for `prepare_cached` calls made
+    /// through this savepoint, e.g. to avoid filling the cache with one-off
+    /// statements during a DDL-heavy migration.
+    pub fn set_statement_caching(&mut self, enabled: bool) {
+        self.cache_statements = enabled
+    }
+
     /// A convenience method which consumes and commits a savepoint.
     pub fn commit(mut self) -> Result<()> {
         self.commit_()
     }
 
     fn commit_(&mut self) -> Result<()> {
-        self.conn.execute_batch(&format!("RELEASE {}", self.name))?;
+        self.execute(&format!("RELEASE {}", self.name))?;
         self.committed = true;
         Ok(())
     }
 
+    /// Commit the savepoint, and immediately begin a new one with the same
+    /// name and depth, e.g. to keep the progress of a long loop if it is
+    /// interrupted: a later rollback only undoes the changes made since.
+    ///
+    /// The changes are only durable if this is the outermost savepoint (whose
+    /// release commits the transaction); otherwise they become part of the
+    /// enclosing transaction or savepoint.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the savepoint cannot be committed, or if the new
+    /// one cannot be begun (in which case the savepoint is committed, and
+    /// dropping it has no effect).
+    pub fn commit_and_continue(&mut self) -> Result<()> {
+        self.commit_()?;
+        self.outermost = self.conn.is_autocommit();
+        self.execute(&format!("SAVEPOINT {}", self.name))?;
+        self.committed = false;
+        Ok(())
+    }
+
     /// A convenience method which rolls back a savepoint.
     ///
     /// ## Note
@@ -282,8 +714,9 @@ impl Savepoint<'_> {
     /// Unlike `Transaction`s, savepoints remain active after they have been
     /// rolled back, and can be rolled back again or committed.
     pub fn rollback(&mut self) -> Result<()> {
-        self.conn
-            .execute_batch(&format!("ROLLBACK TO {}", self.name))
+        self.execute(&format!("ROLLBACK TO {}", self.name))?;
+        self.conn.invalidate_cached_statements();
+        Ok(())
     }
 
     /// Consumes the savepoint, committing or rolling back according to the
@@ -300,12 +733,35 @@ impl Savepoint<'_> {
             return Ok(());
         }
         match self.drop_behavior() {
-            DropBehavior::Commit => self.commit_().or_else(|_| self.rollback()),
-            DropBehavior::Rollback => self.rollback(),
+            DropBehavior::Commit => self
+                .commit_()
+                .or_else(|err| self.rollback_and_release().and(Err(err))),
+            DropBehavior::Rollback => self.rollback_and_release(),
             DropBehavior::Ignore => Ok(()),
             DropBehavior::Panic => panic!("Savepoint dropped unexpectedly."),
         }
     }
+
+    /// Roll back and remove the savepoint: `ROLLBACK TO` alone leaves it on
+    /// the savepoint stack, and leaves open the transaction it started if the
+    /// connection was in autocommit mode.
+    ///
+    /// The error of `RELEASE` is returned even if the transaction started by
+    /// the savepoint could be rolled back instead.
+    fn rollback_and_release(&mut self) -> Result<()> {
+        self.rollback()?;
+        let r = self.commit_();
+        if r.is_err() && self.outermost && !self.conn.is_autocommit() {
+            // Ending the transaction with `RELEASE` needs the locks of a
+            // commit, even without changes (e.g. `SQLITE_BUSY` while another
+            // connection is reading): roll it back so that it is not left
+            // open.
+            if self.conn.execute_internal("ROLLBACK").is_ok() {
+                self.committed = true;
+            }
+        }
+        r
+    }
 }
 
 impl Deref for Savepoint<'_> {
@@ -367,6 +823,25 @@ impl Connection {
         Transaction::new(self, behavior)
     }
 
+    /// Begin a new transaction with a specified behavior, using a specific
+    /// busy timeout (see `busy_timeout`) to begin it and until it ends, e.g. a
+    /// long timeout for a critical write transaction.
+    ///
+    /// The previous busy timeout (see `get_busy_timeout`) is restored when
+    /// the transaction is committed, rolled back or dropped; a busy handler
+    /// set with `busy_handler` or `set_busy_handler` is not restored.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn transaction_with_busy_timeout(
+        &mut self,
+        behavior: TransactionBehavior,
+        timeout: Duration,
+    ) -> Result<Transaction<'_>> {
+        Transaction::with_busy_timeout(self, behavior, timeout)
+    }
+
     /// Begin a new savepoint with the default behavior (DEFERRED).
     ///
     /// The savepoint defaults to rolling back when it is dropped. If you want
@@ -398,7 +873,10 @@ impl Connection {
 
     /// Begin a new savepoint with a specified name.
     ///
-    /// See `savepoint`.
+    /// As with a `SAVEPOINT` statement, if the connection is in autocommit
+    /// mode, the savepoint starts a transaction, which is committed when the
+    /// savepoint is committed (`RELEASE`), and rolled back when it is dropped
+    /// after having been rolled back. See `savepoint`.
     ///
     /// # Failure
     ///
@@ -406,12 +884,224 @@ impl Connection {
     pub fn savepoint_with_name<T: Into<String>>(&mut self, name: T) -> Result<Savepoint<'_>> {
         Savepoint::with_name(self, name)
     }
+
+    /// Begin a new savepoint whose statements are retried, at most
+    /// `attempts` times, if they fail with `SQLITE_BUSY`. See
+    /// `Savepoint::savepoint_with_retry`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn savepoint_with_retry(
+        &mut self,
+        attempts: u32,
+        backoff: Duration,
+    ) -> Result<Savepoint<'_>> {
+        Savepoint::with_retry(self, 0, attempts, backoff)
+    }
+
+    /// Attach the database files `databases` (schema name and path pairs),
+    /// run `f` in a single transaction spanning them and the main database,
+    /// and detach them afterwards (even if `f` or the commit fails).
+    ///
+    /// The transaction is committed if `f` returns `Ok`, and rolled back
+    /// otherwise.
+    ///
+    /// SQLite only guarantees that a transaction updating several database
+    /// files is atomic (i.e. that a crash during the commit cannot leave some
+    /// of the files updated and the others not) if the main database is a
+    /// file, and if all the databases use a rollback journal (`DELETE`,
+    /// `TRUNCATE` or `PERSIST` journal mode). This is checked before the
+    /// transaction is started.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, NO_PARAMS};
+    /// fn archive_orders(conn: &mut Connection) -> Result<usize> {
+    ///     conn.transaction_across(&[("archive", "archive.db")], |tx| {
+    ///         tx.execute(
+    ///             "INSERT INTO archive.orders SELECT * FROM main.orders WHERE done",
+    ///             NO_PARAMS,
+    ///         )?;
+    ///         tx.execute("DELETE FROM main.orders WHERE done", NO_PARAMS)
+    ///     })
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a database cannot be attached (e.g. if this
+    /// connection is already in a transaction), an
+    /// `Error::IncompatibleDatabaseSetting` if the atomicity of the
+    /// transaction is not guaranteed, or the error returned by `f` or by the
+    /// underlying SQLite calls.
+    pub fn transaction_across<P, F, T>(&mut self, databases: &[(&str, P)], f: F) -> Result<T>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&Transaction<'_>) -> Result<T>,
+    {
+        let mut attached = Vec::with_capacity(databases.len());
+        let result = self.run_across(databases, &mut attached, f);
+        let mut detached = Ok(());
+        for name in attached {
+            let mut sql = Sql::new();
+            sql.push_keyword("DETACH")?;
+            sql.push_space();
+            sql.push_identifier(name);
+            detached = detached.and(self.execute_batch(sql.as_str()));
+        }
+        let value = result?;
+        detached.map(|_| value)
+    }
+
+    fn run_across<'a, P, F, T>(
+        &mut self,
+        databases: &[(&'a str, P)],
+        attached: &mut Vec<&'a str>,
+        f: F,
+    ) -> Result<T>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&Transaction<'_>) -> Result<T>,
+    {
+        for &(name, ref path) in databases {
+            let path = path.as_ref();
+            let file = path
+                .to_str()
+                .ok_or_else(|| Error::InvalidPath(path.to_owned()))?;
+            let mut sql = Sql::new();
+            sql.push_keyword("ATTACH")?;
+            sql.push_space();
+            sql.push_string_literal(file);
+            sql.push_space();
+            sql.push_keyword("AS")?;
+            sql.push_space();
+            sql.push_identifier(name);
+            self.execute_batch(sql.as_str())?;
+            attached.push(name);
+        }
+        self.check_atomic_commit()?;
+
+        let tx = self.transaction()?;
+        let value = f(&tx)?;
+        tx.commit()?;
+        Ok(value)
+    }
+
+    fn check_atomic_commit(&self) -> Result<()> {
+        let mut schemas = Vec::new();
+        self.pragma_query(None, "database_list", |row| {
+            schemas.push((row.get::<_, String>(1)?, row.get::<_, String>(2)?));
+            Ok(())
+        })?;
+        for (name, file) in schemas {
+            if name == "temp" {
+                continue;
+            }
+            if name == "main" && file.is_empty() {
+                return Err(Error::IncompatibleDatabaseSetting(
+                    "the main database must be a file for a multi-database transaction to be \
+                     atomic"
+                        .to_owned(),
+                ));
+            }
+            let mode: String = self.pragma_query_value(
+                Some(DatabaseName::Attached(&name)),
+                "journal_mode",
+                |row| row.get(0),
+            )?;
+            match mode.to_ascii_lowercase().as_str() {
+                "delete" | "truncate" | "persist" => {}
+                _ => {
+                    return Err(Error::IncompatibleDatabaseSetting(format!(
+                        "journal mode {} of database {} does not allow an atomic multi-database \
+                         transaction",
+                        mode, name
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Return `tables`, and the tables of the main database whose foreign
+    /// keys reference one of them or a missing (e.g. dropped) table.
+    fn referencing_tables(&self, mut tables: Vec<String>) -> Result<Vec<String>> {
+        if tables.is_empty() {
+            return Ok(tables);
+        }
+        let mut stmt = self.prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?;
+        let all = stmt
+            .query_map(NO_PARAMS, |row| row.get(0))?
+            .collect::<Result<Vec<String>>>()?;
+        let known =
+            |name: &str, names: &[String]| names.iter().any(|n| n.eq_ignore_ascii_case(name));
+
+        let mut referencing = Vec::new();
+        for table in &all {
+            if known(table, &tables) {
+                continue;
+            }
+            let mut references = false;
+            self.pragma(None, "foreign_key_list", table, |row| {
+                let parent: String = row.get(2)?;
+                references |= known(&parent, &tables) || !known(&parent, &all);
+                Ok(())
+            })?;
+            if references {
+                referencing.push(table.clone());
+            }
+        }
+        // Dropped tables cannot be checked.
+        tables.retain(|table| known(table, &all));
+        tables.extend(referencing);
+        Ok(tables)
+    }
+
+    fn foreign_key_check(&self, table: Option<&str>) -> Result<Vec<ForeignKeyViolation>> {
+        let mut rows: Vec<(String, Option<i64>, String, i64)> = Vec::new();
+        let mut push = |row: &Row<'_>| {
+            rows.push((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?));
+            Ok(())
+        };
+        match table {
+            Some(table) => self.pragma(None, "foreign_key_check", &table, &mut push)?,
+            None => self.pragma_query(None, "foreign_key_check", &mut push)?,
+        };
+
+        // foreign_key_check only gives the index of the foreign key in
+        // foreign_key_list.
+        let mut columns: HashMap<(String, i64), Vec<String>> = HashMap::new();
+        let mut violations = Vec::with_capacity(rows.len());
+        for (table, rowid, parent, fk) in rows {
+            if !columns.contains_key(&(table.clone(), fk)) {
+                self.pragma(None, "foreign_key_list", &table, |row| {
+                    let id: i64 = row.get(0)?;
+                    columns
+                        .entry((table.clone(), id))
+                        .or_default()
+                        .push(row.get(3)?);
+                    Ok(())
+                })?;
+            }
+            violations.push(ForeignKeyViolation {
+                columns: columns
+                    .get(&(table.clone(), fk))
+                    .cloned()
+                    .unwrap_or_default(),
+                table,
+                rowid,
+                parent,
+            });
+        }
+        Ok(violations)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::DropBehavior;
-    use crate::{Connection, NO_PARAMS};
+    use crate::{Connection, Error, ErrorCode, TransactionBehavior, NO_PARAMS};
+    use std::time::Duration;
 
     fn checked_memory_handle() -> Connection {
         let db = Connection::open_in_memory().unwrap();
@@ -505,6 +1195,33 @@ mod test {
         assert_current_sum(1, &db);
     }
 
+    #[test]
+    fn test_savepoint_commit_and_continue() {
+        let mut db = checked_memory_handle();
+        {
+            let mut sp = db.savepoint().unwrap();
+            insert(1, &sp);
+            sp.commit_and_continue().unwrap();
+            insert(2, &sp);
+            sp.commit_and_continue().unwrap();
+            insert(4, &sp);
+            assert_current_sum(7, &sp);
+            // will rollback the last insert only
+        }
+        assert_current_sum(3, &db);
+        assert!(db.is_autocommit());
+
+        let mut tx = db.transaction().unwrap();
+        {
+            let mut sp = tx.savepoint().unwrap();
+            insert(8, &sp);
+            sp.commit_and_continue().unwrap();
+            assert_eq!(1, sp.depth());
+            insert(16, &sp);
+        }
+        assert_current_sum(11, &tx);
+    }
+
     #[test]
     fn test_ignore_drop_behavior() {
         let mut db = checked_memory_handle();
@@ -556,6 +1273,238 @@ mod test {
         assert_current_sum(8, &db);
     }
 
+    #[test]
+    fn test_transaction_with_busy_timeout() {
+        let mut db = checked_memory_handle();
+        db.busy_timeout(Duration::from_millis(100)).unwrap();
+        {
+            let tx = db
+                .transaction_with_busy_timeout(
+                    TransactionBehavior::Immediate,
+                    Duration::from_secs(30),
+                )
+                .unwrap();
+            assert_eq!(Duration::from_secs(30), tx.get_busy_timeout().unwrap());
+            tx.commit().unwrap();
+        }
+        assert_eq!(Duration::from_millis(100), db.get_busy_timeout().unwrap());
+        {
+            let _tx = db
+                .transaction_with_busy_timeout(
+                    TransactionBehavior::Deferred,
+                    Duration::from_secs(1),
+                )
+                .unwrap();
+        }
+        assert_eq!(Duration::from_millis(100), db.get_busy_timeout().unwrap());
+    }
+
+    #[test]
+    fn test_savepoint_without_transaction() {
+        let mut db = checked_memory_handle();
+        insert(1, &db);
+        {
+            let sp = db.savepoint_with_name("x").unwrap();
+            assert!(!sp.is_autocommit());
+            insert(2, &sp);
+        }
+        assert!(db.is_autocommit());
+        assert_current_sum(1, &db);
+        {
+            let mut sp = db.savepoint_with_name("x").unwrap();
+            insert(2, &sp);
+            sp.rollback().unwrap();
+            assert!(!sp.is_autocommit());
+            insert(4, &sp);
+            sp.commit().unwrap();
+        }
+        assert!(db.is_autocommit());
+        assert_current_sum(5, &db);
+
+        let mut tx = db.transaction().unwrap();
+        {
+            let sp = tx.savepoint_with_name("x").unwrap();
+            insert(8, &sp);
+        }
+        insert(16, &tx);
+        tx.execute_batch("RELEASE x").unwrap_err();
+        tx.commit().unwrap();
+        assert_current_sum(21, &db);
+    }
+
+    #[test]
+    fn test_savepoint_busy_retry() {
+        use std::thread;
+        use std::time::Duration;
+        use tempdir::TempDir;
+
+        let temp_dir = TempDir::new("test_savepoint_busy_retry").unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let mut db1 = Connection::open(&path).unwrap();
+        db1.execute_batch("CREATE TABLE foo (x INTEGER)").unwrap();
+        let db2 = Connection::open(&path).unwrap();
+
+        // RELEASE of the outermost savepoint needs the exclusive lock, which
+        // cannot be acquired while another connection is reading.
+        db2.execute_batch("BEGIN; SELECT count(*) FROM foo;")
+            .unwrap();
+        {
+            let sp = db1.savepoint().unwrap();
+            sp.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
+            match sp.commit().unwrap_err() {
+                Error::SqliteFailure(ref err, Some(ref msg)) => {
+                    assert_eq!(ErrorCode::DatabaseBusy, err.code);
+                    assert!(
+                        msg.contains("savepoint _rusqlite_sp_0 at depth 0"),
+                        "{}",
+                        msg
+                    );
+                }
+                err => panic!("Unexpected error {}", err),
+            };
+        }
+        // The failed savepoint has been rolled back, and its transaction
+        // ended.
+        let count = |db: &Connection| -> i64 {
+            db.query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))
+                .unwrap()
+        };
+        assert!(db1.is_autocommit());
+        assert_eq!(0, count(&db1));
+
+        // Its RELEASE still fails after the ROLLBACK TO: the error is
+        // returned.
+        {
+            let mut sp = db1.savepoint().unwrap();
+            sp.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
+            sp.set_drop_behavior(DropBehavior::Commit);
+            match sp.finish().unwrap_err() {
+                Error::SqliteFailure(ref err, _) => {
+                    assert_eq!(ErrorCode::DatabaseBusy, err.code)
+                }
+                err => panic!("Unexpected error {}", err),
+            };
+        }
+        assert!(db1.is_autocommit());
+        {
+            let sp = db1.savepoint().unwrap();
+            sp.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
+            match sp.finish().unwrap_err() {
+                Error::SqliteFailure(ref err, Some(ref msg)) => {
+                    assert_eq!(ErrorCode::DatabaseBusy, err.code);
+                    assert!(msg.contains("savepoint _rusqlite_sp_0"), "{}", msg);
+                }
+                err => panic!("Unexpected error {}", err),
+            };
+        }
+        assert!(db1.is_autocommit());
+        assert_eq!(0, count(&db1));
+
+        let reader = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            db2.execute_batch("COMMIT").unwrap();
+        });
+        {
+            let sp = db1
+                .savepoint_with_retry(10, Duration::from_millis(5))
+                .unwrap();
+            sp.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
+            sp.commit().unwrap();
+        }
+        reader.join().unwrap();
+        assert_current_sum(2, &db1);
+
+        {
+            let mut sp = db1.savepoint().unwrap();
+            let sp = sp.savepoint_with_name("inner").unwrap();
+            assert_eq!("inner", sp.name());
+            assert_eq!(1, sp.depth());
+        }
+
+        let mut sp = db1
+            .savepoint_with_retry(3, Duration::from_millis(5))
+            .unwrap();
+        let mut nested = sp.savepoint().unwrap();
+        assert_eq!(3, nested.retry.attempts);
+        let named = nested.savepoint_with_name("inner").unwrap();
+        assert_eq!(Duration::from_millis(5), named.retry.backoff);
+    }
+
+    #[test]
+    fn test_commit_with_deadline() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use tempdir::TempDir;
+
+        let temp_dir = TempDir::new("test_commit_with_deadline").unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let mut db1 = Connection::open(&path).unwrap();
+        db1.execute_batch("CREATE TABLE foo (x INTEGER)").unwrap();
+        db1.busy_timeout(Duration::from_secs(30)).unwrap();
+        let db2 = Connection::open(&path).unwrap();
+
+        // COMMIT needs the exclusive lock, which cannot be acquired while
+        // another connection is reading.
+        db2.execute_batch("BEGIN; SELECT count(*) FROM foo;")
+            .unwrap();
+        {
+            let tx = db1.transaction().unwrap();
+            tx.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
+            let start = Instant::now();
+            let err = tx
+                .commit_with_deadline(start + Duration::from_millis(100))
+                .unwrap_err();
+            assert_eq!(Error::DeadlineExceeded, err);
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+            assert!(elapsed < Duration::from_secs(5), "{:?}", elapsed);
+        }
+        assert!(db1.is_autocommit());
+        assert_eq!(Duration::from_secs(30), db1.get_busy_timeout().unwrap());
+        let count: i64 = db1
+            .query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(0, count);
+
+        let reader = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            db2.execute_batch("COMMIT").unwrap();
+        });
+        {
+            let tx = db1.transaction().unwrap();
+            tx.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
+            tx.commit_with_deadline(Instant::now() + Duration::from_secs(10))
+                .unwrap();
+        }
+        reader.join().unwrap();
+        assert_current_sum(2, &db1);
+
+        // A busy handler is restored.
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        db1.set_busy_handler(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            false
+        })
+        .unwrap();
+        {
+            let tx = db1.transaction().unwrap();
+            tx.execute_batch("INSERT INTO foo VALUES(3)").unwrap();
+            tx.commit_with_deadline(Instant::now() + Duration::from_secs(10))
+                .unwrap();
+        }
+        assert_eq!(0, calls.load(Ordering::SeqCst));
+        let db3 = Connection::open(&path).unwrap();
+        db3.execute_batch("BEGIN; SELECT count(*) FROM foo;")
+            .unwrap();
+        let tx = db1.transaction().unwrap();
+        tx.execute_batch("INSERT INTO foo VALUES(4)").unwrap();
+        assert!(tx.commit().is_err());
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+    }
+
     #[test]
     fn test_rc() {
         use std::rc::Rc;
@@ -566,6 +1515,194 @@ mod test {
         Rc::try_unwrap(rc_txn).unwrap();
     }
 
+    #[test]
+    fn test_check_constraints() {
+        use super::ForeignKeyViolation;
+
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "PRAGMA foreign_keys = ON;
+             CREATE TABLE parent (id INTEGER PRIMARY KEY);
+             CREATE TABLE child (id INTEGER PRIMARY KEY, x INTEGER,
+                                 parent_id INTEGER REFERENCES parent (id)
+                                 DEFERRABLE INITIALLY DEFERRED);
+             INSERT INTO parent VALUES (1);",
+        )
+        .unwrap();
+        let tx = db.transaction().unwrap();
+        tx.execute_batch("INSERT INTO child VALUES (1, 0, 1), (2, 0, 2), (3, 0, 3)")
+            .unwrap();
+        let violations = tx.check_constraints().unwrap();
+        assert_eq!(2, violations.len());
+        assert_eq!(
+            ForeignKeyViolation {
+                table: "child".to_owned(),
+                rowid: Some(2),
+                parent: "parent".to_owned(),
+                columns: vec!["parent_id".to_owned()],
+            },
+            violations[0]
+        );
+        assert_eq!(
+            "child (rowid 3): (parent_id) references a missing row of parent",
+            violations[1].to_string()
+        );
+        assert!(tx.check_table_constraints(&["parent"]).unwrap().is_empty());
+        assert_eq!(2, tx.check_table_constraints(&["child"]).unwrap().len());
+        assert!(tx.check_table_constraints(&["missing"]).is_err());
+        assert!(tx.commit().is_err());
+
+        let tx = db.transaction().unwrap();
+        tx.execute_batch("INSERT INTO parent VALUES (2), (3)")
+            .unwrap();
+        assert!(tx.check_constraints().unwrap().is_empty());
+        tx.commit().unwrap();
+    }
+
+    #[test]
+    fn test_check_constraints_of_changed_tables() {
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "PRAGMA foreign_keys = OFF;
+             CREATE TABLE parent (id INTEGER PRIMARY KEY);
+             CREATE TABLE child (id INTEGER PRIMARY KEY,
+                                 parent_id INTEGER REFERENCES parent (id)
+                                 DEFERRABLE INITIALLY DEFERRED);
+             CREATE TABLE stale (id INTEGER PRIMARY KEY, x INTEGER);
+             CREATE TABLE orphan (id INTEGER PRIMARY KEY,
+                                  stale_id INTEGER REFERENCES stale (id));
+             CREATE TABLE tag (name TEXT PRIMARY KEY) WITHOUT ROWID;
+             INSERT INTO parent VALUES (1), (2);
+             INSERT INTO child VALUES (1, 1);
+             INSERT INTO orphan VALUES (1, 1);
+             PRAGMA foreign_keys = ON;",
+        )
+        .unwrap();
+        let tables = |tx: &super::Transaction<'_>| -> Vec<String> {
+            let mut tables: Vec<String> = tx
+                .check_constraints()
+                .unwrap()
+                .into_iter()
+                .map(|violation| violation.table)
+                .collect();
+            tables.dedup();
+            tables
+        };
+
+        // All the tables are checked unless they are tracked, or when the
+        // tracking starts after some changes.
+        let tx = db.transaction().unwrap();
+        assert_eq!(vec!["orphan"], tables(&tx));
+        drop(tx);
+        let mut tx = db.transaction().unwrap();
+        tx.execute_batch("INSERT INTO parent VALUES (3)").unwrap();
+        tx.track_changed_tables();
+        assert_eq!(vec!["orphan"], tables(&tx));
+        drop(tx);
+
+        // The violation of orphan, which is not changed, is not reported.
+        let mut tx = db.transaction().unwrap();
+        tx.track_changed_tables();
+        assert!(tables(&tx).is_empty());
+        tx.execute_batch("UPDATE stale SET x = 1; INSERT INTO child VALUES (2, 3)")
+            .unwrap();
+        assert_eq!(vec!["child"], tables(&tx));
+        drop(tx);
+
+        // child references parent.
+        let mut tx = db.transaction().unwrap();
+        tx.track_changed_tables();
+        tx.execute_batch("DELETE FROM parent WHERE id = 1").unwrap();
+        assert_eq!(vec!["child"], tables(&tx));
+        drop(tx);
+
+        // The update hook misses changes to WITHOUT ROWID tables.
+        let mut tx = db.transaction().unwrap();
+        tx.track_changed_tables();
+        tx.execute_batch("INSERT INTO tag VALUES ('a')").unwrap();
+        assert_eq!(vec!["orphan"], tables(&tx));
+        drop(tx);
+
+        // orphan references a dropped table.
+        let mut tx = db.transaction().unwrap();
+        tx.track_changed_tables();
+        tx.execute_batch("PRAGMA foreign_keys = OFF; DROP TABLE parent")
+            .unwrap();
+        assert_eq!(vec!["child"], tables(&tx));
+    }
+
+    #[test]
+    fn test_transaction_across() {
+        use crate::{Error, Result};
+        use tempdir::TempDir;
+
+        let temp_dir = TempDir::new("test_transaction_across").unwrap();
+        let path = |name: &str| temp_dir.path().join(name);
+        let mut db = Connection::open(path("main.db3")).unwrap();
+        db.execute_batch("CREATE TABLE foo (x INTEGER)").unwrap();
+        let databases = [("a", path("a.db3")), ("b b", path("b.db3"))];
+
+        let n = db
+            .transaction_across(&databases, |tx| {
+                tx.execute_batch(
+                    "CREATE TABLE a.foo (x INTEGER);
+                     CREATE TABLE \"b b\".foo (x INTEGER);
+                     INSERT INTO main.foo VALUES (1);
+                     INSERT INTO a.foo VALUES (2);
+                     INSERT INTO \"b b\".foo VALUES (3);",
+                )?;
+                tx.query_row(
+                    "SELECT count(*) FROM pragma_database_list",
+                    NO_PARAMS,
+                    |r| r.get::<_, i64>(0),
+                )
+            })
+            .unwrap();
+        assert_eq!(3, n);
+
+        let r: Result<()> = db.transaction_across(&databases, |tx| {
+            tx.execute_batch(
+                "INSERT INTO main.foo VALUES (10);
+                 INSERT INTO a.foo VALUES (10);
+                 INSERT INTO \"b b\".foo VALUES (10);",
+            )?;
+            Err(Error::QueryReturnedNoRows)
+        });
+        assert_eq!(Err(Error::QueryReturnedNoRows), r);
+
+        let sums: Vec<i64> = db
+            .transaction_across(&databases, |tx| {
+                ["main", "a", "\"b b\""]
+                    .iter()
+                    .map(|schema| {
+                        let sql = format!("SELECT SUM(x) FROM {}.foo", schema);
+                        tx.query_row(&sql, NO_PARAMS, |r| r.get(0))
+                    })
+                    .collect()
+            })
+            .unwrap();
+        assert_eq!(vec![1, 2, 3], sums);
+        let n: i64 = db
+            .query_row(
+                "SELECT count(*) FROM pragma_database_list",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(1, n);
+
+        db.pragma_update(None, "journal_mode", &"WAL").unwrap();
+        match db.transaction_across(&databases, |_| Ok(())) {
+            Err(Error::IncompatibleDatabaseSetting(_)) => (),
+            r => panic!("Unexpected result {:?}", r),
+        }
+        let mut db = Connection::open_in_memory().unwrap();
+        match db.transaction_across(&databases, |_| Ok(())) {
+            Err(Error::IncompatibleDatabaseSetting(_)) => (),
+            r => panic!("Unexpected result {:?}", r),
+        }
+    }
+
     fn insert(x: i32, conn: &Connection) {
         conn.execute("INSERT INTO foo VALUES(?)", &[x]).unwrap();
     }
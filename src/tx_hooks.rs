@@ -0,0 +1,561 @@
+//! Transaction hooks installed on every connection.
+//!
+//! SQLite has a single slot per hook, so rusqlite registers its own
+//! trampolines once, when the connection is opened, and the callbacks of the
+//! user (see `Connection::rollback_hook`) are chained to rusqlite's own
+//! bookkeeping instead of replacing it.
+//!
+//! Rows recorded by a hook (see `Connection::track_inserts` and
+//! `Connection::subscribe_changes`) are buffered
+//! until their transaction is known to be committed: `Statement` reports
+//! the outcome of each step, because a commit hook fires before the commit
+//! is done (and it may still fail, e.g. with `SQLITE_BUSY`).
+//!
+//! The update hook also records the tables changed by a `Transaction`, when
+//! requested, see `Transaction::track_changed_tables`.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
+#[cfg(feature = "hooks")]
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::str;
+
+#[cfg(feature = "preupdate_hook")]
+use crate::changes::ChangeCapture;
+use crate::ffi;
+#[cfg(feature = "hooks")]
+use crate::hooks::Action;
+#[cfg(feature = "hooks")]
+use crate::script::skip_comments;
+
+#[cfg(feature = "hooks")]
+pub(crate) type UpdateHook = Box<dyn FnMut(Action, &str, &str, i64) + Send>;
+
+/// State shared with the hooks registered on a connection: it is boxed so
+/// that its address, given to SQLite, is stable.
+pub(crate) struct TxHooks {
+    db: *mut ffi::sqlite3,
+    // Set each time a transaction is rolled back, whichever the cause.
+    rolled_back: Cell<bool>,
+    // Number of items recorded in the buffers, ever.
+    recorded: Cell<u64>,
+    // Savepoints open in the current transaction, innermost last, with the
+    // mark taken when each was opened.
+    #[cfg(feature = "hooks")]
+    savepoints: RefCell<Vec<(String, u64)>>,
+    #[cfg(feature = "hooks")]
+    pub(crate) rollback: RefCell<Option<Box<dyn FnMut() + Send>>>,
+    #[cfg(feature = "hooks")]
+    pub(crate) update: RefCell<Option<UpdateHook>>,
+    #[cfg(feature = "hooks")]
+    pub(crate) inserts: RefCell<Option<TxBuffer<(String, i64)>>>,
+    #[cfg(feature = "preupdate_hook")]
+    pub(crate) changes: RefCell<Option<ChangeCapture>>,
+    touched: RefCell<Option<TouchedTables>>,
+}
+
+/// The tables of the main database changed while they are tracked.
+struct TouchedTables {
+    tables: BTreeSet<String>,
+    // Number of rows changed, reported by the update hook, and by
+    // `sqlite3_total_changes` when the tracking started: the update hook is
+    // not invoked for some changes (e.g. to a `WITHOUT ROWID` table).
+    changes: i64,
+    total_changes_base: i64,
+}
+
+impl TxHooks {
+    /// Register the hooks on `db`.
+    pub(crate) fn install(db: *mut ffi::sqlite3) -> Box<TxHooks> {
+        let hooks = Box::new(TxHooks {
+            db,
+            rolled_back: Cell::new(false),
+            recorded: Cell::new(0),
+            #[cfg(feature = "hooks")]
+            savepoints: RefCell::new(Vec::new()),
+            #[cfg(feature = "hooks")]
+            rollback: RefCell::new(None),
+            #[cfg(feature = "hooks")]
+            update: RefCell::new(None),
+            #[cfg(feature = "hooks")]
+            inserts: RefCell::new(None),
+            #[cfg(feature = "preupdate_hook")]
+            changes: RefCell::new(None),
+            touched: RefCell::new(None),
+        });
+        unsafe {
+            ffi::sqlite3_rollback_hook(
+                db,
+                Some(call_rollback_hook),
+                &*hooks as *const TxHooks as *mut c_void,
+            );
+        }
+        hooks
+    }
+
+    /// Unregister the hooks from `db`, which must not be used anymore once
+    /// this state is dropped.
+    pub(crate) fn uninstall(&mut self, db: *mut ffi::sqlite3) {
+        unsafe {
+            ffi::sqlite3_rollback_hook(db, None, ptr::null_mut());
+            ffi::sqlite3_update_hook(db, None, ptr::null_mut());
+            #[cfg(feature = "preupdate_hook")]
+            ffi::sqlite3_preupdate_hook(db, None, ptr::null_mut());
+        }
+        self.clear();
+    }
+
+    /// Drop the callbacks and the recorded items, e.g. once the connection
+    /// is closed.
+    pub(crate) fn clear(&mut self) {
+        *self.touched.get_mut() = None;
+        #[cfg(feature = "hooks")]
+        {
+            *self.rollback.get_mut() = None;
+            *self.update.get_mut() = None;
+            *self.inserts.get_mut() = None;
+        }
+        #[cfg(feature = "preupdate_hook")]
+        {
+            *self.changes.get_mut() = None;
+        }
+    }
+
+    /// Whether a transaction has been rolled back since the last call.
+    pub(crate) fn take_rolled_back(&self) -> bool {
+        self.rolled_back.replace(false)
+    }
+
+    /// Return a mark to give to `stepped`, before a statement is stepped.
+    pub(crate) fn mark(&self) -> u64 {
+        self.recorded.get()
+    }
+
+    /// Settle the buffers once `stmt` has been stepped: the items recorded by
+    /// a failed statement, or since the savepoint rolled back to by a
+    /// `ROLLBACK TO`, are discarded (their changes are undone), and the items
+    /// recorded by a transaction are committed once a statement completes in
+    /// autocommit mode.
+    pub(crate) fn stepped(&self, stmt: *mut ffi::sqlite3_stmt, mark: u64, rc: c_int) {
+        #[cfg(feature = "hooks")]
+        {
+            if rc != ffi::SQLITE_ROW && rc != ffi::SQLITE_DONE {
+                self.settle(Settle::DiscardSince(mark));
+            } else if rc == ffi::SQLITE_DONE {
+                if let Some(command) = unsafe { savepoint_command(stmt) } {
+                    self.savepoint(command, mark);
+                }
+                if self.is_autocommit() {
+                    self.settle(Settle::Commit);
+                }
+            }
+        }
+        #[cfg(not(feature = "hooks"))]
+        let _ = (stmt, mark, rc);
+    }
+
+    /// Keep track of the savepoints opened since the mark taken before a
+    /// `SAVEPOINT` statement, and discard the items recorded since then on
+    /// `ROLLBACK TO`.
+    #[cfg(feature = "hooks")]
+    fn savepoint(&self, command: SavepointCommand, mark: u64) {
+        let mut savepoints = self.savepoints.borrow_mut();
+        match command {
+            SavepointCommand::Savepoint(name) => savepoints.push((name, mark)),
+            // Savepoint names are case-insensitive, and the innermost one
+            // with the name is used.
+            SavepointCommand::Release(name) => {
+                if let Some(i) = savepoints
+                    .iter()
+                    .rposition(|sp| sp.0.eq_ignore_ascii_case(&name))
+                {
+                    savepoints.truncate(i);
+                }
+            }
+            SavepointCommand::RollbackTo(name) => {
+                if let Some(i) = savepoints
+                    .iter()
+                    .rposition(|sp| sp.0.eq_ignore_ascii_case(&name))
+                {
+                    // The savepoint itself stays open.
+                    let since = savepoints[i].1;
+                    savepoints.truncate(i + 1);
+                    drop(savepoints);
+                    self.settle(Settle::DiscardSince(since));
+                }
+            }
+        }
+    }
+
+    /// Settle the buffers once a statement has been reset or finalized,
+    /// which commits its transaction in autocommit mode.
+    pub(crate) fn reset(&self, rc: c_int) {
+        #[cfg(feature = "hooks")]
+        {
+            if rc == ffi::SQLITE_OK && self.is_autocommit() {
+                self.settle(Settle::Commit);
+            }
+        }
+        #[cfg(not(feature = "hooks"))]
+        let _ = rc;
+    }
+
+    #[cfg(feature = "hooks")]
+    fn is_autocommit(&self) -> bool {
+        unsafe { ffi::sqlite3_get_autocommit(self.db) != 0 }
+    }
+
+    #[cfg(feature = "hooks")]
+    fn settle(&self, op: Settle) {
+        if op == Settle::Commit || op == Settle::Rollback {
+            if let Ok(mut savepoints) = self.savepoints.try_borrow_mut() {
+                savepoints.clear();
+            }
+        }
+        if let Ok(mut inserts) = self.inserts.try_borrow_mut() {
+            if let Some(ref mut inserts) = *inserts {
+                inserts.settle(op);
+            }
+        }
+        #[cfg(feature = "preupdate_hook")]
+        {
+            if let Ok(mut changes) = self.changes.try_borrow_mut() {
+                if let Some(ref mut changes) = *changes {
+                    changes.settle(op);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "hooks")]
+    pub(crate) fn next_seq(&self) -> u64 {
+        let seq = self.recorded.get();
+        self.recorded.set(seq + 1);
+        seq
+    }
+
+    /// Start recording the tables of the main database changed by the
+    /// current transaction, which began when `sqlite3_total_changes` was
+    /// `total_changes_base` (the changes made before are missed), or stop
+    /// recording them with `None`.
+    pub(crate) fn track_tables(&self, total_changes_base: Option<i64>) {
+        *self.touched.borrow_mut() = total_changes_base.map(|total_changes_base| TouchedTables {
+            tables: BTreeSet::new(),
+            changes: 0,
+            total_changes_base,
+        });
+        self.sync_update_hook();
+    }
+
+    /// Return the tables recorded since `track_tables`, or `None` if
+    /// they are not tracked or if some changes were not reported by the
+    /// update hook.
+    pub(crate) fn touched_tables(&self) -> Option<Vec<String>> {
+        let touched = self.touched.borrow();
+        let touched = touched.as_ref()?;
+        if self.total_changes() - touched.total_changes_base != touched.changes {
+            return None;
+        }
+        Some(touched.tables.iter().cloned().collect())
+    }
+
+    pub(crate) fn total_changes(&self) -> i64 {
+        i64::from(unsafe { ffi::sqlite3_total_changes(self.db) })
+    }
+
+    /// Register or unregister the update hook, depending on whether it is
+    /// needed.
+    pub(crate) fn sync_update_hook(&self) {
+        let needed = self.touched.borrow().is_some();
+        #[cfg(feature = "hooks")]
+        let needed = needed || self.update.borrow().is_some() || self.inserts.borrow().is_some();
+        unsafe {
+            if needed {
+                ffi::sqlite3_update_hook(
+                    self.db,
+                    Some(call_update_hook),
+                    self as *const TxHooks as *mut c_void,
+                );
+            } else {
+                ffi::sqlite3_update_hook(self.db, None, ptr::null_mut());
+            }
+        }
+    }
+}
+
+/// What happened to the items recorded by a hook.
+#[cfg(feature = "hooks")]
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Settle {
+    /// The statement which recorded the items since the mark failed.
+    DiscardSince(u64),
+    /// The transaction is committed.
+    Commit,
+    /// The transaction is rolled back.
+    Rollback,
+}
+
+/// Items recorded by a hook, whose transaction may not be committed yet.
+///
+/// Only the `limit` most recent items are kept.
+#[cfg(feature = "hooks")]
+pub(crate) struct TxBuffer<T> {
+    // Items with the sequence number given when they were recorded.
+    items: VecDeque<(u64, T)>,
+    // Number of items, at the back, whose transaction is not committed yet.
+    pending: usize,
+    limit: usize,
+}
+
+#[cfg(feature = "hooks")]
+impl<T> TxBuffer<T> {
+    pub(crate) fn new(limit: usize) -> TxBuffer<T> {
+        TxBuffer {
+            items: VecDeque::new(),
+            pending: 0,
+            limit,
+        }
+    }
+
+    pub(crate) fn push(&mut self, seq: u64, item: T) {
+        self.items.push_back((seq, item));
+        self.pending += 1;
+        while self.items.len() > self.limit {
+            self.items.pop_front();
+            self.pending = self.pending.min(self.items.len());
+        }
+    }
+
+    pub(crate) fn settle(&mut self, op: Settle) {
+        match op {
+            Settle::DiscardSince(mark) => self.discard_since(mark),
+            Settle::Commit => self.commit(),
+            Settle::Rollback => self.rollback(),
+        }
+    }
+
+    fn discard_since(&mut self, mark: u64) {
+        while self.pending > 0 {
+            match self.items.back() {
+                Some(&(seq, _)) if seq >= mark => {
+                    self.items.pop_back();
+                    self.pending -= 1;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn rollback(&mut self) {
+        let committed = self.items.len() - self.pending;
+        self.items.truncate(committed);
+        self.pending = 0;
+    }
+
+    fn commit(&mut self) {
+        self.pending = 0;
+    }
+
+    /// Remove and return the committed items, oldest first.
+    pub(crate) fn take_committed(&mut self) -> Vec<T> {
+        let committed = self.items.len() - self.pending;
+        self.items
+            .drain(..committed)
+            .map(|(_, item)| item)
+            .collect()
+    }
+}
+
+/// A statement opening, releasing or rolling back to a savepoint.
+#[cfg(feature = "hooks")]
+#[derive(Debug, PartialEq)]
+enum SavepointCommand {
+    Savepoint(String),
+    Release(String),
+    RollbackTo(String),
+}
+
+/// Parse `stmt` if it is a savepoint statement.
+#[cfg(feature = "hooks")]
+unsafe fn savepoint_command(stmt: *mut ffi::sqlite3_stmt) -> Option<SavepointCommand> {
+    if stmt.is_null() {
+        return None;
+    }
+    let sql = ffi::sqlite3_sql(stmt);
+    if sql.is_null() {
+        return None;
+    }
+    parse_savepoint_command(CStr::from_ptr(sql).to_str().ok()?)
+}
+
+/// Parse `SAVEPOINT name`, `RELEASE [SAVEPOINT] name` and
+/// `ROLLBACK [TRANSACTION] TO [SAVEPOINT] name`.
+#[cfg(feature = "hooks")]
+fn parse_savepoint_command(mut sql: &str) -> Option<SavepointCommand> {
+    let keyword = next_name(&mut sql)?;
+    if keyword.eq_ignore_ascii_case("SAVEPOINT") {
+        next_name(&mut sql).map(SavepointCommand::Savepoint)
+    } else if keyword.eq_ignore_ascii_case("RELEASE") {
+        skip_optional(&mut sql, "SAVEPOINT");
+        next_name(&mut sql).map(SavepointCommand::Release)
+    } else if keyword.eq_ignore_ascii_case("ROLLBACK") {
+        skip_optional(&mut sql, "TRANSACTION");
+        if !next_name(&mut sql)?.eq_ignore_ascii_case("TO") {
+            return None;
+        }
+        skip_optional(&mut sql, "SAVEPOINT");
+        next_name(&mut sql).map(SavepointCommand::RollbackTo)
+    } else {
+        None
+    }
+}
+
+/// Skip the optional keyword `word`, unless it is the last word: then it is
+/// a name, e.g. in `RELEASE savepoint`.
+#[cfg(feature = "hooks")]
+fn skip_optional(sql: &mut &str, word: &str) {
+    let mut rest = *sql;
+    if let Some(keyword) = next_name(&mut rest) {
+        let mut after = rest;
+        if keyword.eq_ignore_ascii_case(word) && next_name(&mut after).is_some() {
+            *sql = rest;
+        }
+    }
+}
+
+/// Return the next word or (unquoted) name of `sql`, skipping whitespace and
+/// comments.
+#[cfg(feature = "hooks")]
+fn next_name(sql: &mut &str) -> Option<String> {
+    let rest = skip_comments(sql, false);
+    let quote = match rest.chars().next()? {
+        '"' => '"',
+        '`' => '`',
+        '\'' => '\'',
+        '[' => ']',
+        _ => {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$' || !c.is_ascii()))
+                .unwrap_or_else(|| rest.len());
+            if end == 0 {
+                return None;
+            }
+            *sql = &rest[end..];
+            return Some(rest[..end].to_owned());
+        }
+    };
+    let mut name = String::new();
+    let mut chars = rest[1..].char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == quote {
+            // Doubled quotes are escapes (except in brackets).
+            if quote != ']' && chars.peek().map(|&(_, c)| c) == Some(quote) {
+                chars.next();
+            } else {
+                *sql = &rest[i + 2..];
+                return Some(name);
+            }
+        }
+        name.push(c);
+    }
+    None
+}
+
+unsafe extern "C" fn call_rollback_hook(p_arg: *mut c_void) {
+    let hooks = &*(p_arg as *const TxHooks);
+    hooks.rolled_back.set(true);
+    #[cfg(feature = "hooks")]
+    {
+        hooks.settle(Settle::Rollback);
+        if let Ok(mut hook) = hooks.rollback.try_borrow_mut() {
+            if let Some(ref mut hook) = *hook {
+                let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(hook));
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn call_update_hook(
+    p_arg: *mut c_void,
+    action_code: c_int,
+    db_str: *const c_char,
+    tbl_str: *const c_char,
+    row_id: i64,
+) {
+    let hooks = &*(p_arg as *const TxHooks);
+    let db_name = str::from_utf8_unchecked(CStr::from_ptr(db_str).to_bytes());
+    let tbl_name = str::from_utf8_unchecked(CStr::from_ptr(tbl_str).to_bytes());
+
+    if let Ok(mut touched) = hooks.touched.try_borrow_mut() {
+        if let Some(ref mut touched) = *touched {
+            touched.changes += 1;
+            if db_name == "main" && !touched.tables.contains(tbl_name) {
+                touched.tables.insert(tbl_name.to_owned());
+            }
+        }
+    }
+    #[cfg(feature = "hooks")]
+    call_user_update_hook(hooks, action_code, db_name, tbl_name, row_id);
+    #[cfg(not(feature = "hooks"))]
+    let _ = (action_code, row_id);
+}
+
+#[cfg(feature = "hooks")]
+fn call_user_update_hook(
+    hooks: &TxHooks,
+    action_code: c_int,
+    db_name: &str,
+    tbl_name: &str,
+    row_id: i64,
+) {
+    let action = Action::from(action_code);
+    if action == Action::SQLITE_INSERT {
+        if let Ok(mut inserts) = hooks.inserts.try_borrow_mut() {
+            if let Some(ref mut inserts) = *inserts {
+                inserts.push(hooks.next_seq(), (tbl_name.to_owned(), row_id));
+            }
+        }
+    }
+    if let Ok(mut hook) = hooks.update.try_borrow_mut() {
+        if let Some(ref mut hook) = *hook {
+            let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                hook(action, db_name, tbl_name, row_id)
+            }));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "hooks"))]
+mod test {
+    use super::{parse_savepoint_command, SavepointCommand};
+
+    #[test]
+    fn test_parse_savepoint_command() {
+        assert_eq!(
+            Some(SavepointCommand::Savepoint("a".to_owned())),
+            parse_savepoint_command("SAVEPOINT a")
+        );
+        assert_eq!(
+            Some(SavepointCommand::Release("my \"sp\"".to_owned())),
+            parse_savepoint_command("-- c\n release savepoint \"my \"\"sp\"\"\";")
+        );
+        assert_eq!(
+            Some(SavepointCommand::Release("savepoint".to_owned())),
+            parse_savepoint_command("RELEASE savepoint")
+        );
+        assert_eq!(
+            Some(SavepointCommand::RollbackTo("b".to_owned())),
+            parse_savepoint_command("ROLLBACK /* c */ TRANSACTION TO SAVEPOINT [b]")
+        );
+        assert_eq!(
+            Some(SavepointCommand::RollbackTo("c".to_owned())),
+            parse_savepoint_command("rollback to 'c'")
+        );
+        assert_eq!(None, parse_savepoint_command("ROLLBACK"));
+        assert_eq!(None, parse_savepoint_command("ROLLBACK TRANSACTION"));
+        assert_eq!(None, parse_savepoint_command("SELECT 1"));
+    }
+}
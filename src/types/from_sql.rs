@@ -135,6 +135,51 @@ from_sql_integral!(u8);
 from_sql_integral!(u16);
 from_sql_integral!(u32);
 
+/// Wrapper to read an integer into a smaller integral type, with values out
+/// of the range of the type clamped to its minimum or maximum value instead
+/// of failing with `FromSqlError::OutOfRange`.
+///
+/// This is meant for values where approximation is preferable to an error,
+/// e.g. counters or telemetry.
+///
+/// ```rust
+/// # use rusqlite::{Connection, Result, NO_PARAMS};
+/// # use rusqlite::types::Saturating;
+/// fn read_level(conn: &Connection) -> Result<u8> {
+///     let level: Saturating<u8> = conn.query_row("SELECT 1000", NO_PARAMS, |r| r.get(0))?;
+///     Ok(level.0)
+/// }
+/// # assert_eq!(255, read_level(&Connection::open_in_memory().unwrap()).unwrap());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Saturating<T>(pub T);
+
+macro_rules! from_sql_saturating(
+    ($t:ident) => (
+        impl FromSql for Saturating<$t> {
+            fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+                i64::column_result(value).map(|i| {
+                    if i < $t::min_value() as i64 {
+                        Saturating($t::min_value())
+                    } else if i > $t::max_value() as i64 {
+                        Saturating($t::max_value())
+                    } else {
+                        Saturating(i as $t)
+                    }
+                })
+            }
+        }
+    )
+);
+
+from_sql_saturating!(i8);
+from_sql_saturating!(i16);
+from_sql_saturating!(i32);
+from_sql_saturating!(isize);
+from_sql_saturating!(u8);
+from_sql_saturating!(u16);
+from_sql_saturating!(u32);
+
 impl FromSql for i64 {
     fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
         value.as_i64()
@@ -217,7 +262,7 @@ impl FromSql for Value {
 
 #[cfg(test)]
 mod test {
-    use super::FromSql;
+    use super::{FromSql, Saturating};
     use crate::{Connection, Error};
 
     fn checked_memory_handle() -> Connection {
@@ -262,4 +307,29 @@ mod test {
         check_ranges::<u16>(&db, &[-2, -1, 65536], &[0, 1, 65535]);
         check_ranges::<u32>(&db, &[-2, -1, 4_294_967_296], &[0, 1, 4_294_967_295]);
     }
+
+    #[test]
+    fn test_saturating() {
+        let db = checked_memory_handle();
+        let get = |n: i64| -> (Saturating<u8>, Saturating<i16>, Saturating<u32>) {
+            db.query_row("SELECT ?1, ?1, ?1", [n], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })
+            .unwrap()
+        };
+        assert_eq!(
+            (Saturating(0), Saturating(-32768), Saturating(0)),
+            get(-100_000)
+        );
+        assert_eq!((Saturating(42), Saturating(42), Saturating(42)), get(42));
+        assert_eq!(
+            (Saturating(255), Saturating(32767), Saturating(100_000)),
+            get(100_000)
+        );
+        assert_eq!(
+            Saturating(u32::max_value()),
+            db.query_row("SELECT ?", [i64::max_value()], |r| r.get(0))
+                .unwrap()
+        );
+    }
 }
@@ -52,8 +52,10 @@
 //! implements `ToSql` or `FromSql` for the cases where you want to know if a
 //! value was NULL (which gets translated to `None`).
 
-pub use self::from_sql::{FromSql, FromSqlError, FromSqlResult};
-pub use self::to_sql::{ToSql, ToSqlOutput};
+pub use self::from_sql::{FromSql, FromSqlError, FromSqlResult, Saturating};
+#[cfg(feature = "borrowed_blob")]
+pub use self::to_sql::BorrowedBlob;
+pub use self::to_sql::{params_from_iter, Params, ParamsFromIter, ToParams, ToSql, ToSqlOutput};
 pub use self::value::Value;
 pub use self::value_ref::ValueRef;
 
@@ -178,6 +180,35 @@ mod test {
         assert_eq!(from, s);
     }
 
+    #[test]
+    fn test_invalid_utf8_text() {
+        use super::Type;
+
+        let db = checked_memory_handle();
+        db.execute_batch("INSERT INTO foo(t) VALUES (CAST(x'61FF62' AS TEXT))")
+            .unwrap();
+        match db.query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get::<_, String>(0)) {
+            Err(Error::FromSqlConversionFailure(0, Type::Text, _)) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert!(db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get::<_, Value>(0))
+            .is_err());
+        let lossy = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| {
+                assert!(r.get_raw_checked(0).is_err());
+                Ok(r.get_str_lossy(0).unwrap().into_owned())
+            })
+            .unwrap();
+        assert_eq!("a\u{fffd}b", lossy);
+
+        // Embedded NULs are kept
+        let s: String = db
+            .query_row("SELECT CAST(x'610062' AS TEXT)", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!("a\0b", s);
+    }
+
     #[test]
     fn test_value() {
         let db = checked_memory_handle();
@@ -192,6 +223,68 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_value_conversions() {
+        use super::{FromSqlError, ValueRef};
+
+        assert_eq!(Value::Text("a".to_owned()), Value::from("a"));
+        assert_eq!(Value::Blob(vec![1, 2]), Value::from(&[1u8, 2][..]));
+        assert_eq!(Value::Real(0.5), Value::from(0.5f32));
+        assert_eq!(Value::Integer(3), Value::from(Some(3u8)));
+        assert_eq!(Value::Null, Value::from(None::<String>));
+
+        let value = Value::from("a");
+        assert_eq!(Ok("a"), value.as_str());
+        assert_eq!(Err(FromSqlError::InvalidType), value.as_i64());
+        assert_eq!(Ok(3), Value::Integer(3).as_i64());
+        assert_eq!(Ok(&[1u8][..]), Value::Blob(vec![1]).as_blob());
+        assert_eq!(value, Value::from(ValueRef::from(&value)));
+    }
+
+    #[test]
+    fn test_value_ordering() {
+        let values = vec![
+            Value::Null,
+            Value::Real(-1.5),
+            Value::Integer(-1),
+            Value::Integer(1),
+            Value::Real(1.0),
+            Value::Real(1.5),
+            Value::Integer(i64::max_value()),
+            Value::Real(1e19),
+            Value::Text("A".to_owned()),
+            Value::Text("a".to_owned()),
+            Value::Blob(vec![]),
+            Value::Blob(vec![0]),
+        ];
+        for (i, v1) in values.iter().enumerate() {
+            for (j, v2) in values.iter().enumerate() {
+                assert_eq!(Some(i.cmp(&j)), v1.partial_cmp(v2), "{:?} {:?}", v1, v2);
+            }
+        }
+
+        // Check against SQLite (which considers 1 and 1.0 equal)
+        let values: Vec<Value> = values
+            .into_iter()
+            .filter(|v| *v != Value::Real(1.0))
+            .collect();
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE bar (x)").unwrap();
+        for v in values.iter().rev() {
+            db.execute("INSERT INTO bar VALUES (?)", [v]).unwrap();
+        }
+        let mut stmt = db.prepare("SELECT x FROM bar ORDER BY x").unwrap();
+        let sorted: Vec<Value> = stmt
+            .query_map(NO_PARAMS, |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(values.len(), sorted.len());
+        for (v1, v2) in values.iter().zip(sorted.iter()) {
+            assert_eq!(v1.data_type(), v2.data_type());
+        }
+    }
+
     #[test]
     fn test_option() {
         let db = checked_memory_handle();
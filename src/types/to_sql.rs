@@ -14,6 +14,10 @@ pub enum ToSqlOutput<'a> {
     /// An owned SQLite-representable value.
     Owned(Value),
 
+    /// A borrowed BLOB which SQLite does not copy when it is bound.
+    #[cfg(feature = "borrowed_blob")]
+    BorrowedBlob(BorrowedBlob<'a>),
+
     /// A BLOB of the given length that is filled with zeroes.
     #[cfg(feature = "blob")]
     ZeroBlob(i32),
@@ -73,6 +77,8 @@ impl ToSql for ToSqlOutput<'_> {
         Ok(match *self {
             ToSqlOutput::Borrowed(v) => ToSqlOutput::Borrowed(v),
             ToSqlOutput::Owned(ref v) => ToSqlOutput::Borrowed(ValueRef::from(v)),
+            #[cfg(feature = "borrowed_blob")]
+            ToSqlOutput::BorrowedBlob(b) => ToSqlOutput::BorrowedBlob(b),
 
             #[cfg(feature = "blob")]
             ToSqlOutput::ZeroBlob(i) => ToSqlOutput::ZeroBlob(i),
@@ -82,9 +88,63 @@ impl ToSql for ToSqlOutput<'_> {
     }
 }
 
+/// A BLOB parameter that SQLite reads in place instead of copying it when it
+/// is bound (`SQLITE_STATIC`), to avoid copying large BLOBs.
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result};
+/// # use rusqlite::types::BorrowedBlob;
+/// fn insert_chunk(conn: &Connection, id: i64, data: &[u8]) -> Result<usize> {
+///     let mut stmt = conn.prepare("INSERT INTO chunks (id, data) VALUES (?, ?)")?;
+///     // `data` outlives `stmt`.
+///     let data = unsafe { BorrowedBlob::new(data) };
+///     stmt.execute(&[&id as &dyn rusqlite::ToSql, &data])
+/// }
+/// ```
+#[cfg(feature = "borrowed_blob")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BorrowedBlob<'a>(&'a [u8]);
+
+#[cfg(feature = "borrowed_blob")]
+impl<'a> BorrowedBlob<'a> {
+    /// Wrap `blob` to bind it without a copy.
+    ///
+    /// # Safety
+    ///
+    /// SQLite keeps a pointer to `blob` until the parameter is bound to
+    /// another value, the bindings of the statement are cleared (which
+    /// happens when a cached statement is returned to the cache) or the
+    /// statement is finalized, and reads the BLOB each time the statement is
+    /// executed. The caller must make sure that `blob` is neither dropped nor
+    /// modified until then: a borrowed BLOB must not be bound to a statement
+    /// which outlives it, and must not be read again through a named
+    /// parameter left unbound by a later execution.
+    pub unsafe fn new(blob: &'a [u8]) -> BorrowedBlob<'a> {
+        BorrowedBlob(blob)
+    }
+
+    /// Return the borrowed bytes.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+}
+
+#[cfg(feature = "borrowed_blob")]
+impl ToSql for BorrowedBlob<'_> {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::BorrowedBlob(*self))
+    }
+}
+
 /// A trait for types that can be converted into SQLite values.
 pub trait ToSql {
     fn to_sql(&self) -> Result<ToSqlOutput<'_>>;
+
+    /// Return the name of the Rust type of the value (see
+    /// `Statement::parameter_report`).
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
 }
 
 impl ToSql for Box<dyn ToSql> {
@@ -92,6 +152,10 @@ impl ToSql for Box<dyn ToSql> {
         let derefed: &dyn ToSql = &**self;
         derefed.to_sql()
     }
+
+    fn type_name(&self) -> &'static str {
+        (**self).type_name()
+    }
 }
 
 // We should be able to use a generic impl like this:
@@ -141,6 +205,10 @@ where
     fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
         (*self).to_sql()
     }
+
+    fn type_name(&self) -> &'static str {
+        (*self).type_name()
+    }
 }
 
 impl ToSql for String {
@@ -188,6 +256,184 @@ impl ToSql for Cow<'_, str> {
     }
 }
 
+/// Positional parameters, bound in order to a statement by its `execute`
+/// and `query` methods.
+///
+/// Implemented for:
+///
+/// * references to any collection of `ToSql` values, e.g. `&[1, 2]`,
+///   `&vec` or the `&[&dyn ToSql]` built by the `params!` macro,
+/// * arrays (up to 32 values) and vectors of `ToSql` values, e.g. `[1, 2]`
+///   or a `[&dyn ToSql; N]`,
+/// * tuples of up to 16 `ToSql` values, which may have different types,
+///   e.g. `(id, name, score)`, and `()` for no parameters.
+///
+/// Any other iterator of `ToSql` values can be wrapped with
+/// `params_from_iter`.
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result};
+/// fn add_score(conn: &Connection, id: i32, name: &str, score: Option<f64>) -> Result<usize> {
+///     conn.execute(
+///         "INSERT INTO score (id, name, score) VALUES (?, ?, ?)",
+///         (id, name, score),
+///     )
+/// }
+/// ```
+pub trait Params {
+    /// Call `f` with each parameter, in order, stopping at the first error.
+    fn try_for_each_param<F>(self, f: F) -> Result<()>
+    where
+        F: FnMut(&dyn ToSql) -> Result<()>;
+}
+
+impl<'a, I> Params for &'a I
+where
+    I: ?Sized,
+    &'a I: IntoIterator,
+    <&'a I as IntoIterator>::Item: ToSql,
+{
+    fn try_for_each_param<F>(self, f: F) -> Result<()>
+    where
+        F: FnMut(&dyn ToSql) -> Result<()>,
+    {
+        params_from_iter(self).try_for_each_param(f)
+    }
+}
+
+impl<T: ToSql> Params for Vec<T> {
+    fn try_for_each_param<F>(self, f: F) -> Result<()>
+    where
+        F: FnMut(&dyn ToSql) -> Result<()>,
+    {
+        params_from_iter(self).try_for_each_param(f)
+    }
+}
+
+/// Parameters given by an iterator of `ToSql` values, see
+/// `params_from_iter`.
+#[derive(Clone, Debug)]
+pub struct ParamsFromIter<I>(I);
+
+/// Wrap an iterator (or any `IntoIterator`) of `ToSql` values, to pass them
+/// as positional parameters.
+///
+/// ```rust,no_run
+/// # use rusqlite::{params_from_iter, Connection, Result};
+/// fn count_ids(conn: &Connection, ids: &[i64]) -> Result<i64> {
+///     conn.query_row(
+///         "SELECT count(*) FROM foo WHERE id IN (?, ?, ?)",
+///         params_from_iter(ids.iter().take(3)),
+///         |row| row.get(0),
+///     )
+/// }
+/// ```
+pub fn params_from_iter<I>(iter: I) -> ParamsFromIter<I>
+where
+    I: IntoIterator,
+    I::Item: ToSql,
+{
+    ParamsFromIter(iter)
+}
+
+impl<I> Params for ParamsFromIter<I>
+where
+    I: IntoIterator,
+    I::Item: ToSql,
+{
+    fn try_for_each_param<F>(self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&dyn ToSql) -> Result<()>,
+    {
+        for p in self.0 {
+            f(&p)?;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! array_params {
+    ($($n:expr),+) => {
+        $(
+            impl<T: ToSql> Params for [T; $n] {
+                fn try_for_each_param<F>(self, f: F) -> Result<()>
+                where
+                    F: FnMut(&dyn ToSql) -> Result<()>,
+                {
+                    (&self).try_for_each_param(f)
+                }
+            }
+        )+
+    };
+}
+
+array_params!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16);
+array_params!(17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32);
+
+macro_rules! tuple_params {
+    ($($t:ident: $idx:tt),*) => {
+        impl<$($t: ToSql),*> Params for ($($t,)*) {
+            #[allow(unused_mut, unused_variables)]
+            fn try_for_each_param<F>(self, mut f: F) -> Result<()>
+            where
+                F: FnMut(&dyn ToSql) -> Result<()>,
+            {
+                $(f(&self.$idx)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+tuple_params!();
+tuple_params!(T0: 0);
+tuple_params!(T0: 0, T1: 1);
+tuple_params!(T0: 0, T1: 1, T2: 2);
+tuple_params!(T0: 0, T1: 1, T2: 2, T3: 3);
+tuple_params!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4);
+tuple_params!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5);
+tuple_params!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6);
+tuple_params!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6, T7: 7);
+tuple_params!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6, T7: 7, T8: 8);
+tuple_params!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6, T7: 7, T8: 8, T9: 9);
+tuple_params!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6, T7: 7, T8: 8, T9: 9, T10: 10);
+tuple_params!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6, T7: 7, T8: 8, T9: 9, T10: 10, T11: 11);
+tuple_params!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6, T7: 7, T8: 8, T9: 9, T10: 10, T11: 11, T12: 12);
+tuple_params!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6, T7: 7, T8: 8, T9: 9, T10: 10, T11: 11, T12: 12, T13: 13);
+tuple_params!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6, T7: 7, T8: 8, T9: 9, T10: 10, T11: 11, T12: 12, T13: 13, T14: 14);
+tuple_params!(T0: 0, T1: 1, T2: 2, T3: 3, T4: 4, T5: 5, T6: 6, T7: 7, T8: 8, T9: 9, T10: 10, T11: 11, T12: 12, T13: 13, T14: 14, T15: 15);
+
+/// A trait implemented by types that can be bound as named parameters, e.g.
+/// with `Statement::execute_named`.
+///
+/// It can be derived (with the `derive` feature) for structs, whose fields
+/// are bound to the parameters `:field`.
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result, ToParams, ToSql};
+/// struct Person {
+///     name: String,
+///     age: u32,
+/// }
+///
+/// impl ToParams for Person {
+///     fn to_params(&self) -> Vec<(&str, &dyn ToSql)> {
+///         vec![(":name", &self.name), (":age", &self.age)]
+///     }
+/// }
+///
+/// fn insert(conn: &Connection, person: &Person) -> Result<usize> {
+///     conn.execute_named(
+///         "INSERT INTO person (name, age) VALUES (:name, :age)",
+///         &person.to_params(),
+///     )
+/// }
+/// ```
+pub trait ToParams {
+    /// Return the parameter names (with their prefix), and the values.
+    fn to_params(&self) -> Vec<(&str, &dyn ToSql)>;
+}
+
 #[cfg(test)]
 mod test {
     use super::ToSql;
@@ -205,6 +451,57 @@ mod test {
         is_to_sql::<u32>();
     }
 
+    #[test]
+    fn test_tuple_and_array_params() {
+        use crate::types::params_from_iter;
+        use crate::{params, Connection, NO_PARAMS};
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (id INTEGER, name TEXT, score REAL)")
+            .unwrap();
+        let name = String::from("one");
+        db.execute(
+            "INSERT INTO foo VALUES (?, ?, ?)",
+            (1, name.as_str(), Some(1.5)),
+        )
+        .unwrap();
+        let mut stmt = db.prepare("INSERT INTO foo VALUES (?, ?, ?)").unwrap();
+        let params: [&dyn ToSql; 3] = [&2, &"two", &None::<f64>];
+        stmt.execute(params).unwrap();
+        stmt.execute((3, &name, 0.5)).unwrap();
+        stmt.execute(params![4, "four", 0.0]).unwrap();
+        stmt.execute(vec![5.0, 5.0, 0.0]).unwrap();
+
+        let row: (i64, f64) = db
+            .query_row("SELECT count(*), sum(score) FROM foo", (), |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((5, 2.0), row);
+        let count: i64 = db
+            .query_row("SELECT count(*) FROM foo WHERE id > ?", (1,), |r| r.get(0))
+            .unwrap();
+        assert_eq!(4, count);
+        let ids = [2, 3, 4, 5];
+        let count: i64 = db
+            .query_row(
+                "SELECT count(*) FROM foo WHERE id IN (?, ?)",
+                params_from_iter(ids.iter().skip(2)),
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(2, count);
+        db.query_row("SELECT 1", NO_PARAMS, |r| r.get::<_, i64>(0))
+            .unwrap();
+        let sixteen = (0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15);
+        db.query_row(
+            &format!("SELECT ?{}", ", ?".repeat(15)),
+            sixteen,
+            |_| Ok(()),
+        )
+        .unwrap();
+    }
+
     #[test]
     fn test_cow_str() {
         use std::borrow::Cow;
@@ -1,4 +1,7 @@
-use super::{Null, Type};
+use std::cmp::Ordering;
+
+use super::{Null, Type, ValueRef};
+use crate::types::FromSqlResult;
 
 /// Owning [dynamic type value](http://sqlite.org/datatype3.html). Value's type is typically
 /// dictated by SQLite (not by the caller).
@@ -84,6 +87,36 @@ impl From<f64> for Value {
     }
 }
 
+impl From<f32> for Value {
+    fn from(f: f32) -> Value {
+        Value::Real(f.into())
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::Text(s.to_owned())
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(v: &[u8]) -> Value {
+        Value::Blob(v.to_vec())
+    }
+}
+
+impl<T> From<Option<T>> for Value
+where
+    T: Into<Value>,
+{
+    fn from(v: Option<T>) -> Value {
+        match v {
+            Some(x) => x.into(),
+            None => Value::Null,
+        }
+    }
+}
+
 impl From<String> for Value {
     fn from(s: String) -> Value {
         Value::Text(s)
@@ -106,4 +139,36 @@ impl Value {
             Value::Blob(_) => Type::Blob,
         }
     }
+
+    /// If `self` is case `Integer`, returns the integral value. Otherwise,
+    /// returns `Err(FromSqlError::InvalidType)`.
+    pub fn as_i64(&self) -> FromSqlResult<i64> {
+        ValueRef::from(self).as_i64()
+    }
+
+    /// If `self` is case `Real`, returns the floating point value. Otherwise,
+    /// returns `Err(FromSqlError::InvalidType)`.
+    pub fn as_f64(&self) -> FromSqlResult<f64> {
+        ValueRef::from(self).as_f64()
+    }
+
+    /// If `self` is case `Text`, returns the string value. Otherwise, returns
+    /// `Err(FromSqlError::InvalidType)`.
+    pub fn as_str(&self) -> FromSqlResult<&str> {
+        ValueRef::from(self).as_str()
+    }
+
+    /// If `self` is case `Blob`, returns the byte slice. Otherwise, returns
+    /// `Err(FromSqlError::InvalidType)`.
+    pub fn as_blob(&self) -> FromSqlResult<&[u8]> {
+        ValueRef::from(self).as_blob()
+    }
+}
+
+/// Values are ordered like SQLite sorts them (with the `BINARY` collation):
+/// see `ValueRef`.
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        ValueRef::from(self).partial_cmp(&ValueRef::from(other))
+    }
 }
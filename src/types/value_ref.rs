@@ -1,3 +1,6 @@
+use std::cmp::Ordering;
+use std::fmt;
+
 use super::{Type, Value};
 use crate::types::{FromSqlError, FromSqlResult};
 
@@ -5,7 +8,7 @@ use crate::types::{FromSqlError, FromSqlResult};
 /// memory backing this value is owned by SQLite.
 ///
 /// See [`Value`](enum.Value.html) for an owning dynamic type value.
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, PartialEq)]
 pub enum ValueRef<'a> {
     /// The value is a `NULL` value.
     Null,
@@ -19,6 +22,26 @@ pub enum ValueRef<'a> {
     Blob(&'a [u8]),
 }
 
+/// Maximum number of characters of a text value shown by `Debug`.
+const DEBUG_TEXT_LEN: usize = 64;
+
+/// Long texts are truncated and blobs are only shown by their length, so that
+/// rows can be debugged without flooding the output.
+impl fmt::Debug for ValueRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ValueRef::Null => write!(f, "Null"),
+            ValueRef::Integer(i) => write!(f, "Integer({})", i),
+            ValueRef::Real(r) => write!(f, "Real({:?})", r),
+            ValueRef::Text(t) => match t.char_indices().nth(DEBUG_TEXT_LEN) {
+                Some((end, _)) => write!(f, "Text({:?}... ({} bytes))", &t[..end], t.len()),
+                None => write!(f, "Text({:?})", t),
+            },
+            ValueRef::Blob(b) => write!(f, "Blob({} bytes)", b.len()),
+        }
+    }
+}
+
 impl ValueRef<'_> {
     pub fn data_type(&self) -> Type {
         match *self {
@@ -69,6 +92,62 @@ impl<'a> ValueRef<'a> {
     }
 }
 
+/// Values are ordered like SQLite sorts them (with the `BINARY` collation):
+/// `NULL` first, then numbers (integers and reals compared by value), then
+/// texts, then blobs (both compared byte by byte).
+///
+/// To be consistent with `PartialEq`, an integer is ordered before a real of
+/// the same value (e.g. `1 < 1.0`), while SQLite considers them equal.
+impl PartialOrd for ValueRef<'_> {
+    fn partial_cmp(&self, other: &ValueRef<'_>) -> Option<Ordering> {
+        fn rank(value: &ValueRef<'_>) -> u8 {
+            match *value {
+                ValueRef::Null => 0,
+                ValueRef::Integer(_) | ValueRef::Real(_) => 1,
+                ValueRef::Text(_) => 2,
+                ValueRef::Blob(_) => 3,
+            }
+        }
+        match (*self, *other) {
+            (ValueRef::Null, ValueRef::Null) => Some(Ordering::Equal),
+            (ValueRef::Integer(i1), ValueRef::Integer(i2)) => Some(i1.cmp(&i2)),
+            (ValueRef::Real(r1), ValueRef::Real(r2)) => r1.partial_cmp(&r2),
+            (ValueRef::Integer(i), ValueRef::Real(r)) => compare_integer_real(i, r),
+            (ValueRef::Real(r), ValueRef::Integer(i)) => {
+                compare_integer_real(i, r).map(Ordering::reverse)
+            }
+            (ValueRef::Text(t1), ValueRef::Text(t2)) => Some(t1.as_bytes().cmp(t2.as_bytes())),
+            (ValueRef::Blob(b1), ValueRef::Blob(b2)) => Some(b1.cmp(b2)),
+            (v1, v2) => Some(rank(&v1).cmp(&rank(&v2))),
+        }
+    }
+}
+
+/// Compare an integer and a real without losing precision.
+fn compare_integer_real(i: i64, r: f64) -> Option<Ordering> {
+    // 2^63
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if r.is_nan() {
+        None
+    } else if r < -LIMIT {
+        Some(Ordering::Greater)
+    } else if r >= LIMIT {
+        Some(Ordering::Less)
+    } else {
+        let t = r.trunc();
+        Some(i.cmp(&(t as i64)).then_with(|| {
+            if r > t {
+                Ordering::Less
+            } else if r < t {
+                Ordering::Greater
+            } else {
+                // Same value: integers first.
+                Ordering::Less
+            }
+        }))
+    }
+}
+
 impl From<ValueRef<'_>> for Value {
     fn from(borrowed: ValueRef<'_>) -> Value {
         match borrowed {
@@ -105,15 +184,27 @@ impl<'a> From<&'a Value> for ValueRef<'a> {
     }
 }
 
-#[cfg(any(feature = "functions", feature = "session", feature = "vtab"))]
+#[cfg(any(
+    feature = "functions",
+    feature = "preupdate_hook",
+    feature = "session",
+    feature = "vtab"
+))]
 impl<'a> ValueRef<'a> {
     pub(crate) unsafe fn from_value(value: *mut crate::ffi::sqlite3_value) -> ValueRef<'a> {
+        ValueRef::from_value_checked(value).expect("sqlite3_value_text returned invalid UTF-8")
+    }
+
+    /// Like `from_value`, but fails instead of panicking if the value is a
+    /// text which is not valid UTF-8: SQLite does not validate text (e.g. a
+    /// blob cast to text).
+    pub(crate) unsafe fn from_value_checked(
+        value: *mut crate::ffi::sqlite3_value,
+    ) -> Result<ValueRef<'a>, std::str::Utf8Error> {
         use crate::ffi;
-        use std::ffi::CStr;
-        use std::os::raw::c_char;
         use std::slice::from_raw_parts;
 
-        match ffi::sqlite3_value_type(value) {
+        Ok(match ffi::sqlite3_value_type(value) {
             ffi::SQLITE_NULL => ValueRef::Null,
             ffi::SQLITE_INTEGER => ValueRef::Integer(ffi::sqlite3_value_int64(value)),
             ffi::SQLITE_FLOAT => ValueRef::Real(ffi::sqlite3_value_double(value)),
@@ -123,13 +214,13 @@ impl<'a> ValueRef<'a> {
                     !text.is_null(),
                     "unexpected SQLITE_TEXT value type with NULL data"
                 );
-                let s = CStr::from_ptr(text as *const c_char);
-
-                // sqlite3_value_text returns UTF8 data, so our unwrap here should be fine.
-                let s = s
-                    .to_str()
-                    .expect("sqlite3_value_text returned invalid UTF-8");
-                ValueRef::Text(s)
+                // The length must be read after the conversion to UTF-8.
+                let len = ffi::sqlite3_value_bytes(value);
+                assert!(
+                    len >= 0,
+                    "unexpected negative return from sqlite3_value_bytes"
+                );
+                ValueRef::Text(std::str::from_utf8(from_raw_parts(text, len as usize))?)
             }
             ffi::SQLITE_BLOB => {
                 let (blob, len) = (
@@ -154,6 +245,6 @@ impl<'a> ValueRef<'a> {
                 }
             }
             _ => unreachable!("sqlite3_value_type returned invalid value"),
-        }
+        })
     }
 }
@@ -0,0 +1,129 @@
+//! Updates of a subset of the columns of a row.
+use crate::pragma::Sql;
+use crate::types::{params_from_iter, ToSql};
+use crate::{Connection, Result};
+
+impl Connection {
+    /// Update the `columns` (name and value pairs) of the rows of `table`
+    /// whose `key` column (name and value pair) matches, and return the
+    /// number of rows updated.
+    ///
+    /// This is meant for partial updates (e.g. a PATCH endpoint) where the
+    /// set of columns changes from one call to the next: the `UPDATE`
+    /// statement is built with quoted identifiers and cached for each subset
+    /// of columns (regardless of their order), so that no SQL needs to be
+    /// concatenated by hand. Nothing is done if `columns` is empty.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use rusqlite::types::ToSql;
+    /// fn rename_person(conn: &Connection, id: i64, name: &str) -> Result<usize> {
+    ///     conn.update_columns("person", ("id", &id), &[("name", &name as &dyn ToSql)])
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the table or a column does not exist, if a value
+    /// cannot be converted or if the underlying SQLite calls fail.
+    pub fn update_columns(
+        &self,
+        table: &str,
+        key: (&str, &dyn ToSql),
+        columns: &[(&str, &dyn ToSql)],
+    ) -> Result<usize> {
+        if columns.is_empty() {
+            return Ok(0);
+        }
+        let mut columns = columns.to_vec();
+        columns.sort_by_key(|&(name, _)| name);
+
+        let mut sql = Sql::new();
+        sql.push_keyword("UPDATE")?;
+        sql.push_space();
+        sql.push_identifier(table);
+        sql.push_space();
+        sql.push_keyword("SET")?;
+        for (i, &(name, _)) in columns.iter().enumerate() {
+            if i > 0 {
+                sql.push_comma();
+            }
+            sql.push_space();
+            sql.push_identifier(name);
+            sql.push_equal_sign();
+            sql.push_parameter(i + 1);
+        }
+        sql.push_space();
+        sql.push_keyword("WHERE")?;
+        sql.push_space();
+        sql.push_identifier(key.0);
+        sql.push_equal_sign();
+        sql.push_parameter(columns.len() + 1);
+
+        let mut stmt = self.prepare_cached(sql.as_str())?;
+        let params = columns.iter().map(|&(_, value)| value).chain(Some(key.1));
+        stmt.execute(params_from_iter(params))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::types::ToSql;
+    use crate::{Connection, NO_PARAMS};
+
+    #[test]
+    fn test_update_columns() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE \"my person\" (id INTEGER PRIMARY KEY, name TEXT, \"e mail\" TEXT);
+             INSERT INTO \"my person\" VALUES (1, 'a', 'a@x'), (2, 'b', 'b@x');",
+        )
+        .unwrap();
+
+        let n = db
+            .update_columns("my person", ("id", &1), &[("name", &"z" as &dyn ToSql)])
+            .unwrap();
+        assert_eq!(1, n);
+        let n = db
+            .update_columns(
+                "my person",
+                ("id", &2),
+                &[("e mail", &"c@x" as &dyn ToSql), ("name", &"c")],
+            )
+            .unwrap();
+        assert_eq!(1, n);
+        let n = db
+            .update_columns(
+                "my person",
+                ("id", &1),
+                &[("name", &"y" as &dyn ToSql), ("e mail", &None::<String>)],
+            )
+            .unwrap();
+        assert_eq!(1, n);
+        assert_eq!(0, db.update_columns("my person", ("id", &1), &[]).unwrap());
+        assert_eq!(
+            0,
+            db.update_columns("my person", ("id", &3), &[("name", &"x" as &dyn ToSql)])
+                .unwrap()
+        );
+        assert!(db
+            .update_columns("my person", ("id", &1), &[("age", &1 as &dyn ToSql)])
+            .is_err());
+
+        let mut stmt = db
+            .prepare("SELECT name, \"e mail\" FROM \"my person\" ORDER BY id")
+            .unwrap();
+        let rows: Vec<(String, Option<String>)> = stmt
+            .query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<crate::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                ("y".to_owned(), None),
+                ("c".to_owned(), Some("c@x".to_owned())),
+            ],
+            rows
+        );
+    }
+}
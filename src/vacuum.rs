@@ -0,0 +1,104 @@
+//! Auto-vacuum and free pages management.
+use crate::{Connection, Result};
+
+/// Auto-vacuum mode of a database (see
+/// [`PRAGMA auto_vacuum`](https://sqlite.org/pragma.html#pragma_auto_vacuum)).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoVacuum {
+    /// Free pages are kept in the database file (default).
+    None,
+    /// Free pages are moved to the end of the file, and the file truncated,
+    /// on each commit.
+    Full,
+    /// Free pages are only reclaimed by `Connection::incremental_vacuum`.
+    Incremental,
+}
+
+impl Connection {
+    /// Return the auto-vacuum mode of the main database.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn auto_vacuum(&self) -> Result<AutoVacuum> {
+        let mode: i32 = self.pragma_query_value(None, "auto_vacuum", |row| row.get(0))?;
+        Ok(match mode {
+            1 => AutoVacuum::Full,
+            2 => AutoVacuum::Incremental,
+            _ => AutoVacuum::None,
+        })
+    }
+
+    /// Set the auto-vacuum mode of the main database.
+    ///
+    /// The mode can only be changed from or to `None` before the first table
+    /// is created, or by running `VACUUM` afterwards; switching between
+    /// `Full` and `Incremental` is always possible.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn set_auto_vacuum(&self, mode: AutoVacuum) -> Result<()> {
+        let mode = match mode {
+            AutoVacuum::None => 0,
+            AutoVacuum::Full => 1,
+            AutoVacuum::Incremental => 2,
+        };
+        self.pragma_update(None, "auto_vacuum", &mode)
+    }
+
+    /// Return the number of unused pages in the main database file.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn freelist_count(&self) -> Result<u32> {
+        self.pragma_query_value(None, "freelist_count", |row| row.get(0))
+    }
+
+    /// Remove up to `pages` pages (all of them if `pages` is 0) from the
+    /// freelist of the main database, and truncate the file accordingly.
+    /// Return the number of pages removed.
+    ///
+    /// This has no effect unless the auto-vacuum mode is `Incremental`. Unlike
+    /// `VACUUM`, this can be called regularly with a small number of pages to
+    /// reclaim space gradually, without rewriting the whole database.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn incremental_vacuum(&self, pages: u32) -> Result<u32> {
+        let before = self.freelist_count()?;
+        self.pragma_update(None, "incremental_vacuum", &pages)?;
+        let after = self.freelist_count()?;
+        Ok(before.saturating_sub(after))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AutoVacuum;
+    use crate::Connection;
+
+    #[test]
+    fn test_incremental_vacuum() {
+        let db = Connection::open_in_memory().unwrap();
+        assert_eq!(AutoVacuum::None, db.auto_vacuum().unwrap());
+        db.set_auto_vacuum(AutoVacuum::Incremental).unwrap();
+        assert_eq!(AutoVacuum::Incremental, db.auto_vacuum().unwrap());
+
+        db.execute_batch(
+            "CREATE TABLE foo(x BLOB);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 20)
+             INSERT INTO foo SELECT zeroblob(4096) FROM n;
+             DELETE FROM foo;",
+        )
+        .unwrap();
+        let free = db.freelist_count().unwrap();
+        assert!(free >= 20);
+        assert_eq!(5, db.incremental_vacuum(5).unwrap());
+        assert_eq!(free - 5, db.freelist_count().unwrap());
+        assert_eq!(free - 5, db.incremental_vacuum(0).unwrap());
+        assert_eq!(0, db.freelist_count().unwrap());
+    }
+}
@@ -1,6 +1,22 @@
 //! Array Virtual Table.
 //!
 //! Port of [carray](http://www.sqlite.org/cgi/src/finfo?name=ext/misc/carray.c) C extension.
+//!
+//! An `Array` parameter binds a list of values, e.g. to an `IN` clause:
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! # use rusqlite::types::Value;
+//! # use rusqlite::vtab::array;
+//! # use std::rc::Rc;
+//! fn names(conn: &Connection, ids: &[i64]) -> Result<Vec<String>> {
+//!     array::load_module(conn)?;
+//!     let ids = Rc::new(ids.iter().copied().map(Value::from).collect::<Vec<Value>>());
+//!     let mut stmt = conn.prepare("SELECT name FROM people WHERE id IN rarray(?)")?;
+//!     let rows = stmt.query_map(&[&ids], |row| row.get(0))?;
+//!     rows.collect()
+//! }
+//! ```
 use std::default::Default;
 use std::os::raw::{c_char, c_int, c_void};
 use std::rc::Rc;
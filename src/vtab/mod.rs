@@ -21,7 +21,7 @@ use crate::context::set_result;
 use crate::error::error_from_sqlite_code;
 use crate::ffi;
 pub use crate::ffi::{sqlite3_vtab, sqlite3_vtab_cursor};
-use crate::types::{FromSql, FromSqlError, ToSql, ValueRef};
+use crate::types::{FromSql, FromSqlError, ToSql, Type, ValueRef};
 use crate::{str_to_cstring, Connection, Error, InnerConnection, Result};
 
 // let conn: Connection = ...;
@@ -464,7 +464,8 @@ impl Values<'_> {
 
     pub fn get<T: FromSql>(&self, idx: usize) -> Result<T> {
         let arg = self.args[idx];
-        let value = unsafe { ValueRef::from_value(arg) };
+        let value = unsafe { ValueRef::from_value_checked(arg) }
+            .map_err(|err| Error::FromSqlConversionFailure(idx, Type::Text, Box::new(err)))?;
         FromSql::column_result(value).map_err(|err| match err {
             FromSqlError::InvalidType => Error::InvalidFilterParameterType(idx, value.data_type()),
             FromSqlError::Other(err) => {
@@ -984,6 +985,7 @@ pub mod array;
 pub mod csvtab;
 #[cfg(feature = "bundled")]
 pub mod series; // SQLite >= 3.9.0
+pub mod table_function; // SQLite >= 3.9.0
 
 #[cfg(test)]
 mod test {
@@ -0,0 +1,377 @@
+//! Table-valued functions.
+//!
+//! A [table-valued function](https://sqlite.org/vtab.html#tabfunc2) is an
+//! eponymous virtual table with hidden columns: the hidden columns are the
+//! function arguments and the other columns are the function results.
+//! The `table_function!` macro generates such a virtual table from a plain
+//! Rust function.
+//!
+//! # Example
+//!
+//! ```rust
+//! use rusqlite::{table_function, Connection, Result, NO_PARAMS};
+//!
+//! table_function! {
+//!     /// Split `text` on each occurrence of `sep`.
+//!     fn split(text: String, sep: String) -> (idx: i64, part: String) {
+//!         let parts: Vec<String> = text.split(sep.as_str()).map(String::from).collect();
+//!         Ok((0..).zip(parts))
+//!     }
+//! }
+//!
+//! fn main() -> Result<()> {
+//!     let db = Connection::open_in_memory()?;
+//!     split(&db)?;
+//!
+//!     let mut stmt = db.prepare("SELECT part FROM split('a,b,c', ',') ORDER BY idx")?;
+//!     let parts = stmt
+//!         .query_map(NO_PARAMS, |row| row.get::<_, String>(0))?
+//!         .collect::<Result<Vec<_>>>()?;
+//!     assert_eq!(parts, vec!["a", "b", "c"]);
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Table-valued functions require SQLite >= 3.9.0.
+use std::marker::PhantomData;
+use std::os::raw::c_int;
+
+use crate::ffi;
+use crate::types::{ToSql, ToSqlOutput, Value};
+use crate::vtab::{
+    Context, IndexConstraintOp, IndexInfo, VTab, VTabConnection, VTabCursor, Values,
+};
+use crate::{Error, Result};
+
+#[doc(hidden)]
+pub use lazy_static::lazy_static as __lazy_static;
+
+/// Rows produced by a `TableFunction`.
+pub type TableFunctionRows = Box<dyn Iterator<Item = Result<Vec<Value>>>>;
+
+/// Description of a table-valued function.
+///
+/// Usually implemented by the `table_function!` macro.
+pub trait TableFunction: 'static {
+    /// Name of the function.
+    const NAME: &'static str;
+    /// Names of the result columns.
+    const COLUMNS: &'static [&'static str];
+    /// Names of the arguments (declared as hidden columns).
+    const ARGUMENTS: &'static [&'static str];
+
+    /// Compute the result rows from the arguments. `args` contains exactly
+    /// one value per argument, in declaration order.
+    fn call(args: &Values<'_>) -> Result<TableFunctionRows>;
+}
+
+/// Generate a table-valued function from a Rust function.
+///
+/// The generated item is a function with the same name which registers the
+/// table-valued function on a connection. The body must evaluate to a
+/// `Result` of something that can be iterated over tuples matching the
+/// declared result columns.
+///
+/// See the [module documentation](vtab/table_function/index.html) for an
+/// example.
+#[macro_export]
+macro_rules! table_function {
+    (
+        $(#[$meta:meta])*
+        $vis:vis fn $name:ident($($arg:ident: $arg_ty:ty),* $(,)?)
+            -> ($($col:ident: $col_ty:ty),+ $(,)?) $body:block
+    ) => {
+        $(#[$meta])*
+        $vis fn $name(conn: &$crate::Connection) -> $crate::Result<()> {
+            use $crate::vtab::table_function::{
+                __lazy_static, to_value, FunctionTab, TableFunction, TableFunctionRows,
+            };
+
+            struct Function;
+
+            impl TableFunction for Function {
+                const NAME: &'static str = stringify!($name);
+                const COLUMNS: &'static [&'static str] = &[$(stringify!($col)),+];
+                const ARGUMENTS: &'static [&'static str] = &[$(stringify!($arg)),*];
+
+                #[allow(unused_assignments, unused_mut, unused_variables)]
+                #[allow(clippy::redundant_closure_call)]
+                fn call(args: &$crate::vtab::Values<'_>) -> $crate::Result<TableFunctionRows> {
+                    let mut i = 0;
+                    $(
+                        let $arg: $arg_ty = args.get(i)?;
+                        i += 1;
+                    )*
+                    let rows = (move || -> $crate::Result<_> { $body })()?;
+                    Ok(Box::new(rows.into_iter().map(|row| {
+                        let ($($col,)+): ($($col_ty,)+) = row;
+                        Ok(vec![$(to_value(&$col)?),+])
+                    })))
+                }
+            }
+
+            __lazy_static! {
+                static ref MODULE: $crate::vtab::Module<FunctionTab<Function>> =
+                    $crate::vtab::eponymous_only_module::<FunctionTab<Function>>(1);
+            }
+
+            conn.create_module(Function::NAME, &MODULE, None)
+        }
+    };
+}
+
+#[doc(hidden)]
+pub fn to_value(value: &dyn ToSql) -> Result<Value> {
+    match value.to_sql()? {
+        ToSqlOutput::Borrowed(v) => Ok(v.into()),
+        ToSqlOutput::Owned(v) => Ok(v),
+        #[allow(unreachable_patterns)]
+        v => Err(Error::ModuleError(format!("unsupported result {:?}", v))),
+    }
+}
+
+/// Virtual table backing a `TableFunction`.
+#[repr(C)]
+pub struct FunctionTab<F: TableFunction> {
+    /// Base class. Must be first
+    base: ffi::sqlite3_vtab,
+    phantom: PhantomData<F>,
+}
+
+impl<F: TableFunction> VTab for FunctionTab<F> {
+    type Aux = ();
+    type Cursor = FunctionTabCursor<F>;
+
+    fn connect(
+        _: &mut VTabConnection,
+        _aux: Option<&()>,
+        _args: &[&[u8]],
+    ) -> Result<(String, FunctionTab<F>)> {
+        let mut sql = String::from("CREATE TABLE x(");
+        for (i, col) in F::COLUMNS.iter().enumerate() {
+            if i > 0 {
+                sql.push(',');
+            }
+            sql.push_str(col);
+        }
+        for arg in F::ARGUMENTS {
+            sql.push(',');
+            sql.push_str(arg);
+            sql.push_str(" hidden");
+        }
+        sql.push(')');
+        let vtab = FunctionTab {
+            base: ffi::sqlite3_vtab::default(),
+            phantom: PhantomData,
+        };
+        Ok((sql, vtab))
+    }
+
+    fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+        let n_cols = F::COLUMNS.len() as c_int;
+        // Index of the constraint bound to each argument
+        let mut arg_constraints = vec![None; F::ARGUMENTS.len()];
+        for (i, constraint) in info.constraints().enumerate() {
+            if !constraint.is_usable()
+                || constraint.operator() != IndexConstraintOp::SQLITE_INDEX_CONSTRAINT_EQ
+            {
+                continue;
+            }
+            let col = constraint.column();
+            if col >= n_cols {
+                let arg = (col - n_cols) as usize;
+                if arg_constraints[arg].is_none() {
+                    arg_constraints[arg] = Some(i);
+                }
+            }
+        }
+
+        if arg_constraints.iter().all(Option::is_some) {
+            for (i, constraint_idx) in arg_constraints.into_iter().enumerate() {
+                let mut constraint_usage = info.constraint_usage(constraint_idx.unwrap());
+                constraint_usage.set_argv_index(i as c_int + 1);
+                constraint_usage.set_omit(true);
+            }
+            info.set_idx_num(1);
+            info.set_estimated_cost(1f64);
+        } else {
+            // Some arguments are missing: discourage this plan.
+            info.set_idx_num(0);
+            info.set_estimated_cost(2_147_483_647f64);
+        }
+        Ok(())
+    }
+
+    fn open(&self) -> Result<FunctionTabCursor<F>> {
+        Ok(FunctionTabCursor {
+            base: ffi::sqlite3_vtab_cursor::default(),
+            args: Vec::new(),
+            rows: None,
+            row: None,
+            row_id: 0,
+            phantom: PhantomData,
+        })
+    }
+}
+
+/// A cursor for a `FunctionTab`.
+#[repr(C)]
+pub struct FunctionTabCursor<F: TableFunction> {
+    /// Base class. Must be first
+    base: ffi::sqlite3_vtab_cursor,
+    /// The arguments (hidden columns)
+    args: Vec<Value>,
+    /// The remaining rows
+    rows: Option<TableFunctionRows>,
+    /// The current row
+    row: Option<Vec<Value>>,
+    /// The rowid
+    row_id: i64,
+    phantom: PhantomData<F>,
+}
+
+impl<F: TableFunction> FunctionTabCursor<F> {
+    fn advance(&mut self) -> Result<()> {
+        self.row = match self.rows.as_mut().and_then(Iterator::next) {
+            Some(row) => {
+                let row = row?;
+                if row.len() != F::COLUMNS.len() {
+                    return Err(Error::ModuleError(format!(
+                        "{}: expected {} columns, got {}",
+                        F::NAME,
+                        F::COLUMNS.len(),
+                        row.len()
+                    )));
+                }
+                Some(row)
+            }
+            None => None,
+        };
+        self.row_id += 1;
+        Ok(())
+    }
+}
+
+impl<F: TableFunction> VTabCursor for FunctionTabCursor<F> {
+    fn filter(&mut self, idx_num: c_int, _idx_str: Option<&str>, args: &Values<'_>) -> Result<()> {
+        if idx_num == 0 || args.len() != F::ARGUMENTS.len() {
+            return Err(Error::ModuleError(format!(
+                "{}: expected {} arguments",
+                F::NAME,
+                F::ARGUMENTS.len()
+            )));
+        }
+        self.args = (0..args.len())
+            .map(|i| args.get::<Value>(i))
+            .collect::<Result<_>>()?;
+        self.rows = Some(F::call(args)?);
+        self.row_id = 0;
+        self.advance()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.advance()
+    }
+
+    fn eof(&self) -> bool {
+        self.row.is_none()
+    }
+
+    fn column(&self, ctx: &mut Context, i: c_int) -> Result<()> {
+        let i = i as usize;
+        let n_cols = F::COLUMNS.len();
+        let value = if i < n_cols {
+            self.row.as_ref().map(|row| &row[i])
+        } else {
+            self.args.get(i - n_cols)
+        };
+        match value {
+            Some(value) => ctx.set_result(value),
+            None => ctx.set_result(&Value::Null),
+        }
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.row_id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{version_number, Connection, Result, NO_PARAMS};
+
+    table_function! {
+        fn split(text: String, sep: String) -> (idx: i64, part: String) {
+            let parts: Vec<String> = text.split(sep.as_str()).map(String::from).collect();
+            Ok((0..).zip(parts))
+        }
+    }
+
+    table_function! {
+        fn squares(n: i64) -> (value: i64, square: i64) {
+            if n < 0 {
+                return Err(crate::Error::ModuleError("negative".to_owned()));
+            }
+            Ok((1..=n).map(|i| (i, i * i)))
+        }
+    }
+
+    #[test]
+    fn test_table_function() {
+        if version_number() < 3_009_000 {
+            return;
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        split(&db).unwrap();
+        squares(&db).unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT idx, part, text, sep FROM split('a,b,c', ',')")
+            .unwrap();
+        let rows = stmt
+            .query_map(NO_PARAMS, |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(3, rows.len());
+        assert_eq!((2, "c".to_owned()), (rows[2].0, rows[2].1.clone()));
+        assert_eq!("a,b,c", rows[0].2);
+        assert_eq!(",", rows[0].3);
+
+        let sum: i64 = db
+            .query_row("SELECT sum(square) FROM squares(3)", NO_PARAMS, |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(14, sum);
+
+        let sum: i64 = db
+            .query_row(
+                "SELECT sum(s.square) FROM squares(3) AS n, squares(n.value) AS s",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(1 + 1 + 4 + 1 + 4 + 9, sum);
+
+        assert!(db
+            .query_row("SELECT * FROM squares(-1)", NO_PARAMS, |_| Ok(()))
+            .is_err());
+        // Invalid UTF-8 text is reported as an error, without panicking.
+        assert!(db
+            .query_row(
+                "SELECT * FROM split(CAST(x'ff' AS TEXT), ',')",
+                NO_PARAMS,
+                |_| Ok(())
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,324 @@
+//! Manual checkpoints and size limits of WAL files
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::error::error_from_sqlite_code;
+use crate::ffi;
+use crate::{Connection, DatabaseName, Error, Result};
+
+/// Checkpoint mode (see
+/// [PRAGMA wal_checkpoint](https://sqlite.org/pragma.html#pragma_wal_checkpoint)).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckpointMode {
+    /// Checkpoint as many frames as possible without waiting for readers or
+    /// writers.
+    Passive,
+    /// Wait for writers, then checkpoint all frames.
+    Full,
+    /// Like `Full`, then wait for readers so that the next writer restarts
+    /// the WAL file from the beginning.
+    Restart,
+    /// Like `Restart`, and truncate the WAL file to zero bytes (SQLite
+    /// 3.8.8 or later: older versions run a `Passive` checkpoint instead).
+    Truncate,
+}
+
+impl CheckpointMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            CheckpointMode::Passive => "PASSIVE",
+            CheckpointMode::Full => "FULL",
+            CheckpointMode::Restart => "RESTART",
+            CheckpointMode::Truncate => "TRUNCATE",
+        }
+    }
+}
+
+/// State of a WAL file, returned by `Connection::wal_checkpoint_info`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WalInfo {
+    /// Size of the WAL file in bytes. The file is not truncated by
+    /// checkpoints (except `Truncate` ones), but reused from its beginning.
+    pub file_size: u64,
+    /// Number of frames written in the WAL file since it was last reset.
+    pub frames: u32,
+    /// Number of these frames which could not be copied to the database yet.
+    pub pending_frames: u32,
+}
+
+impl WalInfo {
+    /// Return `true` if some frames cannot be checkpointed because readers
+    /// still use them (or because another connection is writing).
+    pub fn checkpoint_blocked(&self) -> bool {
+        self.pending_frames > 0
+    }
+}
+
+impl Connection {
+    /// Run a checkpoint on the WAL file of `db_name` (or of all attached
+    /// databases if `None`), and return the number of frames in the WAL file
+    /// and the number of frames checkpointed (both -1 if the database is not
+    /// in WAL mode).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with `ErrorCode::DatabaseBusy` if a `Full`,
+    /// `Restart` or `Truncate` checkpoint could not complete because of other
+    /// connections, or if the underlying SQLite call fails.
+    pub fn wal_checkpoint(
+        &self,
+        db_name: Option<DatabaseName<'_>>,
+        mode: CheckpointMode,
+    ) -> Result<(i32, i32)> {
+        let mut result = (0, 0, 0);
+        self.pragma(db_name, "wal_checkpoint", &mode.as_str(), |row| {
+            result = (row.get(0)?, row.get(1)?, row.get(2)?);
+            Ok(())
+        })?;
+        let (busy, log, checkpointed): (i32, i32, i32) = result;
+        if busy != 0 {
+            // What `sqlite3_wal_checkpoint_v2` returns, while the pragma
+            // reports it in its result.
+            return Err(error_from_sqlite_code(ffi::SQLITE_BUSY, None));
+        }
+        Ok((log, checkpointed))
+    }
+
+    /// Run a `Passive` checkpoint on the WAL file of `db_name`, and return
+    /// the state of the WAL file.
+    ///
+    /// SQLite only reports the number of frames of the WAL file when running
+    /// a checkpoint: this is not a read-only inspection, the frames which are
+    /// not used by readers are copied to the database. A `Passive`
+    /// checkpoint never waits for other connections, and does nothing if no
+    /// frame can be copied.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with `Error::IncompatibleDatabaseSetting` if the
+    /// database is not in WAL mode, with `ErrorCode::DatabaseBusy` if another
+    /// connection is running a checkpoint, or if the underlying SQLite calls
+    /// fail.
+    pub fn wal_checkpoint_info(&self, db_name: DatabaseName<'_>) -> Result<WalInfo> {
+        let (frames, checkpointed) = self.wal_checkpoint(Some(db_name), CheckpointMode::Passive)?;
+        if frames < 0 {
+            return Err(Error::IncompatibleDatabaseSetting(format!(
+                "database {} is not in WAL mode",
+                db_name.as_str()
+            )));
+        }
+        let mut file = None;
+        self.pragma_query(None, "database_list", |row| {
+            if row.get::<_, String>(1)? == db_name.as_str() {
+                file = Some(row.get::<_, String>(2)?);
+            }
+            Ok(())
+        })?;
+        let file_size = match file {
+            Some(ref file) if !file.is_empty() => {
+                let mut wal_path = OsString::from(file);
+                wal_path.push("-wal");
+                wal_state(Path::new(&wal_path)).map_or(0, |(len, _)| len)
+            }
+            _ => 0,
+        };
+        Ok(WalInfo {
+            file_size,
+            frames: frames as u32,
+            pending_frames: (frames - checkpointed) as u32,
+        })
+    }
+
+    /// Run a `Truncate` checkpoint on the WAL file of `db_name`, which
+    /// copies all its frames to the database and truncates it to zero bytes,
+    /// e.g. when it has grown large after a burst of writes. Does nothing if
+    /// the database is not in WAL mode.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with `ErrorCode::DatabaseBusy` if other connections
+    /// prevent the checkpoint from completing (e.g. a reader using old
+    /// frames), or if the underlying SQLite call fails.
+    pub fn truncate_wal(&self, db_name: DatabaseName<'_>) -> Result<()> {
+        self.wal_checkpoint(Some(db_name), CheckpointMode::Truncate)
+            .map(|_| ())
+    }
+
+    /// Return the number of pages which the WAL file must hold for a commit
+    /// to run an automatic checkpoint (1000 by default), or 0 if automatic
+    /// checkpoints are disabled.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn wal_autocheckpoint(&self) -> Result<u32> {
+        let pages: i64 = self.pragma_query_value(None, "wal_autocheckpoint", |row| row.get(0))?;
+        Ok(pages.max(0) as u32)
+    }
+
+    /// Set the number of pages which the WAL file must hold for a commit to
+    /// run an automatic checkpoint, or disable automatic checkpoints with 0
+    /// (e.g. when they are run by a `Checkpointer`).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn set_wal_autocheckpoint(&self, pages: u32) -> Result<()> {
+        self.pragma_update(None, "wal_autocheckpoint", &pages)
+    }
+
+    /// Return the size in bytes to which the journal (or WAL) file of
+    /// `db_name` is truncated after a transaction or a checkpoint, or `None`
+    /// if it is left as is (the default).
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn journal_size_limit(&self, db_name: DatabaseName<'_>) -> Result<Option<u64>> {
+        let limit: i64 =
+            self.pragma_query_value(Some(db_name), "journal_size_limit", |row| row.get(0))?;
+        Ok(if limit < 0 { None } else { Some(limit as u64) })
+    }
+
+    /// Set the size in bytes to which the journal (or WAL) file of `db_name`
+    /// is truncated after a transaction or a checkpoint, or leave it as is
+    /// with `None`.
+    ///
+    /// A WAL file is only truncated when a checkpoint restarts it from its
+    /// beginning, so this bounds its size on disk between bursts of writes.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::ToSqlConversionFailure)` if `limit` is greater
+    /// than `i64::MAX`, or `Err` if the underlying SQLite call fails.
+    pub fn set_journal_size_limit(
+        &self,
+        db_name: DatabaseName<'_>,
+        limit: Option<u64>,
+    ) -> Result<()> {
+        let limit = match limit {
+            Some(limit) => {
+                i64::try_from(limit).map_err(|err| Error::ToSqlConversionFailure(Box::new(err)))?
+            }
+            None => -1,
+        };
+        self.pragma_update(Some(db_name), "journal_size_limit", &limit)
+    }
+}
+
+/// Size and modification time of the WAL file.
+pub(crate) fn wal_state(wal_path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(wal_path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    use super::{CheckpointMode, WalInfo};
+    use crate::{Connection, DatabaseName, Error, ErrorCode};
+
+    #[test]
+    fn test_wal_checkpoint() {
+        let temp_dir = TempDir::new("test_wal_checkpoint").unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = Connection::open(&path).unwrap();
+        assert_eq!(
+            (-1, -1),
+            db.wal_checkpoint(None, CheckpointMode::Passive).unwrap()
+        );
+        db.pragma_update(None, "journal_mode", &"WAL").unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES(1);")
+            .unwrap();
+
+        let (log, checkpointed) = db
+            .wal_checkpoint(Some(DatabaseName::Main), CheckpointMode::Passive)
+            .unwrap();
+        assert!(log > 0);
+        assert_eq!(log, checkpointed);
+        db.wal_checkpoint(None, CheckpointMode::Truncate).unwrap();
+        assert_eq!(
+            0,
+            fs::metadata(temp_dir.path().join("test.db3-wal"))
+                .unwrap()
+                .len()
+        );
+
+        // A reader holding a snapshot prevents the WAL file from restarting.
+        let reader = Connection::open(&path).unwrap();
+        reader.execute_batch("BEGIN; SELECT * FROM foo;").unwrap();
+        db.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
+        db.busy_timeout(Duration::from_millis(0)).unwrap();
+        match db.wal_checkpoint(None, CheckpointMode::Restart) {
+            Err(Error::SqliteFailure(ref err, _)) if err.code == ErrorCode::DatabaseBusy => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_wal_size_pragmas() {
+        let db = Connection::open_in_memory().unwrap();
+        assert_eq!(1000, db.wal_autocheckpoint().unwrap());
+        db.set_wal_autocheckpoint(0).unwrap();
+        assert_eq!(0, db.wal_autocheckpoint().unwrap());
+
+        assert_eq!(None, db.journal_size_limit(DatabaseName::Main).unwrap());
+        db.set_journal_size_limit(DatabaseName::Main, Some(1 << 20))
+            .unwrap();
+        assert_eq!(
+            Some(1 << 20),
+            db.journal_size_limit(DatabaseName::Main).unwrap()
+        );
+        db.set_journal_size_limit(DatabaseName::Main, None).unwrap();
+        assert_eq!(None, db.journal_size_limit(DatabaseName::Main).unwrap());
+        match db.set_journal_size_limit(DatabaseName::Main, Some(u64::MAX)) {
+            Err(Error::ToSqlConversionFailure(_)) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_wal_checkpoint_info() {
+        let temp_dir = TempDir::new("test_wal_checkpoint_info").unwrap();
+        let path = temp_dir.path().join("test.db3");
+        let db = Connection::open(&path).unwrap();
+        match db.wal_checkpoint_info(DatabaseName::Main) {
+            Err(Error::IncompatibleDatabaseSetting(_)) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        db.pragma_update(None, "journal_mode", &"WAL").unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER); INSERT INTO foo VALUES(1);")
+            .unwrap();
+        let info = db.wal_checkpoint_info(DatabaseName::Main).unwrap();
+        assert!(info.frames > 0);
+        assert!(!info.checkpoint_blocked());
+        let file_size = fs::metadata(temp_dir.path().join("test.db3-wal"))
+            .unwrap()
+            .len();
+        assert_eq!(file_size, info.file_size);
+        db.truncate_wal(DatabaseName::Main).unwrap();
+        assert_eq!(
+            0,
+            fs::metadata(temp_dir.path().join("test.db3-wal"))
+                .unwrap()
+                .len()
+        );
+
+        // A reader holding a snapshot blocks the checkpoint of later frames.
+        let reader = Connection::open(&path).unwrap();
+        reader.execute_batch("BEGIN; SELECT * FROM foo;").unwrap();
+        db.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
+        let info = db.wal_checkpoint_info(DatabaseName::Main).unwrap();
+        assert!(info.checkpoint_blocked());
+        reader.execute_batch("COMMIT").unwrap();
+        let info: WalInfo = db.wal_checkpoint_info(DatabaseName::Main).unwrap();
+        assert!(!info.checkpoint_blocked());
+    }
+}
@@ -0,0 +1,45 @@
+//! Ensure `#[derive(FromRow, ToParams)]` maps struct fields to columns and
+//! named parameters.
+
+use rusqlite::{Connection, FromRow, ToParams, NO_PARAMS};
+
+#[derive(Debug, PartialEq, FromRow, ToParams)]
+struct Person {
+    id: i64,
+    /// Full name.
+    #[rusqlite(rename = "full_name")]
+    pub name: String,
+    pub(crate) tags: Option<Vec<u8>>,
+    r#type: String,
+}
+
+#[test]
+fn test_derive() {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch("CREATE TABLE person(id INTEGER, full_name TEXT, tags BLOB, type TEXT)")
+        .unwrap();
+    let person = Person {
+        id: 1,
+        name: "Alice".to_owned(),
+        tags: None,
+        r#type: "admin".to_owned(),
+    };
+    db.execute_named(
+        "INSERT INTO person VALUES(:id, :full_name, :tags, :type)",
+        &person.to_params(),
+    )
+    .unwrap();
+
+    let people: Vec<Person> = db.query_table("person").unwrap();
+    assert_eq!(vec![person], people);
+    let person: Person = db
+        .query_row_as(
+            "SELECT 'Bob' AS full_name, 'user' AS type, 2 AS id, x'01' AS tags",
+            NO_PARAMS,
+        )
+        .unwrap();
+    assert_eq!(Some(vec![1]), person.tags);
+    assert!(db
+        .query_row_as::<Person, _>("SELECT 1 AS id", NO_PARAMS)
+        .is_err());
+}
@@ -0,0 +1,23 @@
+//! This file contains unit tests for `rusqlite::set_temp_directory`. This
+//! function affects SQLite process-wide and so is not safe to run as a normal
+//! #[test] in the library.
+
+use rusqlite::{set_temp_directory, Connection, TempStore, NO_PARAMS};
+
+fn main() {
+    let temp_dir = tempdir::TempDir::new("temp_directory").unwrap();
+    set_temp_directory(Some(temp_dir.path())).unwrap();
+
+    let db = Connection::open_in_memory().unwrap();
+    db.set_temp_store(TempStore::File).unwrap();
+    db.execute_batch("CREATE TEMP TABLE foo(x INTEGER); INSERT INTO foo VALUES(1);")
+        .unwrap();
+    let dir: String = db
+        .query_row("PRAGMA temp_store_directory", NO_PARAMS, |r| r.get(0))
+        .unwrap();
+    assert_eq!(temp_dir.path().to_str().unwrap(), dir);
+    assert!(set_temp_directory(None).is_err());
+
+    db.close().unwrap();
+    set_temp_directory(None).unwrap();
+}